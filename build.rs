@@ -0,0 +1,11 @@
+fn main() -> std::io::Result<()> {
+    println!("cargo:rerun-if-changed=build.rs");
+    // the protobuf events are only built with the proto feature, so that
+    // protoc is not needed otherwise
+    #[cfg(feature = "proto")]
+    {
+        println!("cargo:rerun-if-changed=proto/events.proto");
+        prost_build::compile_protos(&["proto/events.proto"], &["proto"])?;
+    }
+    Ok(())
+}
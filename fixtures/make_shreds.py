@@ -0,0 +1,306 @@
+"""
+Writes fixtures/shreds.json, the legacy shreds of the golden signatures
+test, independently of the solana shredder the test runs against:
+
+- 4 batches of 10 entries of 5 transfers, the i-th transfer of `i + 1`
+  lamports from the keypair seeded with `[i; 32]` to `[7; 32]`, a batch
+  per FEC set, the sigs are fixtures/signatures.txt
+- the shreds of slot 1000 (parent 999, version 0) signed by the keypair
+  seeded with `[42; 32]`, with the coding shreds of the turbine erasure
+  batch sizes, Reed-Solomon as in reed-solomon-erasure (galois_8)
+- data shred 1 left out, the first FEC set has to be recovered
+
+usage: python3 fixtures/make_shreds.py
+"""
+
+import hashlib
+import json
+import os
+import struct
+
+from cryptography.hazmat.primitives.asymmetric.ed25519 import (
+    Ed25519PrivateKey,
+)
+
+SLOT = 1000
+PARENT_SLOT = 999
+VERSION = 0
+LEADER_SEED = bytes([42] * 32)
+TO = bytes([7] * 32)
+SYSTEM_PROGRAM = bytes(32)
+
+SIZE_OF_PAYLOAD = 1228
+SIZE_OF_COMMON_HEADER = 83
+SIZE_OF_DATA_HEADERS = 88
+SIZE_OF_CODING_HEADERS = 89
+SIZE_OF_ERASURE_SHARD = SIZE_OF_PAYLOAD - SIZE_OF_CODING_HEADERS
+DATA_CAPACITY = SIZE_OF_ERASURE_SHARD - SIZE_OF_DATA_HEADERS
+LEGACY_DATA = 0xA5
+LEGACY_CODE = 0x5A
+DATA_COMPLETE_SHRED = 0x40
+LAST_SHRED_IN_SLOT = 0xC0
+# shredder::ERASURE_BATCH_SIZE, the size of the erasure batch by the
+# number of data shreds
+ERASURE_BATCH_SIZE = [
+    0, 18, 20, 22, 23, 25, 27, 28, 30, 32, 33, 35, 36, 38, 39, 41, 42,
+    43, 45, 46, 48, 49, 51, 52, 53, 55, 56, 58, 59, 60, 62, 63, 64,
+]
+
+
+def keypair(seed):
+    private = Ed25519PrivateKey.from_private_bytes(seed)
+    public = private.public_key().public_bytes_raw()
+    return private, public
+
+
+def short_vec(n):
+    out = bytearray()
+    while True:
+        byte = n & 0x7F
+        n >>= 7
+        if n:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+B58 = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz"
+
+
+def b58encode(data):
+    n = int.from_bytes(data, "big")
+    out = ""
+    while n:
+        n, r = divmod(n, 58)
+        out = B58[r] + out
+    return "1" * (len(data) - len(data.lstrip(b"\0"))) + out
+
+
+def transfer(seed, lamports):
+    """system_transaction::transfer with a default blockhash"""
+    private, public = keypair(seed)
+    data = struct.pack("<IQ", 2, lamports)
+    message = (
+        bytes([1, 0, 1])
+        + short_vec(3)
+        + public
+        + TO
+        + SYSTEM_PROGRAM
+        + bytes(32)
+        + short_vec(1)
+        + bytes([2])
+        + short_vec(2)
+        + bytes([0, 1])
+        + short_vec(len(data))
+        + data
+    )
+    signature = private.sign(message)
+    return signature, short_vec(1) + signature + message
+
+
+def sha256(*parts):
+    return hashlib.sha256(b"".join(parts)).digest()
+
+
+def merkle_root(items):
+    """solana_merkle_tree root, the odd node out is paired with itself"""
+    if not items:
+        return bytes(32)
+    level = [sha256(b"\0", item) for item in items]
+    while len(level) > 1:
+        level = [
+            sha256(b"\1", level[i], level[min(i + 1, len(level) - 1)])
+            for i in range(0, len(level), 2)
+        ]
+    return level[0]
+
+
+def batches():
+    prev_hash = bytes(32)
+    signatures = []
+    out = []
+    for batch in range(4):
+        entries = bytearray(struct.pack("<Q", 10))
+        for entry in range(10):
+            txs = []
+            for tx in range(5):
+                i = batch * 50 + entry * 5 + tx
+                signature, raw = transfer(bytes([i] * 32), i + 1)
+                signatures.append(signature)
+                txs.append((signature, raw))
+            # one hash with the txs mixed in
+            prev_hash = sha256(
+                prev_hash, merkle_root([sig for sig, _ in txs])
+            )
+            entries += struct.pack("<Q", 1) + prev_hash
+            entries += struct.pack("<Q", len(txs))
+            for _, raw in txs:
+                entries += raw
+        out.append(bytes(entries))
+    return out, signatures
+
+
+# GF(2^8) of reed-solomon-erasure's galois_8, polynomial 0x11d
+EXP = [0] * 512
+LOG = [0] * 256
+x = 1
+for i in range(255):
+    EXP[i] = x
+    LOG[x] = i
+    x <<= 1
+    if x & 0x100:
+        x ^= 0x11D
+for i in range(255, 512):
+    EXP[i] = EXP[i - 255]
+
+
+def mul(a, b):
+    if a == 0 or b == 0:
+        return 0
+    return EXP[LOG[a] + LOG[b]]
+
+
+def div(a, b):
+    if a == 0:
+        return 0
+    return EXP[(LOG[a] - LOG[b]) % 255]
+
+
+def gexp(a, n):
+    if n == 0:
+        return 1
+    if a == 0:
+        return 0
+    return EXP[(LOG[a] * n) % 255]
+
+
+def invert(matrix):
+    n = len(matrix)
+    work = [row[:] + [int(i == j) for j in range(n)] for i, row in enumerate(matrix)]
+    for col in range(n):
+        pivot = next(r for r in range(col, n) if work[r][col])
+        work[col], work[pivot] = work[pivot], work[col]
+        scale = work[col][col]
+        work[col] = [div(v, scale) for v in work[col]]
+        for r in range(n):
+            if r != col and work[r][col]:
+                factor = work[r][col]
+                work[r] = [
+                    v ^ mul(factor, p) for v, p in zip(work[r], work[col])
+                ]
+    return [row[n:] for row in work]
+
+
+def parity_rows(num_data, num_coding):
+    """the rows of the systematic encoding matrix below the identity, the
+    vandermonde matrix times the inverse of its top square"""
+    total = num_data + num_coding
+    vandermonde = [
+        [gexp(r, c) for c in range(num_data)] for r in range(total)
+    ]
+    top = invert(vandermonde[:num_data])
+    return [
+        [dot(row, [top[k][c] for k in range(num_data)]) for c in range(num_data)]
+        for row in vandermonde[num_data:]
+    ]
+
+
+def dot(a, b):
+    out = 0
+    for x, y in zip(a, b):
+        out ^= mul(x, y)
+    return out
+
+
+def encode(shards, num_coding):
+    rows = parity_rows(len(shards), num_coding)
+    tables = [[bytes(mul(c, v) for v in range(256)) for c in row] for row in rows]
+    parity = []
+    for table in tables:
+        out = bytearray(len(shards[0]))
+        for shard, mul_table in zip(shards, table):
+            translated = shard.translate(mul_table)
+            out = bytearray(a ^ b for a, b in zip(out, translated))
+        parity.append(bytes(out))
+    return parity
+
+
+def sign(leader, payload):
+    return leader.sign(bytes(payload[64:])) + bytes(payload[64:])
+
+
+def common_header(variant, index, fec_set_index):
+    return bytes(64) + struct.pack(
+        "<BQIHI", variant, SLOT, index, VERSION, fec_set_index
+    )
+
+
+def shred(data, is_last_in_slot, next_shred_index, next_code_index, leader):
+    chunks = [
+        data[i : i + DATA_CAPACITY] for i in range(0, len(data), DATA_CAPACITY)
+    ]
+    assert len(chunks) <= 32, "a batch per FEC set"
+    fec_set_index = next_shred_index
+    data_shreds = []
+    for i, chunk in enumerate(chunks):
+        index = next_shred_index + i
+        flags = 0
+        if i == len(chunks) - 1:
+            flags = LAST_SHRED_IN_SLOT if is_last_in_slot else DATA_COMPLETE_SHRED
+        payload = bytearray(SIZE_OF_PAYLOAD)
+        payload[:SIZE_OF_COMMON_HEADER] = common_header(
+            LEGACY_DATA, index, fec_set_index
+        )
+        payload[SIZE_OF_COMMON_HEADER:SIZE_OF_DATA_HEADERS] = struct.pack(
+            "<HBH",
+            SLOT - PARENT_SLOT,
+            flags,
+            SIZE_OF_DATA_HEADERS + len(chunk),
+        )
+        payload[SIZE_OF_DATA_HEADERS : SIZE_OF_DATA_HEADERS + len(chunk)] = chunk
+        data_shreds.append((index, sign(leader, payload)))
+
+    num_data = len(data_shreds)
+    num_coding = ERASURE_BATCH_SIZE[num_data] - num_data
+    shards = [payload[:SIZE_OF_ERASURE_SHARD] for _, payload in data_shreds]
+    coding_shreds = []
+    for position, parity in enumerate(encode(shards, num_coding)):
+        index = next_code_index + position
+        payload = bytearray(SIZE_OF_PAYLOAD)
+        payload[:SIZE_OF_COMMON_HEADER] = common_header(
+            LEGACY_CODE, index, fec_set_index
+        )
+        payload[SIZE_OF_COMMON_HEADER:SIZE_OF_CODING_HEADERS] = struct.pack(
+            "<HHH", num_data, num_coding, position
+        )
+        payload[SIZE_OF_CODING_HEADERS:] = parity
+        coding_shreds.append((index, sign(leader, payload)))
+    return data_shreds, coding_shreds
+
+
+def main():
+    here = os.path.dirname(os.path.abspath(__file__))
+    leader, _ = keypair(LEADER_SEED)
+    datas, signatures = batches()
+    with open(os.path.join(here, "signatures.txt")) as f:
+        golden = f.read().split()
+    assert [b58encode(sig) for sig in signatures] == golden, "golden sigs"
+
+    raw_shreds = []
+    next_shred_index, next_code_index = 0, 0
+    for i, data in enumerate(datas):
+        data_shreds, coding_shreds = shred(
+            data, i == len(datas) - 1, next_shred_index, next_code_index, leader
+        )
+        next_shred_index = data_shreds[-1][0] + 1
+        next_code_index = coding_shreds[-1][0] + 1
+        raw_shreds += [p for index, p in data_shreds if index != 1]
+        raw_shreds += [p for _, p in coding_shreds]
+
+    with open(os.path.join(here, "shreds.json"), "w") as f:
+        json.dump([list(p) for p in raw_shreds], f, separators=(",", ":"))
+
+
+if __name__ == "__main__":
+    main()
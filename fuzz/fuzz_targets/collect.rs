@@ -0,0 +1,41 @@
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use shreds::shred_processor::ShredProcessor;
+use std::sync::{Mutex, OnceLock};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+// the processor is kept across the inputs, so that the shreds of one can
+// complete the FEC sets of the ones before, as off the wire
+struct Target {
+    runtime: Runtime,
+    processor: ShredProcessor,
+}
+
+static TARGET: OnceLock<Mutex<Target>> = OnceLock::new();
+
+fn target() -> &'static Mutex<Target> {
+    TARGET.get_or_init(|| {
+        let runtime = Runtime::new().expect("runtime");
+        let processor = runtime.block_on(async {
+            let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+            let (error_tx, mut error_rx) = mpsc::channel::<String>(2000);
+            tokio::spawn(
+                async move { while entry_rx.recv().await.is_some() {} },
+            );
+            tokio::spawn(
+                async move { while error_rx.recv().await.is_some() {} },
+            );
+            ShredProcessor::new(entry_tx, error_tx)
+        });
+        Mutex::new(Target { runtime, processor })
+    })
+}
+
+fuzz_target!(|packet: &[u8]| {
+    let mut target = target().lock().unwrap();
+    let Target { runtime, processor } = &mut *target;
+    runtime.block_on(processor.collect_bytes(Bytes::copy_from_slice(packet)));
+});
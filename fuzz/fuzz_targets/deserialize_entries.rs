@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shreds::shred::{deserialize_entries, deserialize_entries_capped};
+
+fuzz_target!(|payload: &[u8]| {
+    let _ = deserialize_entries(payload);
+    // a low cap takes the path of the payloads claiming too many entries
+    let _ = deserialize_entries_capped(payload, 4);
+});
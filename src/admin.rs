@@ -0,0 +1,340 @@
+use log::{error, info, warn};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::entry_processor::ProcessorMetrics;
+use crate::shred_processor::ShredProcessor;
+
+/// Requests are a line and a few headers, anything longer is not ours
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    pub bind: String,
+    pub token: String,
+}
+
+/// Intake is the switch of the receive loop, packets received while it is
+/// paused are read off of the socket and dropped
+#[derive(Debug, Default)]
+pub struct Intake {
+    paused: AtomicBool,
+}
+
+impl Intake {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+/// run serves the admin API on `config.bind`, every request has to carry
+/// `Authorization: Bearer <token>`:
+///
+/// - `GET /stats` the shred processor metrics, and the entry processor
+///   ones under `entry_processor`
+/// - `POST /pause`, `POST /resume` packet intake
+/// - `POST /reset` zero the counters
+/// - `POST /dump` snapshot the hanging FEC sets, see HangingDumps
+/// - `POST /circuit-breaker/reset` resume the live trades, see
+///   CircuitBreaker
+pub async fn run(
+    config: AdminConfig,
+    shred_processor: Arc<RwLock<ShredProcessor>>,
+    processor_metrics: Arc<dyn ProcessorMetrics>,
+    intake: Arc<Intake>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(&config.bind).await?;
+    info!("Admin API listening on {}", listener.local_addr()?);
+    serve(
+        listener,
+        Arc::new(config.token),
+        shred_processor,
+        processor_metrics,
+        intake,
+        circuit_breaker,
+    )
+    .await
+}
+
+pub async fn serve(
+    listener: TcpListener,
+    token: Arc<String>,
+    shred_processor: Arc<RwLock<ShredProcessor>>,
+    processor_metrics: Arc<dyn ProcessorMetrics>,
+    intake: Arc<Intake>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let token = token.clone();
+        let shred_processor = shred_processor.clone();
+        let processor_metrics = processor_metrics.clone();
+        let intake = intake.clone();
+        let circuit_breaker = circuit_breaker.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(
+                stream,
+                &token,
+                &shred_processor,
+                processor_metrics.as_ref(),
+                &intake,
+                circuit_breaker.as_deref(),
+            )
+            .await
+            {
+                warn!("Admin connection from {} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    token: &str,
+    shred_processor: &RwLock<ShredProcessor>,
+    processor_metrics: &dyn ProcessorMetrics,
+    intake: &Intake,
+    circuit_breaker: Option<&CircuitBreaker>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || buf.len() + read > MAX_REQUEST_SIZE {
+            return respond(
+                &mut stream,
+                400,
+                json!({"error": "bad request"}),
+            )
+            .await;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+
+    let Some(request) = parse_request(&String::from_utf8_lossy(&buf)) else {
+        return respond(&mut stream, 400, json!({"error": "bad request"}))
+            .await;
+    };
+    if !request.is_authorized(token) {
+        return respond(&mut stream, 401, json!({"error": "unauthorized"}))
+            .await;
+    }
+
+    info!("Admin request: {} {}", request.method, request.path);
+    let (status, body) = match (
+        request.method.as_str(),
+        request.path.as_str(),
+    ) {
+        ("GET", "/stats") => {
+            let metrics = shred_processor.read().await.metrics();
+            (
+                200,
+                json!({
+                    "paused": intake.is_paused(),
+                    "circuit_breaker": circuit_breaker
+                        .map(|breaker| breaker.to_json()),
+                    "metrics": serde_json::from_str::<serde_json::Value>(
+                        &metrics
+                    )
+                    .unwrap_or_default(),
+                    "entry_processor": {
+                        "mode": processor_metrics.mode(),
+                        "metrics": processor_metrics.to_json(),
+                    },
+                }),
+            )
+        }
+        ("POST", "/pause") => {
+            intake.pause();
+            (200, json!({"paused": true}))
+        }
+        ("POST", "/resume") => {
+            intake.resume();
+            (200, json!({"paused": false}))
+        }
+        ("POST", "/reset") => {
+            shred_processor.write().await.reset_metrics();
+            (200, json!({"reset": true}))
+        }
+        ("POST", "/dump") => {
+            match shred_processor.read().await.snapshot_hanging_fec_sets() {
+                Ok((path, count)) => (
+                    200,
+                    json!({"path": path.display().to_string(), "fec_sets": count}),
+                ),
+                Err(e) => {
+                    error!("Failed to dump hanging FEC sets: {}", e);
+                    (500, json!({"error": e.to_string()}))
+                }
+            }
+        }
+        ("POST", "/circuit-breaker/reset") => match circuit_breaker {
+            Some(breaker) => {
+                breaker.reset();
+                (200, json!({"tripped": false}))
+            }
+            None => (404, json!({"error": "no circuit breaker"})),
+        },
+        _ => (404, json!({"error": "not found"})),
+    };
+    respond(&mut stream, status, body).await
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    body: serde_json::Value,
+) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    bearer: Option<String>,
+}
+
+impl Request {
+    fn is_authorized(&self, token: &str) -> bool {
+        let Some(bearer) = self.bearer.as_deref() else {
+            return false;
+        };
+        // compared in full, so that the time taken does not give away how
+        // much of the token matched
+        bearer.len() == token.len()
+            && bearer
+                .bytes()
+                .zip(token.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let bearer = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+        .map(|bearer| bearer.trim().to_string());
+    Some(Request {
+        method,
+        path,
+        bearer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry_processor::PumpMetrics;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn parse_request_with_bearer() {
+        let request = parse_request(
+            "POST /pause HTTP/1.1\r\nHost: localhost\r\nauthorization: Bearer s3cret\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/pause");
+        assert!(request.is_authorized("s3cret"));
+        assert!(!request.is_authorized("s3cre"));
+        assert!(!request.is_authorized("s3cret!"));
+
+        let request = parse_request("GET /stats HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.bearer, None);
+        assert!(!request.is_authorized(""));
+    }
+
+    async fn send(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume_intake() {
+        let (entry_tx, _entry_rx) = mpsc::channel(1);
+        let (error_tx, _error_rx) = mpsc::channel(1);
+        let shred_processor =
+            Arc::new(RwLock::new(ShredProcessor::new(entry_tx, error_tx)));
+        let intake = Arc::new(Intake::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            Arc::new("s3cret".to_string()),
+            shred_processor,
+            Arc::new(PumpMetrics::default()),
+            intake.clone(),
+            None,
+        ));
+
+        let response = send(addr, "POST /pause HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert!(!intake.is_paused());
+
+        let response = send(
+            addr,
+            "POST /pause HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(intake.is_paused());
+
+        let response = send(
+            addr,
+            "POST /resume HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(!intake.is_paused());
+
+        let response = send(
+            addr,
+            "GET /stats HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"paused\":false"));
+        assert!(response.contains("\"mode\":\"pump\""));
+    }
+}
@@ -0,0 +1,373 @@
+use futures_util::future::join_all;
+use log::{debug, warn};
+use solana_sdk::message::v0::MessageAddressTableLookup;
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use tokio::time::Duration;
+
+use crate::rpc::{RateLimitedRpc, DEFAULT_RPC_CONCURRENCY};
+
+/// Size of the header of a lookup table account (the state discriminator
+/// and the table meta), the addresses follow it
+pub const LOOKUP_TABLE_META_SIZE: usize = 56;
+const LOOKUP_TABLE_DISCRIMINATOR: u32 = 1;
+/// How long a fetched table is used before it is fetched again, tables are
+/// append-only but can be extended, closed and re-created
+pub const LOOKUP_TABLE_TTL: Duration = Duration::from_secs(300);
+/// How long until a table that failed to fetch can be fetched again
+pub const LOOKUP_TABLE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// parse_lookup_table reads the addresses out of the data of an address
+/// lookup table account, None if it is not an initialized table
+pub fn parse_lookup_table(data: &[u8]) -> Option<Vec<Pubkey>> {
+    let discriminator = u32::from_le_bytes(data.get(..4)?.try_into().ok()?);
+    if discriminator != LOOKUP_TABLE_DISCRIMINATOR {
+        return None;
+    }
+    let addresses = data.get(LOOKUP_TABLE_META_SIZE..)?;
+    if addresses.len() % 32 != 0 {
+        return None;
+    }
+    addresses
+        .chunks_exact(32)
+        .map(|address| Pubkey::try_from(address).ok())
+        .collect()
+}
+
+/// resolve_account_keys is the full account list of the message, the
+/// static keys followed by the writable and then the readonly addresses
+/// loaded from the lookup tables, which is the order the instructions
+/// index into; None if a table is unknown or an index is out of its bounds
+pub fn resolve_account_keys<'a>(
+    message: &VersionedMessage,
+    table: impl Fn(&Pubkey) -> Option<&'a [Pubkey]>,
+) -> Option<Vec<Pubkey>> {
+    let VersionedMessage::V0(message) = message else {
+        return Some(message.static_account_keys().to_vec());
+    };
+    let mut writable = Vec::new();
+    let mut readonly = Vec::new();
+    for lookup in message.address_table_lookups.iter() {
+        let addresses = table(&lookup.account_key)?;
+        for &index in lookup.writable_indexes.iter() {
+            writable.push(*addresses.get(index as usize)?);
+        }
+        for &index in lookup.readonly_indexes.iter() {
+            readonly.push(*addresses.get(index as usize)?);
+        }
+    }
+    let mut account_keys = message.account_keys.clone();
+    account_keys.extend(writable);
+    account_keys.extend(readonly);
+    Some(account_keys)
+}
+
+#[derive(Debug)]
+enum CachedTable {
+    Loaded {
+        addresses: Vec<Pubkey>,
+        fetched_at: Instant,
+    },
+    Failed {
+        failed_at: Instant,
+    },
+}
+
+/// LookupTableCache keeps the address lookup tables referenced by V0
+/// transactions, keyed by the table address; tables are fetched on the
+/// first use, after LOOKUP_TABLE_TTL, and when a tx indexes past the end
+/// of the cached copy (the table has been extended since). The fetches run
+/// in background tasks, one per table at a time, the tx that needed the
+/// table is left with its static keys and the later ones get the table
+#[derive(Default)]
+pub struct LookupTableCache {
+    // None only resolves the tables inserted by hand
+    rpc: Option<Arc<RateLimitedRpc>>,
+    tables: Arc<Mutex<HashMap<Pubkey, CachedTable>>>,
+    // the tables with a fetch in flight, not fetched a second time
+    fetching: Arc<Mutex<HashSet<Pubkey>>>,
+}
+
+impl std::fmt::Debug for LookupTableCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LookupTableCache")
+            .field("tables", &self.len())
+            .finish()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl LookupTableCache {
+    pub fn new(rpc: Arc<RateLimitedRpc>) -> Self {
+        LookupTableCache {
+            rpc: Some(rpc),
+            ..Self::default()
+        }
+    }
+
+    /// from_env fetches the tables off of RPC_URL if it is set, without it
+    /// the V0 txs are left with their static keys
+    pub fn from_env() -> Self {
+        match std::env::var("RPC_URL") {
+            Ok(url) => Self::new(Arc::new(RateLimitedRpc::new(
+                url,
+                DEFAULT_RPC_CONCURRENCY,
+            ))),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn tables(&self) -> MutexGuard<'_, HashMap<Pubkey, CachedTable>> {
+        lock(&self.tables)
+    }
+
+    pub fn insert(&self, address: Pubkey, addresses: Vec<Pubkey>) {
+        self.tables().insert(
+            address,
+            CachedTable::Loaded {
+                addresses,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// invalidate drops the table, the next tx using it fetches it again
+    pub fn invalidate(&self, address: &Pubkey) {
+        self.tables().remove(address);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables().is_empty()
+    }
+
+    /// get is the cached table, regardless of its age
+    pub fn get(&self, address: &Pubkey) -> Option<Vec<Pubkey>> {
+        match self.tables().get(address) {
+            Some(CachedTable::Loaded { addresses, .. }) => {
+                Some(addresses.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// resolve is the full account list of the message (see
+    /// resolve_account_keys) off of the cached tables, it never waits on
+    /// RPC; the tables it is missing are fetched in the background
+    pub fn resolve(&self, message: &VersionedMessage) -> Option<Vec<Pubkey>> {
+        for lookup in message.address_table_lookups().unwrap_or_default() {
+            if self.needs_fetch(lookup) {
+                self.spawn_fetch(lookup.account_key);
+            }
+        }
+        let tables = self.tables();
+        resolve_account_keys(message, |address| match tables.get(address) {
+            Some(CachedTable::Loaded { addresses, .. }) => {
+                Some(addresses.as_slice())
+            }
+            _ => None,
+        })
+    }
+
+    /// account_keys is the full account list of the message, or only its
+    /// static keys if a table is not cached yet; the accounts the
+    /// instructions load from the tables are then out of bounds
+    pub fn account_keys(&self, message: &VersionedMessage) -> Vec<Pubkey> {
+        match self.resolve(message) {
+            Some(account_keys) => account_keys,
+            None => {
+                debug!("Lookup tables not cached, using static keys");
+                message.static_account_keys().to_vec()
+            }
+        }
+    }
+
+    /// fetch_tables fetches the tables of the message that are missing or
+    /// stale and waits on them, for the offline tools that would rather
+    /// have every table than keep up
+    pub async fn fetch_tables(&self, message: &VersionedMessage) {
+        let Some(rpc) = &self.rpc else {
+            return;
+        };
+        let fetches = message
+            .address_table_lookups()
+            .unwrap_or_default()
+            .iter()
+            .filter(|lookup| self.needs_fetch(lookup))
+            .map(|lookup| async move {
+                let table = fetch_table(rpc, &lookup.account_key).await;
+                self.tables().insert(lookup.account_key, table);
+            });
+        join_all(fetches).await;
+    }
+
+    fn needs_fetch(&self, lookup: &MessageAddressTableLookup) -> bool {
+        if self.rpc.is_none() {
+            return false;
+        }
+        match self.tables().get(&lookup.account_key) {
+            None => true,
+            Some(CachedTable::Failed { failed_at }) => {
+                failed_at.elapsed() >= LOOKUP_TABLE_RETRY_INTERVAL
+            }
+            Some(CachedTable::Loaded {
+                addresses,
+                fetched_at,
+            }) => {
+                fetched_at.elapsed() >= LOOKUP_TABLE_TTL
+                    || lookup
+                        .writable_indexes
+                        .iter()
+                        .chain(lookup.readonly_indexes.iter())
+                        .any(|&index| index as usize >= addresses.len())
+            }
+        }
+    }
+
+    /// spawn_fetch fetches the table in a task of its own, unless it is
+    /// already being fetched; the tasks of different tables run side by
+    /// side, bounded by the concurrency of the rpc
+    fn spawn_fetch(&self, address: Pubkey) {
+        let Some(rpc) = self.rpc.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !lock(&self.fetching).insert(address) {
+            return;
+        }
+        let tables = self.tables.clone();
+        let fetching = self.fetching.clone();
+        runtime.spawn(async move {
+            let table = fetch_table(&rpc, &address).await;
+            lock(&tables).insert(address, table);
+            lock(&fetching).remove(&address);
+        });
+    }
+}
+
+async fn fetch_table(rpc: &RateLimitedRpc, address: &Pubkey) -> CachedTable {
+    match rpc.call(|client| client.get_account_data(address)).await {
+        Ok(data) => match parse_lookup_table(&data) {
+            Some(addresses) => CachedTable::Loaded {
+                addresses,
+                fetched_at: Instant::now(),
+            },
+            None => {
+                warn!("{} is not an address lookup table", address);
+                CachedTable::Failed {
+                    failed_at: Instant::now(),
+                }
+            }
+        },
+        Err(e) => {
+            warn!("Failed to fetch lookup table {}: {}", address, e);
+            CachedTable::Failed {
+                failed_at: Instant::now(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::message::v0;
+
+    fn table_data(addresses: &[Pubkey]) -> Vec<u8> {
+        let mut data = vec![0; LOOKUP_TABLE_META_SIZE];
+        data[..4].copy_from_slice(&LOOKUP_TABLE_DISCRIMINATOR.to_le_bytes());
+        for address in addresses {
+            data.extend_from_slice(address.as_ref());
+        }
+        data
+    }
+
+    #[test]
+    fn parses_lookup_table_account() {
+        let addresses =
+            (0..3).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let data = table_data(&addresses);
+        assert_eq!(parse_lookup_table(&data), Some(addresses));
+        assert_eq!(
+            parse_lookup_table(&data[..LOOKUP_TABLE_META_SIZE + 5]),
+            None
+        );
+        assert_eq!(parse_lookup_table(&[0; LOOKUP_TABLE_META_SIZE]), None);
+    }
+
+    #[test]
+    fn resolves_loaded_addresses_after_static_keys() {
+        let static_keys =
+            (0..2).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let table = Pubkey::new_unique();
+        let addresses =
+            (0..4).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let message = VersionedMessage::V0(v0::Message {
+            account_keys: static_keys.clone(),
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: table,
+                writable_indexes: vec![3],
+                readonly_indexes: vec![0, 2],
+            }],
+            ..v0::Message::default()
+        });
+
+        let cache = LookupTableCache::default();
+        assert_eq!(cache.resolve(&message), None);
+        assert_eq!(cache.account_keys(&message), static_keys);
+
+        cache.insert(table, addresses.clone());
+        assert_eq!(
+            cache.resolve(&message),
+            Some(vec![
+                static_keys[0],
+                static_keys[1],
+                addresses[3],
+                addresses[0],
+                addresses[2],
+            ])
+        );
+
+        // past the end of the cached table
+        cache.insert(table, addresses[..2].to_vec());
+        assert_eq!(cache.resolve(&message), None);
+
+        cache.invalidate(&table);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetches_missing_tables_once_in_the_background() {
+        let table = Pubkey::new_unique();
+        let message = VersionedMessage::V0(v0::Message {
+            account_keys: vec![Pubkey::new_unique()],
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: table,
+                writable_indexes: vec![0],
+                readonly_indexes: vec![],
+            }],
+            ..v0::Message::default()
+        });
+        // nothing listens there, the fetch fails without being awaited
+        let cache = LookupTableCache::new(Arc::new(RateLimitedRpc::new(
+            "http://127.0.0.1:1".to_string(),
+            1,
+        )));
+
+        assert_eq!(cache.resolve(&message), None);
+        assert_eq!(cache.resolve(&message), None);
+        assert_eq!(lock(&cache.fetching).len(), 1);
+        assert!(cache.is_empty());
+    }
+}
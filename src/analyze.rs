@@ -0,0 +1,331 @@
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::alt::LookupTableCache;
+use crate::arb::get_account_key_safely;
+use crate::constants;
+use crate::entry_processor::EntriesWithMeta;
+use crate::pump::{find_create_accounts, PumpIx};
+use crate::raydium::{parse_amm_instruction, ParsedAmmInstruction};
+use crate::shred_processor::reconstruct_entries;
+
+// position of the mint within the pump buy/sell instructions
+const SWAP_IX_MINT_POSITION: usize = 2;
+// position of the amm id within the raydium swap instructions
+const SWAP_IX_AMM_POSITION: usize = 1;
+const LARGEST_SWAPS: usize = 10;
+
+#[derive(Debug, Default, Serialize)]
+pub struct CaptureSummary {
+    pub slots: usize,
+    pub entries: usize,
+    pub transactions: usize,
+    pub raydium_amm_txs: usize,
+    pub raydium_cp_txs: usize,
+    pub orca_txs: usize,
+    pub pump_txs: usize,
+    pub new_pump_tokens: Vec<NewPumpToken>,
+    pub mints: BTreeMap<String, MintActivity>,
+    pub largest_swaps: Vec<SwapSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewPumpToken {
+    pub sig: String,
+    pub mint: String,
+    pub name: String,
+    pub symbol: String,
+}
+
+/// MintActivity is the pump activity of a mint, the SOL amounts are the
+/// limits of the instructions (max cost of buys, min output of sells)
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MintActivity {
+    pub creates: usize,
+    pub buys: usize,
+    pub sells: usize,
+    pub buy_sol_limit: u64,
+    pub sell_sol_limit: u64,
+}
+
+/// SwapSummary is a raydium swap, the amount is in the raw units of
+/// whichever side of the pool goes in
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapSummary {
+    pub sig: String,
+    pub amm_id: String,
+    pub amount_in: u64,
+    pub base_in: bool,
+}
+
+/// run replays the capture (a packets.json-style array of packets) and
+/// prints the summary as a table, or as JSON with `json`; the lookup tables
+/// are fetched off of RPC_URL if it is set
+pub async fn run(
+    path: &str,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string(path)?;
+    let raw_shreds: Vec<Vec<u8>> = serde_json::from_str(&data)?;
+    let entries = reconstruct_entries(raw_shreds).await;
+    let summary = summarize(&entries, &LookupTableCache::from_env()).await;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        print_table(&summary);
+    }
+    Ok(())
+}
+
+/// summarize goes over the txs of the entries, with the accounts they load
+/// from `lookup_tables`
+pub async fn summarize(
+    entries: &[EntriesWithMeta],
+    lookup_tables: &LookupTableCache,
+) -> CaptureSummary {
+    let mut summary = CaptureSummary::default();
+    let mut slots = HashSet::new();
+    for entries_with_meta in entries {
+        slots.insert(entries_with_meta.slot);
+        summary.entries += entries_with_meta.entries.len();
+        for tx in entries_with_meta
+            .entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter())
+        {
+            summary.transactions += 1;
+            lookup_tables.fetch_tables(&tx.message).await;
+            let account_keys = lookup_tables.account_keys(&tx.message);
+            summary.add_transaction(tx, &account_keys);
+        }
+    }
+    summary.slots = slots.len();
+    summary
+        .largest_swaps
+        .sort_by(|a, b| b.amount_in.cmp(&a.amount_in));
+    summary.largest_swaps.truncate(LARGEST_SWAPS);
+    summary
+}
+
+impl CaptureSummary {
+    fn add_transaction(
+        &mut self,
+        tx: &VersionedTransaction,
+        account_keys: &[Pubkey],
+    ) {
+        let sig = tx
+            .signatures
+            .first()
+            .map(|sig| sig.to_string())
+            .unwrap_or_default();
+        if account_keys.contains(&constants::WHIRLPOOL_PUBKEY) {
+            self.orca_txs += 1;
+        }
+        if account_keys.contains(&constants::RAYDIUM_CP_PUBKEY) {
+            self.raydium_cp_txs += 1;
+        }
+        if account_keys.contains(&constants::RAYDIUM_AMM_PUBKEY) {
+            self.raydium_amm_txs += 1;
+        }
+        if account_keys.contains(&constants::PUMP_FUN_PROGRAM_PUBKEY) {
+            self.pump_txs += 1;
+        }
+
+        if let Some(accounts) = find_create_accounts(tx, account_keys) {
+            let metadata = tx
+                .message
+                .instructions()
+                .iter()
+                .find_map(|ix| match PumpIx::decode(&ix.data) {
+                    Some(PumpIx::Create(metadata)) => Some(metadata),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            self.mint_activity(&accounts.mint).creates += 1;
+            self.new_pump_tokens.push(NewPumpToken {
+                sig: sig.clone(),
+                mint: accounts.mint.to_string(),
+                name: metadata.name,
+                symbol: metadata.symbol,
+            });
+        }
+
+        for ix in tx.message.instructions() {
+            let Some(program_id) =
+                account_keys.get(ix.program_id_index as usize)
+            else {
+                continue;
+            };
+            if *program_id == *constants::PUMP_FUN_PROGRAM_PUBKEY {
+                let Some(mint) = get_account_key_safely(
+                    account_keys,
+                    ix,
+                    SWAP_IX_MINT_POSITION,
+                ) else {
+                    continue;
+                };
+                match PumpIx::decode(&ix.data) {
+                    Some(PumpIx::Buy(buy)) => {
+                        let activity = self.mint_activity(&mint);
+                        activity.buys += 1;
+                        activity.buy_sol_limit = activity
+                            .buy_sol_limit
+                            .saturating_add(buy.max_sol_cost);
+                    }
+                    Some(PumpIx::Sell(sell)) => {
+                        let activity = self.mint_activity(&mint);
+                        activity.sells += 1;
+                        activity.sell_sol_limit = activity
+                            .sell_sol_limit
+                            .saturating_add(sell.min_sol_output);
+                    }
+                    _ => {}
+                }
+            } else if *program_id == *constants::RAYDIUM_AMM_PUBKEY {
+                let (amount_in, base_in) =
+                    match parse_amm_instruction(&ix.data) {
+                        Ok(ParsedAmmInstruction::SwapBaseIn(swap)) => {
+                            (swap.amount_in, true)
+                        }
+                        Ok(ParsedAmmInstruction::SwapBaseOut(swap)) => {
+                            (swap.max_amount_in, false)
+                        }
+                        _ => continue,
+                    };
+                let Some(amm_id) = get_account_key_safely(
+                    account_keys,
+                    ix,
+                    SWAP_IX_AMM_POSITION,
+                ) else {
+                    continue;
+                };
+                self.largest_swaps.push(SwapSummary {
+                    sig: sig.clone(),
+                    amm_id: amm_id.to_string(),
+                    amount_in,
+                    base_in,
+                });
+            }
+        }
+    }
+
+    fn mint_activity(&mut self, mint: &Pubkey) -> &mut MintActivity {
+        self.mints.entry(mint.to_string()).or_default()
+    }
+}
+
+pub fn print_table(summary: &CaptureSummary) {
+    println!("{:<24} {:>10}", "slots", summary.slots);
+    println!("{:<24} {:>10}", "entries", summary.entries);
+    println!("{:<24} {:>10}", "transactions", summary.transactions);
+    println!("{:<24} {:>10}", "raydium amm txs", summary.raydium_amm_txs);
+    println!("{:<24} {:>10}", "raydium cp txs", summary.raydium_cp_txs);
+    println!("{:<24} {:>10}", "orca txs", summary.orca_txs);
+    println!("{:<24} {:>10}", "pump txs", summary.pump_txs);
+    println!(
+        "{:<24} {:>10}",
+        "new pump tokens",
+        summary.new_pump_tokens.len()
+    );
+
+    println!();
+    println!("{:<44} {:<12} {:<88}", "mint", "symbol", "sig");
+    for token in summary.new_pump_tokens.iter() {
+        println!("{:<44} {:<12} {:<88}", token.mint, token.symbol, token.sig);
+    }
+
+    println!();
+    println!(
+        "{:<44} {:>7} {:>6} {:>6} {:>16} {:>16}",
+        "mint", "creates", "buys", "sells", "buy sol limit", "sell sol limit"
+    );
+    for (mint, activity) in summary.mints.iter() {
+        println!(
+            "{:<44} {:>7} {:>6} {:>6} {:>16} {:>16}",
+            mint,
+            activity.creates,
+            activity.buys,
+            activity.sells,
+            activity.buy_sol_limit,
+            activity.sell_sol_limit
+        );
+    }
+
+    println!();
+    println!(
+        "{:<44} {:>20} {:>7} {:<88}",
+        "amm", "amount in", "base in", "sig"
+    );
+    for swap in summary.largest_swaps.iter() {
+        println!(
+            "{:<44} {:>20} {:>7} {:<88}",
+            swap.amm_id, swap.amount_in, swap.base_in, swap.sig
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pump::{
+        PumpSwapIx, PUMP_BUY_DISCRIMINATOR, PUMP_SELL_DISCRIMINATOR,
+    };
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::Transaction;
+
+    fn pump_swap_tx(method_id: [u8; 8], mint: Pubkey, sol: u64) -> Entry {
+        let payer = Keypair::new();
+        let data = borsh::to_vec(&PumpSwapIx {
+            method_id,
+            amount: 1,
+            max_sol_cost: sol,
+        })
+        .unwrap();
+        let ix = Instruction {
+            program_id: *constants::PUMP_FUN_PROGRAM_PUBKEY,
+            accounts: vec![
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(mint, false),
+            ],
+            data,
+        };
+        let tx = Transaction::new(
+            &[&payer],
+            Message::new(&[ix], Some(&payer.pubkey())),
+            Hash::default(),
+        );
+        Entry::new(&Hash::default(), 1, vec![tx])
+    }
+
+    #[tokio::test]
+    async fn summarizes_pump_swaps_per_mint() {
+        let mint = Pubkey::new_unique();
+        let entries = vec![EntriesWithMeta {
+            slot: 1,
+            entries: vec![
+                pump_swap_tx(PUMP_BUY_DISCRIMINATOR, mint, 100),
+                pump_swap_tx(PUMP_BUY_DISCRIMINATOR, mint, 50),
+                pump_swap_tx(PUMP_SELL_DISCRIMINATOR, mint, 30),
+            ],
+            trace: Default::default(),
+            received_at: None,
+        }];
+        let summary = summarize(&entries, &LookupTableCache::default()).await;
+        assert_eq!(summary.slots, 1);
+        assert_eq!(summary.transactions, 3);
+        assert_eq!(summary.pump_txs, 3);
+        let activity = &summary.mints[&mint.to_string()];
+        assert_eq!(activity.buys, 2);
+        assert_eq!(activity.sells, 1);
+        assert_eq!(activity.buy_sol_limit, 150);
+        assert_eq!(activity.sell_sol_limit, 30);
+    }
+}
@@ -1,5 +1,32 @@
-use clap::{arg, Parser};
+use clap::parser::ValueSource;
+use clap::{arg, ArgMatches, CommandFactory, FromArgMatches, Parser};
 use serde::Deserialize;
+use std::ffi::OsString;
+
+use crate::arb::{ARB_TRADE_SIZE, DEFAULT_FEE_BPS};
+use crate::benchmark::BenchmarkCommitment;
+use crate::circuit_breaker::{
+    DEFAULT_LOSS_WINDOW_SECS, DEFAULT_MAX_CONSECUTIVE_LOSSES,
+};
+use crate::dedup::{
+    DEFAULT_DEDUP_MAX_AGE_SECS, DEFAULT_DEDUP_MAX_SIGNATURES,
+};
+use crate::enrich::{DEFAULT_ENRICH_CONCURRENCY, DEFAULT_ENRICH_TIMEOUT_MS};
+use crate::entry_processor::DEFAULT_MINT_COOLDOWN_MS;
+use crate::ingest::DEFAULT_INGEST_QUEUE_SIZE;
+use crate::listener::PACKET_SIZE;
+#[cfg(feature = "parquet")]
+use crate::parquet_processor::{
+    DEFAULT_PARQUET_MAX_AGE_SECS, DEFAULT_PARQUET_MAX_ROWS,
+};
+use crate::queue::{DEFAULT_ENTRY_QUEUE_SIZE, DEFAULT_ERROR_QUEUE_SIZE};
+use crate::raydium::DEFAULT_EMA_HALF_LIFE;
+use crate::reorder::DEFAULT_GAP_TIMEOUT_MS;
+use crate::rpc::DEFAULT_RPC_CONCURRENCY;
+use crate::shred::DEFAULT_MAX_ENTRIES;
+use crate::sink::{SinkKind, DEFAULT_EVENT_LOG_MAX_BYTES};
+use crate::source::SourceKind;
+use crate::udp::DEFAULT_RCVBUF_BYTES;
 
 #[derive(Parser, Debug)]
 pub struct App {
@@ -13,16 +40,336 @@ pub struct App {
 #[derive(Parser, Debug, Deserialize)]
 #[command(name = "shreds", version = "1.0", author = "piotrostr")]
 pub struct Args {
-    /// Sets the bind address
+    /// TOML file to read the options from, the keys are the flags without
+    /// the dashes (`bind = "0.0.0.0:8001"`), the options of a subcommand go
+    /// under a table named after it (`[pump-mode]`); a flag on the command
+    /// line takes precedence over the file, the file over the defaults
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Sets the bind address, the service modes take a comma separated
+    /// list (e.g. `0.0.0.0:8001,[::]:8001`) and receive on all of them
     #[arg(short, long, default_value = "0.0.0.0:8001")]
     pub bind: Option<String>,
 
+    /// Where the service modes read the shreds off of, `udp` is the bind
+    /// addresses, `replay` plays back the `--replay-file` capture,
+    /// `shredstream` the `--shredstream-url` proxy (needs the `shredstream`
+    /// feature)
+    #[arg(long, value_enum, default_value_t = SourceKind::Udp)]
+    pub source: SourceKind,
+
+    /// Capture of `save` (packets.json) the `replay` source plays back
+    #[arg(long, default_value = "packets.json")]
+    pub replay_file: String,
+
+    /// Pause between the packets of the `replay` source, in microseconds,
+    /// they are played back as fast as they are processed by default
+    #[arg(long, default_value_t = 0)]
+    pub replay_interval_us: u64,
+
+    /// gRPC address of the shredstream proxy the `shredstream` source reads
+    #[arg(long, default_value = "http://127.0.0.1:9999")]
+    pub shredstream_url: String,
+
+    /// Ignore shreds of slots before this one
+    #[arg(long)]
+    pub from_slot: Option<u64>,
+
+    /// Ignore shreds of slots after this one
+    #[arg(long)]
+    pub to_slot: Option<u64>,
+
+    /// Only process the shreds of this shred version (the cluster), all
+    /// are processed by default
+    #[arg(long)]
+    pub shred_version: Option<u16>,
+
+    /// Drop the shreds of slots this far behind the highest slot ended (the
+    /// last shred of which came in), not the highest one seen; the number of
+    /// slots kept track of is bounded with or without it
+    #[arg(long)]
+    pub max_slot_lag: Option<u64>,
+
+    /// Size of the UDP receive buffer (SO_RCVBUF), in bytes
+    #[arg(long, default_value_t = DEFAULT_RCVBUF_BYTES)]
+    pub rcvbuf_bytes: usize,
+
+    /// Size of the buffer each datagram is read into, datagrams longer than
+    /// this are truncated (raise it for sources framing jumbo shreds)
+    #[arg(long, default_value_t = PACKET_SIZE)]
+    pub packet_size: usize,
+
+    /// Packets queued between the receive loop and the shred processor,
+    /// past this they are dropped (see the ingest metrics)
+    #[arg(long, default_value_t = DEFAULT_INGEST_QUEUE_SIZE)]
+    pub ingest_queue_size: usize,
+
+    /// Batches of entries queued for the entry processor, past this the
+    /// shred processor waits on it (see the queue metrics)
+    #[arg(long, default_value_t = DEFAULT_ENTRY_QUEUE_SIZE)]
+    pub entry_queue_size: usize,
+
+    /// Errors queued for the entry processor
+    #[arg(long, default_value_t = DEFAULT_ERROR_QUEUE_SIZE)]
+    pub error_queue_size: usize,
+
     /// URL to send webhooks to
     #[arg(long, default_value = "http://0.0.0.0:6969")]
     pub post_url: Option<String>,
 
+    /// Sent as `Authorization: Bearer <token>` with every webhook, falls
+    /// back to the WEBHOOK_TOKEN env var
+    #[arg(long)]
+    pub webhook_token: Option<String>,
+
+    /// Extra header sent with every webhook, as `Name: value`, repeatable
+    #[arg(long = "webhook-header")]
+    pub webhook_headers: Vec<String>,
+
+    /// Sign the webhook bodies into `X-Signature: sha256=<hmac>` with this
+    /// secret, falls back to the WEBHOOK_HMAC_SECRET env var
+    #[arg(long)]
+    pub webhook_hmac_secret: Option<String>,
+
+    /// PEM certificate to trust for the webhook receiver on top of the
+    /// system roots
+    #[arg(long)]
+    pub webhook_ca_cert: Option<String>,
+
+    /// Skip verifying the certificate of the webhook receiver (testing only)
+    #[arg(long)]
+    pub webhook_insecure: bool,
+
+    /// Keep retrying the health check of the webhook receiver for this many
+    /// seconds before giving up
+    #[arg(long, default_value_t = 30)]
+    pub health_check_timeout_secs: u64,
+
     #[arg(short, long, default_value = "stdout")]
     pub log_target: Option<String>,
+
+    /// OTLP endpoint to export the traces of the FEC sets to, e.g.
+    /// http://localhost:4317, nothing is traced if unset
+    #[cfg(feature = "otel")]
+    #[arg(long)]
+    pub otel_endpoint: Option<String>,
+
+    /// Where to deliver the detected events, `webhook` posts to `post_url`
+    #[arg(long, value_enum, default_value_t = SinkKind::Webhook)]
+    pub sink: SinkKind,
+
+    /// Commitment the pubsub baseline of `benchmark` and `pubsub` listens
+    /// at
+    #[arg(long, value_enum, default_value_t = BenchmarkCommitment::Processed)]
+    pub benchmark_commitment: BenchmarkCommitment,
+
+    /// Lead over pubsub, in ms, the shreds need on a signature for the
+    /// benchmark to count it as a win
+    #[arg(long, default_value_t = 0)]
+    pub benchmark_win_threshold_ms: u64,
+
+    /// File to append events to for the `file` sink, broker url for the
+    /// `kafka` and `nats` sinks
+    #[arg(long, default_value = "events.jsonl")]
+    pub sink_target: String,
+
+    /// Also append every emitted event to this JSON lines file, whatever
+    /// the sink, to audit and replay what was detected
+    #[arg(long)]
+    pub event_log: Option<String>,
+
+    /// Write the sigs the service modes detect, with the unix millis their
+    /// shreds came in at, to this JSON lines file, or POST them to
+    /// `<url>/v2/sig` if it is an http(s) url; they are only debug-logged
+    /// otherwise
+    #[arg(long)]
+    pub sigs_out: Option<String>,
+
+    /// Rotate the event log once it grows past this many bytes
+    #[arg(long, default_value_t = DEFAULT_EVENT_LOG_MAX_BYTES)]
+    pub event_log_max_bytes: u64,
+
+    /// Directory the hanging FEC set snapshots are written to
+    #[arg(long, default_value = ".")]
+    pub dump_dir: String,
+
+    /// Snapshot the hanging FEC sets every this many seconds
+    #[arg(long)]
+    pub dump_interval_secs: Option<u64>,
+
+    /// Number of hanging FEC set snapshots to keep
+    #[arg(long, default_value_t = 10)]
+    pub max_dumps: usize,
+
+    /// Write the deshredded payload and the entries of every completed FEC
+    /// set to `slot-<n>.bin` and `slot-<n>.json` in this directory, to diff
+    /// against solana-ledger-tool (I/O heavy, for debugging only)
+    #[arg(long)]
+    pub dump_entries: Option<String>,
+
+    /// Entry count prefixes past this are taken as corrupt, only this many
+    /// entries are read out of such a FEC set
+    #[arg(long, default_value_t = DEFAULT_MAX_ENTRIES)]
+    pub max_entries: u64,
+
+    /// Send the entries of each slot on in the order of their FEC sets
+    /// rather than as they complete, for order-sensitive consumers (adds
+    /// latency)
+    #[arg(long)]
+    pub ordered_entries: bool,
+
+    /// Longest a FEC set is held back for the ones before it with
+    /// `--ordered-entries`, in milliseconds
+    #[arg(long, default_value_t = DEFAULT_GAP_TIMEOUT_MS)]
+    pub ordering_gap_timeout_ms: u64,
+
+    /// Skip the Reed-Solomon recovery, only the FEC sets with all of their
+    /// data shreds are sent on and the rest are dropped (lower latency and
+    /// CPU, at the cost of the sets missing a data shred)
+    #[arg(long)]
+    pub no_recovery: bool,
+
+    /// Drop the repair responses (the shreds with a nonce trailer) rather
+    /// than collecting them less the nonce, for the turbine shreds alone
+    #[arg(long)]
+    pub drop_repair_shreds: bool,
+
+    /// Signatures the service remembers to pass every tx on to the entry
+    /// processor once, even if it comes out of two FEC sets; 0 turns the
+    /// dedup off
+    #[arg(long, default_value_t = DEFAULT_DEDUP_MAX_SIGNATURES)]
+    pub dedup_max_signatures: usize,
+
+    /// Longest a signature is remembered for by the dedup, in seconds
+    #[arg(long, default_value_t = DEFAULT_DEDUP_MAX_AGE_SECS)]
+    pub dedup_max_age_secs: u64,
+
+    /// Also send every received packet on to this UDP address, repeatable,
+    /// to fan the shreds out to other listeners
+    #[arg(long = "forward")]
+    pub forward: Vec<String>,
+
+    /// Report the receive loop when no packets arrive for this many seconds
+    /// while the intake is live
+    #[arg(long)]
+    pub watchdog_timeout_secs: Option<u64>,
+
+    /// Restart the receive loop on top of reporting it, requires
+    /// `--watchdog-timeout-secs`
+    #[arg(long, requires = "watchdog_timeout_secs")]
+    pub watchdog_restart: bool,
+
+    /// Serve the admin API (pause, resume, stats, dump) on this address
+    #[arg(long)]
+    pub admin_bind: Option<String>,
+
+    /// Bearer token of the admin API, falls back to the ADMIN_TOKEN env var
+    #[arg(long)]
+    pub admin_token: Option<String>,
+}
+
+impl App {
+    /// load parses the command line on top of the `--config` file
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(std::env::args_os())
+    }
+
+    pub fn load_from(
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> anyhow::Result<Self> {
+        let args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+        let matches = App::command().get_matches_from(&args);
+        let Some(path) = matches.get_one::<String>("config") else {
+            return Ok(
+                App::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+            );
+        };
+        let config: toml::value::Table =
+            toml::from_str(&std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!("Failed to read config {}: {}", path, e)
+            })?)?;
+
+        let mut merged = args[..1].to_vec();
+        let mut global = config.clone();
+        if let Some((name, sub_matches)) = matches.subcommand() {
+            let sub_config = global.remove(name);
+            // the global options, then the subcommand and its options
+            let position = args
+                .iter()
+                .position(|arg| arg.to_str() == Some(name))
+                .expect("subcommand is on the command line");
+            merged.extend(config_args(&global, &matches)?);
+            merged.extend_from_slice(&args[1..=position]);
+            if let Some(sub_config) = sub_config {
+                let sub_config = sub_config.as_table().ok_or_else(|| {
+                    anyhow::anyhow!("[{}] has to be a table", name)
+                })?;
+                merged.extend(config_args(sub_config, sub_matches)?);
+            }
+            merged.extend_from_slice(&args[position + 1..]);
+        } else {
+            merged.extend(config_args(&global, &matches)?);
+            merged.extend_from_slice(&args[1..]);
+        }
+        Ok(App::parse_from(merged))
+    }
+}
+
+/// config_args turns the options of the config which were not given on the
+/// command line into flags
+fn config_args(
+    config: &toml::value::Table,
+    matches: &ArgMatches,
+) -> anyhow::Result<Vec<OsString>> {
+    let ids = matches.ids().map(|id| id.as_str()).collect::<Vec<_>>();
+    let mut args = Vec::new();
+    for (key, value) in config {
+        // the tables of the other subcommands
+        if value.is_table() {
+            continue;
+        }
+        let id = key.replace('-', "_");
+        // the unknown keys are passed on for clap to reject
+        if ids.contains(&id.as_str())
+            && matches.value_source(&id) == Some(ValueSource::CommandLine)
+        {
+            continue;
+        }
+        push_config_value(
+            &format!("--{}", key.replace('_', "-")),
+            value,
+            &mut args,
+        )?;
+    }
+    Ok(args)
+}
+
+fn push_config_value(
+    flag: &str,
+    value: &toml::Value,
+    args: &mut Vec<OsString>,
+) -> anyhow::Result<()> {
+    match value {
+        toml::Value::Boolean(true) => args.push(flag.into()),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(value) => {
+            args.push(flag.into());
+            args.push(value.into());
+        }
+        toml::Value::Integer(_) | toml::Value::Float(_) => {
+            args.push(flag.into());
+            args.push(value.to_string().into());
+        }
+        // repeatable flags, e.g. `forward = ["a:8001", "b:8001"]`
+        toml::Value::Array(values) => {
+            for value in values {
+                push_config_value(flag, value, args)?;
+            }
+        }
+        _ => anyhow::bail!("Unsupported value of {} in config", flag),
+    }
+    Ok(())
 }
 
 #[derive(Debug, Parser)]
@@ -31,7 +378,12 @@ pub enum Command {
     Save,
 
     /// Download Raydium JSON
-    Download,
+    Download {
+        /// Keep downloading it this often, in seconds, logging the pools of
+        /// the mints of interest which are new or changed in each version
+        #[arg(long)]
+        watch_secs: Option<u64>,
+    },
 
     /// Run benchmark
     Benchmark,
@@ -39,12 +391,293 @@ pub enum Command {
     /// Run in pubsub mode
     Pubsub,
 
-    /// Run in service mode (sends pump webhooks to `post_url`)
-    PumpMode,
+    /// Run in service mode (emits pump events to the sink)
+    PumpMode {
+        /// Resolve the metadata URI of new tokens and attach the image,
+        /// description and socials to the events
+        #[arg(long)]
+        enrich: bool,
+
+        /// Longest an event is held back for its metadata, in milliseconds
+        #[arg(long, default_value_t = DEFAULT_ENRICH_TIMEOUT_MS)]
+        enrich_timeout_ms: u64,
+
+        /// Metadata fetches in flight at once
+        #[arg(long, default_value_t = DEFAULT_ENRICH_CONCURRENCY)]
+        enrich_concurrency: usize,
+
+        /// Only emit the tokens of the creators in this file, one pubkey per
+        /// line, reloaded on SIGHUP
+        #[arg(long)]
+        creator_allow: Option<String>,
+
+        /// Never emit the tokens of the creators in this file, one pubkey
+        /// per line, reloaded on SIGHUP
+        #[arg(long)]
+        creator_deny: Option<String>,
+
+        /// Only emit the launches the dev bought at least this much SOL of
+        #[arg(long, default_value_t = 0.)]
+        min_dev_buy_sol: f64,
+
+        /// Emit a mint at most once within this many milliseconds, 0 turns
+        /// the cooldown off
+        #[arg(long, default_value_t = DEFAULT_MINT_COOLDOWN_MS)]
+        mint_cooldown_ms: u64,
+
+        /// Threads of a dedicated rayon pool for the scan of the entries,
+        /// rather than the global one, which has a thread per core next to
+        /// the tokio workers (see the README)
+        #[arg(long)]
+        scan_threads: Option<usize>,
+    },
 
     /// Run in arb mode (listens for raydium txs)
-    ArbMode,
+    ArbMode {
+        /// Report the trades that would be made to the sink without signing
+        /// anything
+        #[arg(long)]
+        paper: bool,
+
+        /// Sign and submit the trades with the fund keypair
+        #[arg(long, conflicts_with = "paper")]
+        live: bool,
+
+        /// Cap on the SOL leg of a live trade, in lamports
+        #[arg(long, default_value_t = ARB_TRADE_SIZE)]
+        max_trade_size: u64,
+
+        /// Minimum expected profit of a live trade, in lamports
+        #[arg(long, default_value_t = 100_000)]
+        min_profit: u64,
+
+        /// Priority fee, in micro-lamports per compute unit
+        #[arg(long, default_value_t = 100_000)]
+        priority_fee: u64,
+
+        /// Buffer taken off of the simulated output of each leg, in bps
+        #[arg(long, default_value_t = 50)]
+        slippage_bps: u64,
+
+        /// Load pools missing from raydium.json when swaps are seen on them
+        #[arg(long)]
+        lazy_load_pools: bool,
+
+        /// RPC requests in flight at once while loading pools, keep it low
+        /// against public endpoints
+        #[arg(long, default_value_t = DEFAULT_RPC_CONCURRENCY)]
+        rpc_concurrency: usize,
+
+        /// Swap fee assumed for pools whose fee is unknown, in bps
+        #[arg(long, default_value_t = DEFAULT_FEE_BPS)]
+        default_fee_bps: u64,
+
+        /// Path to a JSON file with an object of amm id to the swap fee of
+        /// the pool in bps, the fees take precedence over the on-chain ones
+        #[arg(long)]
+        fee_overrides: Option<String>,
+
+        /// Start from the pool states in this file instead of loading them
+        /// all over RPC, the file is kept up to date while running
+        #[arg(long)]
+        pool_snapshot: Option<String>,
+
+        /// Report the sandwiches around the Raydium AMM swaps of each slot
+        #[arg(long)]
+        detect_sandwiches: bool,
+
+        /// Smallest swap counted as a sandwich victim on SOL pools, in SOL
+        #[arg(long, default_value_t = 1.)]
+        sandwich_min_sol: f64,
+
+        /// Smallest swap counted as a sandwich victim on USDC pools, in USDC
+        #[arg(long, default_value_t = 200.)]
+        sandwich_min_usdc: f64,
+
+        /// Most swaps on the pool from the front-run to the back-run
+        #[arg(long, default_value_t = 4)]
+        sandwich_max_distance: usize,
+
+        /// Half-life of the moving average of the pool prices, in seconds
+        #[arg(long, default_value_t = DEFAULT_EMA_HALF_LIFE.as_secs())]
+        ema_half_life_secs: u64,
+
+        /// Report the swap volume of each pool per slot
+        #[arg(long)]
+        emit_volume: bool,
+
+        /// Losses in a row (failed sends, reverted trades) after which the
+        /// live trades stop until reset over the admin API, 0 to never stop
+        #[arg(long, default_value_t = DEFAULT_MAX_CONSECUTIVE_LOSSES)]
+        max_consecutive_losses: usize,
+
+        /// Window the losses in a row have to fall within, in seconds
+        #[arg(long, default_value_t = DEFAULT_LOSS_WINDOW_SECS)]
+        loss_window_secs: u64,
+
+        /// Download raydium.json again this often, in seconds, loading the
+        /// pools of the mints of interest which are new in it
+        #[arg(long)]
+        refresh_raydium_json_secs: Option<u64>,
+    },
 
     /// Raydium new listings
-    GraduatesMode,
+    GraduatesMode {
+        /// Look the mint authority of the graduating tokens up over RPC
+        /// (RPC_URL) and tell if it was revoked in the events
+        #[arg(long)]
+        check_mint_authority: bool,
+
+        /// Mint lookups in flight at once
+        #[arg(long, default_value_t = DEFAULT_RPC_CONCURRENCY)]
+        rpc_concurrency: usize,
+    },
+
+    /// Write a row per transaction (signature, slot, programs, swap, SOL
+    /// amount) to Parquet files, for offline analysis
+    #[cfg(feature = "parquet")]
+    ParquetMode {
+        /// Directory the files are written to
+        #[arg(long)]
+        dir: String,
+
+        /// Rows per file
+        #[arg(long, default_value_t = DEFAULT_PARQUET_MAX_ROWS)]
+        max_rows: usize,
+
+        /// Start a new file at least this often, in seconds
+        #[arg(long, default_value_t = DEFAULT_PARQUET_MAX_AGE_SECS)]
+        max_age_secs: u64,
+    },
+
+    /// Retry the reconstruction of dumped hanging FEC sets, reporting a
+    /// verdict per set
+    Recover {
+        /// Dump to load, the latest snapshot in `dump_dir` by default
+        path: Option<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Decode a single shred from stdin (hex, base58 or base64) and print
+    /// its header
+    DecodeShred {
+        /// Print the header as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check the structure of a packet capture (runts, shreds, slots and
+    /// complete vs hanging FEC sets) without replaying it
+    Validate {
+        /// Packet capture, as written by save mode
+        path: String,
+
+        /// Print the stats as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the leader of each slot in a range, off of the RPC leader
+    /// schedule
+    Leaders {
+        /// First slot
+        from: u64,
+
+        /// Last slot, `from` alone by default
+        to: Option<u64>,
+
+        /// Directory the leader schedule of each epoch is cached in
+        #[arg(long, default_value = ".")]
+        cache_dir: String,
+
+        /// Print the leaders as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Replay a packet capture and summarize the programs and mints in it
+    Analyze {
+        /// Packet capture, as written by save mode
+        path: String,
+
+        /// Print the summary as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Replay a packet capture and time the arb prefilter against the per
+    /// tx parsing of the programs it replaced
+    BenchmarkPrefilter {
+        /// Packet capture, as written by save mode
+        path: String,
+
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_fills_in_what_is_not_on_the_command_line() {
+        let path = std::env::temp_dir()
+            .join(format!("shreds-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+bind = "0.0.0.0:9001"
+post_url = "http://receiver:6969"
+max-slot-lag = 100
+webhook_insecure = true
+forward = ["127.0.0.1:8002", "127.0.0.1:8003"]
+
+[pump-mode]
+enrich = true
+min_dev_buy_sol = 0.5
+"#,
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        let app = App::load_from([
+            "shreds",
+            "--config",
+            config,
+            "--bind",
+            "0.0.0.0:8001",
+            "pump-mode",
+            "--min-dev-buy-sol",
+            "2",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // the flags win over the file, the file over the defaults
+        assert_eq!(app.args.bind.as_deref(), Some("0.0.0.0:8001"));
+        assert_eq!(
+            app.args.post_url.as_deref(),
+            Some("http://receiver:6969")
+        );
+        assert_eq!(app.args.max_slot_lag, Some(100));
+        assert!(app.args.webhook_insecure);
+        assert_eq!(app.args.forward.len(), 2);
+        assert_eq!(app.args.max_entries, DEFAULT_MAX_ENTRIES);
+        match app.command {
+            Command::PumpMode {
+                enrich,
+                min_dev_buy_sol,
+                ..
+            } => {
+                assert!(enrich);
+                assert_eq!(min_dev_buy_sol, 2.);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
 }
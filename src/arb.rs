@@ -1,20 +1,40 @@
+use crate::alt::LookupTableCache;
+use crate::circuit_breaker::CircuitBreaker;
 use crate::constants;
+use crate::jupiter::{self, JupiterRoute, RouteMints};
 use crate::raydium::{
-    calculate_price, initialize_raydium_amm_pools, parse_amm_instruction,
-    swap_exact_amount, ParsedAccounts, ParsedAmmInstruction, RaydiumAmmPool,
+    detect_quote, initialize_raydium_amm_pools, load_raydium_amm_pool,
+    parse_amm_instruction, swap_exact_amount, ParsedAccounts,
+    ParsedAmmInstruction, QuoteAsset, RaydiumAmmPool, RaydiumAmmPoolSnapshot,
+    RaydiumDecimals, RaydiumJsonRefresher, DEFAULT_EMA_HALF_LIFE,
+    DEFAULT_TOKEN_DECIMALS,
 };
-use crate::util::env;
-use log::{error, info, warn};
-use solana_client::nonblocking::rpc_client::RpcClient;
+use crate::raydium_clmm::{
+    load_raydium_clmm_pool, ClmmSwapAccounts, ClmmSwapIx, RaydiumClmmPool,
+};
+use crate::rpc::{RateLimitedRpc, DEFAULT_RPC_CONCURRENCY};
+use crate::sandwich::{SandwichConfig, SwapRecord};
+use crate::sink::{idempotency_key, ARB_TRADE_TOPIC, NEW_RAYDIUM_POOL_TOPIC};
+use crate::util::pubkey_to_string;
+use crate::wallet::fund_pubkey;
+use log::{debug, error, info, warn};
+use raydium_amm::instruction::InitializeInstruction2;
+use raydium_amm::math::SwapDirection;
+use raydium_library::amm::{AmmKeys, CalculateResult};
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::Slot;
 use solana_sdk::instruction::CompiledInstruction;
-use solana_sdk::message::VersionedMessage;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 use solana_sdk::transaction::VersionedTransaction;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{sleep, Duration};
 
 pub fn get_mints_of_interest() -> Vec<Pubkey> {
     [
@@ -32,18 +52,651 @@ pub fn get_mints_of_interest() -> Vec<Pubkey> {
     .collect::<Vec<_>>()
 }
 
+/// Size of the SOL leg of an arb trade
+pub const ARB_TRADE_SIZE: u64 = LAMPORTS_PER_SOL / 10;
+/// Minimum relative price difference between two pools worth acting on
+pub const MIN_ARB_SPREAD: f64 = 0.01;
+/// Pools not updated for longer are left out of the arbitrage, their
+/// reserves have likely drifted from the chain, see RaydiumAmmPool::is_stale
+pub const MAX_ARB_PRICE_AGE: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeMode {
+    /// Only track pool states
+    #[default]
+    ReadOnly,
+    /// Compute and report the trades that would be sent, never signing
+    Paper,
+    /// Sign and submit the trades
+    Live,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArbConfig {
+    pub trade_mode: TradeMode,
+    /// Cap on the SOL leg of a live trade, in lamports
+    pub max_trade_size: u64,
+    /// Minimum expected profit of a live trade, in lamports
+    pub min_profit: u64,
+    /// Priority fee, in micro-lamports per compute unit
+    pub priority_fee: u64,
+    /// Buffer taken off of the simulated output of each leg, in bps
+    pub slippage_bps: u64,
+    /// Load pools not in raydium.json on the first swap seen on them
+    pub lazy_load_pools: bool,
+    /// RPC requests in flight at once while loading pools
+    pub rpc_concurrency: usize,
+    pub fee_schedule: FeeSchedule,
+    /// Pool state snapshot to start from and to keep up to date, see
+    /// PoolsState::export_snapshot
+    pub pool_snapshot: Option<PathBuf>,
+    /// Look for sandwiches around the Raydium AMM swaps, see
+    /// sandwich::detect_sandwiches
+    pub sandwich: Option<SandwichConfig>,
+    /// Half-life of the moving average of the pool prices
+    pub ema_half_life: Duration,
+    /// Report the swap volume of each pool per slot, see VolumeAggregator
+    pub emit_volume: bool,
+    /// Stop the live trades after a streak of losses, see CircuitBreaker
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Download raydium.json again this often and load the new pools, see
+    /// run_raydium_json_refresh
+    pub raydium_json_refresh: Option<Duration>,
+}
+
+impl Default for ArbConfig {
+    fn default() -> Self {
+        ArbConfig {
+            trade_mode: TradeMode::default(),
+            max_trade_size: ARB_TRADE_SIZE,
+            min_profit: 100_000,
+            priority_fee: 100_000,
+            slippage_bps: 50,
+            lazy_load_pools: false,
+            rpc_concurrency: DEFAULT_RPC_CONCURRENCY,
+            fee_schedule: FeeSchedule::default(),
+            pool_snapshot: None,
+            sandwich: None,
+            ema_half_life: DEFAULT_EMA_HALF_LIFE,
+            emit_volume: false,
+            circuit_breaker: None,
+            raydium_json_refresh: None,
+        }
+    }
+}
+
+/// Swap fee of a pool, as a fraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fee {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Fee {
+    pub fn from_bps(bps: u64) -> Self {
+        Fee {
+            numerator: bps,
+            denominator: 10_000,
+        }
+    }
+}
+
+/// Raydium AMM v4 swap fee, 25 bps
+pub const DEFAULT_FEE_BPS: u64 = 25;
+
+/// FeeSchedule decides the fee a pool is simulated with: a per-pool
+/// override if there is one, the fee from the pool state if it is known
+/// (non-zero), the default otherwise, e.g. for pools that were registered
+/// with reserves only
+#[derive(Debug, Clone)]
+pub struct FeeSchedule {
+    pub default: Fee,
+    pub overrides: HashMap<Pubkey, Fee>,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        FeeSchedule {
+            default: Fee::from_bps(DEFAULT_FEE_BPS),
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl FeeSchedule {
+    /// load reads the overrides from the file at `overrides_path`, a JSON
+    /// object of amm id to bps
+    pub fn load(
+        default_bps: u64,
+        overrides_path: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let mut overrides = HashMap::new();
+        if let Some(path) = overrides_path {
+            let bps: HashMap<String, u64> =
+                serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            for (amm_id, bps) in bps {
+                overrides
+                    .insert(Pubkey::from_str(&amm_id)?, Fee::from_bps(bps));
+            }
+            info!("Loaded {} pool fee overrides", overrides.len());
+        }
+        Ok(FeeSchedule {
+            default: Fee::from_bps(default_bps),
+            overrides,
+        })
+    }
+
+    pub fn fee_for(&self, pool: &RaydiumAmmPool) -> Fee {
+        if let Some(fee) = self.overrides.get(&pool.amm_keys.amm_pool) {
+            return *fee;
+        }
+        match (
+            pool.state.swap_fee_numerator,
+            pool.state.swap_fee_denominator,
+        ) {
+            (0, _) | (_, 0) => self.default,
+            (numerator, denominator) => Fee {
+                numerator,
+                denominator,
+            },
+        }
+    }
+}
+
+/// Reserves and fees of a SOL-quoted pool, as used for trade simulation
+#[derive(Debug, Clone, Copy)]
+pub struct PoolReserves {
+    pub pc_vault_amount: u64,
+    pub coin_vault_amount: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+    pub sol_is_coin: bool,
+}
+
+impl PoolReserves {
+    pub fn new(pool: &RaydiumAmmPool, fee: Fee) -> Self {
+        PoolReserves {
+            pc_vault_amount: pool.state.pool_pc_vault_amount,
+            coin_vault_amount: pool.state.pool_coin_vault_amount,
+            swap_fee_numerator: fee.numerator,
+            swap_fee_denominator: fee.denominator,
+            sol_is_coin: pool.amm_keys.amm_coin_mint
+                == *constants::WSOL_PUBKEY,
+        }
+    }
+
+    /// buy is the amount of tokens received for `sol_in` lamports
+    pub fn buy(&self, sol_in: u64) -> u64 {
+        self.swap(
+            if self.sol_is_coin {
+                SwapDirection::Coin2PC
+            } else {
+                SwapDirection::PC2Coin
+            },
+            sol_in,
+        )
+    }
+
+    /// sell is the amount of lamports received for `token_in` tokens
+    pub fn sell(&self, token_in: u64) -> u64 {
+        self.swap(
+            if self.sol_is_coin {
+                SwapDirection::PC2Coin
+            } else {
+                SwapDirection::Coin2PC
+            },
+            token_in,
+        )
+    }
+
+    fn swap(&self, swap_direction: SwapDirection, amount_in: u64) -> u64 {
+        swap_exact_amount(
+            self.pc_vault_amount,
+            self.coin_vault_amount,
+            self.swap_fee_numerator,
+            self.swap_fee_denominator,
+            swap_direction,
+            amount_in,
+            true,
+        )
+    }
+}
+
+/// TradeDecision is the projected outcome of buying on one pool and
+/// selling the received tokens on the other, after the fees of both pools
+/// and the slippage buffer
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeDecision {
+    pub amount_in: u64,
+    /// Tokens out of the buy leg
+    pub token_amount: u64,
+    /// Tokens out of the buy leg less the slippage buffer, this is what
+    /// goes into the sell leg
+    pub min_token_amount: u64,
+    /// Lamports out of the sell leg
+    pub amount_out: u64,
+    /// Lamports out of the sell leg less the slippage buffer
+    pub min_amount_out: u64,
+    pub projected_profit: i64,
+    pub accepted: bool,
+}
+
+pub fn simulate_arb_trade(
+    buy_pool: &PoolReserves,
+    sell_pool: &PoolReserves,
+    amount_in: u64,
+    slippage_bps: u64,
+    min_profit: u64,
+) -> TradeDecision {
+    let token_amount = buy_pool.buy(amount_in);
+    let min_token_amount = less_slippage(token_amount, slippage_bps);
+    let amount_out = sell_pool.sell(min_token_amount);
+    let min_amount_out = less_slippage(amount_out, slippage_bps);
+    let projected_profit = min_amount_out as i64 - amount_in as i64;
+    TradeDecision {
+        amount_in,
+        token_amount,
+        min_token_amount,
+        amount_out,
+        min_amount_out,
+        projected_profit,
+        accepted: min_token_amount > 0
+            && projected_profit >= min_profit as i64,
+    }
+}
+
+/// less_slippage is the amount less `slippage_bps` of it
+pub fn less_slippage(amount: u64, slippage_bps: u64) -> u64 {
+    (amount as u128 * 10_000u128.saturating_sub(slippage_bps as u128)
+        / 10_000) as u64
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArbOpportunity {
+    /// The swap that moved the pool out of line
+    pub trigger_signature: String,
+    /// Slot of the trigger swap
+    pub slot: Slot,
+    /// When the opportunity was detected, unix millis
+    pub timestamp: i64,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub mint: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub buy_pool: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub sell_pool: Pubkey,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub amount_in: u64,
+    pub expected_profit: i64,
+}
+
+/// ArbTradeEvent is what gets reported for every acted-on opportunity, the
+/// shape is the same regardless of the mode so that runs can be diffed
+#[derive(Debug, Clone, Serialize)]
+pub struct ArbTradeEvent {
+    pub mode: TradeMode,
+    pub opportunity: ArbOpportunity,
+    /// Signature of the submitted trade, None if nothing was sent
+    pub signature: Option<String>,
+    pub idempotency_key: String,
+}
+
+impl ArbTradeEvent {
+    pub fn new(
+        mode: TradeMode,
+        opportunity: ArbOpportunity,
+        signature: Option<String>,
+    ) -> Self {
+        let idempotency_key = idempotency_key(
+            ARB_TRADE_TOPIC,
+            &[
+                &opportunity.trigger_signature,
+                &opportunity.buy_pool.to_string(),
+                &opportunity.sell_pool.to_string(),
+            ],
+        );
+        ArbTradeEvent {
+            mode,
+            opportunity,
+            signature,
+            idempotency_key,
+        }
+    }
+}
+
+/// NewRaydiumPoolEvent is a pool created with Initialize2, the amounts are
+/// the initial liquidity and trading starts at `open_time` (unix seconds)
+#[derive(Debug, Clone, Serialize)]
+pub struct NewRaydiumPoolEvent {
+    pub signature: String,
+    pub slot: Slot,
+    /// When the creation was seen, unix millis
+    pub timestamp: i64,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub amm_id: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub coin_mint: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub pc_mint: Pubkey,
+    pub init_coin_amount: u64,
+    pub init_pc_amount: u64,
+    pub open_time: u64,
+    pub idempotency_key: String,
+    #[serde(skip)]
+    pub amm_keys: AmmKeys,
+}
+
+// account positions of the Initialize2 instruction
+const INIT_AMM_ID: usize = 4;
+const INIT_AMM_AUTHORITY: usize = 5;
+const INIT_AMM_OPEN_ORDERS: usize = 6;
+const INIT_LP_MINT: usize = 7;
+const INIT_COIN_MINT: usize = 8;
+const INIT_PC_MINT: usize = 9;
+const INIT_COIN_VAULT: usize = 10;
+const INIT_PC_VAULT: usize = 11;
+const INIT_TARGET_ORDERS: usize = 12;
+const INIT_MARKET_PROGRAM: usize = 15;
+const INIT_MARKET: usize = 16;
+
+/// parse_new_pool reads the pool being created out of an Initialize2
+/// instruction, None if it is missing accounts
+pub fn parse_new_pool(
+    initialize: &InitializeInstruction2,
+    instruction: &CompiledInstruction,
+    account_keys: &[Pubkey],
+    signature: &Signature,
+    slot: Slot,
+) -> Option<NewRaydiumPoolEvent> {
+    let key = |position| {
+        get_account_key_safely(account_keys, instruction, position)
+    };
+    let amm_keys = AmmKeys {
+        amm_pool: key(INIT_AMM_ID)?,
+        amm_coin_mint: key(INIT_COIN_MINT)?,
+        amm_pc_mint: key(INIT_PC_MINT)?,
+        amm_authority: key(INIT_AMM_AUTHORITY)?,
+        amm_target: key(INIT_TARGET_ORDERS)?,
+        amm_coin_vault: key(INIT_COIN_VAULT)?,
+        amm_pc_vault: key(INIT_PC_VAULT)?,
+        amm_lp_mint: key(INIT_LP_MINT)?,
+        amm_open_order: key(INIT_AMM_OPEN_ORDERS)?,
+        market_program: key(INIT_MARKET_PROGRAM)?,
+        market: key(INIT_MARKET)?,
+        nonce: initialize.nonce,
+    };
+    Some(NewRaydiumPoolEvent {
+        signature: signature.to_string(),
+        slot,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        amm_id: amm_keys.amm_pool,
+        coin_mint: amm_keys.amm_coin_mint,
+        pc_mint: amm_keys.amm_pc_mint,
+        init_coin_amount: initialize.init_coin_amount,
+        init_pc_amount: initialize.init_pc_amount,
+        open_time: initialize.open_time,
+        idempotency_key: idempotency_key(
+            NEW_RAYDIUM_POOL_TOPIC,
+            &[&signature.to_string()],
+        ),
+        amm_keys,
+    })
+}
+
+impl NewRaydiumPoolEvent {
+    /// pool is the pool as of its creation, the decimals of the token side
+    /// are assumed (DEFAULT_TOKEN_DECIMALS) and the fee is left to the
+    /// FeeSchedule until the pool is loaded
+    pub fn pool(&self) -> RaydiumAmmPool {
+        let quote = detect_quote(&self.coin_mint, &self.pc_mint);
+        let quote_decimals =
+            quote.asset.decimals().unwrap_or(DEFAULT_TOKEN_DECIMALS);
+        let (token, coin_decimals, pc_decimals) = if quote.is_coin {
+            (self.pc_mint, quote_decimals, DEFAULT_TOKEN_DECIMALS)
+        } else {
+            (self.coin_mint, DEFAULT_TOKEN_DECIMALS, quote_decimals)
+        };
+        RaydiumAmmPool {
+            token,
+            amm_keys: self.amm_keys,
+            state: CalculateResult {
+                pool_pc_vault_amount: self.init_pc_amount,
+                pool_coin_vault_amount: self.init_coin_amount,
+                pool_lp_amount: 0,
+                swap_fee_numerator: 0,
+                swap_fee_denominator: 0,
+            },
+            decimals: RaydiumDecimals {
+                coin_decimals,
+                pc_decimals,
+                // the lp mint is created with the decimals of the coin
+                lp_decimals: coin_decimals,
+            },
+            last_update_ms: Some(self.timestamp),
+            last_update_slot: Some(self.slot),
+            ema_price: None,
+        }
+    }
+}
+
+/// Pause between two lazy pool loads, keeps a flood of swaps on unknown
+/// pools from hammering the RPC
+pub const POOL_LOAD_INTERVAL: Duration = Duration::from_millis(250);
+/// How long until an untracked pool can be requested for loading again
+pub const POOL_LOAD_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+pub const POOL_LOAD_QUEUE_SIZE: usize = 100;
+/// How many CLMM pools are tracked at most, the one swapped on the least
+/// recently is dropped for a new one past that
+pub const MAX_RAYDIUM_CLMM_POOLS: usize = 10_000;
+
+/// PoolLoadRequest is a pool queued for run_pool_loader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolLoadRequest {
+    RaydiumAmm(Pubkey),
+    RaydiumClmm(Pubkey),
+}
+
+impl PoolLoadRequest {
+    pub fn pool_id(&self) -> Pubkey {
+        match self {
+            PoolLoadRequest::RaydiumAmm(pool_id)
+            | PoolLoadRequest::RaydiumClmm(pool_id) => *pool_id,
+        }
+    }
+}
+
+/// run_pool_loader lazily loads the pools that swaps are seen on but that
+/// are not tracked yet (not in raydium.json), and the state of the CLMM
+/// pools, see PoolsState::request_pool_load
+pub async fn run_pool_loader(pools_state: Arc<RwLock<PoolsState>>) {
+    let (pool_load_tx, mut pool_load_rx) =
+        mpsc::channel(POOL_LOAD_QUEUE_SIZE);
+    pools_state.write().await.pool_load_tx = Some(pool_load_tx);
+
+    let rpc = pools_state.read().await.rpc();
+    let fee_payer = fund_pubkey();
+    while let Some(request) = pool_load_rx.recv().await {
+        match request {
+            PoolLoadRequest::RaydiumAmm(amm_id) => {
+                match load_raydium_amm_pool(&rpc, &amm_id, fee_payer).await {
+                    Ok(pool) => {
+                        info!(
+                            "Lazily loaded pool {} ({})",
+                            amm_id, pool.token
+                        );
+                        pools_state.write().await.insert_raydium_pool(pool);
+                    }
+                    Err(e) => {
+                        warn!("Failed to lazily load pool {}: {}", amm_id, e);
+                    }
+                }
+            }
+            PoolLoadRequest::RaydiumClmm(pool_id) => {
+                match load_raydium_clmm_pool(&rpc, &pool_id).await {
+                    Ok(pool) => {
+                        info!("Loaded Raydium CLMM pool {}", pool_id);
+                        pools_state
+                            .write()
+                            .await
+                            .insert_raydium_clmm_pool(pool);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to load Raydium CLMM pool {}: {}",
+                            pool_id, e
+                        );
+                    }
+                }
+            }
+        }
+        sleep(POOL_LOAD_INTERVAL).await;
+    }
+}
+
+/// run_raydium_json_refresh downloads raydium.json every `interval` and
+/// loads the pools of the mints of interest which are new or changed in it,
+/// so that the pools Raydium adds are tracked without a restart
+pub async fn run_raydium_json_refresh(
+    pools_state: Arc<RwLock<PoolsState>>,
+    interval: Duration,
+) {
+    let mut refresher =
+        match RaydiumJsonRefresher::new(get_mints_of_interest()).await {
+            Ok(refresher) => refresher,
+            Err(e) => {
+                error!("Not refreshing raydium.json: {}", e);
+                return;
+            }
+        };
+    let rpc = pools_state.read().await.rpc();
+    let fee_payer = fund_pubkey();
+    loop {
+        sleep(interval).await;
+        let diff = match refresher.refresh().await {
+            Ok(diff) => diff,
+            Err(e) => {
+                warn!("Failed to refresh raydium.json: {}", e);
+                continue;
+            }
+        };
+        for (mint, (amm_keys, _)) in diff.new.iter().chain(&diff.changed) {
+            let amm_id = amm_keys.amm_pool;
+            match load_raydium_amm_pool(&rpc, &amm_id, fee_payer).await {
+                Ok(pool) => {
+                    info!(
+                        "Loaded pool {} ({}) off of raydium.json",
+                        amm_id, mint
+                    );
+                    pools_state.write().await.insert_raydium_pool(pool);
+                }
+                Err(e) => {
+                    warn!("Failed to load pool {}: {}", amm_id, e);
+                }
+            }
+            sleep(POOL_LOAD_INTERVAL).await;
+        }
+    }
+}
+
+/// Version of the pool snapshot layout, bumped on breaking changes
+pub const POOL_SNAPSHOT_VERSION: u32 = 1;
+/// How often the pool snapshot is rewritten
+pub const POOL_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// PoolSnapshot is the tracked pools as written to disk, `taken_at` is in
+/// unix seconds
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    pub version: u32,
+    pub taken_at: i64,
+    pub pools: Vec<RaydiumAmmPoolSnapshot>,
+}
+
+/// run_pool_snapshots rewrites the pool snapshot every
+/// POOL_SNAPSHOT_INTERVAL
+pub async fn run_pool_snapshots(
+    pools_state: Arc<RwLock<PoolsState>>,
+    path: PathBuf,
+) {
+    loop {
+        sleep(POOL_SNAPSHOT_INTERVAL).await;
+        if let Err(e) = pools_state.read().await.export_snapshot(&path).await
+        {
+            error!("Failed to write pool snapshot: {}", e);
+        }
+    }
+}
+
+/// run_pool_resync reloads the pools restored from a snapshot one by one,
+/// to catch up with what happened on them while the process was down
+pub async fn run_pool_resync(pools_state: Arc<RwLock<PoolsState>>) {
+    let (amm_ids, rpc) = {
+        let pools_state = pools_state.read().await;
+        (
+            pools_state
+                .raydium_pools
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            pools_state.rpc(),
+        )
+    };
+    let fee_payer = fund_pubkey();
+    info!("Resyncing {} pools from the snapshot", amm_ids.len());
+    for amm_id in amm_ids {
+        match load_raydium_amm_pool(&rpc, &amm_id, fee_payer).await {
+            Ok(pool) => {
+                pools_state.write().await.insert_raydium_pool(pool);
+            }
+            Err(e) => {
+                warn!("Failed to resync pool {}: {}", amm_id, e);
+            }
+        }
+        sleep(POOL_LOAD_INTERVAL).await;
+    }
+    info!("Pools resynced");
+}
+
 #[derive(Debug, Default)]
 pub struct PoolsState {
     pub raydium_cp_count: u64,
     pub raydium_amm_count: u64,
+    pub raydium_clmm_count: u64,
+    // CLMM `swap`s on pools not tracked yet, which do not carry the mints
+    // to register the pool off of, until the pool is loaded
+    pub raydium_clmm_unregistered_swaps: u64,
     pub orca_count: u64,
+    // Raydium AMM swaps routed through Jupiter, counted per pool hop
+    pub jupiter_raydium_count: u64,
     pub orca_token_to_pool: HashMap<Pubkey, Arc<OrcaPool>>,
     // program_id to pool
     pub raydium_pools: HashMap<Pubkey, Arc<RwLock<RaydiumAmmPool>>>,
     // mint to program_id vec
     pub raydium_pools_by_mint: HashMap<Pubkey, Vec<Pubkey>>,
     pub raydium_pool_ids: Vec<Pubkey>,
+    // pool id to pool, registered off of the first `swap_v2` seen on them
+    // or once loaded, up to MAX_RAYDIUM_CLMM_POOLS
+    pub raydium_clmm_pools: HashMap<Pubkey, RaydiumClmmPool>,
     pub signatures: Vec<Signature>,
+    // set by run_pool_loader, swaps on untracked pools are sent here
+    pub pool_load_tx: Option<mpsc::Sender<PoolLoadRequest>>,
+    // pool id to the time of the last load request, doubles as a negative
+    // cache for accounts that fail to load
+    pub pool_load_requests: HashMap<Pubkey, Instant>,
+    // pools created since the last take_new_pools
+    pub new_pools: Vec<NewRaydiumPoolEvent>,
+    // swaps on the tracked pools since the last take_swaps, in order
+    pub swaps: Vec<SwapRecord>,
+    pub fee_schedule: FeeSchedule,
+    // of the moving average of the pool prices, see Ema
+    pub ema_half_life: Duration,
+    // resolves the accounts V0 txs load from address lookup tables, taken
+    // out to resolve the txs before the state is locked
+    pub lookup_tables: Arc<LookupTableCache>,
+    // shared by the pool init, the lazy loads and the resync
+    pub rpc: Option<Arc<RateLimitedRpc>>,
 }
 
 #[derive(Debug, Default)]
@@ -53,12 +706,10 @@ impl PoolsState {
     /// Initialize the state of the pools, this has to be called every time
     /// after struct is created for arb
     pub async fn initialize(&mut self) {
-        initialize_raydium_amm_pools(
-            &RpcClient::new(env("RPC_URL").to_string()),
-            self,
-            get_mints_of_interest(),
-        )
-        .await;
+        let rpc = self.rpc();
+        self.lookup_tables = Arc::new(LookupTableCache::new(rpc.clone()));
+        initialize_raydium_amm_pools(&rpc, self, get_mints_of_interest())
+            .await;
         info!(
             "Initialized Raydium AMM pools: {}",
             self.raydium_pools.len()
@@ -67,32 +718,224 @@ impl PoolsState {
         // TODO orca etc
     }
 
-    pub fn reduce_orca_tx(&mut self, _tx: VersionedTransaction) {
+    /// rpc is the rate limited client pools are loaded through, one with
+    /// the default limit if none was set
+    pub fn rpc(&self) -> Arc<RateLimitedRpc> {
+        self.rpc.clone().unwrap_or_else(|| {
+            Arc::new(
+                RateLimitedRpc::from_env(DEFAULT_RPC_CONCURRENCY)
+                    .expect("RPC for the pools"),
+            )
+        })
+    }
+
+    /// initialize_warm starts from the pool snapshot rather than from RPC,
+    /// falling back to initialize if there is no usable snapshot; returns
+    /// whether the pools came from the snapshot and need a resync
+    pub async fn initialize_warm(&mut self, snapshot: &Path) -> bool {
+        match self.load_snapshot(snapshot) {
+            Ok(count) if count > 0 => {
+                self.lookup_tables =
+                    Arc::new(LookupTableCache::new(self.rpc()));
+                true
+            }
+            Ok(_) => {
+                warn!("Pool snapshot {} is empty", snapshot.display());
+                self.initialize().await;
+                false
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to load pool snapshot {}: {}",
+                    snapshot.display(),
+                    e
+                );
+                self.initialize().await;
+                false
+            }
+        }
+    }
+
+    /// export_snapshot writes the tracked pools to `path` (through a
+    /// temporary file, so that a crash does not leave half a snapshot);
+    /// returns the number of pools written
+    pub async fn export_snapshot(
+        &self,
+        path: &Path,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut pools = Vec::with_capacity(self.raydium_pools.len());
+        for pool in self.raydium_pools.values() {
+            pools.push(RaydiumAmmPoolSnapshot::from(&*pool.read().await));
+        }
+        let snapshot = PoolSnapshot {
+            version: POOL_SNAPSHOT_VERSION,
+            taken_at: chrono::Utc::now().timestamp(),
+            pools,
+        };
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&snapshot)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(snapshot.pools.len())
+    }
+
+    /// load_snapshot starts tracking the pools of a snapshot written by
+    /// export_snapshot, replacing the tracked ones with the same amm id;
+    /// returns the number of pools loaded
+    pub fn load_snapshot(
+        &mut self,
+        path: &Path,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let snapshot: PoolSnapshot =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if snapshot.version > POOL_SNAPSHOT_VERSION {
+            return Err(format!(
+                "pool snapshot version {} is newer than {}",
+                snapshot.version, POOL_SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        let count = snapshot.pools.len();
+        for pool in snapshot.pools {
+            self.insert_raydium_pool(pool.into());
+        }
+        info!(
+            "Loaded {} pools from {} ({}s old)",
+            count,
+            path.display(),
+            chrono::Utc::now().timestamp() - snapshot.taken_at
+        );
+        Ok(count)
+    }
+
+    pub fn insert_raydium_pool(&mut self, pool: RaydiumAmmPool) {
+        let amm_id = pool.amm_keys.amm_pool;
+        let mint = pool.token;
+        self.raydium_pools
+            .insert(amm_id, Arc::new(RwLock::new(pool)));
+        let amm_ids = self.raydium_pools_by_mint.entry(mint).or_default();
+        // reloaded pools replace the tracked ones
+        if !amm_ids.contains(&amm_id) {
+            amm_ids.push(amm_id);
+        }
+    }
+
+    /// register_new_pool starts tracking a pool as it is created and queues
+    /// a load for its on-chain state, which also brings the exact decimals
+    pub fn register_new_pool(&mut self, event: NewRaydiumPoolEvent) {
+        info!(
+            "New Raydium pool {} ({} / {}), opens at {}",
+            event.amm_id, event.coin_mint, event.pc_mint, event.open_time
+        );
+        if !self.raydium_pools.contains_key(&event.amm_id) {
+            self.insert_raydium_pool(event.pool());
+            self.request_pool_load(PoolLoadRequest::RaydiumAmm(event.amm_id));
+        }
+        self.new_pools.push(event);
+    }
+
+    /// take_new_pools drains the pools created since the last call
+    pub fn take_new_pools(&mut self) -> Vec<NewRaydiumPoolEvent> {
+        std::mem::take(&mut self.new_pools)
+    }
+
+    /// take_swaps drains the swaps applied since the last call
+    pub fn take_swaps(&mut self) -> Vec<SwapRecord> {
+        std::mem::take(&mut self.swaps)
+    }
+
+    /// request_pool_load queues a pool for the loader, at most once per
+    /// POOL_LOAD_RETRY_INTERVAL, dropping it if the queue is full; the
+    /// requests older than that are forgotten as new ones are queued
+    fn request_pool_load(&mut self, request: PoolLoadRequest) {
+        let Some(pool_load_tx) = &self.pool_load_tx else {
+            return;
+        };
+        let pool_id = request.pool_id();
+        if let Some(requested_at) = self.pool_load_requests.get(&pool_id) {
+            if requested_at.elapsed() < POOL_LOAD_RETRY_INTERVAL {
+                return;
+            }
+        }
+        if pool_load_tx.try_send(request).is_ok() {
+            self.pool_load_requests.retain(|_, requested_at| {
+                requested_at.elapsed() < POOL_LOAD_RETRY_INTERVAL
+            });
+            self.pool_load_requests.insert(pool_id, Instant::now());
+        }
+    }
+
+    /// insert_raydium_clmm_pool takes in a pool read off of its account,
+    /// keeping the swaps recorded on it if it is tracked already
+    pub fn insert_raydium_clmm_pool(&mut self, pool: RaydiumClmmPool) {
+        match self.raydium_clmm_pools.get_mut(&pool.pool_id) {
+            Some(tracked) => tracked.refresh(pool),
+            None => self.track_raydium_clmm_pool(pool),
+        }
+    }
+
+    /// track_raydium_clmm_pool starts tracking a pool, making room for it
+    /// past MAX_RAYDIUM_CLMM_POOLS
+    fn track_raydium_clmm_pool(&mut self, pool: RaydiumClmmPool) {
+        if self.raydium_clmm_pools.len() >= MAX_RAYDIUM_CLMM_POOLS {
+            // the pools never swapped on since they were loaded go first
+            if let Some(pool_id) = self
+                .raydium_clmm_pools
+                .values()
+                .min_by_key(|pool| pool.last_swap_at)
+                .map(|pool| pool.pool_id)
+            {
+                debug!("Dropping Raydium CLMM pool {}", pool_id);
+                self.raydium_clmm_pools.remove(&pool_id);
+            }
+        }
+        self.raydium_clmm_pools.insert(pool.pool_id, pool);
+    }
+
+    pub fn reduce_orca_tx(&mut self, _tx: &VersionedTransaction) {
         // TODO: Implement Orca transaction processing
     }
 
+    /// reduce_raydium_amm_tx applies the swaps of the tx (landed in `slot`)
+    /// to the tracked pools and returns the arb opportunities the swaps have
+    /// opened up; the pool accounts can be behind lookup tables, unlike the
+    /// program, `account_keys` are those resolved off of lookup_tables
     pub async fn reduce_raydium_amm_tx(
         &mut self,
         tx: Arc<VersionedTransaction>,
-    ) {
-        let raydium_amm_program_id = Pubkey::from_str(constants::RAYDIUM_AMM)
-            .expect("Failed to parse Raydium AMM program ID");
+        account_keys: &[Pubkey],
+        slot: Slot,
+    ) -> Vec<ArbOpportunity> {
+        let raydium_amm_program_id = *constants::RAYDIUM_AMM_PUBKEY;
+        let mut opportunities = Vec::new();
+        let Some(signature) = tx.signatures.first() else {
+            return opportunities;
+        };
+        let Some(&signer) = tx.message.static_account_keys().first() else {
+            return opportunities;
+        };
 
         for (idx, instruction) in tx.message.instructions().iter().enumerate()
         {
-            let program_id = tx.message.static_account_keys()
-                [instruction.program_id_index as usize];
+            let Some(program_id) =
+                account_keys.get(instruction.program_id_index as usize)
+            else {
+                continue;
+            };
 
-            if program_id == raydium_amm_program_id {
+            if *program_id == raydium_amm_program_id {
                 match parse_amm_instruction(&instruction.data) {
                     Ok(parsed_instruction) => {
-                        self.process_raydium_instruction(
-                            &parsed_instruction,
-                            instruction,
-                            &tx.message,
-                            &tx.signatures[0],
-                        )
-                        .await;
+                        opportunities.extend(
+                            self.process_raydium_instruction(
+                                &parsed_instruction,
+                                instruction,
+                                account_keys,
+                                signature,
+                                &signer,
+                                slot,
+                            )
+                            .await,
+                        );
                     }
                     Err(e) => {
                         warn!("Error parsing instruction {}: {:?}", idx, e);
@@ -100,32 +943,223 @@ impl PoolsState {
                 }
             }
         }
+        opportunities
+    }
+
+    /// reduce_jupiter_tx applies the Raydium AMM swaps of the Jupiter routes
+    /// of the tx, returning how many there were and the arb opportunities;
+    /// the amounts are only known for the single step routes, the pools of
+    /// the multi step ones are not moved. The pools are moved by Jupiter's
+    /// quote, not by what the swap executed at (which is only in the
+    /// logs). The AMM program and pools of the steps are mostly in lookup
+    /// tables, see reduce_raydium_amm_tx
+    pub async fn reduce_jupiter_tx(
+        &mut self,
+        tx: Arc<VersionedTransaction>,
+        account_keys: &[Pubkey],
+        slot: Slot,
+    ) -> (usize, Vec<ArbOpportunity>) {
+        let jupiter_program_id = *constants::JUPITER_V6_PUBKEY;
+        let mut opportunities = Vec::new();
+        let mut routed_swaps = 0;
+        let Some(signature) = tx.signatures.first() else {
+            return (routed_swaps, opportunities);
+        };
+        let Some(&signer) = tx.message.static_account_keys().first() else {
+            return (routed_swaps, opportunities);
+        };
+
+        for instruction in tx.message.instructions() {
+            if account_keys.get(instruction.program_id_index as usize)
+                != Some(&jupiter_program_id)
+            {
+                continue;
+            }
+            let steps = jupiter::raydium_amm_steps(account_keys, instruction);
+            routed_swaps += steps.len();
+            // the amounts of the route are those of the hop if it is the
+            // only one
+            let route = JupiterRoute::decode(&instruction.data)
+                .filter(|route| route.steps == 1);
+            let (Some(route), [parsed_accounts]) = (route, steps.as_slice())
+            else {
+                for parsed_accounts in &steps {
+                    debug!(
+                        "{} Jupiter route through {}, amounts unknown",
+                        signature, parsed_accounts.amm_id
+                    );
+                }
+                continue;
+            };
+            let Some(coin_mint) =
+                self.coin_mint_of(&parsed_accounts.amm_id).await
+            else {
+                self.request_pool_load(PoolLoadRequest::RaydiumAmm(
+                    parsed_accounts.amm_id,
+                ));
+                continue;
+            };
+            // the route goes either way through the pool, unlike a direct
+            // swap the side of the exact amount does not tell which
+            let Some(coin_in) =
+                RouteMints::from_route(account_keys, instruction)
+                    .is_input(&coin_mint)
+            else {
+                debug!(
+                    "{} Jupiter route through {}, mints unknown",
+                    signature, parsed_accounts.amm_id
+                );
+                continue;
+            };
+            // as for SwapBaseOut (max in, out) and SwapBaseIn (in, min out)
+            let (amount_specified, other_amount_threshold) =
+                if route.exact_out {
+                    (route.quoted_amount, route.amount)
+                } else {
+                    (route.amount, route.quoted_amount)
+                };
+            opportunities.extend(
+                self.update_pool_state_swap(
+                    parsed_accounts,
+                    amount_specified,
+                    other_amount_threshold,
+                    !route.exact_out,
+                    if coin_in {
+                        SwapDirection::Coin2PC
+                    } else {
+                        SwapDirection::PC2Coin
+                    },
+                    signature,
+                    &signer,
+                    slot,
+                )
+                .await,
+            );
+        }
+        self.jupiter_raydium_count += routed_swaps as u64;
+        (routed_swaps, opportunities)
     }
 
     pub fn reduce_raydium_cp_tx(&mut self, _tx: VersionedTransaction) {
         panic!("Not implemented yet");
     }
 
+    /// reduce_raydium_clmm_tx records which way the swaps of the tx moved
+    /// the price of the CLMM pools, registering the pools not seen before;
+    /// see reduce_raydium_amm_tx for `account_keys`
+    pub async fn reduce_raydium_clmm_tx(
+        &mut self,
+        tx: Arc<VersionedTransaction>,
+        account_keys: &[Pubkey],
+    ) {
+        let Some(signature) = tx.signatures.first() else {
+            return;
+        };
+        for instruction in tx.message.instructions() {
+            if account_keys.get(instruction.program_id_index as usize)
+                != Some(&constants::RAYDIUM_CLMM_PUBKEY)
+            {
+                continue;
+            }
+            let Some(swap) = ClmmSwapIx::decode(&instruction.data) else {
+                continue;
+            };
+            let Some(accounts) =
+                ClmmSwapAccounts::parse(account_keys, instruction)
+            else {
+                warn!(
+                    "{} Failed to get account keys for Raydium CLMM swap",
+                    signature
+                );
+                continue;
+            };
+            // the price and the liquidity are only in the account, loaded
+            // until it is read
+            if self
+                .raydium_clmm_pools
+                .get(&accounts.pool_id)
+                .map_or(true, |pool| pool.decimals.is_none())
+            {
+                self.request_pool_load(PoolLoadRequest::RaydiumClmm(
+                    accounts.pool_id,
+                ));
+            }
+            if !self.raydium_clmm_pools.contains_key(&accounts.pool_id) {
+                match RaydiumClmmPool::from_swap(&accounts) {
+                    Some(pool) => {
+                        info!("Tracking Raydium CLMM pool {}", pool.pool_id);
+                        self.track_raydium_clmm_pool(pool);
+                    }
+                    // `swap` does not carry the mints to orient the vaults
+                    None => {
+                        self.raydium_clmm_unregistered_swaps += 1;
+                        debug!(
+                            "{} Raydium CLMM swap on untracked pool {}",
+                            signature, accounts.pool_id
+                        );
+                        continue;
+                    }
+                }
+            }
+            let pool = self
+                .raydium_clmm_pools
+                .get_mut(&accounts.pool_id)
+                .expect("registered above");
+            match pool.apply_swap(&accounts, &swap) {
+                Some(direction) => debug!(
+                    "{} CLMM pool {} price {:?}",
+                    signature, accounts.pool_id, direction
+                ),
+                None => warn!(
+                    "{} Vault mismatch on CLMM pool {}",
+                    signature, accounts.pool_id
+                ),
+            }
+        }
+    }
+
     async fn process_raydium_instruction(
         &mut self,
         parsed_instruction: &ParsedAmmInstruction,
         instruction: &CompiledInstruction,
-        message: &VersionedMessage,
+        account_keys: &[Pubkey],
         signature: &Signature,
-    ) {
+        signer: &Pubkey,
+        slot: Slot,
+    ) -> Option<ArbOpportunity> {
+        // the accounts of Initialize2 are laid out differently from swaps
+        if let ParsedAmmInstruction::Initialize2(initialize) =
+            parsed_instruction
+        {
+            match parse_new_pool(
+                initialize,
+                instruction,
+                account_keys,
+                signature,
+                slot,
+            ) {
+                Some(event) => self.register_new_pool(event),
+                None => warn!(
+                    "{} Failed to get account keys for Raydium Initialize2",
+                    signature
+                ),
+            }
+            return None;
+        }
+
         let amm_id_index = 1; // Amm account index
         let pool_coin_token_account_index = 5; // Pool Coin Token Account index
         let pool_pc_token_account_index = 6; // Pool Pc Token Account index
 
         let amm_id =
-            get_account_key_safely(message, instruction, amm_id_index);
+            get_account_key_safely(account_keys, instruction, amm_id_index);
         let pool_coin_vault = get_account_key_safely(
-            message,
+            account_keys,
             instruction,
             pool_coin_token_account_index,
         );
         let pool_pc_vault = get_account_key_safely(
-            message,
+            account_keys,
             instruction,
             pool_pc_token_account_index,
         );
@@ -137,12 +1171,14 @@ impl PoolsState {
                 "{} Failed to get account keys for Raydium AMM instruction",
                 signature.to_string()
             );
-            return;
+            return None;
         }
         let amm_id = amm_id.unwrap();
         let pool_coin_vault = pool_coin_vault.unwrap();
         let pool_pc_vault = pool_pc_vault.unwrap();
 
+        // the direct swaps are taken as coin in on base in and pc in on
+        // base out
         match parsed_instruction {
             ParsedAmmInstruction::SwapBaseOut(swap_instruction) => {
                 self.update_pool_state_swap(
@@ -154,9 +1190,12 @@ impl PoolsState {
                     swap_instruction.max_amount_in,
                     swap_instruction.amount_out,
                     false,
+                    SwapDirection::PC2Coin,
                     signature,
+                    signer,
+                    slot,
                 )
-                .await;
+                .await
             }
             ParsedAmmInstruction::SwapBaseIn(swap_instruction) => {
                 self.update_pool_state_swap(
@@ -168,25 +1207,46 @@ impl PoolsState {
                     swap_instruction.amount_in,
                     swap_instruction.minimum_amount_out,
                     true,
+                    SwapDirection::Coin2PC,
                     signature,
+                    signer,
+                    slot,
                 )
-                .await;
+                .await
             }
             // Handle other instruction types...
             _ => {
-                warn!("Unhandled instruction type: {:?}", parsed_instruction)
+                warn!("Unhandled instruction type: {:?}", parsed_instruction);
+                None
             }
         }
     }
 
+    /// coin_mint_of is the coin mint of a tracked pool
+    async fn coin_mint_of(&self, amm_id: &Pubkey) -> Option<Pubkey> {
+        let pool = self.raydium_pools.get(amm_id)?;
+        let coin_mint = pool.read().await.amm_keys.amm_coin_mint;
+        Some(coin_mint)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn update_pool_state_swap(
         &mut self,
         parsed_accounts: &ParsedAccounts,
         amount_specified: u64,
         other_amount_threshold: u64,
         is_swap_base_in: bool,
+        swap_direction: SwapDirection,
         signature: &Signature,
-    ) {
+        signer: &Pubkey,
+        slot: Slot,
+    ) -> Option<ArbOpportunity> {
+        if !self.raydium_pools.contains_key(&parsed_accounts.amm_id) {
+            self.request_pool_load(PoolLoadRequest::RaydiumAmm(
+                parsed_accounts.amm_id,
+            ));
+            return None;
+        }
         if let Some(pool) = self.raydium_pools.get(&parsed_accounts.amm_id) {
             let mut pool = pool.write().await;
             if !(pool.amm_keys.amm_coin_vault
@@ -202,145 +1262,868 @@ impl PoolsState {
                     pool.amm_keys.amm_pc_vault,
                     parsed_accounts.pool_pc_vault,
                 );
-                return;
+                return None;
             };
+            let coin_in = matches!(swap_direction, SwapDirection::Coin2PC);
 
-            let swap_direction = if is_swap_base_in {
-                raydium_amm::math::SwapDirection::Coin2PC
+            // the base out math panics on taking out more than the pool has,
+            // the pc comes out of a coin in swap and the coin otherwise
+            let vault_out = if coin_in {
+                pool.state.pool_pc_vault_amount
             } else {
-                raydium_amm::math::SwapDirection::PC2Coin
+                pool.state.pool_coin_vault_amount
             };
+            if !is_swap_base_in && other_amount_threshold >= vault_out {
+                warn!(
+                    "{} swap out of {} exceeds the vault of {}",
+                    signature, other_amount_threshold, pool.amm_keys.amm_pool
+                );
+                return None;
+            }
 
-            let (pc_amount, coin_amount) = if is_swap_base_in {
+            let fee = self.fee_schedule.fee_for(&pool);
+            let (amount_in, amount_out) = if is_swap_base_in {
                 let swap_amount_out = swap_exact_amount(
                     pool.state.pool_pc_vault_amount,
                     pool.state.pool_coin_vault_amount,
-                    pool.state.swap_fee_numerator,
-                    pool.state.swap_fee_denominator,
+                    fee.numerator,
+                    fee.denominator,
                     swap_direction,
                     amount_specified,
                     true,
                 );
-
-                if is_swap_base_in {
-                    (
-                        pool.state
-                            .pool_pc_vault_amount
-                            .saturating_add(swap_amount_out),
-                        pool.state
-                            .pool_coin_vault_amount
-                            .saturating_sub(amount_specified),
-                    )
-                } else {
-                    (
-                        pool.state
-                            .pool_pc_vault_amount
-                            .saturating_sub(amount_specified),
-                        pool.state
-                            .pool_coin_vault_amount
-                            .saturating_add(swap_amount_out),
-                    )
-                }
+                (amount_specified, swap_amount_out)
             } else {
                 let swap_amount_in = swap_exact_amount(
                     pool.state.pool_pc_vault_amount,
                     pool.state.pool_coin_vault_amount,
-                    pool.state.swap_fee_numerator,
-                    pool.state.swap_fee_denominator,
+                    fee.numerator,
+                    fee.denominator,
                     swap_direction,
                     other_amount_threshold,
                     false,
                 );
-
-                if is_swap_base_in {
-                    (
-                        pool.state
-                            .pool_pc_vault_amount
-                            .saturating_add(other_amount_threshold),
-                        pool.state
-                            .pool_coin_vault_amount
-                            .saturating_sub(swap_amount_in),
-                    )
-                } else {
-                    (
-                        pool.state
-                            .pool_pc_vault_amount
-                            .saturating_sub(swap_amount_in),
-                        pool.state
-                            .pool_coin_vault_amount
-                            .saturating_add(other_amount_threshold),
-                    )
-                }
+                (swap_amount_in, other_amount_threshold)
+            };
+            let (pc_amount, coin_amount) = if coin_in {
+                (
+                    pool.state
+                        .pool_pc_vault_amount
+                        .saturating_add(amount_out),
+                    pool.state
+                        .pool_coin_vault_amount
+                        .saturating_sub(amount_in),
+                )
+            } else {
+                (
+                    pool.state.pool_pc_vault_amount.saturating_sub(amount_in),
+                    pool.state
+                        .pool_coin_vault_amount
+                        .saturating_add(amount_out),
+                )
             };
 
-            let sol_is_pc = pool.amm_keys.amm_pc_mint
-                == Pubkey::from_str(constants::WSOL).expect("pubkey");
-            let sol_is_coin = pool.amm_keys.amm_coin_mint
-                == Pubkey::from_str(constants::WSOL).expect("pubkey");
+            let quote = pool.quote();
+            let quote_amount = swap_quote_amount(
+                quote.is_coin,
+                amount_specified,
+                other_amount_threshold,
+                coin_in,
+            ) as f64
+                / 10u64.pow(pool.quote_decimals(&quote) as u32) as f64;
 
-            let sol_amount = if sol_is_coin {
-                if is_swap_base_in {
-                    amount_specified
-                } else {
-                    other_amount_threshold
-                }
-            } else if sol_is_pc {
-                if is_swap_base_in {
-                    other_amount_threshold
-                } else {
-                    amount_specified
-                }
-            } else {
-                0
-            } as f64
-                / 10u64.pow(9u32) as f64;
+            let initial_price = pool.price_in_quote(&quote);
 
             pool.state.pool_pc_vault_amount = pc_amount;
             pool.state.pool_coin_vault_amount = coin_amount;
+            pool.touch(Some(slot));
 
-            let initial_price = calculate_price(&pool.state, &pool.decimals);
-
-            let new_price = calculate_price(&pool.state, &pool.decimals);
+            let new_price = pool.price_in_quote(&quote);
+            // the average is of the SOL price, see ema_price_of, the pools
+            // quoted in anything else are left without one
+            if let Some(price) = pool.price_in_sol() {
+                pool.observe_price(price, self.ema_half_life);
+            }
 
-            if sol_amount > 10. {
+            if is_large_swap(quote.asset, quote_amount) {
+                let event = LargeSwapEvent {
+                    signature: signature.to_string(),
+                    slot,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                    swap_direction: format!("{:?}", swap_direction),
+                    is_swap_base_in,
+                    amm_id: parsed_accounts.amm_id.to_string(),
+                    pc_mint: pool.amm_keys.amm_pc_mint.to_string(),
+                    amount_specified,
+                    coin_mint: pool.amm_keys.amm_coin_mint.to_string(),
+                    other_amount_threshold,
+                    quote: quote.asset,
+                    quote_mint: quote.mint.to_string(),
+                    quote_amount,
+                    initial_price,
+                    new_price,
+                };
                 info!(
-                    "large swap: ({}) {}",
-                    sol_amount,
-                    serde_json::to_string_pretty(&serde_json::json!({
-                        "event": "Swap",
-                        "signature": signature.to_string(),
-                        "swap_direction": format!("{:?}", swap_direction),
-                        "is_swap_base_in": is_swap_base_in,
-                        "amm_id": parsed_accounts.amm_id.to_string(),
-                        "pc_mint": pool.amm_keys.amm_pc_mint.to_string(),
-                        "amount_specified": amount_specified,
-                        "coin_mint": pool.amm_keys.amm_coin_mint.to_string(),
-                        "other_amount_threshold": other_amount_threshold,
-                        "initial_price": initial_price,
-                        "new_price": new_price,
-                    }))
-                    .unwrap()
+                    "large swap: ({} {:?}) {}",
+                    quote_amount,
+                    quote.asset,
+                    serde_json::to_string_pretty(&event).unwrap()
                 );
             }
+
+            let mint = pool.token;
+            self.swaps.push(SwapRecord {
+                signature: signature.to_string(),
+                signer: *signer,
+                amm_id: parsed_accounts.amm_id,
+                mint,
+                // a buy if the quote goes in
+                is_buy: coin_in == quote.is_coin,
+                quote: quote.asset,
+                quote_amount,
+            });
+            drop(pool);
+            return self
+                .check_arbitrage_opportunity(&mint, signature, slot)
+                .await;
+        }
+        None
+    }
+
+    /// prices_of is the SOL price of the mint in each of its tracked pools
+    /// quoted in SOL, with the SOL liquidity of the pool and the moving
+    /// average of the price; the pools not
+    /// updated within `max_age` are left out if it is set
+    async fn prices_of(
+        &self,
+        mint: &Pubkey,
+        max_age: Option<Duration>,
+    ) -> Vec<(Pubkey, f64, u64, Option<f64>)> {
+        let mut prices = Vec::new();
+        let Some(amm_ids) = self.raydium_pools_by_mint.get(mint) else {
+            return prices;
+        };
+        for amm_id in amm_ids {
+            let Some(pool) = self.raydium_pools.get(amm_id) else {
+                continue;
+            };
+            let pool = pool.read().await;
+            if max_age.is_some_and(|max_age| pool.is_stale(max_age)) {
+                continue;
+            }
+            let Some(price) = pool.price_in_sol() else {
+                continue;
+            };
+            let liquidity = if pool.quote().is_coin {
+                pool.state.pool_coin_vault_amount
+            } else {
+                pool.state.pool_pc_vault_amount
+            };
+            let ema_price = pool.ema_price.map(|ema| ema.value);
+            prices.push((*amm_id, price, liquidity, ema_price));
         }
+        prices
     }
 
-    fn _check_arbitrage_opportunity(
+    /// price_of is the SOL price of the mint in its deepest SOL pool, None
+    /// if none of its pools are tracked
+    pub async fn price_of(&self, mint: &Pubkey) -> Option<f64> {
+        self.prices_of(mint, None)
+            .await
+            .into_iter()
+            .max_by_key(|(_, _, liquidity, _)| *liquidity)
+            .map(|(_, price, _, _)| price)
+    }
+
+    /// ema_price_of is the moving average of the SOL price of the mint in
+    /// its deepest SOL pool, None until a swap is seen on that pool
+    pub async fn ema_price_of(&self, mint: &Pubkey) -> Option<f64> {
+        self.prices_of(mint, None)
+            .await
+            .into_iter()
+            .max_by_key(|(_, _, liquidity, _)| *liquidity)
+            .and_then(|(_, _, _, ema_price)| ema_price)
+    }
+
+    /// fresh_price_of is price_of over the pools updated within `max_age`,
+    /// None if all of the pools of the mint are stale
+    pub async fn fresh_price_of(
         &self,
-        _pool: RaydiumAmmPool,
+        mint: &Pubkey,
+        max_age: Duration,
     ) -> Option<f64> {
-        None
+        self.prices_of(mint, Some(max_age))
+            .await
+            .into_iter()
+            .max_by_key(|(_, _, liquidity, _)| *liquidity)
+            .map(|(_, price, _, _)| price)
+    }
+
+    /// best_price is the pool with the lowest SOL price of the mint, that
+    /// is the cheapest to buy it in, and the price
+    pub async fn best_price(&self, mint: &Pubkey) -> Option<(Pubkey, f64)> {
+        self.prices_of(mint, None)
+            .await
+            .into_iter()
+            .map(|(amm_id, price, _, _)| (amm_id, price))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// check_arbitrage_opportunity compares the SOL prices of the pools of
+    /// the mint updated within MAX_ARB_PRICE_AGE and returns the widest
+    /// spread if it is worth it
+    async fn check_arbitrage_opportunity(
+        &self,
+        mint: &Pubkey,
+        signature: &Signature,
+        slot: Slot,
+    ) -> Option<ArbOpportunity> {
+        let prices = self
+            .prices_of(mint, Some(MAX_ARB_PRICE_AGE))
+            .await
+            .into_iter()
+            .map(|(amm_id, price, _, _)| (amm_id, price))
+            .collect::<Vec<_>>();
+
+        let (buy_pool, buy_price) =
+            prices.iter().copied().min_by(|a, b| a.1.total_cmp(&b.1))?;
+        let (sell_pool, sell_price) =
+            prices.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let spread = sell_price / buy_price - 1.;
+        if buy_pool == sell_pool
+            || !spread.is_finite()
+            || spread < MIN_ARB_SPREAD
+        {
+            return None;
+        }
+
+        Some(ArbOpportunity {
+            trigger_signature: signature.to_string(),
+            slot,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            mint: *mint,
+            buy_pool,
+            sell_pool,
+            buy_price,
+            sell_price,
+            amount_in: ARB_TRADE_SIZE,
+            expected_profit: (ARB_TRADE_SIZE as f64 * spread) as i64,
+        })
+    }
+}
+
+/// LargeSwapEvent is a Raydium swap over the large swap threshold of its
+/// quote, the prices are of the token in the quote before and after it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename = "Swap")]
+pub struct LargeSwapEvent {
+    pub signature: String,
+    pub slot: Slot,
+    pub timestamp: i64,
+    pub swap_direction: String,
+    pub is_swap_base_in: bool,
+    pub amm_id: String,
+    pub pc_mint: String,
+    pub amount_specified: u64,
+    pub coin_mint: String,
+    pub other_amount_threshold: u64,
+    pub quote: QuoteAsset,
+    pub quote_mint: String,
+    pub quote_amount: f64,
+    pub initial_price: Option<f64>,
+    pub new_price: Option<f64>,
+}
+
+/// Swaps above these (in UI units of the quote) are logged, there is no
+/// threshold for quotes other than WSOL and USDC as their value is unknown
+pub const LARGE_SWAP_SOL: f64 = 10.;
+pub const LARGE_SWAP_USDC: f64 = 2_000.;
+
+pub fn is_large_swap(quote: QuoteAsset, quote_amount: f64) -> bool {
+    match quote {
+        QuoteAsset::Wsol => quote_amount > LARGE_SWAP_SOL,
+        QuoteAsset::Usdc => quote_amount > LARGE_SWAP_USDC,
+        QuoteAsset::Other => false,
+    }
+}
+
+/// swap_quote_amount is the raw amount of the quote side of the swap, the
+/// amount specified (the input on either base) if the quote goes in and the
+/// threshold otherwise
+pub fn swap_quote_amount(
+    quote_is_coin: bool,
+    amount_specified: u64,
+    other_amount_threshold: u64,
+    coin_in: bool,
+) -> u64 {
+    if quote_is_coin == coin_in {
+        amount_specified
+    } else {
+        other_amount_threshold
     }
 }
 
+/// get_account_key_safely is the account at `account_index` of the
+/// instruction, `account_keys` has to include the accounts loaded from
+/// lookup tables for V0 txs (see alt::resolve_account_keys)
 pub fn get_account_key_safely(
-    message: &VersionedMessage,
+    account_keys: &[Pubkey],
     instruction: &CompiledInstruction,
     account_index: usize,
 ) -> Option<Pubkey> {
     instruction
         .accounts
         .get(account_index)
-        .and_then(|&index| message.static_account_keys().get(index as usize))
+        .and_then(|&index| account_keys.get(index as usize))
         .copied()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raydium::detect_quote;
+
+    fn reserves(pc_vault_amount: u64) -> PoolReserves {
+        PoolReserves {
+            pc_vault_amount,
+            coin_vault_amount: 1_000_000_000_000,
+            swap_fee_numerator: 25,
+            swap_fee_denominator: 10_000,
+            sol_is_coin: false,
+        }
+    }
+
+    #[test]
+    fn registers_new_pool_from_initialize2() {
+        let accounts =
+            (0..21).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let mut data = vec![1, 254];
+        data.extend_from_slice(&1_700_000_000u64.to_le_bytes()); // open time
+        data.extend_from_slice(&(79 * LAMPORTS_PER_SOL).to_le_bytes()); // pc
+        data.extend_from_slice(&206_900_000_000_000u64.to_le_bytes()); // coin
+        let mut account_keys = accounts.clone();
+        account_keys[INIT_PC_MINT] = *constants::WSOL_PUBKEY;
+        account_keys.push(*constants::RAYDIUM_AMM_PUBKEY);
+        let instruction = CompiledInstruction {
+            program_id_index: 21,
+            accounts: (0..21).collect(),
+            data,
+        };
+        let Ok(ParsedAmmInstruction::Initialize2(initialize)) =
+            parse_amm_instruction(&instruction.data)
+        else {
+            panic!("not an Initialize2");
+        };
+        let event = parse_new_pool(
+            &initialize,
+            &instruction,
+            &account_keys,
+            &Signature::default(),
+            42,
+        )
+        .unwrap();
+        assert_eq!(event.amm_id, account_keys[INIT_AMM_ID]);
+        assert_eq!(event.coin_mint, account_keys[INIT_COIN_MINT]);
+        assert_eq!(event.pc_mint, *constants::WSOL_PUBKEY);
+        assert_eq!(event.open_time, 1_700_000_000);
+        assert_eq!(event.slot, 42);
+        assert_eq!(event.init_pc_amount, 79 * LAMPORTS_PER_SOL);
+
+        let mut pools_state = PoolsState::default();
+        pools_state.register_new_pool(event.clone());
+        pools_state.register_new_pool(event);
+        assert_eq!(pools_state.take_new_pools().len(), 2);
+        assert!(pools_state.new_pools.is_empty());
+        let token = account_keys[INIT_COIN_MINT];
+        assert_eq!(
+            pools_state.raydium_pools_by_mint[&token],
+            vec![account_keys[INIT_AMM_ID]]
+        );
+        let pool = pools_state.raydium_pools[&account_keys[INIT_AMM_ID]]
+            .try_read()
+            .unwrap()
+            .clone();
+        assert_eq!(pool.token, token);
+        // 79 SOL against 206,900,000 tokens of 6 decimals
+        let price = pool.price_in_sol().unwrap();
+        assert!((price - 79. / 206_900_000.).abs() < 1e-15);
+
+        // missing accounts
+        let truncated = CompiledInstruction {
+            accounts: (0..10).collect(),
+            ..instruction
+        };
+        assert!(parse_new_pool(
+            &initialize,
+            &truncated,
+            &account_keys,
+            &Signature::default(),
+            42,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn pool_loads_are_requested_once_per_retry_interval() {
+        let (pool_load_tx, mut pool_load_rx) = mpsc::channel(10);
+        let mut pools_state = PoolsState {
+            pool_load_tx: Some(pool_load_tx),
+            ..Default::default()
+        };
+        let (pool_id, other_pool_id) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+
+        pools_state.request_pool_load(PoolLoadRequest::RaydiumAmm(pool_id));
+        pools_state.request_pool_load(PoolLoadRequest::RaydiumAmm(pool_id));
+        assert_eq!(
+            pool_load_rx.try_recv().unwrap(),
+            PoolLoadRequest::RaydiumAmm(pool_id)
+        );
+        assert!(pool_load_rx.try_recv().is_err());
+
+        // requested again once the interval passed, the expired requests
+        // are pruned as another pool is queued
+        let expired = Instant::now()
+            .checked_sub(POOL_LOAD_RETRY_INTERVAL + Duration::from_secs(1))
+            .unwrap();
+        pools_state.pool_load_requests.insert(pool_id, expired);
+        pools_state
+            .request_pool_load(PoolLoadRequest::RaydiumAmm(other_pool_id));
+        assert_eq!(pools_state.pool_load_requests.len(), 1);
+        pools_state.request_pool_load(PoolLoadRequest::RaydiumAmm(pool_id));
+        assert_eq!(
+            pool_load_rx.try_recv().unwrap(),
+            PoolLoadRequest::RaydiumAmm(other_pool_id)
+        );
+        assert_eq!(
+            pool_load_rx.try_recv().unwrap(),
+            PoolLoadRequest::RaydiumAmm(pool_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn clmm_swaps_queue_the_pool_loads() {
+        use crate::raydium_clmm::CLMM_SWAP_DISCRIMINATOR;
+        use solana_sdk::hash::Hash;
+        use solana_sdk::message::{Message, MessageHeader, VersionedMessage};
+
+        let (pool_load_tx, mut pool_load_rx) = mpsc::channel(10);
+        let mut pools_state = PoolsState {
+            pool_load_tx: Some(pool_load_tx),
+            ..Default::default()
+        };
+        // a `swap`, without the mints, the pool state at 2 and the vaults
+        // at 5 and 6
+        let mut account_keys =
+            (0..7).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        account_keys.push(*constants::RAYDIUM_CLMM_PUBKEY);
+        let pool_id = account_keys[2];
+        let mut data = CLMM_SWAP_DISCRIMINATOR.to_vec();
+        data.extend(
+            borsh::to_vec(&ClmmSwapIx {
+                amount: 1_000_000,
+                other_amount_threshold: 0,
+                sqrt_price_limit_x64: 0,
+                is_base_input: true,
+            })
+            .unwrap(),
+        );
+        let tx = Arc::new(VersionedTransaction {
+            signatures: vec![Signature::new_unique()],
+            message: VersionedMessage::Legacy(Message {
+                header: MessageHeader {
+                    num_required_signatures: 1,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 1,
+                },
+                account_keys: account_keys.clone(),
+                recent_blockhash: Hash::default(),
+                instructions: vec![CompiledInstruction {
+                    program_id_index: 7,
+                    accounts: (0..7).collect(),
+                    data,
+                }],
+            }),
+        });
+
+        pools_state
+            .reduce_raydium_clmm_tx(tx.clone(), &account_keys)
+            .await;
+        assert_eq!(pools_state.raydium_clmm_unregistered_swaps, 1);
+        assert!(pools_state.raydium_clmm_pools.is_empty());
+        assert_eq!(
+            pool_load_rx.try_recv().unwrap(),
+            PoolLoadRequest::RaydiumClmm(pool_id)
+        );
+
+        // once loaded, the swaps go on the pool, with its price
+        pools_state.insert_raydium_clmm_pool(RaydiumClmmPool {
+            pool_id,
+            vault_0: account_keys[5],
+            vault_1: account_keys[6],
+            decimals: Some((9, 6)),
+            sqrt_price_x64: 1u128 << 64,
+            ..Default::default()
+        });
+        pools_state.reduce_raydium_clmm_tx(tx, &account_keys).await;
+        assert_eq!(pools_state.raydium_clmm_unregistered_swaps, 1);
+        let pool = &pools_state.raydium_clmm_pools[&pool_id];
+        assert_eq!(pool.swaps_down, 1);
+        assert!(pool.price().is_some());
+        assert!(pool_load_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn clmm_pools_are_capped() {
+        let mut pools_state = PoolsState::default();
+        let pool = |swapped: bool| RaydiumClmmPool {
+            pool_id: Pubkey::new_unique(),
+            last_swap_at: swapped.then(Instant::now),
+            ..Default::default()
+        };
+        let idle = pool(false);
+        let idle_id = idle.pool_id;
+        pools_state.insert_raydium_clmm_pool(idle);
+        for _ in 1..MAX_RAYDIUM_CLMM_POOLS {
+            pools_state.insert_raydium_clmm_pool(pool(true));
+        }
+        assert_eq!(
+            pools_state.raydium_clmm_pools.len(),
+            MAX_RAYDIUM_CLMM_POOLS
+        );
+
+        let new = pool(true);
+        let new_id = new.pool_id;
+        pools_state.insert_raydium_clmm_pool(new);
+        assert_eq!(
+            pools_state.raydium_clmm_pools.len(),
+            MAX_RAYDIUM_CLMM_POOLS
+        );
+        assert!(pools_state.raydium_clmm_pools.contains_key(&new_id));
+        assert!(!pools_state.raydium_clmm_pools.contains_key(&idle_id));
+    }
+
+    fn amm_keys() -> AmmKeys {
+        AmmKeys {
+            amm_pool: Pubkey::new_unique(),
+            amm_coin_mint: Pubkey::new_unique(),
+            amm_pc_mint: *constants::WSOL_PUBKEY,
+            amm_authority: Pubkey::new_unique(),
+            amm_target: Pubkey::new_unique(),
+            amm_coin_vault: Pubkey::new_unique(),
+            amm_pc_vault: Pubkey::new_unique(),
+            amm_lp_mint: Pubkey::new_unique(),
+            amm_open_order: Pubkey::new_unique(),
+            market_program: Pubkey::new_unique(),
+            market: Pubkey::new_unique(),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn fee_schedule_falls_back_when_fees_are_unknown() {
+        let event = NewRaydiumPoolEvent {
+            signature: Signature::default().to_string(),
+            slot: 0,
+            timestamp: 0,
+            amm_id: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: *constants::WSOL_PUBKEY,
+            init_coin_amount: 1_000_000_000_000,
+            init_pc_amount: 100 * LAMPORTS_PER_SOL,
+            open_time: 0,
+            idempotency_key: String::new(),
+            amm_keys: amm_keys(),
+        };
+        let mut pool = event.pool();
+        assert_eq!(pool.state.swap_fee_denominator, 0);
+
+        let mut fee_schedule = FeeSchedule::default();
+        assert_eq!(fee_schedule.fee_for(&pool), Fee::from_bps(25));
+        // the fallback keeps the simulation from dividing by zero
+        let reserves = PoolReserves::new(&pool, fee_schedule.fee_for(&pool));
+        let tokens = reserves.buy(LAMPORTS_PER_SOL);
+        assert!(tokens > 0);
+        let pricier = PoolReserves::new(&pool, Fee::from_bps(100))
+            .buy(LAMPORTS_PER_SOL);
+        assert!(pricier < tokens);
+
+        pool.state.swap_fee_numerator = 30;
+        pool.state.swap_fee_denominator = 10_000;
+        assert_eq!(fee_schedule.fee_for(&pool), Fee::from_bps(30));
+
+        fee_schedule
+            .overrides
+            .insert(pool.amm_keys.amm_pool, Fee::from_bps(100));
+        assert_eq!(fee_schedule.fee_for(&pool), Fee::from_bps(100));
+    }
+
+    #[tokio::test]
+    async fn prices_across_pools_of_a_mint() {
+        let event = NewRaydiumPoolEvent {
+            signature: Signature::default().to_string(),
+            slot: 0,
+            timestamp: 0,
+            amm_id: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: *constants::WSOL_PUBKEY,
+            init_coin_amount: 1_000_000_000_000,
+            init_pc_amount: 100 * LAMPORTS_PER_SOL,
+            open_time: 0,
+            idempotency_key: String::new(),
+            amm_keys: amm_keys(),
+        };
+        let mut pools_state = PoolsState::default();
+        assert_eq!(pools_state.price_of(&event.coin_mint).await, None);
+
+        // 1,000,000 tokens of 6 decimals against 100 SOL, and a shallower
+        // pool of the same mint against 50 SOL
+        let deep = event.pool();
+        let mut shallow = event.pool();
+        shallow.amm_keys.amm_pool = Pubkey::new_unique();
+        shallow.state.pool_pc_vault_amount = 50 * LAMPORTS_PER_SOL;
+        pools_state.insert_raydium_pool(deep.clone());
+        pools_state.insert_raydium_pool(shallow.clone());
+
+        let price = pools_state.price_of(&event.coin_mint).await.unwrap();
+        assert!((price - 1e-4).abs() < 1e-15);
+        let (amm_id, price) =
+            pools_state.best_price(&event.coin_mint).await.unwrap();
+        assert_eq!(amm_id, shallow.amm_keys.amm_pool);
+        assert!((price - 5e-5).abs() < 1e-15);
+        assert_eq!(pools_state.best_price(&Pubkey::new_unique()).await, None);
+    }
+
+    #[tokio::test]
+    async fn swaps_out_of_more_than_the_vault_are_skipped() {
+        let event = NewRaydiumPoolEvent {
+            signature: Signature::default().to_string(),
+            slot: 0,
+            timestamp: 0,
+            amm_id: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: *constants::WSOL_PUBKEY,
+            init_coin_amount: 1_000_000_000_000,
+            init_pc_amount: 100 * LAMPORTS_PER_SOL,
+            open_time: 0,
+            idempotency_key: String::new(),
+            amm_keys: amm_keys(),
+        };
+        let pool = event.pool();
+        let parsed_accounts = ParsedAccounts {
+            amm_id: pool.amm_keys.amm_pool,
+            pool_coin_vault: pool.amm_keys.amm_coin_vault,
+            pool_pc_vault: pool.amm_keys.amm_pc_vault,
+        };
+        let mut pools_state = PoolsState::default();
+        pools_state.insert_raydium_pool(pool);
+        let state = |pools_state: &PoolsState| {
+            let pool = pools_state.raydium_pools[&parsed_accounts.amm_id]
+                .try_read()
+                .unwrap();
+            (
+                pool.state.pool_coin_vault_amount,
+                pool.state.pool_pc_vault_amount,
+            )
+        };
+
+        // 200 SOL out of the 100 SOL vault, though less than the coin one
+        pools_state
+            .update_pool_state_swap(
+                &parsed_accounts,
+                u64::MAX,
+                200 * LAMPORTS_PER_SOL,
+                false,
+                SwapDirection::Coin2PC,
+                &Signature::default(),
+                &Pubkey::new_unique(),
+                1,
+            )
+            .await;
+        assert_eq!(
+            state(&pools_state),
+            (1_000_000_000_000, 100 * LAMPORTS_PER_SOL)
+        );
+
+        // as many tokens out is well within the coin vault
+        pools_state
+            .update_pool_state_swap(
+                &parsed_accounts,
+                u64::MAX,
+                200 * LAMPORTS_PER_SOL,
+                false,
+                SwapDirection::PC2Coin,
+                &Signature::default(),
+                &Pubkey::new_unique(),
+                1,
+            )
+            .await;
+        assert_ne!(
+            state(&pools_state),
+            (1_000_000_000_000, 100 * LAMPORTS_PER_SOL)
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_pools_are_left_out_of_fresh_prices() {
+        let event = NewRaydiumPoolEvent {
+            signature: Signature::default().to_string(),
+            slot: 7,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            amm_id: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: *constants::WSOL_PUBKEY,
+            init_coin_amount: 1_000_000_000_000,
+            init_pc_amount: 100 * LAMPORTS_PER_SOL,
+            open_time: 0,
+            idempotency_key: String::new(),
+            amm_keys: amm_keys(),
+        };
+        let max_age = Duration::from_secs(60);
+        let mut pool = event.pool();
+        assert_eq!(pool.last_update_slot, Some(7));
+        assert!(!pool.is_stale(max_age));
+
+        // last touched two minutes ago
+        pool.last_update_ms = Some(event.timestamp - 120_000);
+        assert!(pool.is_stale(max_age));
+        let mut pools_state = PoolsState::default();
+        pools_state.insert_raydium_pool(pool.clone());
+        assert!(pools_state.price_of(&event.coin_mint).await.is_some());
+        assert_eq!(
+            pools_state.fresh_price_of(&event.coin_mint, max_age).await,
+            None
+        );
+
+        // nor arbitraged against a fresh pool of the mint
+        let other_pool = NewRaydiumPoolEvent {
+            amm_keys: amm_keys(),
+            init_pc_amount: 110 * LAMPORTS_PER_SOL,
+            ..event.clone()
+        }
+        .pool();
+        pools_state.insert_raydium_pool(other_pool);
+        pool.last_update_ms =
+            Some(event.timestamp - MAX_ARB_PRICE_AGE.as_millis() as i64 - 1);
+        pools_state.insert_raydium_pool(pool.clone());
+        let signature = Signature::default();
+        assert!(pools_state
+            .check_arbitrage_opportunity(&event.coin_mint, &signature, 8)
+            .await
+            .is_none());
+
+        pool.touch(Some(8));
+        assert_eq!(pool.last_update_slot, Some(8));
+        pools_state.insert_raydium_pool(pool);
+        assert!(pools_state
+            .fresh_price_of(&event.coin_mint, max_age)
+            .await
+            .is_some());
+        assert!(pools_state
+            .check_arbitrage_opportunity(&event.coin_mint, &signature, 8)
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn pool_snapshot_round_trip() {
+        let event = NewRaydiumPoolEvent {
+            signature: Signature::default().to_string(),
+            slot: 0,
+            timestamp: 0,
+            amm_id: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: *constants::WSOL_PUBKEY,
+            init_coin_amount: 1_000_000_000_000,
+            init_pc_amount: 100 * LAMPORTS_PER_SOL,
+            open_time: 0,
+            idempotency_key: String::new(),
+            amm_keys: amm_keys(),
+        };
+        let mut pools_state = PoolsState::default();
+        pools_state.insert_raydium_pool(event.pool());
+        let path = std::env::temp_dir()
+            .join(format!("shreds-pools-{}.json", std::process::id()));
+        assert_eq!(pools_state.export_snapshot(&path).await.unwrap(), 1);
+
+        let mut restored = PoolsState::default();
+        assert_eq!(restored.load_snapshot(&path).unwrap(), 1);
+        let amm_id = event.amm_keys.amm_pool;
+        let pool = restored.raydium_pools[&amm_id].read().await.clone();
+        let expected = event.pool();
+        assert_eq!(pool.token, expected.token);
+        assert_eq!(
+            pool.amm_keys.amm_pc_vault,
+            expected.amm_keys.amm_pc_vault
+        );
+        assert_eq!(pool.decimals, expected.decimals);
+        assert_eq!(
+            pool.state.pool_pc_vault_amount,
+            expected.state.pool_pc_vault_amount
+        );
+        assert_eq!(restored.raydium_pools_by_mint[&pool.token], vec![amm_id]);
+
+        // snapshots from a newer layout are not guessed at
+        let mut snapshot: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+                .unwrap();
+        snapshot["version"] = (POOL_SNAPSHOT_VERSION + 1).into();
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+        let loaded = PoolsState::default().load_snapshot(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn usdc_quoted_swap_amount() {
+        let token = Pubkey::new_unique();
+        let quote = detect_quote(&token, &constants::USDC_PUBKEY);
+        assert_eq!(quote.asset, QuoteAsset::Usdc);
+
+        // selling the coin token for 2,500 USDC (6 decimals) on the pc side
+        let amount = swap_quote_amount(
+            quote.is_coin,
+            1_000_000_000,
+            2_500_000_000,
+            true,
+        ) as f64
+            / 1e6;
+        assert_eq!(amount, 2_500.);
+        assert!(is_large_swap(quote.asset, amount));
+        // 11 is a large swap in SOL but not in USDC
+        assert!(is_large_swap(QuoteAsset::Wsol, 11.));
+        assert!(!is_large_swap(QuoteAsset::Usdc, 11.));
+        assert!(!is_large_swap(QuoteAsset::Other, amount));
+    }
+
+    #[test]
+    fn simulate_arb_trade_rejects_same_price() {
+        let pool = reserves(100 * LAMPORTS_PER_SOL);
+        let decision =
+            simulate_arb_trade(&pool, &pool, ARB_TRADE_SIZE, 50, 0);
+        assert!(decision.projected_profit < 0);
+        assert!(!decision.accepted);
+    }
+
+    #[test]
+    fn simulate_arb_trade_accepts_spread() {
+        let buy_pool = reserves(100 * LAMPORTS_PER_SOL);
+        let sell_pool = reserves(120 * LAMPORTS_PER_SOL);
+        let decision =
+            simulate_arb_trade(&buy_pool, &sell_pool, ARB_TRADE_SIZE, 50, 0);
+        assert!(decision.min_token_amount < decision.token_amount);
+        assert!(decision.min_amount_out < decision.amount_out);
+        assert!(decision.projected_profit > 0);
+        assert!(decision.accepted);
+
+        // same trade, but requiring more profit than the spread allows
+        let decision = simulate_arb_trade(
+            &buy_pool,
+            &sell_pool,
+            ARB_TRADE_SIZE,
+            50,
+            ARB_TRADE_SIZE,
+        );
+        assert!(!decision.accepted);
+    }
+}
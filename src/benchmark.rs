@@ -1,30 +1,67 @@
 use std::collections::HashMap;
+use std::hint::black_box;
 use std::ops::Div;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
+use clap::ValueEnum;
 use futures_util::stream::StreamExt;
 use log::info;
+use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_client::rpc_config::{
     RpcTransactionLogsConfig, RpcTransactionLogsFilter,
 };
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
 
-use crate::util::env;
+use crate::constants;
+use crate::entry_processor::arb_tx_filter;
+use crate::shred_processor::reconstruct_entries;
+use crate::util::require_env;
 
 pub type Sigs = Arc<RwLock<Vec<(u64, String)>>>;
 
+/// BenchmarkCommitment is the commitment the pubsub baseline is subscribed
+/// at, `processed` is the closest to the shreds, `confirmed` is what most
+/// RPC users go by
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchmarkCommitment {
+    #[default]
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<BenchmarkCommitment> for CommitmentConfig {
+    fn from(commitment: BenchmarkCommitment) -> Self {
+        match commitment {
+            BenchmarkCommitment::Processed => CommitmentConfig::processed(),
+            BenchmarkCommitment::Confirmed => CommitmentConfig::confirmed(),
+            BenchmarkCommitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
 pub async fn listen_pubsub(
     pubkeys: Vec<String>,
     sigs: Sigs,
+    commitment: BenchmarkCommitment,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let pubsub_client = PubsubClient::new(&env("WS_URL")).await?;
+    info!("Subscribing to pubsub logs at {:?} commitment", commitment);
+    let pubsub_client =
+        PubsubClient::new(&require_env("WS_URL", "pubsub")?).await?;
     let (mut stream, unsub) = pubsub_client
         .logs_subscribe(
             RpcTransactionLogsFilter::Mentions(pubkeys),
             RpcTransactionLogsConfig {
-                commitment: Some(CommitmentConfig::processed()),
+                commitment: Some(commitment.into()),
             },
         )
         .await?;
@@ -41,42 +78,279 @@ pub async fn listen_pubsub(
     Ok(())
 }
 
+/// BenchmarkSummary is how the shreds fared against the pubsub baseline on
+/// the signatures seen by both, the leads are in ms, positive when the
+/// shreds were ahead
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BenchmarkSummary {
+    pub pubsub_sigs: usize,
+    pub shreds_sigs: usize,
+    /// Signatures seen by both
+    pub overlapping: usize,
+    /// Signatures seen by pubsub only
+    pub miss_count: usize,
+    pub slower_count: usize,
+    pub faster_count: usize,
+    /// Signatures the shreds were ahead on by more than the threshold
+    pub win_count: usize,
+    pub win_threshold_ms: u64,
+    /// None without overlapping signatures, as are the leads
+    pub win_rate: Option<f64>,
+    pub average_lead_ms: Option<f64>,
+    pub median_lead_ms: Option<f64>,
+}
+
+/// compare_results matches up the signatures of both sources, the shreds
+/// only count as winning a signature if they beat pubsub by more than
+/// `win_threshold_ms`
 pub fn compare_results(
     pubsub_sigs: Vec<(u64, String)>,
     shreds_sigs: Vec<(u64, String)>,
-) {
-    let mut miss_count = 0;
-    let mut slower_count = 0;
-    let mut faster_count = 0;
+    win_threshold_ms: u64,
+) -> BenchmarkSummary {
+    let mut summary = BenchmarkSummary {
+        pubsub_sigs: pubsub_sigs.len(),
+        shreds_sigs: shreds_sigs.len(),
+        win_threshold_ms,
+        ..Default::default()
+    };
     let mut shreds_sigs_map: HashMap<String, u64> = HashMap::new();
     for (timestamp, sig) in shreds_sigs.iter() {
         shreds_sigs_map.insert(sig.clone(), *timestamp);
     }
 
-    let mut average_diff = 0f64;
-    let mut count = 0;
-
+    let mut leads = Vec::new();
     for (pubsub_timestamp, sig) in pubsub_sigs.iter() {
         if let Some(shreds_timestamp) = shreds_sigs_map.remove(sig) {
-            let diff = shreds_timestamp as f64 - *pubsub_timestamp as f64;
-            info!("{} diff: {}", sig, diff);
-            average_diff += diff;
-            count += 1;
+            let lead = *pubsub_timestamp as f64 - shreds_timestamp as f64;
+            info!("{} lead: {}", sig, lead);
+            leads.push(lead);
             match shreds_timestamp.cmp(pubsub_timestamp) {
                 std::cmp::Ordering::Equal => {}
-                std::cmp::Ordering::Less => faster_count += 1,
-                std::cmp::Ordering::Greater => slower_count += 1,
+                std::cmp::Ordering::Less => summary.faster_count += 1,
+                std::cmp::Ordering::Greater => summary.slower_count += 1,
+            }
+            if lead > win_threshold_ms as f64 {
+                summary.win_count += 1;
             }
         } else {
-            miss_count += 1;
+            summary.miss_count += 1;
         }
     }
 
+    summary.overlapping = leads.len();
+    if !leads.is_empty() {
+        leads.sort_by(|a, b| a.total_cmp(b));
+        let mid = leads.len() / 2;
+        summary.median_lead_ms = Some(if leads.len() % 2 == 0 {
+            (leads[mid - 1] + leads[mid]) / 2.
+        } else {
+            leads[mid]
+        });
+        summary.average_lead_ms =
+            Some(leads.iter().sum::<f64>().div(leads.len() as f64));
+        summary.win_rate =
+            Some(summary.win_count as f64 / leads.len() as f64);
+    }
+
     info!("Benchmark results:");
-    info!("Pubsub sigs: {}", pubsub_sigs.len());
-    info!("Shreds sigs: {}", shreds_sigs.len());
-    info!("Miss count: {}", miss_count);
-    info!("Slower count: {}", slower_count);
-    info!("Faster count: {}", faster_count);
-    info!("Average diff: {}", average_diff.div(count as f64));
+    info!("Pubsub sigs: {}", summary.pubsub_sigs);
+    info!("Shreds sigs: {}", summary.shreds_sigs);
+    info!("Miss count: {}", summary.miss_count);
+    info!("Slower count: {}", summary.slower_count);
+    info!("Faster count: {}", summary.faster_count);
+    match (
+        summary.win_rate,
+        summary.average_lead_ms,
+        summary.median_lead_ms,
+    ) {
+        (Some(win_rate), Some(average_lead), Some(median_lead)) => {
+            info!("Average lead: {:.1}ms", average_lead);
+            info!(
+                "Verdict: shreds ahead by >{}ms on {}/{} ({:.1}%), median lead {:.1}ms",
+                win_threshold_ms,
+                summary.win_count,
+                summary.overlapping,
+                win_rate * 100.,
+                median_lead
+            );
+        }
+        _ => info!("Verdict: no overlapping signatures to compare"),
+    }
+    summary
+}
+
+/// How many times each filter goes over the txs of the capture, enough for
+/// the timings of small captures to settle
+pub const PREFILTER_ROUNDS: usize = 100;
+
+/// PrefilterSummary is the time the arb processor spends deciding whether
+/// to look into a tx, with the programs parsed off of their strings per tx
+/// as it used to (`before`) and with arb_tx_filter (`after`); the filters
+/// run on a single thread without waiting on anything, so the time is CPU
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PrefilterSummary {
+    pub transactions: usize,
+    /// Txs let through, the same for both filters
+    pub passed: usize,
+    pub rounds: usize,
+    pub before_ns_per_tx: f64,
+    pub after_ns_per_tx: f64,
+}
+
+/// parse_per_tx_filter is the check the arb processor made before the
+/// prefilter, every program of interest parsed again for each tx
+fn parse_per_tx_filter(tx: &VersionedTransaction) -> bool {
+    [
+        constants::WHIRLPOOL,
+        constants::RAYDIUM_CP,
+        constants::RAYDIUM_AMM,
+        constants::RAYDIUM_CLMM,
+        constants::JUPITER_V6,
+    ]
+    .iter()
+    .any(|program| {
+        tx.message.static_account_keys().contains(
+            &Pubkey::from_str(program).expect("Failed to parse pubkey"),
+        )
+    })
+}
+
+/// compare_prefilter runs both filters over the txs `rounds` times each
+pub fn compare_prefilter(
+    txs: &[VersionedTransaction],
+    rounds: usize,
+) -> PrefilterSummary {
+    let time = |filter: fn(&VersionedTransaction) -> bool| {
+        let started_at = Instant::now();
+        let mut passed = 0;
+        for _ in 0..rounds {
+            passed = txs.iter().filter(|tx| black_box(filter(tx))).count();
+        }
+        let elapsed = started_at.elapsed().as_nanos() as f64;
+        (passed, elapsed / (txs.len() * rounds).max(1) as f64)
+    };
+    let (passed_before, before_ns_per_tx) = time(parse_per_tx_filter);
+    let (passed, after_ns_per_tx) = time(arb_tx_filter);
+    assert_eq!(passed, passed_before, "the filters disagree");
+    PrefilterSummary {
+        transactions: txs.len(),
+        passed,
+        rounds,
+        before_ns_per_tx,
+        after_ns_per_tx,
+    }
+}
+
+/// run_prefilter replays the capture (a packets.json-style array of
+/// packets) and prints the PrefilterSummary of its txs, as JSON with `json`
+pub async fn run_prefilter(
+    path: &str,
+    json: bool,
+) -> Result<PrefilterSummary, Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string(path)?;
+    let raw_shreds: Vec<Vec<u8>> = serde_json::from_str(&data)?;
+    let txs = reconstruct_entries(raw_shreds)
+        .await
+        .into_iter()
+        .flat_map(|entries_with_meta| entries_with_meta.entries)
+        .flat_map(|entry| entry.transactions)
+        .collect::<Vec<_>>();
+    let summary = compare_prefilter(&txs, PREFILTER_ROUNDS);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        println!(
+            "{} txs, {} passed, {} rounds: {:.1}ns/tx before, {:.1}ns/tx \
+             after ({:.1}x)",
+            summary.transactions,
+            summary.passed,
+            summary.rounds,
+            summary.before_ns_per_tx,
+            summary.after_ns_per_tx,
+            summary.before_ns_per_tx / summary.after_ns_per_tx,
+        );
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sigs(sigs: &[(u64, &str)]) -> Vec<(u64, String)> {
+        sigs.iter()
+            .map(|(timestamp, sig)| (*timestamp, sig.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn summarizes_the_leads() {
+        let summary = compare_results(
+            sigs(&[
+                (100, "a"),
+                (100, "b"),
+                (100, "c"),
+                (100, "d"),
+                (100, "e"),
+            ]),
+            sigs(&[(50, "a"), (95, "b"), (100, "c"), (120, "d"), (1, "f")]),
+            10,
+        );
+        assert_eq!(
+            summary,
+            BenchmarkSummary {
+                pubsub_sigs: 5,
+                shreds_sigs: 5,
+                overlapping: 4,
+                miss_count: 1,
+                slower_count: 1,
+                faster_count: 2,
+                win_count: 1,
+                win_threshold_ms: 10,
+                win_rate: Some(0.25),
+                average_lead_ms: Some(8.75),
+                median_lead_ms: Some(2.5),
+            }
+        );
+    }
+
+    #[test]
+    fn no_overlapping_signatures() {
+        let summary =
+            compare_results(sigs(&[(100, "a")]), sigs(&[(50, "b")]), 0);
+        assert_eq!(summary.overlapping, 0);
+        assert_eq!(summary.miss_count, 1);
+        assert_eq!(summary.win_rate, None);
+        assert_eq!(summary.average_lead_ms, None);
+        assert_eq!(summary.median_lead_ms, None);
+    }
+
+    #[tokio::test]
+    async fn prefilter_lets_through_what_the_per_tx_parse_did() {
+        let raw_shreds = crate::shred::load_fixture();
+        let mut txs = reconstruct_entries(raw_shreds)
+            .await
+            .into_iter()
+            .flat_map(|entries_with_meta| entries_with_meta.entries)
+            .flat_map(|entry| entry.transactions)
+            .collect::<Vec<_>>();
+        // the fixture is all transfers, one of them made to call the AMM
+        txs[0].message = solana_sdk::message::VersionedMessage::Legacy(
+            solana_sdk::message::Message::new(
+                &[solana_sdk::instruction::Instruction::new_with_bytes(
+                    *constants::RAYDIUM_AMM_PUBKEY,
+                    &[],
+                    vec![],
+                )],
+                None,
+            ),
+        );
+
+        let summary = compare_prefilter(&txs, 1);
+        assert_eq!(summary.transactions, txs.len());
+        assert_eq!(summary.passed, 1);
+        assert!(summary.before_ns_per_tx > 0.);
+        assert!(summary.after_ns_per_tx > 0.);
+    }
 }
@@ -0,0 +1,214 @@
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::time::{sleep, Duration};
+
+use crate::sink::{emit, Sink, CIRCUIT_BREAKER_TOPIC};
+
+pub const DEFAULT_MAX_CONSECUTIVE_LOSSES: usize = 5;
+pub const DEFAULT_LOSS_WINDOW_SECS: u64 = 600;
+/// How often the status of a sent trade is checked
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// A trade not landed by then expired with its blockhash, it cost nothing
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// CircuitBreakerConfig is the number of losses in a row, all within
+/// `window`, after which the live trading stops
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerConfig {
+    pub max_consecutive_losses: usize,
+    pub window: Duration,
+}
+
+/// CircuitBreaker guards the submission of the live trades, a loss is a
+/// trade which failed to send or reverted on-chain (the legs carry the
+/// thresholds of the trade decision, so an unprofitable trade reverts); once
+/// tripped the trades are only paper traded until it is reset
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    // the losses of the current streak, oldest first
+    losses: VecDeque<Instant>,
+    tripped: Option<CircuitBreakerEvent>,
+}
+
+/// CircuitBreakerEvent is the alert sent when the breaker trips
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerEvent {
+    /// When the breaker tripped, unix millis
+    pub timestamp: i64,
+    pub consecutive_losses: usize,
+    pub window_secs: u64,
+    /// What the last loss was
+    pub reason: String,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// record_loss counts a loss, returning the alert if it tripped the
+    /// breaker
+    pub fn record_loss(
+        &self,
+        reason: &str,
+        now: Instant,
+    ) -> Option<CircuitBreakerEvent> {
+        let mut state = self.state.lock().expect("breaker state");
+        while state.losses.front().is_some_and(|&loss| {
+            now.duration_since(loss) > self.config.window
+        }) {
+            state.losses.pop_front();
+        }
+        state.losses.push_back(now);
+        if state.tripped.is_some()
+            || state.losses.len() < self.config.max_consecutive_losses
+        {
+            return None;
+        }
+        let event = CircuitBreakerEvent {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            consecutive_losses: state.losses.len(),
+            window_secs: self.config.window.as_secs(),
+            reason: reason.to_string(),
+        };
+        state.tripped = Some(event.clone());
+        Some(event)
+    }
+
+    /// record_win ends the streak of losses
+    pub fn record_win(&self) {
+        self.state.lock().expect("breaker state").losses.clear();
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.state.lock().expect("breaker state").tripped.is_some()
+    }
+
+    /// reset lets the live trades through again
+    pub fn reset(&self) {
+        let mut state = self.state.lock().expect("breaker state");
+        state.losses.clear();
+        state.tripped = None;
+        info!("Circuit breaker reset, resuming live trading");
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let state = self.state.lock().expect("breaker state");
+        serde_json::json!({
+            "tripped": state.tripped,
+            "consecutive_losses": state.losses.len(),
+            "max_consecutive_losses": self.config.max_consecutive_losses,
+            "window_secs": self.config.window.as_secs(),
+        })
+    }
+}
+
+/// report_loss records the loss and alerts on the sink if it tripped the
+/// breaker
+pub async fn report_loss(
+    breaker: &CircuitBreaker,
+    sink: &Sink,
+    reason: &str,
+) {
+    warn!("Live trade loss: {}", reason);
+    if let Some(event) = breaker.record_loss(reason, Instant::now()) {
+        error!(
+            "Circuit breaker tripped after {} losses, paper trading until \
+             reset: {}",
+            event.consecutive_losses, reason
+        );
+        emit(&**sink, CIRCUIT_BREAKER_TOPIC, &event).await;
+    }
+}
+
+/// track_confirmation waits for the sent trade to land and records it on
+/// the breaker, a trade which never lands is not counted either way
+pub async fn track_confirmation(
+    rpc_client: Arc<RpcClient>,
+    signature: Signature,
+    breaker: Arc<CircuitBreaker>,
+    sink: Sink,
+) {
+    match wait_for_confirmation(&rpc_client, &signature).await {
+        Some(Ok(())) => breaker.record_win(),
+        Some(Err(e)) => {
+            report_loss(
+                &breaker,
+                &sink,
+                &format!("{} reverted: {}", signature, e),
+            )
+            .await
+        }
+        None => debug!("Trade {} did not land", signature),
+    }
+}
+
+/// wait_for_confirmation polls the status of the sent tx until it lands,
+/// None if it does not within CONFIRMATION_TIMEOUT
+pub async fn wait_for_confirmation(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+) -> Option<Result<(), TransactionError>> {
+    let started_at = Instant::now();
+    while started_at.elapsed() < CONFIRMATION_TIMEOUT {
+        sleep(CONFIRMATION_POLL_INTERVAL).await;
+        match rpc_client.get_signature_status(signature).await {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => {}
+            Err(e) => debug!("Failed to get status of {}: {}", signature, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_after_consecutive_losses_within_window() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            max_consecutive_losses: 3,
+            window: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+
+        assert!(breaker.record_loss("a", start).is_none());
+        assert!(breaker.record_loss("b", start).is_none());
+        // a win ends the streak
+        breaker.record_win();
+        assert!(breaker.record_loss("c", start).is_none());
+        assert!(breaker.record_loss("d", start).is_none());
+        // the losses out of the window do not count
+        let later = start + Duration::from_secs(61);
+        assert!(breaker.record_loss("e", later).is_none());
+        assert!(breaker.record_loss("f", later).is_none());
+        assert!(!breaker.is_tripped());
+
+        let event = breaker.record_loss("g", later).unwrap();
+        assert_eq!(event.consecutive_losses, 3);
+        assert_eq!(event.reason, "g");
+        assert!(breaker.is_tripped());
+        // the alert goes out once
+        assert!(breaker.record_loss("h", later).is_none());
+
+        breaker.reset();
+        assert!(!breaker.is_tripped());
+        assert!(breaker.record_loss("i", later).is_none());
+    }
+}
@@ -1,6 +1,69 @@
+use once_cell::sync::Lazy;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
 pub const WHIRLPOOL: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
 pub const RAYDIUM_CP: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
 pub const RAYDIUM_AMM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const RAYDIUM_CLMM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const JUPITER_V6: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVaV4";
 pub const WSOL: &str = "So11111111111111111111111111111111111111112";
+pub const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 pub const PUMP_FUN_MINT_AUTHORITY: &str =
     "TSLvdd1pWpHVjahSpsvCXUbgwsL3JAcvokwaKt1eokM";
+pub const PUMP_FUN_PROGRAM: &str =
+    "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+pub const PUMP_MIGRATION_PROGRAM: &str =
+    "39azUYFWPz3VHgKCf3VChUwbpURdCHRxjWVowf5jUJjg";
+pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const ASSOCIATED_TOKEN_PROGRAM: &str =
+    "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+
+// parsed once, the string consts above are used on the hot path otherwise
+pub static WHIRLPOOL_PUBKEY: Lazy<Pubkey> = Lazy::new(|| parse(WHIRLPOOL));
+pub static RAYDIUM_CP_PUBKEY: Lazy<Pubkey> = Lazy::new(|| parse(RAYDIUM_CP));
+pub static RAYDIUM_AMM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(RAYDIUM_AMM));
+pub static RAYDIUM_CLMM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(RAYDIUM_CLMM));
+pub static JUPITER_V6_PUBKEY: Lazy<Pubkey> = Lazy::new(|| parse(JUPITER_V6));
+pub static WSOL_PUBKEY: Lazy<Pubkey> = Lazy::new(|| parse(WSOL));
+pub static USDC_PUBKEY: Lazy<Pubkey> = Lazy::new(|| parse(USDC));
+pub static PUMP_FUN_MINT_AUTHORITY_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(PUMP_FUN_MINT_AUTHORITY));
+pub static PUMP_FUN_PROGRAM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(PUMP_FUN_PROGRAM));
+pub static PUMP_MIGRATION_PROGRAM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(PUMP_MIGRATION_PROGRAM));
+pub static TOKEN_PROGRAM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(TOKEN_PROGRAM));
+pub static ASSOCIATED_TOKEN_PROGRAM_PUBKEY: Lazy<Pubkey> =
+    Lazy::new(|| parse(ASSOCIATED_TOKEN_PROGRAM));
+
+fn parse(pubkey: &str) -> Pubkey {
+    Pubkey::from_str(pubkey).expect("Failed to parse pubkey")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_pubkeys_match_originals() {
+        for (lazy, original) in [
+            (&WHIRLPOOL_PUBKEY, WHIRLPOOL),
+            (&RAYDIUM_CP_PUBKEY, RAYDIUM_CP),
+            (&RAYDIUM_AMM_PUBKEY, RAYDIUM_AMM),
+            (&RAYDIUM_CLMM_PUBKEY, RAYDIUM_CLMM),
+            (&JUPITER_V6_PUBKEY, JUPITER_V6),
+            (&WSOL_PUBKEY, WSOL),
+            (&PUMP_FUN_MINT_AUTHORITY_PUBKEY, PUMP_FUN_MINT_AUTHORITY),
+            (&PUMP_FUN_PROGRAM_PUBKEY, PUMP_FUN_PROGRAM),
+            (&PUMP_MIGRATION_PROGRAM_PUBKEY, PUMP_MIGRATION_PROGRAM),
+            (&TOKEN_PROGRAM_PUBKEY, TOKEN_PROGRAM),
+            (&ASSOCIATED_TOKEN_PROGRAM_PUBKEY, ASSOCIATED_TOKEN_PROGRAM),
+        ] {
+            assert_eq!(**lazy, Pubkey::from_str(original).unwrap());
+        }
+    }
+}
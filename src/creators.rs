@@ -0,0 +1,159 @@
+use log::{error, info};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// CreatorFilter decides which creators the pump events are emitted for,
+/// with an allow list only those creators pass, with a deny list everyone
+/// but those creators, the lists are files of one pubkey per line
+#[derive(Debug)]
+pub struct CreatorFilter {
+    allow_path: Option<PathBuf>,
+    deny_path: Option<PathBuf>,
+    lists: RwLock<CreatorLists>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CreatorLists {
+    allow: Option<HashSet<Pubkey>>,
+    deny: HashSet<Pubkey>,
+}
+
+/// read_creators parses a list of creators, blank lines and lines starting
+/// with `#` are skipped
+pub fn read_creators(path: &Path) -> anyhow::Result<HashSet<Pubkey>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!("Failed to read {}: {}", path.display(), e)
+    })?;
+    parse_creators(&contents)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+fn parse_creators(contents: &str) -> anyhow::Result<HashSet<Pubkey>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(idx, line)| {
+            Pubkey::from_str(line).map_err(|e| {
+                anyhow::anyhow!("line {}: {} ({})", idx + 1, e, line)
+            })
+        })
+        .collect()
+}
+
+impl CreatorFilter {
+    pub fn load(
+        allow_path: Option<PathBuf>,
+        deny_path: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let filter = CreatorFilter {
+            allow_path,
+            deny_path,
+            lists: RwLock::new(CreatorLists::default()),
+        };
+        filter.reload()?;
+        Ok(filter)
+    }
+
+    /// reload re-reads the lists, the current ones are kept if either file
+    /// cannot be read
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let allow =
+            self.allow_path.as_deref().map(read_creators).transpose()?;
+        let deny = self
+            .deny_path
+            .as_deref()
+            .map(read_creators)
+            .transpose()?
+            .unwrap_or_default();
+        info!(
+            "Loaded creator filter: {} allowed, {} denied",
+            allow
+                .as_ref()
+                .map(|allow| allow.len().to_string())
+                .unwrap_or_else(|| "all".to_string()),
+            deny.len()
+        );
+        *self.lists.write().expect("creator lists") =
+            CreatorLists { allow, deny };
+        Ok(())
+    }
+
+    /// allows is whether the events of this creator are emitted
+    pub fn allows(&self, creator: &Pubkey) -> bool {
+        let lists = self.lists.read().expect("creator lists");
+        !lists.deny.contains(creator)
+            && lists
+                .allow
+                .as_ref()
+                .map_or(true, |allow| allow.contains(creator))
+    }
+}
+
+/// reload_on_hangup reloads the lists of the filter on every SIGHUP
+pub async fn reload_on_hangup(filter: Arc<CreatorFilter>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        if let Err(e) = filter.reload() {
+            error!("Failed to reload the creator filter: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_creators() {
+        let dir = std::env::temp_dir()
+            .join(format!("creators-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (allowed, denied, other) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let deny_path = dir.join("deny.txt");
+        std::fs::write(&deny_path, format!("# ruggers\n\n{}\n", denied))
+            .unwrap();
+
+        let filter =
+            CreatorFilter::load(None, Some(deny_path.clone())).unwrap();
+        assert!(filter.allows(&allowed));
+        assert!(!filter.allows(&denied));
+
+        let allow_path = dir.join("allow.txt");
+        std::fs::write(&allow_path, format!("{}\n{}\n", allowed, denied))
+            .unwrap();
+        let filter =
+            CreatorFilter::load(Some(allow_path.clone()), Some(deny_path))
+                .unwrap();
+        assert!(filter.allows(&allowed));
+        assert!(!filter.allows(&denied));
+        assert!(!filter.allows(&other));
+
+        // a broken list keeps the previous one
+        std::fs::write(&allow_path, "not a pubkey\n").unwrap();
+        assert!(filter.reload().is_err());
+        assert!(filter.allows(&allowed));
+        std::fs::write(&allow_path, format!("{}\n", other)).unwrap();
+        filter.reload().unwrap();
+        assert!(filter.allows(&other));
+        assert!(!filter.allows(&allowed));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use base64::Engine;
+use serde::Serialize;
+use solana_ledger::shred::layout;
+use std::io::Read;
+
+use crate::shred::{
+    get_coding_shred_header, get_fec_set_index, get_shred_data_flags,
+    get_shred_index, get_shred_variant, get_shred_version, is_shred_data,
+    CodingShredHeader,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Hex,
+    Base58,
+    Base64,
+}
+
+/// DecodedShred is what the header accessors read off of a raw shred, the
+/// fields which do not apply to its type are None
+#[derive(Debug, Serialize)]
+pub struct DecodedShred {
+    pub encoding: Encoding,
+    pub len: usize,
+    pub variant: String,
+    pub is_data: bool,
+    pub slot: Option<u64>,
+    pub index: Option<u32>,
+    pub fec_set_index: Option<u32>,
+    pub version: Option<u16>,
+    pub flags: Option<DataFlags>,
+    /// size of the data shred as written in its header
+    pub data_len: Option<u16>,
+    pub num_data_shreds: Option<u16>,
+    pub num_coding_shreds: Option<u16>,
+    pub position: Option<u16>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DataFlags {
+    pub block_complete: bool,
+    pub batch_complete: bool,
+    pub batch_tick: u8,
+}
+
+/// run decodes the single shred on stdin and prints its header, as JSON
+/// with `json`
+pub fn run(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let (encoding, raw_shred) = decode_input(&input)?;
+    let decoded = decode_shred(encoding, &raw_shred)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&decoded)?);
+    } else {
+        print_shred(&decoded);
+    }
+    Ok(())
+}
+
+/// decode_input detects the encoding of a pasted shred: hex if it is all
+/// hex digits, base58 if it is all base58 characters, base64 otherwise
+pub fn decode_input(
+    input: &str,
+) -> Result<(Encoding, Vec<u8>), Box<dyn std::error::Error>> {
+    let input = input
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let input = input.strip_prefix("0x").unwrap_or(&input);
+    if input.is_empty() {
+        return Err("No shred on stdin".into());
+    }
+    if input.len() % 2 == 0 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok((Encoding::Hex, hex::decode(input)?));
+    }
+    if input.chars().all(is_base58_char) {
+        return Ok((Encoding::Base58, bs58::decode(input).into_vec()?));
+    }
+    Ok((
+        Encoding::Base64,
+        base64::engine::general_purpose::STANDARD.decode(input)?,
+    ))
+}
+
+fn is_base58_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() && !matches!(c, '0' | 'O' | 'I' | 'l')
+}
+
+/// decode_shred parses the common header of the shred and the header of
+/// its type, it has to be long enough for the common header
+pub fn decode_shred(
+    encoding: Encoding,
+    raw_shred: &[u8],
+) -> Result<DecodedShred, Box<dyn std::error::Error>> {
+    let variant = get_shred_variant(raw_shred)?;
+    let is_data = is_shred_data(raw_shred);
+    let mut decoded = DecodedShred {
+        encoding,
+        len: raw_shred.len(),
+        variant: format!("{:?}", variant),
+        is_data,
+        slot: layout::get_slot(raw_shred),
+        index: get_shred_index(raw_shred).ok(),
+        fec_set_index: get_fec_set_index(raw_shred).ok(),
+        version: get_shred_version(raw_shred).ok(),
+        flags: None,
+        data_len: None,
+        num_data_shreds: None,
+        num_coding_shreds: None,
+        position: None,
+    };
+    if is_data {
+        let (block_complete, batch_complete, batch_tick) =
+            get_shred_data_flags(raw_shred);
+        decoded.flags = Some(DataFlags {
+            block_complete,
+            batch_complete,
+            batch_tick,
+        });
+        decoded.data_len = raw_shred
+            .get(0x56..0x58)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+    } else if let Ok(CodingShredHeader {
+        num_data_shreds,
+        num_coding_shreds,
+        position,
+    }) = get_coding_shred_header(raw_shred)
+    {
+        decoded.num_data_shreds = Some(num_data_shreds);
+        decoded.num_coding_shreds = Some(num_coding_shreds);
+        decoded.position = Some(position);
+    }
+    Ok(decoded)
+}
+
+pub fn print_shred(decoded: &DecodedShred) {
+    fn or_dash<T: ToString>(value: Option<T>) -> String {
+        value
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+    println!("{:<20} {:?}", "encoding", decoded.encoding);
+    println!("{:<20} {}", "length", decoded.len);
+    println!("{:<20} {}", "variant", decoded.variant);
+    println!(
+        "{:<20} {}",
+        "type",
+        if decoded.is_data { "data" } else { "coding" }
+    );
+    println!("{:<20} {}", "slot", or_dash(decoded.slot));
+    println!("{:<20} {}", "index", or_dash(decoded.index));
+    println!("{:<20} {}", "fec set index", or_dash(decoded.fec_set_index));
+    println!("{:<20} {}", "version", or_dash(decoded.version));
+    if let Some(flags) = &decoded.flags {
+        println!("{:<20} {}", "block complete", flags.block_complete);
+        println!("{:<20} {}", "batch complete", flags.batch_complete);
+        println!("{:<20} {}", "batch tick", flags.batch_tick);
+        println!("{:<20} {}", "data length", or_dash(decoded.data_len));
+    } else {
+        println!(
+            "{:<20} {}",
+            "num data shreds",
+            or_dash(decoded.num_data_shreds)
+        );
+        println!(
+            "{:<20} {}",
+            "num coding shreds",
+            or_dash(decoded.num_coding_shreds)
+        );
+        println!("{:<20} {}", "position", or_dash(decoded.position));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shred::shred_entries;
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+
+    #[test]
+    fn decodes_shred_in_any_encoding() {
+        let entries = vec![Entry::new(&Hash::default(), 1, vec![])];
+        let (data_shreds, coding_shreds) =
+            shred_entries(42, &entries, true, 0, 0, true);
+        let raw_shred = data_shreds[0].payload().clone();
+
+        for (encoding, input) in [
+            (Encoding::Hex, hex::encode(&raw_shred)),
+            (Encoding::Base58, bs58::encode(&raw_shred).into_string()),
+            (
+                Encoding::Base64,
+                base64::engine::general_purpose::STANDARD.encode(&raw_shred),
+            ),
+        ] {
+            let (detected, decoded) =
+                decode_input(&format!("{}\n", input)).unwrap();
+            assert_eq!(detected, encoding);
+            assert_eq!(decoded, raw_shred);
+        }
+
+        let decoded = decode_shred(Encoding::Hex, &raw_shred).unwrap();
+        assert!(decoded.is_data);
+        assert_eq!(decoded.slot, Some(42));
+        assert_eq!(decoded.index, Some(0));
+        assert_eq!(decoded.fec_set_index, Some(0));
+        let last_shred = data_shreds.last().unwrap().payload();
+        let decoded = decode_shred(Encoding::Hex, last_shred).unwrap();
+        assert!(decoded.flags.unwrap().block_complete);
+
+        let coding_shred = coding_shreds[0].payload();
+        let decoded = decode_shred(Encoding::Hex, coding_shred).unwrap();
+        assert!(!decoded.is_data);
+        assert_eq!(decoded.num_data_shreds, Some(data_shreds.len() as u16));
+        assert!(decode_input(" \n").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use log::{debug, error};
+use solana_entry::entry::Entry;
+use solana_sdk::signature::Signature;
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::entry_processor::EntriesWithMeta;
+
+/// Signatures remembered at most, the oldest are forgotten first
+pub const DEFAULT_DEDUP_MAX_SIGNATURES: usize = 100_000;
+/// Longest a signature is remembered for
+pub const DEFAULT_DEDUP_MAX_AGE_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupConfig {
+    pub max_signatures: usize,
+    pub max_age: Duration,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            max_signatures: DEFAULT_DEDUP_MAX_SIGNATURES,
+            max_age: Duration::from_secs(DEFAULT_DEDUP_MAX_AGE_SECS),
+        }
+    }
+}
+
+/// TxDedup is the window of the transaction signatures seen, bounded by
+/// count and age; unlike the shred uniqueness check it catches the same tx
+/// coming out of two FEC sets (e.g. of a duplicate block of the slot)
+#[derive(Debug)]
+pub struct TxDedup {
+    config: DedupConfig,
+    seen: HashSet<Signature>,
+    // by when they were first seen, the front is evicted first
+    order: VecDeque<(Instant, Signature)>,
+}
+
+impl TxDedup {
+    pub fn new(config: DedupConfig) -> Self {
+        TxDedup {
+            config,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// insert is false for a signature already in the window
+    pub fn insert(&mut self, signature: Signature, now: Instant) -> bool {
+        self.evict(now);
+        if !self.seen.insert(signature) {
+            return false;
+        }
+        self.order.push_back((now, signature));
+        true
+    }
+
+    // makes room for one more signature on top of dropping the expired ones
+    fn evict(&mut self, now: Instant) {
+        while let Some(&(seen_at, signature)) = self.order.front() {
+            if self.order.len() < self.config.max_signatures
+                && now.duration_since(seen_at) <= self.config.max_age
+            {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&signature);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// filter drops the txs of the entries that were seen before, returns
+    /// how many were dropped; the entries themselves are kept
+    pub fn filter(&mut self, entries: &mut [Entry], now: Instant) -> usize {
+        let mut suppressed = 0;
+        for entry in entries.iter_mut() {
+            entry.transactions.retain(|tx| {
+                // the unsigned txs are dropped before they get here
+                let Some(signature) = tx.signatures.first() else {
+                    return true;
+                };
+                let fresh = self.insert(*signature, now);
+                if !fresh {
+                    suppressed += 1;
+                }
+                fresh
+            });
+        }
+        suppressed
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DedupMetrics {
+    duplicate_txs_suppressed: AtomicU64,
+}
+
+impl DedupMetrics {
+    pub fn duplicate_txs_suppressed(&self) -> u64 {
+        self.duplicate_txs_suppressed.load(Ordering::Relaxed)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "duplicate_txs_suppressed": self.duplicate_txs_suppressed(),
+        })
+    }
+}
+
+/// run sits between the shred processor and the entry processor, it passes
+/// the entries of `entry_rx` on to `entry_tx` without the txs already
+/// passed on, so that the counters and the sinks see every tx once
+pub async fn run(
+    mut entry_rx: mpsc::Receiver<EntriesWithMeta>,
+    entry_tx: mpsc::Sender<EntriesWithMeta>,
+    config: DedupConfig,
+    metrics: Arc<DedupMetrics>,
+) {
+    let mut dedup = TxDedup::new(config);
+    while let Some(mut entries_with_meta) = entry_rx.recv().await {
+        let suppressed =
+            dedup.filter(&mut entries_with_meta.entries, Instant::now());
+        if suppressed > 0 {
+            debug!(
+                "Suppressed {} duplicate txs in slot {}",
+                suppressed, entries_with_meta.slot
+            );
+            metrics
+                .duplicate_txs_suppressed
+                .fetch_add(suppressed as u64, Ordering::Relaxed);
+        }
+        let slot = entries_with_meta.slot;
+        if let Err(e) = entry_tx.send(entries_with_meta).await {
+            error!("Failed to send entries for slot {}: {:?}", slot, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::Keypair;
+    use solana_sdk::system_transaction;
+    use solana_sdk::transaction::VersionedTransaction;
+
+    fn transfer() -> VersionedTransaction {
+        VersionedTransaction::from(system_transaction::transfer(
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            1,
+            Hash::new_unique(),
+        ))
+    }
+
+    #[test]
+    fn bounds_the_window_by_count_and_age() {
+        let mut dedup = TxDedup::new(DedupConfig {
+            max_signatures: 2,
+            max_age: Duration::from_secs(10),
+        });
+        let now = Instant::now();
+        let [a, b, c] = [0; 3].map(|_| Signature::new_unique());
+        assert!(dedup.insert(a, now));
+        assert!(!dedup.insert(a, now));
+        assert!(dedup.insert(b, now));
+        // `a` makes room for `c`
+        assert!(dedup.insert(c, now));
+        assert_eq!(dedup.len(), 2);
+        assert!(dedup.insert(a, now));
+
+        // all of them expire
+        let later = now + Duration::from_secs(11);
+        assert!(dedup.insert(c, later));
+        assert_eq!(dedup.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn suppresses_the_txs_seen_before() {
+        let (a, b) = (transfer(), transfer());
+        let entries = |transactions| {
+            vec![Entry {
+                num_hashes: 1,
+                hash: Hash::default(),
+                transactions,
+            }]
+        };
+        let (in_tx, in_rx) = mpsc::channel(4);
+        let (out_tx, mut out_rx) = mpsc::channel(4);
+        let metrics = Arc::new(DedupMetrics::default());
+        tokio::spawn(run(
+            in_rx,
+            out_tx,
+            DedupConfig::default(),
+            metrics.clone(),
+        ));
+
+        for (slot, transactions) in
+            [(1, vec![a.clone()]), (2, vec![a.clone(), b.clone()])]
+        {
+            in_tx
+                .send(EntriesWithMeta {
+                    entries: entries(transactions),
+                    slot,
+                    trace: Default::default(),
+                    received_at: None,
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(out_rx.recv().await.unwrap().entries, entries(vec![a]));
+        let second = out_rx.recv().await.unwrap();
+        assert_eq!(second.slot, 2);
+        assert_eq!(second.entries, entries(vec![b]));
+        assert_eq!(metrics.duplicate_txs_suppressed(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::entry_processor::CreatePumpTokenEvent;
+
+pub const DEFAULT_ENRICH_TIMEOUT_MS: u64 = 2_000;
+pub const DEFAULT_ENRICH_CONCURRENCY: usize = 16;
+
+/// Gateway the `ipfs://` metadata URIs are fetched through
+pub const IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+
+#[derive(Debug, Clone)]
+pub struct EnrichConfig {
+    /// Cap on the whole enrichment of an event, waiting for a free slot
+    /// included
+    pub timeout: Duration,
+    /// Metadata fetches in flight at once
+    pub concurrency: usize,
+}
+
+impl Default for EnrichConfig {
+    fn default() -> Self {
+        EnrichConfig {
+            timeout: Duration::from_millis(DEFAULT_ENRICH_TIMEOUT_MS),
+            concurrency: DEFAULT_ENRICH_CONCURRENCY,
+        }
+    }
+}
+
+/// TokenMetadata is the part of the off-chain metadata of a token (the JSON
+/// behind its URI) that the buyers filter on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenMetadata {
+    pub image: Option<String>,
+    pub description: Option<String>,
+    pub twitter: Option<String>,
+    pub telegram: Option<String>,
+    pub website: Option<String>,
+}
+
+/// Enricher resolves the metadata URIs of new tokens, it is cheap to clone
+/// and the clones share the concurrency limit
+#[derive(Debug, Clone)]
+pub struct Enricher {
+    client: reqwest::Client,
+    permits: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+/// resolve_uri is the URL the metadata URI can be fetched from
+pub fn resolve_uri(uri: &str) -> String {
+    match uri.strip_prefix("ipfs://") {
+        Some(cid) => format!("{}{}", IPFS_GATEWAY, cid),
+        None => uri.to_string(),
+    }
+}
+
+impl Enricher {
+    pub fn new(config: &EnrichConfig) -> Self {
+        Enricher {
+            client: reqwest::Client::new(),
+            permits: Arc::new(Semaphore::new(config.concurrency)),
+            timeout: config.timeout,
+        }
+    }
+
+    /// fetch gets the metadata behind `uri`, failing if it takes longer
+    /// than the timeout
+    pub async fn fetch(&self, uri: &str) -> anyhow::Result<TokenMetadata> {
+        let url = resolve_uri(uri);
+        tokio::time::timeout(self.timeout, async {
+            let _permit = self.permits.acquire().await?;
+            let metadata = self
+                .client
+                .get(&url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<TokenMetadata>()
+                .await?;
+            Ok::<_, anyhow::Error>(metadata)
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out fetching {}", url))?
+    }
+
+    /// enrich attaches the metadata to the event, the event is left as is
+    /// if the URI cannot be fetched
+    pub async fn enrich(&self, event: &mut CreatePumpTokenEvent) {
+        if event.uri.is_empty() {
+            return;
+        }
+        match self.fetch(&event.uri).await {
+            Ok(metadata) => event.metadata = Some(metadata),
+            Err(e) => {
+                warn!("Failed to enrich {} ({}): {}", event.sig, event.uri, e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_metadata() {
+        let metadata: TokenMetadata = serde_json::from_str(
+            r#"{
+                "name": "gringo",
+                "symbol": "GRINGO",
+                "description": "the gringo",
+                "image": "https://cf-ipfs.com/ipfs/Qm",
+                "showName": true,
+                "twitter": "https://x.com/gringo"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            metadata,
+            TokenMetadata {
+                image: Some("https://cf-ipfs.com/ipfs/Qm".to_string()),
+                description: Some("the gringo".to_string()),
+                twitter: Some("https://x.com/gringo".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(resolve_uri("ipfs://Qm"), "https://ipfs.io/ipfs/Qm");
+        assert_eq!(
+            resolve_uri("https://cf-ipfs.com/ipfs/Qm"),
+            "https://cf-ipfs.com/ipfs/Qm"
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_event_as_is_on_failure() {
+        let enricher = Enricher::new(&EnrichConfig {
+            timeout: Duration::from_millis(500),
+            concurrency: 1,
+        });
+        let mut event = CreatePumpTokenEvent {
+            uri: "http://127.0.0.1:1/metadata.json".to_string(),
+            ..Default::default()
+        };
+        enricher.enrich(&mut event).await;
+        assert_eq!(event.metadata, None);
+    }
+}
@@ -1,37 +1,202 @@
-use borsh::BorshDeserialize;
+use once_cell::sync::Lazy;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use solana_sdk::clock::Slot;
-use std::str::FromStr;
-use std::sync::Arc;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use solana_entry::entry::Entry;
 use solana_sdk::pubkey::Pubkey;
 use tokio::sync::mpsc;
 
-use crate::arb::PoolsState;
+use crate::alt::LookupTableCache;
+use crate::arb::{
+    ArbConfig, ArbOpportunity, ArbTradeEvent, PoolsState, TradeMode,
+};
+use crate::circuit_breaker::{
+    report_loss, track_confirmation, CircuitBreaker,
+};
 use crate::constants;
-use crate::pump::{PumpCreateIx, PumpSwapIx};
+use crate::creators::CreatorFilter;
+use crate::enrich::{EnrichConfig, Enricher, TokenMetadata};
+use crate::executor::ArbExecutor;
+use crate::otel::FecSetTrace;
+use crate::pump::{find_cpi_create, find_create_accounts, PumpIx};
+use crate::sandwich::SandwichDetector;
+use crate::sink::{
+    emit, idempotency_key, Sink, ARB_TRADE_TOPIC, NEW_RAYDIUM_POOL_TOPIC,
+    PUMP_BUY_TOPIC, SANDWICH_TOPIC, SLOT_VOLUME_TOPIC,
+};
 use crate::util::{pubkey_to_string, string_to_pubkey};
+use crate::volume::VolumeAggregator;
 
 // those are virtual btw
 // Initial SOL reserves: 30,000,000,000 lamports (30 SOL)
 // Initial token reserves: 1,073,000,000,000,000 tokens
 pub const DEFAULT_SOL_INITIAL_RESERVES: u64 = 30_000_000_000;
 pub const DEFAULT_TOKEN_INITIAL_RESERVES: u64 = 1_073_000_000_000_000;
+pub const PUMP_TOKEN_DECIMALS: u32 = 6;
+pub const PUMP_TOKEN_TOTAL_SUPPLY: u64 = 1_000_000_000_000_000;
 
+static ARB_PROGRAMS: Lazy<HashSet<Pubkey>> = Lazy::new(|| {
+    HashSet::from([
+        *constants::WHIRLPOOL_PUBKEY,
+        *constants::RAYDIUM_CP_PUBKEY,
+        *constants::RAYDIUM_AMM_PUBKEY,
+        *constants::RAYDIUM_CLMM_PUBKEY,
+        *constants::JUPITER_V6_PUBKEY,
+    ])
+});
+
+/// TxFilter is a cheap check run on every transaction before any of the
+/// per-program reduction, txs it rejects are skipped altogether
+pub type TxFilter = fn(&VersionedTransaction) -> bool;
+
+pub fn touches_any(
+    tx: &VersionedTransaction,
+    programs: &HashSet<Pubkey>,
+) -> bool {
+    tx.message
+        .static_account_keys()
+        .iter()
+        .any(|key| programs.contains(key))
+}
+
+/// invokes tells if an instruction of the tx calls the program directly,
+/// the programs called are never loaded from lookup tables
+pub fn invokes(tx: &VersionedTransaction, program_id: &Pubkey) -> bool {
+    let static_keys = tx.message.static_account_keys();
+    tx.message.instructions().iter().any(|instruction| {
+        static_keys.get(instruction.program_id_index as usize)
+            == Some(program_id)
+    })
+}
+
+pub fn arb_tx_filter(tx: &VersionedTransaction) -> bool {
+    touches_any(tx, &ARB_PROGRAMS)
+}
+
+pub fn pump_tx_filter(tx: &VersionedTransaction) -> bool {
+    tx.message
+        .static_account_keys()
+        .contains(&constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY)
+}
+
+/// ProcessorMetrics are the counters of an entry processor, read by the
+/// metrics loop of the service while the processor runs and reported under
+/// the `mode` label
+pub trait ProcessorMetrics: Send + Sync {
+    fn mode(&self) -> &'static str;
+
+    fn to_json(&self) -> serde_json::Value;
+}
+
+/// ArbMetrics are the counters of the arb mode
+#[derive(Debug, Default)]
+pub struct ArbMetrics {
+    // as of the last batch of entries processed
+    pools_tracked: AtomicU64,
+    raydium_amm_txs: AtomicU64,
+    /// Raydium AMM swaps routed through Jupiter, per pool hop
+    jupiter_routed_swaps: AtomicU64,
+    opportunities: AtomicU64,
+    live_trades: AtomicU64,
+    trade_failures: AtomicU64,
+    events_emitted: AtomicU64,
+    // swaps which came in after the volume of their slot was emitted
+    late_volume_swaps: AtomicU64,
+}
+
+impl ProcessorMetrics for ArbMetrics {
+    fn mode(&self) -> &'static str {
+        "arb"
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "pools_tracked": self.pools_tracked.load(Ordering::Relaxed),
+            "raydium_amm_txs": self.raydium_amm_txs.load(Ordering::Relaxed),
+            "jupiter_routed_swaps": self
+                .jupiter_routed_swaps
+                .load(Ordering::Relaxed),
+            "opportunities": self.opportunities.load(Ordering::Relaxed),
+            "live_trades": self.live_trades.load(Ordering::Relaxed),
+            "trade_failures": self.trade_failures.load(Ordering::Relaxed),
+            "events_emitted": self.events_emitted.load(Ordering::Relaxed),
+            "late_volume_swaps": self
+                .late_volume_swaps
+                .load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// PumpMetrics are the counters of the pump mode
+#[derive(Debug, Default)]
+pub struct PumpMetrics {
+    creates_detected: AtomicU64,
+    /// Creates made through another program, see pump::find_cpi_create
+    cpi_creates: AtomicU64,
+    creates_filtered: AtomicU64,
+    // creates of a mint that already fired within the cooldown
+    creates_cooled_down: AtomicU64,
+    events_emitted: AtomicU64,
+}
+
+impl ProcessorMetrics for PumpMetrics {
+    fn mode(&self) -> &'static str {
+        "pump"
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "creates_detected": self.creates_detected.load(Ordering::Relaxed),
+            "cpi_creates": self.cpi_creates.load(Ordering::Relaxed),
+            "creates_filtered": self.creates_filtered.load(Ordering::Relaxed),
+            "creates_cooled_down": self
+                .creates_cooled_down
+                .load(Ordering::Relaxed),
+            "events_emitted": self.events_emitted.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[derive(Debug)]
 pub struct EntriesWithMeta {
     pub entries: Vec<Entry>,
     pub slot: Slot,
+    pub trace: FecSetTrace,
+    /// When the first shred of the FEC set came in, None for the entries
+    /// not reconstructed off of the shreds
+    pub received_at: Option<Instant>,
+}
+
+/// SigWithMeta is the sig of a detected tx, `received_at` is that of the
+/// entries it came in
+#[derive(Debug, Clone)]
+pub struct SigWithMeta {
+    pub sig: String,
+    pub received_at: Option<Instant>,
 }
 
 pub struct ArbEntryProcessor {
     entry_rx: mpsc::Receiver<EntriesWithMeta>,
     error_rx: mpsc::Receiver<String>,
     pools_state: Arc<RwLock<PoolsState>>,
-    sig_tx: mpsc::Sender<String>,
+    sig_tx: mpsc::Sender<SigWithMeta>,
+    tx_filter: TxFilter,
+    trade_mode: TradeMode,
+    executor: Option<ArbExecutor>,
+    sandwich_detector: Option<SandwichDetector>,
+    volume_aggregator: Option<VolumeAggregator>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    sink: Sink,
+    metrics: Arc<ArbMetrics>,
 }
 
 impl ArbEntryProcessor {
@@ -39,21 +204,49 @@ impl ArbEntryProcessor {
         entry_rx: mpsc::Receiver<EntriesWithMeta>,
         error_rx: mpsc::Receiver<String>,
         pools_state: Arc<RwLock<PoolsState>>,
-        sig_tx: mpsc::Sender<String>,
+        sig_tx: mpsc::Sender<SigWithMeta>,
+        arb_config: ArbConfig,
+        sink: Sink,
     ) -> Self {
+        let trade_mode = arb_config.trade_mode;
+        let sandwich_detector =
+            arb_config.sandwich.clone().map(SandwichDetector::new);
+        let volume_aggregator =
+            arb_config.emit_volume.then(VolumeAggregator::default);
+        let circuit_breaker = arb_config.circuit_breaker.clone();
+        let executor = match trade_mode {
+            TradeMode::Live => Some(
+                ArbExecutor::new(arb_config)
+                    .expect("Failed to set up trading"),
+            ),
+            _ => None,
+        };
         ArbEntryProcessor {
             entry_rx,
             error_rx,
             pools_state,
             sig_tx,
+            tx_filter: arb_tx_filter,
+            trade_mode,
+            executor,
+            sandwich_detector,
+            volume_aggregator,
+            circuit_breaker,
+            sink,
+            metrics: Arc::new(ArbMetrics::default()),
         }
     }
 
+    pub fn metrics(&self) -> Arc<ArbMetrics> {
+        self.metrics.clone()
+    }
+
     pub async fn receive_entries(&mut self) {
         loop {
             tokio::select! {
                 Some(entries) = self.entry_rx.recv() => {
-                    self.process_entries(entries).await;
+                    let trace = entries.trace.clone();
+                    trace.instrument(self.process_entries(entries)).await;
                 }
                 Some(error) = self.error_rx.recv() => {
                     error!("{}", error);
@@ -66,7 +259,7 @@ impl ArbEntryProcessor {
         &mut self,
         entries_with_meta: EntriesWithMeta,
     ) {
-        let mut pools_state = self.pools_state.write().await;
+        let mut opportunities = Vec::new();
         debug!(
             "OK: entries {} txs: {}",
             entries_with_meta.entries.len(),
@@ -76,38 +269,233 @@ impl ArbEntryProcessor {
                 .map(|e| e.transactions.len())
                 .sum::<usize>(),
         );
+        // the lookup tables are resolved off of the cache, the missing ones
+        // are fetched in the background for the txs that come later
+        let lookup_tables =
+            self.pools_state.read().await.lookup_tables.clone();
+        let mut txs = Vec::new();
         for entry in entries_with_meta.entries {
             for tx in entry.transactions {
-                if tx.message.static_account_keys().contains(
-                    &Pubkey::from_str(constants::WHIRLPOOL)
-                        .expect("Failed to parse pubkey"),
-                ) {
-                    pools_state.orca_count += 1;
-                    pools_state.reduce_orca_tx(tx);
-                } else if tx.message.static_account_keys().contains(
-                    &Pubkey::from_str(constants::RAYDIUM_CP)
-                        .expect("Failed to parse pubkey"),
-                ) {
-                    pools_state.raydium_cp_count += 1;
-                    // pools_state.reduce_raydium_cp_tx(tx);
-                } else if tx.message.static_account_keys().contains(
-                    &Pubkey::from_str(constants::RAYDIUM_AMM)
-                        .expect("Failed to parse pubkey"),
-                ) {
-                    pools_state.raydium_amm_count += 1;
-                    self.sig_tx
-                        .send(tx.signatures[0].to_string())
-                        .await
-                        .unwrap();
-                    pools_state.reduce_raydium_amm_tx(Arc::new(tx)).await;
-                };
+                if !(self.tx_filter)(&tx) {
+                    continue;
+                }
+                let account_keys = lookup_tables.account_keys(&tx.message);
+                txs.push((tx, account_keys));
             }
-            debug!(
-                "orca: {}, raydium cp: {}, raydium amm: {}",
-                pools_state.orca_count,
-                pools_state.raydium_cp_count,
-                pools_state.raydium_amm_count
+        }
+
+        let mut pools_state = self.pools_state.write().await;
+        for (tx, account_keys) in txs {
+            // a tx can call more than one of the programs, each of the
+            // handlers picks the instructions of its own out of it; the
+            // steps of the Jupiter routes are CPIs, not seen by the others
+            let tx = Arc::new(tx);
+            if invokes(&tx, &constants::JUPITER_V6_PUBKEY) {
+                let (routed_swaps, routed_opportunities) = pools_state
+                    .reduce_jupiter_tx(
+                        tx.clone(),
+                        &account_keys,
+                        entries_with_meta.slot,
+                    )
+                    .await;
+                self.metrics
+                    .jupiter_routed_swaps
+                    .fetch_add(routed_swaps as u64, Ordering::Relaxed);
+                opportunities.extend(routed_opportunities);
+            }
+            if invokes(&tx, &constants::WHIRLPOOL_PUBKEY) {
+                pools_state.orca_count += 1;
+                pools_state.reduce_orca_tx(&tx);
+            }
+            if invokes(&tx, &constants::RAYDIUM_CP_PUBKEY) {
+                pools_state.raydium_cp_count += 1;
+                // pools_state.reduce_raydium_cp_tx(tx);
+            }
+            if invokes(&tx, &constants::RAYDIUM_AMM_PUBKEY) {
+                pools_state.raydium_amm_count += 1;
+                self.metrics.raydium_amm_txs.fetch_add(1, Ordering::Relaxed);
+                if let Some(signature) = tx.signatures.first() {
+                    let sig = SigWithMeta {
+                        sig: signature.to_string(),
+                        received_at: entries_with_meta.received_at,
+                    };
+                    if let Err(e) = self.sig_tx.send(sig).await {
+                        error!("Failed to send signature: {}", e);
+                    }
+                }
+                opportunities.extend(
+                    pools_state
+                        .reduce_raydium_amm_tx(
+                            tx.clone(),
+                            &account_keys,
+                            entries_with_meta.slot,
+                        )
+                        .await,
+                );
+            }
+            if invokes(&tx, &constants::RAYDIUM_CLMM_PUBKEY) {
+                pools_state.raydium_clmm_count += 1;
+                pools_state
+                    .reduce_raydium_clmm_tx(tx.clone(), &account_keys)
+                    .await;
+            }
+        }
+        debug!(
+            "orca: {}, raydium cp: {}, raydium amm: {}, raydium clmm: {} \
+             ({} on untracked pools), jupiter raydium: {}",
+            pools_state.orca_count,
+            pools_state.raydium_cp_count,
+            pools_state.raydium_amm_count,
+            pools_state.raydium_clmm_count,
+            pools_state.raydium_clmm_unregistered_swaps,
+            pools_state.jupiter_raydium_count
+        );
+        let new_pools = pools_state.take_new_pools();
+        let swaps = pools_state.take_swaps();
+        self.metrics
+            .pools_tracked
+            .store(pools_state.raydium_pools.len() as u64, Ordering::Relaxed);
+        drop(pools_state);
+        self.metrics
+            .opportunities
+            .fetch_add(opportunities.len() as u64, Ordering::Relaxed);
+
+        for new_pool in new_pools {
+            self.emit(NEW_RAYDIUM_POOL_TOPIC, &new_pool).await;
+        }
+        let volumes = self
+            .volume_aggregator
+            .as_mut()
+            .map(|aggregator| {
+                let volumes =
+                    aggregator.record(entries_with_meta.slot, &swaps);
+                self.metrics
+                    .late_volume_swaps
+                    .store(aggregator.late_swaps(), Ordering::Relaxed);
+                volumes
+            })
+            .unwrap_or_default();
+        for volume in volumes {
+            self.emit(SLOT_VOLUME_TOPIC, &volume).await;
+        }
+        let sandwiches = self
+            .sandwich_detector
+            .as_mut()
+            .map(|detector| detector.record(entries_with_meta.slot, swaps))
+            .unwrap_or_default();
+        for sandwich in sandwiches {
+            info!(
+                "sandwich: {}",
+                serde_json::to_string(&sandwich).expect("json")
             );
+            self.emit(SANDWICH_TOPIC, &sandwich).await;
+        }
+        for opportunity in opportunities {
+            self.act_on_opportunity(opportunity).await;
+        }
+    }
+
+    /// emit sends the event to the sink, counting it
+    async fn emit<T: Serialize>(&self, topic: &str, event: &T) {
+        emit(&*self.sink, topic, event).await;
+        self.metrics.events_emitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    async fn act_on_opportunity(&mut self, opportunity: ArbOpportunity) {
+        match self.trade_mode {
+            TradeMode::ReadOnly => {
+                info!(
+                    "arb opportunity: {}",
+                    serde_json::to_string(&opportunity).expect("json")
+                );
+            }
+            TradeMode::Paper => {
+                let event =
+                    ArbTradeEvent::new(self.trade_mode, opportunity, None);
+                info!(
+                    "paper trade: {}",
+                    serde_json::to_string_pretty(&event).expect("pretty")
+                );
+                self.emit(ARB_TRADE_TOPIC, &event).await;
+            }
+            TradeMode::Live
+                if self
+                    .circuit_breaker
+                    .as_ref()
+                    .is_some_and(|breaker| breaker.is_tripped()) =>
+            {
+                let event =
+                    ArbTradeEvent::new(TradeMode::Paper, opportunity, None);
+                info!(
+                    "paper trade (circuit breaker tripped): {}",
+                    serde_json::to_string_pretty(&event).expect("pretty")
+                );
+                self.emit(ARB_TRADE_TOPIC, &event).await;
+            }
+            TradeMode::Live => {
+                let Some(executor) = self.executor.as_mut() else {
+                    error!("Live trade mode without an executor");
+                    return;
+                };
+                let pools = {
+                    let pools_state = self.pools_state.read().await;
+                    match (
+                        pools_state.raydium_pools.get(&opportunity.buy_pool),
+                        pools_state.raydium_pools.get(&opportunity.sell_pool),
+                    ) {
+                        (Some(buy_pool), Some(sell_pool)) => Some((
+                            buy_pool.read().await.clone(),
+                            sell_pool.read().await.clone(),
+                        )),
+                        _ => None,
+                    }
+                };
+                let Some((buy_pool, sell_pool)) = pools else {
+                    error!("Pools of the opportunity are not tracked");
+                    return;
+                };
+                let signature = match executor
+                    .execute(&opportunity, &buy_pool, &sell_pool)
+                    .await
+                {
+                    Ok(Some(signature)) => {
+                        self.metrics
+                            .live_trades
+                            .fetch_add(1, Ordering::Relaxed);
+                        if let Some(breaker) = &self.circuit_breaker {
+                            tokio::spawn(track_confirmation(
+                                executor.rpc_client(),
+                                signature,
+                                breaker.clone(),
+                                self.sink.clone(),
+                            ));
+                        }
+                        signature.to_string()
+                    }
+                    Ok(None) => return,
+                    Err(e) => {
+                        error!("Failed to execute arb trade: {:?}", e);
+                        self.metrics
+                            .trade_failures
+                            .fetch_add(1, Ordering::Relaxed);
+                        if let Some(breaker) = &self.circuit_breaker {
+                            report_loss(breaker, &self.sink, &e.to_string())
+                                .await;
+                        }
+                        return;
+                    }
+                };
+                let event = ArbTradeEvent::new(
+                    self.trade_mode,
+                    opportunity,
+                    Some(signature),
+                );
+                info!(
+                    "live trade: {}",
+                    serde_json::to_string_pretty(&event).expect("pretty")
+                );
+                self.emit(ARB_TRADE_TOPIC, &event).await;
+            }
         }
     }
 }
@@ -115,9 +503,84 @@ impl ArbEntryProcessor {
 pub struct PumpEntryProcessor {
     entry_rx: mpsc::Receiver<EntriesWithMeta>,
     error_rx: mpsc::Receiver<String>,
-    sig_tx: mpsc::Sender<String>,
-    post_url: String,
-    client: reqwest::Client,
+    sig_tx: mpsc::Sender<SigWithMeta>,
+    sink: Sink,
+    tx_filter: TxFilter,
+    enricher: Option<Enricher>,
+    creator_filter: Option<Arc<CreatorFilter>>,
+    min_dev_buy_lamports: u64,
+    mint_cooldown: MintCooldown,
+    // the scan runs on the global rayon pool without it
+    scan_pool: Option<rayon::ThreadPool>,
+    lookup_tables: Arc<LookupTableCache>,
+    metrics: Arc<PumpMetrics>,
+}
+
+/// PumpConfig is the optional behaviour of the pump mode
+#[derive(Debug, Clone)]
+pub struct PumpConfig {
+    /// Resolve the metadata of the new tokens before emitting them
+    pub enrich: Option<EnrichConfig>,
+    /// Only emit the events of the creators this lets through
+    pub creator_filter: Option<Arc<CreatorFilter>>,
+    /// Only emit the launches the dev bought at least this much of, see
+    /// CreatePumpTokenEvent::dev_buy_lamports
+    pub min_dev_buy_lamports: u64,
+    /// Shortest time between two events of the same mint, the repeats
+    /// within it are dropped; zero turns the cooldown off
+    pub mint_cooldown: Duration,
+    /// Threads of a dedicated rayon pool for the scan of the entries, the
+    /// global pool (one thread per core) is used if None
+    pub scan_threads: Option<usize>,
+}
+
+/// Shortest time between two events of the same mint by default
+pub const DEFAULT_MINT_COOLDOWN_MS: u64 = 60_000;
+
+impl Default for PumpConfig {
+    fn default() -> Self {
+        PumpConfig {
+            enrich: None,
+            creator_filter: None,
+            min_dev_buy_lamports: 0,
+            mint_cooldown: Duration::from_millis(DEFAULT_MINT_COOLDOWN_MS),
+            scan_threads: None,
+        }
+    }
+}
+
+/// MintCooldown is the timed set of the mints that fired, a mint is let
+/// through once per `window`, on top of the idempotency keys, for the
+/// creates duplicated across the reconstructed entries
+#[derive(Debug)]
+pub struct MintCooldown {
+    window: Duration,
+    fired: Mutex<HashMap<Pubkey, Instant>>,
+}
+
+impl MintCooldown {
+    pub fn new(window: Duration) -> Self {
+        MintCooldown {
+            window,
+            fired: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// try_fire is false for a mint that fired within the window
+    pub fn try_fire(&self, mint: Pubkey, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+        let mut fired = self.fired.lock().expect("mint cooldown");
+        fired.retain(|_, fired_at| {
+            now.duration_since(*fired_at) < self.window
+        });
+        if fired.contains_key(&mint) {
+            return false;
+        }
+        fired.insert(mint, now);
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,6 +602,13 @@ pub struct CreatePumpTokenEvent {
         deserialize_with = "string_to_pubkey"
     )]
     pub associated_bounding_curve: Pubkey,
+    /// Fee payer of the create tx
+    #[serde(
+        default,
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub creator: Pubkey,
     pub name: String,
     pub symbol: String,
     pub uri: String,
@@ -147,6 +617,17 @@ pub struct CreatePumpTokenEvent {
     pub num_dev_buy_txs: u64,
     pub virtual_sol_reserves: u64,
     pub virtual_token_reserves: u64,
+    pub price_per_token: Option<f64>,
+    pub market_cap_sol: Option<f64>,
+    #[serde(default)]
+    pub idempotency_key: String,
+    /// Resolved from `uri` with `--enrich`, None if it could not be
+    #[serde(default)]
+    pub metadata: Option<TokenMetadata>,
+    /// Created by another program calling into pump, the dev buys made
+    /// within the same call are not known
+    #[serde(default)]
+    pub via_cpi: bool,
 }
 
 impl Default for CreatePumpTokenEvent {
@@ -157,6 +638,7 @@ impl Default for CreatePumpTokenEvent {
             mint: Pubkey::default(),
             bounding_curve: Pubkey::default(),
             associated_bounding_curve: Pubkey::default(),
+            creator: Pubkey::default(),
             name: "".to_string(),
             symbol: "".to_string(),
             uri: "".to_string(),
@@ -165,31 +647,75 @@ impl Default for CreatePumpTokenEvent {
             num_dev_buy_txs: 0,
             virtual_sol_reserves: DEFAULT_SOL_INITIAL_RESERVES,
             virtual_token_reserves: DEFAULT_TOKEN_INITIAL_RESERVES,
+            price_per_token: None,
+            market_cap_sol: None,
+            idempotency_key: "".to_string(),
+            metadata: None,
+            via_cpi: false,
         }
     }
 }
 
+impl CreatePumpTokenEvent {
+    /// dev_buy_lamports is the SOL the dev put into the launch, the larger
+    /// of the max cost of the buys and what the tokens they hold cost on
+    /// the curve
+    pub fn dev_buy_lamports(&self) -> u64 {
+        self.dev_max_sol_cost
+            .max(bonding_curve_cost(self.dev_bought_amount))
+    }
+}
+
 impl PumpEntryProcessor {
     pub fn new(
         entry_rx: mpsc::Receiver<EntriesWithMeta>,
         error_rx: mpsc::Receiver<String>,
-        sig_tx: mpsc::Sender<String>,
-        post_url: String,
+        sig_tx: mpsc::Sender<SigWithMeta>,
+        sink: Sink,
+        pump_config: PumpConfig,
     ) -> Self {
         PumpEntryProcessor {
             entry_rx,
             error_rx,
             sig_tx,
-            post_url,
-            client: reqwest::Client::new(),
+            sink,
+            tx_filter: pump_tx_filter,
+            enricher: pump_config.enrich.as_ref().map(Enricher::new),
+            creator_filter: pump_config.creator_filter,
+            min_dev_buy_lamports: pump_config.min_dev_buy_lamports,
+            mint_cooldown: MintCooldown::new(pump_config.mint_cooldown),
+            scan_pool: pump_config.scan_threads.map(|num_threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .thread_name(|i| format!("pump-scan-{}", i))
+                    .build()
+                    .expect("pump scan pool")
+            }),
+            lookup_tables: Arc::default(),
+            metrics: Arc::new(PumpMetrics::default()),
         }
     }
 
+    /// with_lookup_tables resolves the accounts the V0 txs load from lookup
+    /// tables, only the static keys are read without it
+    pub fn with_lookup_tables(
+        mut self,
+        lookup_tables: Arc<LookupTableCache>,
+    ) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<PumpMetrics> {
+        self.metrics.clone()
+    }
+
     pub async fn receive_entries(&mut self) {
         loop {
             tokio::select! {
                 Some(entries) = self.entry_rx.recv() => {
-                    self.process_entries(entries).await;
+                    let trace = entries.trace.clone();
+                    trace.instrument(self.process_entries(entries)).await;
                 }
                 Some(error) = self.error_rx.recv() => {
                     error!("{}", error);
@@ -198,100 +724,523 @@ impl PumpEntryProcessor {
         }
     }
 
+    /// should_emit applies the creator and dev buy filters to the event
+    fn should_emit(&self, event: &CreatePumpTokenEvent) -> bool {
+        if let Some(filter) = &self.creator_filter {
+            if !filter.allows(&event.creator) {
+                debug!(
+                    "Filtered out {} of creator {}",
+                    event.sig, event.creator
+                );
+                return false;
+            }
+        }
+        if event.dev_buy_lamports() < self.min_dev_buy_lamports {
+            debug!(
+                "Filtered out {}, dev bought {} lamports",
+                event.sig,
+                event.dev_buy_lamports()
+            );
+            return false;
+        }
+        true
+    }
+
     /// TODO each vec of entries should be included metadata about slot of deshred
     pub async fn process_entries(&self, entries_with_meta: EntriesWithMeta) {
-        let events = entries_with_meta
-            .entries
-            .par_iter()
-            .map(|entry| {
-                entry
-                    .transactions
-                    .par_iter()
-                    .filter_map(|tx| {
-                        let mut event = CreatePumpTokenEvent::default();
-                        let account_keys = tx.message.static_account_keys();
-                        if account_keys.len() == 18
-                            && account_keys.contains(
-                                &Pubkey::from_str(
-                                    constants::PUMP_FUN_MINT_AUTHORITY,
-                                )
-                                .expect("Failed to parse pubkey"),
-                            )
-                        {
-                            println!("Found pump tx: {:#?}", tx);
-                            event.mint = account_keys[1];
-                            event.bounding_curve = account_keys[3];
-                            event.associated_bounding_curve = account_keys[4];
-                            tx.message.instructions().iter().for_each(|ix| {
-                                if let Ok(swap) =
-                                    PumpSwapIx::try_from_slice(&ix.data)
-                                {
-                                    event.dev_bought_amount += swap.amount;
-                                    event.dev_max_sol_cost +=
-                                        swap.max_sol_cost;
-                                    event.num_dev_buy_txs += 1;
-                                    event.virtual_sol_reserves +=
-                                        deduct_fee(swap.max_sol_cost);
-                                    event.virtual_token_reserves -=
-                                        swap.amount;
-                                } else if let Ok(token_metadata) =
-                                    PumpCreateIx::try_from_slice(&ix.data)
-                                {
-                                    event.name = token_metadata.name;
-                                    event.symbol = token_metadata.symbol;
-                                    event.uri = token_metadata.uri;
-                                }
-                            });
-                        } else {
-                            return None;
-                        }
-                        event.sig = tx.signatures[0].to_string();
-                        event.slot = entries_with_meta.slot;
-                        Some(event)
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .flatten()
-            .collect::<Vec<_>>();
+        let EntriesWithMeta { entries, slot, .. } = &entries_with_meta;
+        // only the cached tables are used, a missing one is fetched in the
+        // background and the tx is scanned with its static keys
+        let mut txs = Vec::new();
+        for tx in entries.iter().flat_map(|entry| entry.transactions.iter()) {
+            if (self.tx_filter)(tx) {
+                let account_keys =
+                    self.lookup_tables.account_keys(&tx.message);
+                txs.push((tx, account_keys));
+            }
+        }
+        let cpi_creates = &self.metrics.cpi_creates;
+        let scan = || scan_creates(&txs, *slot, cpi_creates);
+        let events = match &self.scan_pool {
+            Some(scan_pool) => scan_pool.install(scan),
+            None => scan(),
+        };
 
+        self.metrics
+            .creates_detected
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
         // this might be tiny bit blocking
         for event in events {
-            info!(
-                "Sending webhook: {}",
-                serde_json::to_string_pretty(&event).expect("pretty")
-            );
-            self.post_webhook(event.clone()).await;
-            self.sig_tx.send(event.sig.clone()).await.unwrap();
+            if !self.should_emit(&event) {
+                self.metrics
+                    .creates_filtered
+                    .fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if !self.mint_cooldown.try_fire(event.mint, Instant::now()) {
+                debug!("Mint {} of {} cooling down", event.mint, event.sig);
+                self.metrics
+                    .creates_cooled_down
+                    .fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            self.metrics.events_emitted.fetch_add(1, Ordering::Relaxed);
+            let sig = SigWithMeta {
+                sig: event.sig.clone(),
+                received_at: entries_with_meta.received_at,
+            };
+            if let Err(e) = self.sig_tx.send(sig).await {
+                error!("Failed to send signature: {}", e);
+            }
+            match &self.enricher {
+                // the fetch takes up to the timeout, so it runs off of the
+                // detection loop
+                Some(enricher) => {
+                    let enricher = enricher.clone();
+                    let sink = self.sink.clone();
+                    let mut event = event;
+                    tokio::spawn(async move {
+                        enricher.enrich(&mut event).await;
+                        emit_create_event(&sink, &event).await;
+                    });
+                }
+                None => emit_create_event(&self.sink, &event).await,
+            }
         }
     }
+}
 
-    async fn post_webhook(&self, event: CreatePumpTokenEvent) {
-        let url = self.post_url.clone() + "/v2/pump-buy";
-        match self.client.post(url.clone()).json(&event).send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    info!("Webhook sent: {}", event.sig);
-                } else {
-                    error!("Failed to send webhook to {}: {:?}", url, event);
+/// scan_creates finds the pump creates of the txs, with their resolved
+/// account keys, in parallel on the rayon pool it is called from
+fn scan_creates(
+    txs: &[(&VersionedTransaction, Vec<Pubkey>)],
+    slot: Slot,
+    cpi_creates: &AtomicU64,
+) -> Vec<CreatePumpTokenEvent> {
+    txs.par_iter()
+        .filter_map(|(tx, account_keys)| {
+            let signature = tx.signatures.first()?;
+            let mut event = CreatePumpTokenEvent::default();
+            let accounts = match find_create_accounts(tx, account_keys) {
+                Some(accounts) => Some(accounts),
+                None => find_cpi_create(tx, account_keys).map(|create| {
+                    let count =
+                        cpi_creates.fetch_add(1, Ordering::Relaxed) + 1;
+                    info!("CPI pump create {} ({} so far)", signature, count);
+                    event.via_cpi = true;
+                    if let Some(create_ix) = create.ix {
+                        event.name = create_ix.name;
+                        event.symbol = create_ix.symbol;
+                        event.uri = create_ix.uri;
+                    }
+                    create.accounts
+                }),
+            };
+            if let Some(accounts) = accounts {
+                if !accounts.has_valid_associated_bonding_curve() {
+                    warn!(
+                        "Associated bonding curve mismatch, dropping {}: {:?}",
+                        signature, accounts
+                    );
+                    return None;
                 }
+                println!("Found pump tx: {:#?}", tx);
+                event.mint = accounts.mint;
+                event.bounding_curve = accounts.bonding_curve;
+                event.associated_bounding_curve =
+                    accounts.associated_bonding_curve;
+                event.creator =
+                    tx.message.static_account_keys()[0];
+                tx.message.instructions().iter().for_each(|ix| {
+                    match PumpIx::decode(&ix.data) {
+                        Some(PumpIx::Buy(buy)) => {
+                            event.dev_bought_amount = event
+                                .dev_bought_amount
+                                .saturating_add(buy.amount);
+                            event.dev_max_sol_cost = event
+                                .dev_max_sol_cost
+                                .saturating_add(buy.max_sol_cost);
+                            event.num_dev_buy_txs += 1;
+                            event.virtual_sol_reserves = event
+                                .virtual_sol_reserves
+                                .saturating_add(deduct_fee(
+                                    buy.max_sol_cost,
+                                ));
+                            event.virtual_token_reserves = event
+                                .virtual_token_reserves
+                                .saturating_sub(buy.amount);
+                        }
+                        // the SOL out is only known to be at
+                        // least the min output
+                        Some(PumpIx::Sell(sell)) => {
+                            event.dev_bought_amount = event
+                                .dev_bought_amount
+                                .saturating_sub(sell.amount);
+                            event.virtual_sol_reserves = event
+                                .virtual_sol_reserves
+                                .saturating_sub(
+                                    sell.min_sol_output,
+                                );
+                            event.virtual_token_reserves = event
+                                .virtual_token_reserves
+                                .saturating_add(sell.amount);
+                        }
+                        Some(PumpIx::Create(token_metadata)) => {
+                            event.name = token_metadata.name;
+                            event.symbol = token_metadata.symbol;
+                            event.uri = token_metadata.uri;
+                        }
+                        _ => {}
+                    }
+                });
+            } else {
+                return None;
             }
-            Err(e) => {
-                error!("Failed to send webhook: {:?}", e);
-            }
-        }
-    }
+            event.price_per_token = bonding_curve_price(
+                event.virtual_sol_reserves,
+                event.virtual_token_reserves,
+            );
+            event.market_cap_sol = event.price_per_token.map(
+                |price| {
+                    price * PUMP_TOKEN_TOTAL_SUPPLY as f64
+                        / 10u64.pow(PUMP_TOKEN_DECIMALS) as f64
+                },
+            );
+            event.sig = signature.to_string();
+            event.idempotency_key =
+                idempotency_key(PUMP_BUY_TOPIC, &[&event.sig]);
+            event.slot = slot;
+            Some(event)
+        })
+        .collect::<Vec<_>>()
+}
+
+async fn emit_create_event(sink: &Sink, event: &CreatePumpTokenEvent) {
+    info!(
+        "Emitting event: {}",
+        serde_json::to_string_pretty(event).expect("pretty")
+    );
+    emit(&**sink, PUMP_BUY_TOPIC, event).await;
 }
 
 /// deduct_fee takes the 1% fee from the amount of SOL out
 /// e.g. if you buy 1 sol worth of the token at start, the max_sol_amount will
 /// amount to 1.01 sol, only 1 sol goes to the pool, 0.01 is the fee
 pub fn deduct_fee(sol_amount: u64) -> u64 {
-    (sol_amount * 100) / 101
+    (sol_amount as u128 * 100 / 101) as u64
+}
+
+/// bonding_curve_price is the constant-product spot price of the pump
+/// bonding curve in SOL per whole token, None when there are no tokens left
+pub fn bonding_curve_price(
+    virtual_sol_reserves: u64,
+    virtual_token_reserves: u64,
+) -> Option<f64> {
+    if virtual_token_reserves == 0 {
+        return None;
+    }
+    let sol = virtual_sol_reserves as f64 / LAMPORTS_PER_SOL as f64;
+    let tokens =
+        virtual_token_reserves as f64 / 10u64.pow(PUMP_TOKEN_DECIMALS) as f64;
+    Some(sol / tokens)
+}
+
+/// bonding_curve_cost is what buying `token_amount` off of a fresh curve
+/// costs in lamports, before the fee
+pub fn bonding_curve_cost(token_amount: u64) -> u64 {
+    if token_amount >= DEFAULT_TOKEN_INITIAL_RESERVES {
+        return u64::MAX;
+    }
+    let k = DEFAULT_SOL_INITIAL_RESERVES as u128
+        * DEFAULT_TOKEN_INITIAL_RESERVES as u128;
+    let sol_reserves =
+        k / (DEFAULT_TOKEN_INITIAL_RESERVES - token_amount) as u128;
+    (sol_reserves - DEFAULT_SOL_INITIAL_RESERVES as u128) as u64
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pump::{
+        derive_associated_token_account, PumpCreateIx, PumpSwapIx,
+        PUMP_BUY_DISCRIMINATOR, PUMP_CREATE_DISCRIMINATOR,
+    };
+    use crate::sink::{StdoutSink, VecSink};
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{Message, MessageHeader, VersionedMessage};
+    use solana_sdk::signature::Signature;
+
+    /// create_tx is a launch of `mint` by `creator` with a dev buy
+    fn create_tx(creator: Pubkey, mint: Pubkey) -> VersionedTransaction {
+        let bonding_curve = Pubkey::new_unique();
+        let account_keys = vec![
+            creator,
+            mint,
+            *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY,
+            bonding_curve,
+            derive_associated_token_account(&bonding_curve, &mint),
+            *constants::PUMP_FUN_PROGRAM_PUBKEY,
+        ];
+        let create = borsh::to_vec(&PumpCreateIx {
+            method_id: PUMP_CREATE_DISCRIMINATOR,
+            name: "gringo".to_string(),
+            symbol: "GRINGO".to_string(),
+            uri: "https://cf-ipfs.com/ipfs/Qm".to_string(),
+        })
+        .unwrap();
+        let buy = borsh::to_vec(&PumpSwapIx {
+            method_id: PUMP_BUY_DISCRIMINATOR,
+            amount: 35_793_563_230_000,
+            max_sol_cost: 1_010_000_000,
+        })
+        .unwrap();
+        VersionedTransaction {
+            signatures: vec![Signature::new_unique()],
+            message: VersionedMessage::Legacy(Message {
+                header: MessageHeader {
+                    num_required_signatures: 2,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 2,
+                },
+                account_keys,
+                recent_blockhash: Hash::default(),
+                instructions: vec![
+                    CompiledInstruction::new_from_raw_parts(
+                        5,
+                        create,
+                        vec![1, 2, 3, 4, 0],
+                    ),
+                    CompiledInstruction::new_from_raw_parts(
+                        5,
+                        buy,
+                        vec![1, 3, 4, 0],
+                    ),
+                ],
+            }),
+        }
+    }
+
+    #[test]
+    fn invokes_only_the_programs_called() {
+        let tx = create_tx(Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(invokes(&tx, &constants::PUMP_FUN_PROGRAM_PUBKEY));
+        // among the accounts, but not called
+        assert!(!invokes(&tx, &constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY));
+        assert!(!invokes(&tx, &constants::JUPITER_V6_PUBKEY));
+    }
+
+    #[tokio::test]
+    async fn emits_create_events_with_the_slot() {
+        let (_entry_tx, entry_rx) = mpsc::channel(1);
+        let (_error_tx, error_rx) = mpsc::channel(1);
+        let (sig_tx, _sig_rx) = mpsc::channel(2);
+        let sink = Arc::new(VecSink::default());
+        let processor = PumpEntryProcessor::new(
+            entry_rx,
+            error_rx,
+            sig_tx,
+            sink.clone(),
+            PumpConfig::default(),
+        );
+        let (creator, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let tx = create_tx(creator, mint);
+        let sig = tx.signatures[0].to_string();
+
+        processor
+            .process_entries(EntriesWithMeta {
+                entries: vec![Entry {
+                    num_hashes: 1,
+                    hash: Hash::default(),
+                    transactions: vec![tx],
+                }],
+                slot: 42,
+                trace: FecSetTrace::default(),
+                received_at: None,
+            })
+            .await;
+
+        let events = sink.events_of::<CreatePumpTokenEvent>(PUMP_BUY_TOPIC);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.slot, 42);
+        assert_eq!(event.sig, sig);
+        assert_eq!(event.mint, mint);
+        assert_eq!(event.creator, creator);
+        assert_eq!(event.name, "gringo");
+        assert_eq!(event.dev_bought_amount, 35_793_563_230_000);
+        assert_eq!(event.dev_max_sol_cost, 1_010_000_000);
+        assert_eq!(event.num_dev_buy_txs, 1);
+
+        let metrics = processor.metrics();
+        assert_eq!(metrics.mode(), "pump");
+        assert_eq!(metrics.to_json()["creates_detected"], 1);
+        assert_eq!(metrics.to_json()["events_emitted"], 1);
+        assert_eq!(metrics.to_json()["creates_filtered"], 0);
+    }
+
+    #[tokio::test]
+    async fn fires_a_mint_once_within_the_cooldown() {
+        let (_entry_tx, entry_rx) = mpsc::channel(1);
+        let (_error_tx, error_rx) = mpsc::channel(1);
+        let (sig_tx, _sig_rx) = mpsc::channel(2);
+        let sink = Arc::new(VecSink::default());
+        let processor = PumpEntryProcessor::new(
+            entry_rx,
+            error_rx,
+            sig_tx,
+            sink.clone(),
+            // on by default
+            PumpConfig::default(),
+        );
+        let mint = Pubkey::new_unique();
+        let tx = create_tx(Pubkey::new_unique(), mint);
+        // the same create out of two FEC sets
+        for slot in [42, 43] {
+            processor
+                .process_entries(EntriesWithMeta {
+                    entries: vec![Entry {
+                        num_hashes: 1,
+                        hash: Hash::default(),
+                        transactions: vec![tx.clone()],
+                    }],
+                    slot,
+                    trace: FecSetTrace::default(),
+                    received_at: None,
+                })
+                .await;
+        }
+
+        let events = sink.events_of::<CreatePumpTokenEvent>(PUMP_BUY_TOPIC);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].slot, 42);
+        let metrics = processor.metrics().to_json();
+        assert_eq!(metrics["creates_detected"], 2);
+        assert_eq!(metrics["creates_cooled_down"], 1);
+        assert_eq!(metrics["events_emitted"], 1);
+    }
+
+    #[tokio::test]
+    async fn scans_on_a_dedicated_pool() {
+        let (_entry_tx, entry_rx) = mpsc::channel(1);
+        let (_error_tx, error_rx) = mpsc::channel(1);
+        let (sig_tx, _sig_rx) = mpsc::channel(2);
+        let sink = Arc::new(VecSink::default());
+        let processor = PumpEntryProcessor::new(
+            entry_rx,
+            error_rx,
+            sig_tx,
+            sink.clone(),
+            PumpConfig {
+                scan_threads: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            processor
+                .scan_pool
+                .as_ref()
+                .map(|pool| pool.current_num_threads()),
+            Some(2)
+        );
+        let transactions = (0..4)
+            .map(|_| create_tx(Pubkey::new_unique(), Pubkey::new_unique()))
+            .collect();
+        processor
+            .process_entries(EntriesWithMeta {
+                entries: vec![Entry {
+                    num_hashes: 1,
+                    hash: Hash::default(),
+                    transactions,
+                }],
+                slot: 42,
+                trace: FecSetTrace::default(),
+                received_at: None,
+            })
+            .await;
+        assert_eq!(
+            sink.events_of::<CreatePumpTokenEvent>(PUMP_BUY_TOPIC).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn mint_cooldown_expires() {
+        let cooldown = MintCooldown::new(Duration::from_secs(1));
+        let (mint, now) = (Pubkey::new_unique(), Instant::now());
+        assert!(cooldown.try_fire(mint, now));
+        assert!(!cooldown.try_fire(mint, now + Duration::from_millis(999)));
+        assert!(cooldown.try_fire(Pubkey::new_unique(), now));
+        assert!(cooldown.try_fire(mint, now + Duration::from_secs(1)));
+        // off with a zero window
+        let off = MintCooldown::new(Duration::ZERO);
+        assert!(off.try_fire(mint, now));
+        assert!(off.try_fire(mint, now));
+    }
+
+    #[test]
+    fn test_bonding_curve_price() {
+        let price = bonding_curve_price(
+            DEFAULT_SOL_INITIAL_RESERVES,
+            DEFAULT_TOKEN_INITIAL_RESERVES,
+        )
+        .unwrap();
+        assert!((price - 30. / 1_073_000_000.).abs() < 1e-15);
+        assert_eq!(
+            bonding_curve_price(DEFAULT_SOL_INITIAL_RESERVES, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_min_dev_buy() {
+        let (_entry_tx, entry_rx) = mpsc::channel(1);
+        let (_error_tx, error_rx) = mpsc::channel(1);
+        let (sig_tx, _sig_rx) = mpsc::channel(1);
+        let processor = PumpEntryProcessor::new(
+            entry_rx,
+            error_rx,
+            sig_tx,
+            Arc::new(StdoutSink),
+            PumpConfig {
+                min_dev_buy_lamports: LAMPORTS_PER_SOL,
+                ..Default::default()
+            },
+        );
+
+        // no dev buy
+        assert!(!processor.should_emit(&CreatePumpTokenEvent::default()));
+        // a buy signed for up to 0.5 SOL
+        assert!(!processor.should_emit(&CreatePumpTokenEvent {
+            dev_max_sol_cost: LAMPORTS_PER_SOL / 2,
+            ..Default::default()
+        }));
+        // a buy signed for up to 2 SOL
+        assert!(processor.should_emit(&CreatePumpTokenEvent {
+            dev_max_sol_cost: 2 * LAMPORTS_PER_SOL,
+            ..Default::default()
+        }));
+        // 100M tokens cost about 3.1 SOL off of a fresh curve
+        assert!(processor.should_emit(&CreatePumpTokenEvent {
+            dev_bought_amount: 100_000_000_000_000,
+            ..Default::default()
+        }));
+        assert!(!processor.should_emit(&CreatePumpTokenEvent {
+            dev_bought_amount: 10_000_000_000_000,
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_bonding_curve_cost() {
+        assert_eq!(bonding_curve_cost(0), 0);
+        let cost = bonding_curve_cost(100_000_000_000_000);
+        assert!((cost as f64 / LAMPORTS_PER_SOL as f64 - 3.083).abs() < 1e-3);
+        assert_eq!(
+            bonding_curve_cost(DEFAULT_TOKEN_INITIAL_RESERVES),
+            u64::MAX
+        );
+    }
 
     #[test]
     fn test_deduct_fee() {
@@ -0,0 +1,334 @@
+use log::{error, info};
+use raydium_library::amm::{self, openbook};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use solana_sdk::signer::Signer;
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::arb::{
+    less_slippage, simulate_arb_trade, ArbConfig, ArbOpportunity,
+    PoolReserves,
+};
+use crate::circuit_breaker::wait_for_confirmation;
+use crate::constants;
+use crate::pump::derive_associated_token_account;
+use crate::raydium::RaydiumAmmPool;
+use crate::util::require_env;
+use crate::wallet::KeypairPool;
+
+pub const COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// ArbExecutor turns arb opportunities into a single buy-then-sell
+/// transaction signed with one of the fund keypairs (`FUND_KEYPAIR_PATH`),
+/// taken round robin so that back-to-back trades do not queue up on a
+/// single wallet
+///
+/// The SOL leg is paid out of the WSOL associated token account of the
+/// signing wallet, which has to be funded upfront. Both legs carry the thresholds of
+/// the simulated trade decision, so a trade that would come out below
+/// `min_profit` fails on-chain rather than losing money. What the buy leg
+/// fills above the amount the sell leg sells is swept once the trade lands,
+/// see TokenSweep
+pub struct ArbExecutor {
+    rpc_client: Arc<RpcClient>,
+    wallets: KeypairPool,
+    config: ArbConfig,
+    market_keys: HashMap<Pubkey, Arc<openbook::MarketPubkeys>>,
+}
+
+impl ArbExecutor {
+    pub fn new(config: ArbConfig) -> anyhow::Result<Self> {
+        let wallets = KeypairPool::from_env()?;
+        info!("Arb executor using wallets: {:?}", wallets.pubkeys());
+        Ok(ArbExecutor {
+            rpc_client: Arc::new(RpcClient::new(require_env(
+                "RPC_URL",
+                "live trading",
+            )?)),
+            wallets,
+            config,
+            market_keys: HashMap::new(),
+        })
+    }
+
+    pub fn rpc_client(&self) -> Arc<RpcClient> {
+        self.rpc_client.clone()
+    }
+
+    /// execute sends the trade for the opportunity, returning None if it was
+    /// skipped because of the configured limits
+    pub async fn execute(
+        &mut self,
+        opportunity: &ArbOpportunity,
+        buy_pool: &RaydiumAmmPool,
+        sell_pool: &RaydiumAmmPool,
+    ) -> anyhow::Result<Option<Signature>> {
+        let amount_in = opportunity.amount_in.min(self.config.max_trade_size);
+        let decision = simulate_arb_trade(
+            &PoolReserves::new(
+                buy_pool,
+                self.config.fee_schedule.fee_for(buy_pool),
+            ),
+            &PoolReserves::new(
+                sell_pool,
+                self.config.fee_schedule.fee_for(sell_pool),
+            ),
+            amount_in,
+            self.config.slippage_bps,
+            self.config.min_profit,
+        );
+        info!(
+            "trade decision for {}: {}",
+            opportunity.trigger_signature,
+            serde_json::to_string(&decision).expect("json")
+        );
+        if !decision.accepted {
+            return Ok(None);
+        }
+
+        let payer = self.wallets.next();
+        let owner = payer.pubkey();
+        let wsol_account =
+            derive_associated_token_account(&owner, &constants::WSOL_PUBKEY);
+        let token_account =
+            derive_associated_token_account(&owner, &opportunity.mint);
+
+        let buy_market_keys = self.get_market_keys(buy_pool).await?;
+        let sell_market_keys = self.get_market_keys(sell_pool).await?;
+        let instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(
+                COMPUTE_UNIT_LIMIT,
+            ),
+            ComputeBudgetInstruction::set_compute_unit_price(
+                self.config.priority_fee,
+            ),
+            create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &opportunity.mint,
+            ),
+            amm::swap(
+                &constants::RAYDIUM_AMM_PUBKEY,
+                &buy_pool.amm_keys,
+                &buy_market_keys,
+                &owner,
+                &wsol_account,
+                &token_account,
+                amount_in,
+                decision.min_token_amount,
+                true,
+            )?,
+            amm::swap(
+                &constants::RAYDIUM_AMM_PUBKEY,
+                &sell_pool.amm_keys,
+                &sell_market_keys,
+                &owner,
+                &token_account,
+                &wsol_account,
+                decision.min_token_amount,
+                decision.min_amount_out,
+                true,
+            )?,
+        ];
+
+        let blockhash = self.rpc_client.get_latest_blockhash().await?;
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&owner),
+            &[payer.as_ref()],
+            blockhash,
+        );
+        let signature = self
+            .rpc_client
+            .send_transaction_with_config(
+                &tx,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?;
+        info!("Sent arb trade: {}", signature);
+
+        // the buy leg can fill above min_token_amount, which is all the sell
+        // leg sells, the rest is sold off once the trade lands
+        tokio::spawn(
+            TokenSweep {
+                rpc_client: self.rpc_client.clone(),
+                payer,
+                mint: opportunity.mint,
+                sell_pool: sell_pool.clone(),
+                sell_market_keys,
+                sell_reserves: PoolReserves::new(
+                    sell_pool,
+                    self.config.fee_schedule.fee_for(sell_pool),
+                ),
+                slippage_bps: self.config.slippage_bps,
+                priority_fee: self.config.priority_fee,
+            }
+            .run(signature),
+        );
+
+        Ok(Some(signature))
+    }
+
+    async fn get_market_keys(
+        &mut self,
+        pool: &RaydiumAmmPool,
+    ) -> anyhow::Result<Arc<openbook::MarketPubkeys>> {
+        if let Some(market_keys) = self.market_keys.get(&pool.amm_keys.market)
+        {
+            return Ok(market_keys.clone());
+        }
+        let market_keys = Arc::new(
+            openbook::get_keys_for_market(
+                &self.rpc_client,
+                &pool.amm_keys.market_program,
+                &pool.amm_keys.market,
+            )
+            .await?,
+        );
+        self.market_keys
+            .insert(pool.amm_keys.market, market_keys.clone());
+        Ok(market_keys)
+    }
+}
+
+/// TokenSweep sells the whole balance of the token account of the wallet
+/// on the sell pool of the trade and closes the account, so that what the
+/// buy leg filled above the amount the sell leg sold is not left behind
+struct TokenSweep {
+    rpc_client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mint: Pubkey,
+    sell_pool: RaydiumAmmPool,
+    sell_market_keys: Arc<openbook::MarketPubkeys>,
+    sell_reserves: PoolReserves,
+    slippage_bps: u64,
+    priority_fee: u64,
+}
+
+impl TokenSweep {
+    /// run waits for the trade to land first, the leftover is only known
+    /// after it
+    async fn run(self, trade: Signature) {
+        let Some(Ok(())) =
+            wait_for_confirmation(&self.rpc_client, &trade).await
+        else {
+            return;
+        };
+        if let Err(e) = self.sweep(&trade).await {
+            error!("Failed to sweep the tokens left by {}: {:?}", trade, e);
+        }
+    }
+
+    async fn sweep(&self, trade: &Signature) -> anyhow::Result<()> {
+        let owner = self.payer.pubkey();
+        let token_account =
+            derive_associated_token_account(&owner, &self.mint);
+        let leftover = self
+            .rpc_client
+            .get_token_account_balance(&token_account)
+            .await?
+            .amount
+            .parse::<u64>()?;
+        info!("{} of {} left over after {}", leftover, self.mint, trade);
+        let mut instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(
+                COMPUTE_UNIT_LIMIT,
+            ),
+            ComputeBudgetInstruction::set_compute_unit_price(
+                self.priority_fee,
+            ),
+        ];
+        if leftover > 0 {
+            let wsol_account = derive_associated_token_account(
+                &owner,
+                &constants::WSOL_PUBKEY,
+            );
+            instructions.push(amm::swap(
+                &constants::RAYDIUM_AMM_PUBKEY,
+                &self.sell_pool.amm_keys,
+                &self.sell_market_keys,
+                &owner,
+                &token_account,
+                &wsol_account,
+                leftover,
+                less_slippage(
+                    self.sell_reserves.sell(leftover),
+                    self.slippage_bps,
+                ),
+                true,
+            )?);
+        }
+        instructions.push(close_token_account(&token_account, &owner));
+
+        let blockhash = self.rpc_client.get_latest_blockhash().await?;
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&owner),
+            &[self.payer.as_ref()],
+            blockhash,
+        );
+        let signature = self
+            .rpc_client
+            .send_transaction_with_config(
+                &tx,
+                RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )
+            .await?;
+        info!("Sent sweep of {} {}: {}", leftover, self.mint, signature);
+        Ok(())
+    }
+}
+
+/// close_token_account is the `CloseAccount` instruction of the token
+/// program, the rent of the emptied account goes back to the owner
+pub fn close_token_account(account: &Pubkey, owner: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *constants::TOKEN_PROGRAM_PUBKEY,
+        accounts: vec![
+            AccountMeta::new(*account, false),
+            AccountMeta::new(*owner, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+        data: vec![9],
+    }
+}
+
+/// create_associated_token_account_idempotent is the `CreateIdempotent`
+/// instruction of the associated token program
+pub fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *constants::ASSOCIATED_TOKEN_PROGRAM_PUBKEY,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(
+                derive_associated_token_account(owner, mint),
+                false,
+            ),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(
+                *constants::TOKEN_PROGRAM_PUBKEY,
+                false,
+            ),
+        ],
+        data: vec![1],
+    }
+}
@@ -0,0 +1,133 @@
+use bytes::Bytes;
+use log::{info, warn};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+/// Packets waiting to be forwarded, past this they are dropped rather than
+/// holding up the receive loop
+pub const FORWARD_QUEUE_SIZE: usize = 10_000;
+
+/// Forwarder re-emits the received packets to the downstream listeners, the
+/// packets are queued and sent from a separate task on its own socket, so
+/// a slow or unreachable downstream never slows the ingest
+#[derive(Debug)]
+pub struct Forwarder {
+    tx: mpsc::Sender<Bytes>,
+    metrics: Arc<ForwardMetrics>,
+}
+
+#[derive(Debug, Default)]
+pub struct ForwardMetrics {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    send_errors: AtomicU64,
+}
+
+impl ForwardMetrics {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "forwarded": self.forwarded.load(Ordering::Relaxed),
+            "dropped": self.dropped.load(Ordering::Relaxed),
+            "send_errors": self.send_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// resolve_targets parses the `--forward` addresses
+pub fn resolve_targets(
+    targets: &[String],
+) -> std::io::Result<Vec<SocketAddr>> {
+    targets
+        .iter()
+        .map(|target| {
+            target.to_socket_addrs()?.next().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid forward address: {}", target),
+                )
+            })
+        })
+        .collect()
+}
+
+impl Forwarder {
+    /// spawn binds the sending socket and starts the task sending every
+    /// packet to each of the `targets`, in order
+    pub async fn spawn(targets: Vec<SocketAddr>) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        info!("Forwarding packets to {:?}", targets);
+        let (tx, mut rx) = mpsc::channel::<Bytes>(FORWARD_QUEUE_SIZE);
+        let metrics = Arc::new(ForwardMetrics::default());
+        tokio::spawn({
+            let metrics = metrics.clone();
+            async move {
+                while let Some(packet) = rx.recv().await {
+                    for target in targets.iter() {
+                        match socket.send_to(&packet, target).await {
+                            Ok(_) => {
+                                metrics
+                                    .forwarded
+                                    .fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(e) => {
+                                metrics
+                                    .send_errors
+                                    .fetch_add(1, Ordering::Relaxed);
+                                warn!(
+                                    "Failed to forward to {}: {}",
+                                    target, e
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Forwarder { tx, metrics })
+    }
+
+    /// forward queues the packet without waiting, it is dropped (and
+    /// counted) if the queue is full
+    pub fn forward(&self, packet: Bytes) {
+        if self.tx.try_send(packet).is_err() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<ForwardMetrics> {
+        self.metrics.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn forwards_packets_to_every_target() {
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let forwarder = Forwarder::spawn(vec![
+            first.local_addr().unwrap(),
+            second.local_addr().unwrap(),
+        ])
+        .await
+        .unwrap();
+
+        forwarder.forward(Bytes::from_static(b"shred"));
+        let mut buf = [0u8; 16];
+        for socket in [&first, &second] {
+            let (received, _) =
+                timeout(Duration::from_secs(1), socket.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(&buf[..received], b"shred");
+        }
+        assert!(resolve_targets(&["not an address".to_string()]).is_err());
+    }
+}
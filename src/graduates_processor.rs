@@ -1,41 +1,126 @@
-use log::{error, info};
-use solana_program::pubkey::Pubkey;
+use log::{error, info, warn};
+use serde::Serialize;
+use solana_sdk::clock::Slot;
 use solana_sdk::message::VersionedMessage;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::transaction::VersionedTransaction;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc;
 
-use crate::entry_processor::EntriesWithMeta;
+use crate::alt::LookupTableCache;
+use crate::arb::parse_new_pool;
+use crate::constants::{
+    PUMP_MIGRATION_PROGRAM_PUBKEY, RAYDIUM_AMM_PUBKEY, WSOL_PUBKEY,
+};
+use crate::entry_processor::{
+    EntriesWithMeta, ProcessorMetrics, SigWithMeta,
+};
+use crate::raydium::{parse_amm_instruction, ParsedAmmInstruction};
+use crate::rpc::{RateLimitedRpc, DEFAULT_RPC_CONCURRENCY};
+use crate::sink::{emit, idempotency_key, Sink, GRADUATE_TOPIC};
 
-const PUMP_MIGRATION_PROGRAM: &str =
-    "39azUYFWPz3VHgKCf3VChUwbpURdCHRxjWVowf5jUJjg";
-const RAYDIUM_LP_PROGRAM: &str =
-    "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+// size of an SPL token mint account, the token-2022 ones are longer with
+// the extensions but start the same
+const SPL_MINT_LEN: usize = 82;
 
-lazy_static::lazy_static! {
-    static ref PUMP_MIGRATION_PUBKEY: Pubkey = Pubkey::from_str(PUMP_MIGRATION_PROGRAM).unwrap();
-    static ref RAYDIUM_LP_PUBKEY: Pubkey = Pubkey::from_str(RAYDIUM_LP_PROGRAM).unwrap();
+#[derive(Debug, Clone)]
+pub struct GraduatesConfig {
+    /// Look the mint authority of the graduating tokens up over RPC
+    pub check_mint_authority: bool,
+    /// Mint lookups in flight at once
+    pub rpc_concurrency: usize,
+}
+
+impl Default for GraduatesConfig {
+    fn default() -> Self {
+        GraduatesConfig {
+            check_mint_authority: false,
+            rpc_concurrency: DEFAULT_RPC_CONCURRENCY,
+        }
+    }
 }
 
 pub struct GraduatesProcessor {
     entry_rx: mpsc::Receiver<EntriesWithMeta>,
     error_rx: mpsc::Receiver<String>,
-    sig_tx: mpsc::Sender<String>,
+    sig_tx: mpsc::Sender<SigWithMeta>,
+    sink: Sink,
+    rpc: Option<Arc<RateLimitedRpc>>,
+    lookup_tables: Arc<LookupTableCache>,
+    metrics: Arc<GraduatesMetrics>,
+}
+
+/// GraduatesMetrics are the counters of the graduates mode, the mint
+/// authority ones only with the lookups on
+#[derive(Debug, Default)]
+pub struct GraduatesMetrics {
+    graduations_detected: AtomicU64,
+    mint_authority_revoked: AtomicU64,
+    mint_authority_not_revoked: AtomicU64,
+    mint_authority_unknown: AtomicU64,
+}
+
+impl GraduatesMetrics {
+    fn record_mint_authority(&self, revoked: Option<bool>) {
+        let counter = match revoked {
+            Some(true) => &self.mint_authority_revoked,
+            Some(false) => &self.mint_authority_not_revoked,
+            None => &self.mint_authority_unknown,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl ProcessorMetrics for GraduatesMetrics {
+    fn mode(&self) -> &'static str {
+        "graduates"
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "graduations_detected":
+                self.graduations_detected.load(Ordering::Relaxed),
+            "mint_authority_revoked":
+                self.mint_authority_revoked.load(Ordering::Relaxed),
+            "mint_authority_not_revoked":
+                self.mint_authority_not_revoked.load(Ordering::Relaxed),
+            "mint_authority_unknown":
+                self.mint_authority_unknown.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraduateEvent {
+    pub sig: String,
+    pub slot: Slot,
+    /// The graduating token, the side of the new pool other than WSOL
+    pub mint: Option<String>,
+    /// Whether the mint authority of the token was revoked (no more can be
+    /// minted), None without the lookups or if the mint could not be
+    /// fetched
+    pub mint_authority_revoked: Option<bool>,
+    pub idempotency_key: String,
 }
 
-fn filter_transaction(transaction: &VersionedTransaction) -> bool {
+/// filter_transaction tells the migrations apart, V0 txs which take the
+/// pump migration account and call the Raydium AMM; see graduating_mint
+/// for `account_keys`
+fn filter_transaction(
+    transaction: &VersionedTransaction,
+    account_keys: &[Pubkey],
+) -> bool {
     if let VersionedMessage::V0(message) = &transaction.message {
         // Check if the transaction is signed by PUMP_MIGRATION_PROGRAM
-        let is_signed_by_pump = message
-            .account_keys
+        let is_signed_by_pump = account_keys
             .iter()
-            .any(|key| key == &*PUMP_MIGRATION_PUBKEY);
+            .any(|key| key == &*PUMP_MIGRATION_PROGRAM_PUBKEY);
 
-        // Check if any instruction uses RAYDIUM_LP_PROGRAM
+        // Check if any instruction uses the Raydium AMM (LP) program
         let uses_raydium = message.instructions.iter().any(|instruction| {
-            let program_id =
-                &message.account_keys[instruction.program_id_index as usize];
-            program_id == &*RAYDIUM_LP_PUBKEY
+            account_keys.get(instruction.program_id_index as usize)
+                == Some(&*RAYDIUM_AMM_PUBKEY)
         });
 
         return is_signed_by_pump && uses_raydium;
@@ -43,24 +128,117 @@ fn filter_transaction(transaction: &VersionedTransaction) -> bool {
     false
 }
 
+/// graduating_mint is the token the migration creates the Raydium pool of,
+/// read off of its Initialize2; `account_keys` include those loaded from
+/// the lookup tables, see LookupTableCache::account_keys
+pub fn graduating_mint(
+    transaction: &VersionedTransaction,
+    account_keys: &[Pubkey],
+) -> Option<Pubkey> {
+    let signature = transaction.signatures.first()?;
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .find_map(|instruction| {
+            if account_keys.get(instruction.program_id_index as usize)
+                != Some(&*RAYDIUM_AMM_PUBKEY)
+            {
+                return None;
+            }
+            let Ok(ParsedAmmInstruction::Initialize2(initialize)) =
+                parse_amm_instruction(&instruction.data)
+            else {
+                return None;
+            };
+            let pool = parse_new_pool(
+                &initialize,
+                instruction,
+                account_keys,
+                signature,
+                0,
+            )?;
+            Some(if pool.coin_mint == *WSOL_PUBKEY {
+                pool.pc_mint
+            } else {
+                pool.coin_mint
+            })
+        })
+}
+
+/// mint_authority_revoked reads the mint authority (a COption<Pubkey>, its
+/// u32 tag up front) off of the mint account data, None if it is not a mint
+pub fn mint_authority_revoked(data: &[u8]) -> Option<bool> {
+    if data.len() < SPL_MINT_LEN {
+        return None;
+    }
+    match data[..4] {
+        [0, 0, 0, 0] => Some(true),
+        [1, 0, 0, 0] => Some(false),
+        _ => None,
+    }
+}
+
+/// fetch_mint_authority_revoked is None for the mints that cannot be
+/// fetched, e.g. not yet visible at the commitment of the RPC
+pub async fn fetch_mint_authority_revoked(
+    rpc: &RateLimitedRpc,
+    mint: &Pubkey,
+) -> Option<bool> {
+    match rpc.call(|client| client.get_account_data(mint)).await {
+        Ok(data) => mint_authority_revoked(&data),
+        Err(e) => {
+            warn!("Failed to fetch mint {}: {}", mint, e);
+            None
+        }
+    }
+}
+
 impl GraduatesProcessor {
     pub fn new(
         entry_rx: mpsc::Receiver<EntriesWithMeta>,
         error_rx: mpsc::Receiver<String>,
-        sig_tx: mpsc::Sender<String>,
+        sig_tx: mpsc::Sender<SigWithMeta>,
+        sink: Sink,
     ) -> Self {
         Self {
             entry_rx,
             error_rx,
             sig_tx,
+            sink,
+            rpc: None,
+            lookup_tables: Arc::default(),
+            metrics: Arc::new(GraduatesMetrics::default()),
         }
     }
 
+    /// with_lookup_tables resolves the accounts the V0 txs load from lookup
+    /// tables, only the static keys are read without it
+    pub fn with_lookup_tables(
+        mut self,
+        lookup_tables: Arc<LookupTableCache>,
+    ) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
+    }
+
+    /// with_rpc looks the mint authority of the graduating tokens up, the
+    /// events are held back until the lookup is done
+    pub fn with_rpc(mut self, rpc: Arc<RateLimitedRpc>) -> Self {
+        self.rpc = Some(rpc);
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<GraduatesMetrics> {
+        self.metrics.clone()
+    }
+
     pub async fn receive_entries(&mut self) {
         loop {
             tokio::select! {
                 Some(entries) = self.entry_rx.recv() => {
-                    self.process_entries(entries).await;
+                    let trace = entries.trace.clone();
+                    trace.instrument(self.process_entries(entries)).await;
                 }
                 Some(error) = self.error_rx.recv() => {
                     error!("{}", error);
@@ -75,12 +253,40 @@ impl GraduatesProcessor {
     ) {
         for entry in entries_with_meta.entries {
             for tx in entry.transactions {
-                if filter_transaction(&tx) {
+                // the programs are never loaded from the lookup tables, only
+                // the txs calling the AMM are resolved
+                if !tx
+                    .message
+                    .static_account_keys()
+                    .contains(&RAYDIUM_AMM_PUBKEY)
+                {
+                    continue;
+                }
+                let account_keys =
+                    self.lookup_tables.account_keys(&tx.message);
+                if filter_transaction(&tx, &account_keys) {
                     info!("Found matching transaction: {:?}", tx.signatures);
                     if let Some(sig) = tx.signatures.first() {
-                        if let Err(e) =
-                            self.sig_tx.send(sig.to_string()).await
-                        {
+                        let mint = graduating_mint(&tx, &account_keys);
+                        let event = GraduateEvent {
+                            sig: sig.to_string(),
+                            slot: entries_with_meta.slot,
+                            mint: mint.map(|mint| mint.to_string()),
+                            mint_authority_revoked: None,
+                            idempotency_key: idempotency_key(
+                                GRADUATE_TOPIC,
+                                &[&sig.to_string()],
+                            ),
+                        };
+                        self.metrics
+                            .graduations_detected
+                            .fetch_add(1, Ordering::Relaxed);
+                        self.emit_event(event, mint).await;
+                        let sig = SigWithMeta {
+                            sig: sig.to_string(),
+                            received_at: entries_with_meta.received_at,
+                        };
+                        if let Err(e) = self.sig_tx.send(sig).await {
                             error!("Failed to send signature: {}", e);
                         }
                     }
@@ -88,5 +294,85 @@ impl GraduatesProcessor {
             }
         }
     }
+
+    async fn emit_event(
+        &self,
+        mut event: GraduateEvent,
+        mint: Option<Pubkey>,
+    ) {
+        let Some(rpc) = self.rpc.clone() else {
+            emit(&*self.sink, GRADUATE_TOPIC, &event).await;
+            return;
+        };
+        // the lookup takes a round trip or more, so it runs off of the
+        // detection loop
+        let sink = self.sink.clone();
+        let metrics = self.metrics.clone();
+        tokio::spawn(async move {
+            if let Some(mint) = mint {
+                event.mint_authority_revoked =
+                    fetch_mint_authority_revoked(&rpc, &mint).await;
+            }
+            metrics.record_mint_authority(event.mint_authority_revoked);
+            emit(&*sink, GRADUATE_TOPIC, &event).await;
+        });
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::v0;
+
+    #[test]
+    fn reads_the_mint_authority() {
+        let mut data = vec![0u8; SPL_MINT_LEN];
+        assert_eq!(mint_authority_revoked(&data), Some(true));
+        data[0] = 1;
+        data[4..36].copy_from_slice(&Pubkey::new_unique().to_bytes());
+        assert_eq!(mint_authority_revoked(&data), Some(false));
+        // not a mint
+        assert_eq!(mint_authority_revoked(&data[..40]), None);
+        data[0] = 2;
+        assert_eq!(mint_authority_revoked(&data), None);
+    }
+
+    #[test]
+    fn finds_the_graduating_mint() {
+        let mut account_keys =
+            (0..21).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        // the pc side (9th) is WSOL, the token is the coin side (8th)
+        account_keys[9] = *WSOL_PUBKEY;
+        account_keys.push(*RAYDIUM_AMM_PUBKEY);
+        let mut data = vec![1, 254];
+        data.extend_from_slice(&1_700_000_000u64.to_le_bytes()); // open time
+        data.extend_from_slice(&79u64.to_le_bytes()); // pc
+        data.extend_from_slice(&206u64.to_le_bytes()); // coin
+        let tx = VersionedTransaction {
+            signatures: vec![Default::default()],
+            message: VersionedMessage::V0(v0::Message {
+                account_keys: account_keys.clone(),
+                instructions: vec![CompiledInstruction {
+                    program_id_index: 21,
+                    accounts: (0..21).collect(),
+                    data,
+                }],
+                ..Default::default()
+            }),
+        };
+        assert_eq!(
+            graduating_mint(&tx, &account_keys),
+            Some(account_keys[8])
+        );
+    }
+
+    #[tokio::test]
+    async fn unreachable_mints_are_unknown() {
+        let rpc = RateLimitedRpc::new("http://127.0.0.1:1".to_string(), 1);
+        assert_eq!(
+            fetch_mint_authority_revoked(&rpc, &Pubkey::new_unique()).await,
+            None
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use bytes::Bytes;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::shred_processor::ShredProcessor;
+
+/// Packets waiting for the shred processor, past this the receive loop
+/// drops them rather than waiting, about a second of a busy shredstream
+pub const DEFAULT_INGEST_QUEUE_SIZE: usize = 65_536;
+
+/// Most packets collected per take of the processor lock
+pub const INGEST_BATCH_SIZE: usize = 256;
+
+/// IngestTx is the receive loop end of the ingest queue, pushing never
+/// waits, so the kernel is drained at the rate packets come in regardless
+/// of how long the processor holds its lock
+#[derive(Debug, Clone)]
+pub struct IngestTx {
+    tx: flume::Sender<Bytes>,
+    metrics: Arc<IngestMetrics>,
+}
+
+/// IngestRx is the shred processor end of the ingest queue
+#[derive(Debug)]
+pub struct IngestRx {
+    rx: flume::Receiver<Bytes>,
+    metrics: Arc<IngestMetrics>,
+}
+
+#[derive(Debug, Default)]
+pub struct IngestMetrics {
+    capacity: AtomicU64,
+    // as of the last batch collected
+    depth: AtomicU64,
+    max_depth: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl IngestMetrics {
+    pub fn depth(&self) -> u64 {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "capacity": self.capacity.load(Ordering::Relaxed),
+            "depth": self.depth(),
+            "max_depth": self.max_depth.swap(0, Ordering::Relaxed),
+            "dropped": self.dropped(),
+        })
+    }
+
+    fn record_depth(&self, depth: usize) {
+        self.depth.store(depth as u64, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth as u64, Ordering::Relaxed);
+    }
+}
+
+/// channel makes the bounded queue between the receive loop and the shred
+/// processor
+pub fn channel(capacity: usize) -> (IngestTx, IngestRx) {
+    let (tx, rx) = flume::bounded(capacity);
+    let metrics = Arc::new(IngestMetrics::default());
+    metrics.capacity.store(capacity as u64, Ordering::Relaxed);
+    (
+        IngestTx {
+            tx,
+            metrics: metrics.clone(),
+        },
+        IngestRx { rx, metrics },
+    )
+}
+
+impl IngestTx {
+    /// push queues the packet, it is dropped (and counted) if the queue is
+    /// full
+    pub fn push(&self, packet: Bytes) {
+        if self.tx.try_send(packet).is_err() {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl IngestRx {
+    pub fn metrics(&self) -> Arc<IngestMetrics> {
+        self.metrics.clone()
+    }
+
+    /// run feeds the queued packets into the processor until every IngestTx
+    /// is dropped, whatever piled up while waiting for the lock is collected
+    /// under the same lock
+    pub async fn run(self, shred_processor: Arc<RwLock<ShredProcessor>>) {
+        while let Ok(packet) = self.rx.recv_async().await {
+            let mut shred_processor = shred_processor.write().await;
+            shred_processor.collect_bytes(packet).await;
+            for packet in self.rx.try_iter().take(INGEST_BATCH_SIZE - 1) {
+                shred_processor.collect_bytes(packet).await;
+            }
+            drop(shred_processor);
+            self.metrics.record_depth(self.rx.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn queues_packets_for_the_processor() {
+        let (tx, rx) = channel(2);
+        let metrics = rx.metrics();
+        for _ in 0..3 {
+            tx.push(Bytes::from_static(&[0xff; 10]));
+        }
+        assert_eq!(metrics.dropped(), 1);
+
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let shred_processor =
+            Arc::new(RwLock::new(ShredProcessor::new(entry_tx, error_tx)));
+        drop(tx);
+        rx.run(shred_processor.clone()).await;
+
+        let stats: serde_json::Value =
+            serde_json::from_str(&shred_processor.read().await.metrics())
+                .unwrap();
+        assert_eq!(stats["malformed_shreds"], 2);
+        assert_eq!(metrics.depth(), 0);
+    }
+}
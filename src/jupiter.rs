@@ -0,0 +1,294 @@
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::arb::get_account_key_safely;
+use crate::constants;
+use crate::raydium::ParsedAccounts;
+
+// anchor discriminators of the Jupiter v6 routes, sha256("global:<name>")
+pub const ROUTE_DISCRIMINATOR: [u8; 8] =
+    [229, 23, 203, 151, 122, 227, 173, 42];
+pub const SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR: [u8; 8] =
+    [193, 32, 155, 51, 65, 214, 156, 129];
+pub const EXACT_OUT_ROUTE_DISCRIMINATOR: [u8; 8] =
+    [208, 51, 239, 151, 123, 43, 237, 92];
+pub const SHARED_ACCOUNTS_EXACT_OUT_ROUTE_DISCRIMINATOR: [u8; 8] =
+    [176, 209, 105, 168, 154, 125, 69, 62];
+
+// the routes end with the two amounts, slippage_bps (u16) and
+// platform_fee_bps (u8), whatever the steps of the route plan before them
+const ROUTE_TAIL_LEN: usize = 8 + 8 + 2 + 1;
+
+// positions of the accounts of a Raydium AMM step within the remaining
+// accounts of the route, off of the AMM program account; unlike a direct
+// swap there is no token program nor target orders
+const RAYDIUM_STEP_AMM_ID_OFFSET: usize = 1;
+const RAYDIUM_STEP_POOL_COIN_VAULT_OFFSET: usize = 4;
+const RAYDIUM_STEP_POOL_PC_VAULT_OFFSET: usize = 5;
+
+// positions of the mints among the accounts of the routes, `route` has no
+// source mint
+const ROUTE_DESTINATION_MINT_INDEX: usize = 5;
+const SHARED_ACCOUNTS_ROUTE_SOURCE_MINT_INDEX: usize = 7;
+const EXACT_OUT_ROUTE_SOURCE_MINT_INDEX: usize = 5;
+
+/// JupiterRoute is what the route instructions tell of the swap: `amount`
+/// is the input and `quoted_amount` the output for the exact in routes,
+/// the other way around for the exact out ones. `quoted_amount` is the
+/// quote Jupiter made when the tx was built, the executed amount is only
+/// bound by it through the slippage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JupiterRoute {
+    pub steps: u32,
+    pub amount: u64,
+    pub quoted_amount: u64,
+    pub exact_out: bool,
+}
+
+impl JupiterRoute {
+    /// decode is None for the other instructions of the program
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+        // the shared accounts routes have the id (u8) before the plan
+        let (plan_offset, exact_out) = match discriminator {
+            ROUTE_DISCRIMINATOR => (8, false),
+            SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR => (9, false),
+            EXACT_OUT_ROUTE_DISCRIMINATOR => (8, true),
+            SHARED_ACCOUNTS_EXACT_OUT_ROUTE_DISCRIMINATOR => (9, true),
+            _ => return None,
+        };
+        let tail_start = data.len().checked_sub(ROUTE_TAIL_LEN)?;
+        if plan_offset + 4 > tail_start {
+            return None;
+        }
+        let steps = &data[plan_offset..plan_offset + 4];
+        let tail = &data[tail_start..];
+        Some(JupiterRoute {
+            steps: u32::from_le_bytes(steps.try_into().ok()?),
+            amount: u64::from_le_bytes(tail[..8].try_into().ok()?),
+            quoted_amount: u64::from_le_bytes(tail[8..16].try_into().ok()?),
+            exact_out,
+        })
+    }
+}
+
+/// RouteMints are the mints the route swaps from and to, as far as the
+/// accounts of the route tell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteMints {
+    pub source: Option<Pubkey>,
+    pub destination: Option<Pubkey>,
+}
+
+impl RouteMints {
+    /// from_route reads the mints off of the accounts of a route
+    /// instruction, both are None for the other instructions
+    pub fn from_route(
+        account_keys: &[Pubkey],
+        instruction: &CompiledInstruction,
+    ) -> Self {
+        let key_at = |position| {
+            get_account_key_safely(account_keys, instruction, position)
+        };
+        let Some(discriminator) = instruction
+            .data
+            .get(..8)
+            .and_then(|data| <[u8; 8]>::try_from(data).ok())
+        else {
+            return RouteMints::default();
+        };
+        // the destination mint follows the source one where there is one
+        let source_index = match discriminator {
+            ROUTE_DISCRIMINATOR => {
+                return RouteMints {
+                    source: None,
+                    destination: key_at(ROUTE_DESTINATION_MINT_INDEX),
+                }
+            }
+            SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR
+            | SHARED_ACCOUNTS_EXACT_OUT_ROUTE_DISCRIMINATOR => {
+                SHARED_ACCOUNTS_ROUTE_SOURCE_MINT_INDEX
+            }
+            EXACT_OUT_ROUTE_DISCRIMINATOR => {
+                EXACT_OUT_ROUTE_SOURCE_MINT_INDEX
+            }
+            _ => return RouteMints::default(),
+        };
+        RouteMints {
+            source: key_at(source_index),
+            destination: key_at(source_index + 1),
+        }
+    }
+
+    /// is_input tells if `mint` goes into a single step route, off of the
+    /// destination if the source is not known
+    pub fn is_input(&self, mint: &Pubkey) -> Option<bool> {
+        match (self.source, self.destination) {
+            (Some(source), _) => Some(source == *mint),
+            (None, Some(destination)) => Some(destination != *mint),
+            (None, None) => None,
+        }
+    }
+}
+
+/// raydium_amm_steps are the accounts of the Raydium AMM pools the route
+/// goes through, in order; the swaps themselves are CPIs, which are not in
+/// the shreds, so the pools are told by the AMM program among the accounts
+pub fn raydium_amm_steps(
+    account_keys: &[Pubkey],
+    instruction: &CompiledInstruction,
+) -> Vec<ParsedAccounts> {
+    let key_at = |position| {
+        get_account_key_safely(account_keys, instruction, position)
+    };
+    (0..instruction.accounts.len())
+        .filter(|&position| {
+            key_at(position) == Some(*constants::RAYDIUM_AMM_PUBKEY)
+        })
+        .filter_map(|position| {
+            Some(ParsedAccounts {
+                amm_id: key_at(position + RAYDIUM_STEP_AMM_ID_OFFSET)?,
+                pool_coin_vault: key_at(
+                    position + RAYDIUM_STEP_POOL_COIN_VAULT_OFFSET,
+                )?,
+                pool_pc_vault: key_at(
+                    position + RAYDIUM_STEP_POOL_PC_VAULT_OFFSET,
+                )?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route_ix(
+        discriminator: [u8; 8],
+        plan: &[u8],
+        amount: u64,
+        quoted_amount: u64,
+    ) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        data.extend(plan);
+        data.extend(amount.to_le_bytes());
+        data.extend(quoted_amount.to_le_bytes());
+        data.extend(50u16.to_le_bytes()); // slippage_bps
+        data.push(0); // platform_fee_bps
+        data
+    }
+
+    #[test]
+    fn decodes_routes() {
+        // a single step, the swap variant and percent, input and output
+        // indexes are not read
+        let plan = [&1u32.to_le_bytes()[..], &[7, 100, 0, 1]].concat();
+        assert_eq!(
+            JupiterRoute::decode(&route_ix(
+                ROUTE_DISCRIMINATOR,
+                &plan,
+                1_000,
+                990
+            )),
+            Some(JupiterRoute {
+                steps: 1,
+                amount: 1_000,
+                quoted_amount: 990,
+                exact_out: false,
+            })
+        );
+        let shared_plan = [&[3][..], &plan].concat();
+        assert_eq!(
+            JupiterRoute::decode(&route_ix(
+                SHARED_ACCOUNTS_EXACT_OUT_ROUTE_DISCRIMINATOR,
+                &shared_plan,
+                500,
+                510
+            )),
+            Some(JupiterRoute {
+                steps: 1,
+                amount: 500,
+                quoted_amount: 510,
+                exact_out: true,
+            })
+        );
+        assert_eq!(
+            JupiterRoute::decode(&route_ix([0; 8], &plan, 1_000, 990)),
+            None
+        );
+        assert_eq!(JupiterRoute::decode(&ROUTE_DISCRIMINATOR), None);
+        assert_eq!(
+            JupiterRoute::decode(&route_ix(ROUTE_DISCRIMINATOR, &[], 1, 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_the_route_mints() {
+        let account_keys =
+            (0..16).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let instruction = |discriminator: [u8; 8]| CompiledInstruction {
+            program_id_index: 0,
+            accounts: (1..16).collect(),
+            data: route_ix(discriminator, &[0; 4], 1_000, 990),
+        };
+
+        let mints = RouteMints::from_route(
+            &account_keys,
+            &instruction(SHARED_ACCOUNTS_ROUTE_DISCRIMINATOR),
+        );
+        assert_eq!(mints.source, Some(account_keys[8]));
+        assert_eq!(mints.destination, Some(account_keys[9]));
+        assert_eq!(mints.is_input(&account_keys[8]), Some(true));
+        assert_eq!(mints.is_input(&account_keys[9]), Some(false));
+
+        let mints = RouteMints::from_route(
+            &account_keys,
+            &instruction(EXACT_OUT_ROUTE_DISCRIMINATOR),
+        );
+        assert_eq!(mints.source, Some(account_keys[6]));
+        assert_eq!(mints.destination, Some(account_keys[7]));
+
+        // only the destination, whatever is not it goes in
+        let mints = RouteMints::from_route(
+            &account_keys,
+            &instruction(ROUTE_DISCRIMINATOR),
+        );
+        assert_eq!(mints.source, None);
+        assert_eq!(mints.is_input(&account_keys[6]), Some(false));
+        assert_eq!(mints.is_input(&account_keys[1]), Some(true));
+
+        let mints =
+            RouteMints::from_route(&account_keys, &instruction([0; 8]));
+        assert_eq!(mints, RouteMints::default());
+        assert_eq!(mints.is_input(&account_keys[1]), None);
+    }
+
+    #[test]
+    fn finds_the_raydium_amm_steps() {
+        let mut account_keys =
+            (0..24).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        account_keys[3] = *constants::RAYDIUM_AMM_PUBKEY;
+        // the route accounts, the Raydium step from the 3rd one on
+        let instruction = CompiledInstruction {
+            program_id_index: 0,
+            accounts: (1..24).collect(),
+            data: vec![],
+        };
+        assert_eq!(
+            raydium_amm_steps(&account_keys, &instruction),
+            vec![ParsedAccounts {
+                amm_id: account_keys[4],
+                pool_coin_vault: account_keys[7],
+                pool_pc_vault: account_keys[8],
+            }]
+        );
+
+        // cut off before the vaults
+        let truncated = CompiledInstruction {
+            accounts: (1..6).collect(),
+            ..instruction
+        };
+        assert!(raydium_amm_steps(&account_keys, &truncated).is_empty());
+    }
+}
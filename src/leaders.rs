@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Context};
+use log::info;
+use serde::Serialize;
+use solana_sdk::clock::{Epoch, Slot};
+use solana_sdk::epoch_schedule::EpochSchedule;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::rpc::{RateLimitedRpc, DEFAULT_RPC_CONCURRENCY};
+use crate::util::require_env;
+
+/// Prefix of the cached schedules, `leader_schedule-<epoch>.json`
+pub const LEADER_SCHEDULE_PREFIX: &str = "leader_schedule";
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SlotLeader {
+    pub slot: Slot,
+    pub leader: String,
+}
+
+/// run prints the leader of every slot from `from` through `to` as a
+/// table, or as JSON with `json`; the schedule of each epoch is fetched
+/// from RPC_URL once and cached in `cache_dir`
+pub async fn run(
+    from: Slot,
+    to: Slot,
+    cache_dir: &Path,
+    json: bool,
+) -> anyhow::Result<()> {
+    if from > to {
+        return Err(anyhow!("Slot {} is past slot {}", from, to));
+    }
+    let rpc = RateLimitedRpc::new(
+        require_env("RPC_URL", "leaders")?,
+        DEFAULT_RPC_CONCURRENCY,
+    );
+    let epoch_schedule = rpc
+        .call(|client| client.get_epoch_schedule())
+        .await
+        .context("Failed to get the epoch schedule")?;
+
+    let mut leaders = Vec::new();
+    for epoch in epoch_schedule.get_epoch(from)..=epoch_schedule.get_epoch(to)
+    {
+        let schedule =
+            load_epoch_leaders(&rpc, &epoch_schedule, epoch, cache_dir)
+                .await?;
+        leaders.extend(slot_leaders(
+            &schedule,
+            epoch_schedule.get_first_slot_in_epoch(epoch),
+            from,
+            to,
+        ));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&leaders)?);
+    } else {
+        print_leaders(&leaders);
+    }
+    Ok(())
+}
+
+fn cache_path(cache_dir: &Path, epoch: Epoch) -> PathBuf {
+    cache_dir.join(format!("{}-{}.json", LEADER_SCHEDULE_PREFIX, epoch))
+}
+
+/// load_epoch_leaders is the leader of each slot of the epoch, by its index
+/// in the epoch, read from the cache if it has been fetched before (the
+/// schedule of an epoch does not change once it is out)
+async fn load_epoch_leaders(
+    rpc: &RateLimitedRpc,
+    epoch_schedule: &EpochSchedule,
+    epoch: Epoch,
+    cache_dir: &Path,
+) -> anyhow::Result<Vec<Option<String>>> {
+    let path = cache_path(cache_dir, epoch);
+    if let Ok(data) = std::fs::read_to_string(&path) {
+        if let Ok(leaders) = serde_json::from_str(&data) {
+            return Ok(leaders);
+        }
+    }
+
+    let first_slot = epoch_schedule.get_first_slot_in_epoch(epoch);
+    info!("Fetching the leader schedule of epoch {}", epoch);
+    let schedule = rpc
+        .call(|client| client.get_leader_schedule(Some(first_slot)))
+        .await
+        .context("Failed to get the leader schedule")?
+        .ok_or_else(|| {
+            anyhow!("No leader schedule for epoch {} yet", epoch)
+        })?;
+    let leaders =
+        epoch_leaders(schedule, epoch_schedule.get_slots_in_epoch(epoch));
+
+    std::fs::create_dir_all(cache_dir)?;
+    std::fs::write(&path, serde_json::to_string(&leaders)?)?;
+    Ok(leaders)
+}
+
+/// epoch_leaders lays the schedule out by slot, the RPC gives the slot
+/// indexes of each leader within the epoch
+pub fn epoch_leaders(
+    schedule: HashMap<String, Vec<usize>>,
+    slots_in_epoch: u64,
+) -> Vec<Option<String>> {
+    let mut leaders = vec![None; slots_in_epoch as usize];
+    for (leader, slot_indexes) in schedule {
+        for slot_index in slot_indexes {
+            if let Some(slot_leader) = leaders.get_mut(slot_index) {
+                *slot_leader = Some(leader.clone());
+            }
+        }
+    }
+    leaders
+}
+
+/// slot_leaders picks the slots from `from` through `to` out of the leaders
+/// of the epoch starting at `first_slot`
+pub fn slot_leaders(
+    epoch_leaders: &[Option<String>],
+    first_slot: Slot,
+    from: Slot,
+    to: Slot,
+) -> Vec<SlotLeader> {
+    (from.max(first_slot)..=to)
+        .map_while(|slot| {
+            let leader = epoch_leaders.get((slot - first_slot) as usize)?;
+            Some((slot, leader))
+        })
+        .filter_map(|(slot, leader)| {
+            leader.clone().map(|leader| SlotLeader { slot, leader })
+        })
+        .collect()
+}
+
+pub fn print_leaders(leaders: &[SlotLeader]) {
+    println!("{:<12} leader", "slot");
+    for SlotLeader { slot, leader } in leaders {
+        println!("{:<12} {}", slot, leader);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_the_schedule_out_by_slot() {
+        let schedule = HashMap::from([
+            ("A".to_string(), vec![0, 1, 2, 3]),
+            ("B".to_string(), vec![4, 5, 6, 7]),
+            // past the end of the epoch
+            ("C".to_string(), vec![8]),
+        ]);
+        let leaders = epoch_leaders(schedule, 8);
+        assert_eq!(leaders.len(), 8);
+
+        // the range is clipped to the epoch starting at slot 100
+        let picked = slot_leaders(&leaders, 100, 90, 104);
+        assert_eq!(
+            picked,
+            (100..=104)
+                .map(|slot| SlotLeader {
+                    slot,
+                    leader: if slot < 104 { "A" } else { "B" }.to_string(),
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(slot_leaders(&leaders, 100, 106, 120).len(), 2);
+        assert!(slot_leaders(&leaders, 100, 108, 120).is_empty());
+    }
+}
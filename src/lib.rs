@@ -1,16 +1,46 @@
+pub mod admin;
+pub mod alt;
+pub mod analyze;
 pub mod app;
 pub mod arb;
 pub mod benchmark;
+pub mod circuit_breaker;
 pub mod constants;
+pub mod creators;
+pub mod decode;
+pub mod dedup;
+pub mod enrich;
 pub mod entry_processor;
+pub mod executor;
+pub mod forward;
 pub mod graduates_processor;
+pub mod ingest;
+pub mod jupiter;
+pub mod leaders;
 pub mod listener;
 pub mod logger;
+pub mod otel;
+#[cfg(feature = "parquet")]
+pub mod parquet_processor;
+#[cfg(feature = "proto")]
+pub mod proto;
 pub mod pump;
+pub mod queue;
 pub mod raydium;
+pub mod raydium_clmm;
 pub mod recovery;
+pub mod reorder;
+pub mod rpc;
+pub mod sandwich;
 pub mod service;
 pub mod shred;
 pub mod shred_processor;
+pub mod sink;
+pub mod source;
 pub mod structs;
+pub mod udp;
 pub mod util;
+pub mod validate;
+pub mod volume;
+pub mod wallet;
+pub mod watchdog;
@@ -1,28 +1,51 @@
+use bytes::Bytes;
 use log::{error, info};
+use solana_ledger::shred::layout;
 use std::io::Write;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
 use tokio::signal;
 use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 
-use crate::arb::PoolsState;
+use crate::arb::{run_pool_loader, PoolsState};
 use crate::benchmark::Sigs;
-use crate::entry_processor::{ArbEntryProcessor, PumpEntryProcessor};
+use crate::entry_processor::{
+    ArbEntryProcessor, PumpConfig, PumpEntryProcessor, SigWithMeta,
+};
+#[cfg(feature = "parquet")]
+use crate::parquet_processor::ParquetProcessor;
+use crate::queue::{QueueDepth, QueueSizes};
+use crate::rpc::RateLimitedRpc;
 use crate::service::Mode;
-use crate::shred_processor::ShredProcessor;
+use crate::shred_processor::{ShredProcessor, SlotRange};
+use crate::sink::Sink;
+use crate::udp::{self, UdpMetrics};
 
+/// Default receive buffer size, the max shred size (the IPv6 minimum MTU
+/// without the IP and UDP headers), sources framing larger datagrams need
+/// `--packet-size`
 pub const PACKET_SIZE: usize = 1280 - 40 - 8;
 
 pub async fn listen(
     socket: Arc<UdpSocket>,
     received_packets: Arc<Mutex<Vec<Vec<u8>>>>,
+    slot_range: SlotRange,
+    packet_size: usize,
 ) {
-    let mut buf = [0u8; PACKET_SIZE]; // max shred size
+    let mut buf = udp::receive_buffer(packet_size);
+    let udp_metrics = UdpMetrics::default();
     loop {
         match socket.recv_from(&mut buf).await {
             Ok((received, _)) => {
+                let received =
+                    udp_metrics.check_truncation(received, packet_size);
                 let packet = Vec::from(&buf[..received]);
+                match layout::get_slot(&packet) {
+                    Some(slot) if slot_range.contains(slot) => {}
+                    _ => continue,
+                }
                 received_packets.lock().await.push(packet);
             }
             Err(e) => {
@@ -45,28 +68,82 @@ pub async fn dump_to_file(received_packets: Arc<Mutex<Vec<Vec<u8>>>>) {
 
 pub async fn run_listener_with_algo(
     bind_addr: &str,
+    rcvbuf_bytes: usize,
+    packet_size: usize,
+    queue_sizes: QueueSizes,
+    slot_range: SlotRange,
     shreds_sigs: Option<Sigs>,
     mode: Mode,
-    post_url: String,
+    sink: Sink,
     benchmark: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let socket = Arc::new(
-        UdpSocket::bind(bind_addr)
-            .await
-            .expect("Couldn't bind to address"),
+        udp::bind(bind_addr, rcvbuf_bytes).expect("Couldn't bind to address"),
     );
-    let (entry_tx, entry_rx) = tokio::sync::mpsc::channel(2000);
-    let (error_tx, error_rx) = tokio::sync::mpsc::channel(2000);
-    let (sig_tx, mut sig_rx) = tokio::sync::mpsc::channel(2000);
-    let shred_processor =
-        Arc::new(RwLock::new(ShredProcessor::new(entry_tx, error_tx)));
-
     info!("Listening on {}", bind_addr);
+    let listener = spawn_listener_with_algo(
+        socket,
+        packet_size,
+        queue_sizes,
+        slot_range,
+        shreds_sigs,
+        mode,
+        sink,
+        benchmark,
+    )
+    .await?;
+
+    signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    listener.shutdown().await;
+
+    Ok(())
+}
+
+/// ListenerTasks are the tasks of a listener started with
+/// spawn_listener_with_algo
+pub struct ListenerTasks {
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl ListenerTasks {
+    /// shutdown aborts the tasks and waits for them to wind down, which
+    /// drops the socket and the channels between them
+    pub async fn shutdown(self) {
+        for handle in self.handles.iter() {
+            handle.abort();
+        }
+        for handle in self.handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+/// spawn_listener_with_algo starts the receive loop on `socket` with the
+/// shred and entry processors of `mode` behind it, the signatures the entry
+/// processor reports are pushed to `shreds_sigs` with `benchmark`
+pub async fn spawn_listener_with_algo(
+    socket: Arc<UdpSocket>,
+    packet_size: usize,
+    queue_sizes: QueueSizes,
+    slot_range: SlotRange,
+    shreds_sigs: Option<Sigs>,
+    mode: Mode,
+    sink: Sink,
+    benchmark: bool,
+) -> std::io::Result<ListenerTasks> {
+    let (entry_tx, entry_rx) =
+        tokio::sync::mpsc::channel(queue_sizes.entries);
+    let (error_tx, error_rx) = tokio::sync::mpsc::channel(queue_sizes.errors);
+    let entry_queue = QueueDepth::new(&entry_tx);
+    let (sig_tx, mut sig_rx) = tokio::sync::mpsc::channel(2000);
+    let shred_processor = Arc::new(RwLock::new(
+        ShredProcessor::new(entry_tx, error_tx).with_slot_range(slot_range),
+    ));
 
     // metrics loop
     info!("Starting metrics loop");
     let shred_processor_clone = shred_processor.clone();
-    tokio::spawn(async move {
+    let metrics_handle = tokio::spawn(async move {
         loop {
             sleep(Duration::from_secs(6)).await;
             {
@@ -74,24 +151,27 @@ pub async fn run_listener_with_algo(
                 info!("metrics: {}", metrics);
                 drop(metrics);
             }
+            info!("entry queue metrics: {}", entry_queue.to_json());
         }
     });
 
     info!("Starting shred processor");
-    let mut buf = [0u8; PACKET_SIZE]; // max shred size
+    let mut buf = udp::receive_buffer(packet_size);
     let shred_processor = shred_processor.clone();
-    tokio::spawn(async move {
+    let udp_metrics = shred_processor.read().await.udp_metrics();
+    udp_metrics.add_local_port(socket.local_addr()?.port());
+    let receiver_handle = tokio::spawn(async move {
         loop {
             match socket.recv_from(&mut buf).await {
                 Ok((received, _)) => {
-                    let packet = Vec::from(&buf[..received]);
-                    shred_processor
-                        .write()
-                        .await
-                        .collect(Arc::new(packet))
-                        .await;
+                    udp_metrics.record_packet();
+                    let received =
+                        udp_metrics.check_truncation(received, packet_size);
+                    let packet = Bytes::copy_from_slice(&buf[..received]);
+                    shred_processor.write().await.collect_bytes(packet).await;
                 }
                 Err(e) => {
+                    udp_metrics.record_recv_error();
                     error!("Error receiving packet: {:?}", e);
                 }
             }
@@ -99,43 +179,69 @@ pub async fn run_listener_with_algo(
     });
 
     info!("Starting entry processor");
-    match mode {
-        Mode::Arb => tokio::spawn(async move {
-            let pools_state = Arc::new(RwLock::new(PoolsState::default()));
-            pools_state.write().await.initialize().await;
-            let mut entry_processor = ArbEntryProcessor::new(
-                entry_rx,
-                error_rx,
-                pools_state.clone(),
-                sig_tx,
-            );
-            entry_processor.receive_entries().await;
-        }),
-        Mode::Pump => {
-            info!("Starting entries rx (<=> webhook tx) pump mode");
+    let entry_processor_handle = match mode {
+        Mode::Arb(arb_config) => {
+            let rpc = RateLimitedRpc::from_env(arb_config.rpc_concurrency)?;
+            tokio::spawn(async move {
+                let pools_state = Arc::new(RwLock::new(PoolsState {
+                    fee_schedule: arb_config.fee_schedule.clone(),
+                    rpc: Some(Arc::new(rpc)),
+                    ..Default::default()
+                }));
+                pools_state.write().await.initialize().await;
+                if arb_config.lazy_load_pools {
+                    tokio::spawn(run_pool_loader(pools_state.clone()));
+                }
+                let mut entry_processor = ArbEntryProcessor::new(
+                    entry_rx,
+                    error_rx,
+                    pools_state.clone(),
+                    sig_tx,
+                    arb_config,
+                    sink,
+                );
+                entry_processor.receive_entries().await;
+            })
+        }
+        Mode::Pump(pump_config) => {
+            info!("Starting entries rx (<=> sink tx) pump mode");
             tokio::spawn(async move {
                 let mut entry_processor = PumpEntryProcessor::new(
-                    entry_rx, error_rx, sig_tx, post_url,
+                    entry_rx,
+                    error_rx,
+                    sig_tx,
+                    sink,
+                    pump_config,
                 );
                 entry_processor.receive_entries().await;
             })
         }
-        Mode::Graduates => {
-            info!("Starting entries rx (<=> webhook tx) graduates mode");
+        Mode::Graduates(_) => {
+            info!("Starting entries rx (<=> sink tx) graduates mode");
             tokio::spawn(async move {
                 let mut entry_processor = PumpEntryProcessor::new(
-                    entry_rx, error_rx, sig_tx, post_url,
+                    entry_rx,
+                    error_rx,
+                    sig_tx,
+                    sink,
+                    PumpConfig::default(),
                 );
                 entry_processor.receive_entries().await;
             })
         }
+        #[cfg(feature = "parquet")]
+        Mode::Parquet(parquet_config) => tokio::spawn(async move {
+            let mut entry_processor =
+                ParquetProcessor::new(entry_rx, error_rx, parquet_config);
+            entry_processor.receive_entries().await;
+        }),
     };
 
     info!("Starting sigs loop");
-    tokio::spawn({
+    let sigs_handle = tokio::spawn({
         let shreds_sigs = shreds_sigs.clone();
         async move {
-            while let Some(sig) = sig_rx.recv().await {
+            while let Some(SigWithMeta { sig, .. }) = sig_rx.recv().await {
                 if benchmark {
                     if let Some(shreds_sigs) = &shreds_sigs {
                         let timestamp = chrono::Utc::now().timestamp_millis();
@@ -150,18 +256,24 @@ pub async fn run_listener_with_algo(
         }
     });
 
-    signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
-
-    Ok(())
+    Ok(ListenerTasks {
+        handles: vec![
+            metrics_handle,
+            receiver_handle,
+            entry_processor_handle,
+            sigs_handle,
+        ],
+    })
 }
 
 pub async fn run_listener_with_save(
     bind_addr: &str,
+    rcvbuf_bytes: usize,
+    packet_size: usize,
+    slot_range: SlotRange,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let socket = Arc::new(
-        UdpSocket::bind(bind_addr)
-            .await
-            .expect("Couldn't bind to address"),
+        udp::bind(bind_addr, rcvbuf_bytes).expect("Couldn't bind to address"),
     );
     let received_packets = Arc::new(Mutex::new(Vec::new()));
 
@@ -170,7 +282,7 @@ pub async fn run_listener_with_save(
     let socket_clone = socket.clone();
 
     tokio::spawn(async move {
-        listen(socket_clone, rx).await;
+        listen(socket_clone, rx, slot_range, packet_size).await;
     });
 
     loop {
@@ -186,3 +298,116 @@ pub async fn run_listener_with_save(
     dump_to_file(received_packets).await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+    use crate::entry_processor::PumpConfig;
+    use crate::pump::derive_associated_token_account;
+    use crate::shred::shred_entries;
+    use crate::sink::StdoutSink;
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Keypair;
+    use solana_sdk::signer::Signer;
+    use solana_sdk::transaction::{Transaction, VersionedTransaction};
+
+    const SLOT: u64 = 1_000;
+
+    /// pump_create_tx carries just the accounts of a pump `create` that the
+    /// pump entry processor picks the creations up by
+    fn pump_create_tx() -> VersionedTransaction {
+        let payer = Keypair::new();
+        let (mint, bonding_curve) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let create = Instruction::new_with_bytes(
+            *constants::PUMP_FUN_PROGRAM_PUBKEY,
+            &[],
+            vec![
+                AccountMeta::new(mint, false),
+                AccountMeta::new_readonly(
+                    *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY,
+                    false,
+                ),
+                AccountMeta::new(bonding_curve, false),
+                AccountMeta::new(
+                    derive_associated_token_account(&bonding_curve, &mint),
+                    false,
+                ),
+            ],
+        );
+        VersionedTransaction::from(Transaction::new_signed_with_payer(
+            &[create],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        ))
+    }
+
+    /// capture shreds the txs into a slot the way a leader would
+    fn capture(txs: Vec<VersionedTransaction>) -> Vec<Vec<u8>> {
+        let entries = vec![Entry::new(&Hash::default(), 1, txs)];
+        let (data_shreds, coding_shreds) =
+            shred_entries(SLOT, &entries, true, 0, 0, true);
+        data_shreds
+            .iter()
+            .chain(coding_shreds.iter())
+            .map(|shred| shred.payload().clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn capture_replayed_over_udp_comes_out_as_sigs() {
+        let txs = vec![pump_create_tx(), pump_create_tx()];
+        let expected = txs
+            .iter()
+            .map(|tx| tx.signatures[0].to_string())
+            .collect::<Vec<_>>();
+
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let addr = socket.local_addr().unwrap();
+        let shreds_sigs: Sigs = Arc::new(RwLock::new(Vec::new()));
+        let listener = spawn_listener_with_algo(
+            socket,
+            PACKET_SIZE,
+            QueueSizes::default(),
+            SlotRange::default(),
+            Some(shreds_sigs.clone()),
+            Mode::Pump(PumpConfig::default()),
+            Arc::new(StdoutSink),
+            true,
+        )
+        .await
+        .unwrap();
+
+        let source = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for packet in capture(txs) {
+            source.send_to(&packet, addr).await.unwrap();
+        }
+
+        let sigs = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let sigs = shreds_sigs.read().await.clone();
+                if sigs.len() >= expected.len() {
+                    return sigs;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("sigs of the capture");
+        let mut sigs =
+            sigs.into_iter().map(|(_, sig)| sig).collect::<Vec<_>>();
+        sigs.sort();
+        let mut expected = expected;
+        expected.sort();
+        assert_eq!(sigs, expected);
+
+        listener.shutdown().await;
+        // the socket is released along with the receive loop
+        UdpSocket::bind(addr).await.unwrap();
+    }
+}
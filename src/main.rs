@@ -1,12 +1,35 @@
-use clap::Parser;
-use shreds::app::{App, Command};
-use shreds::service::{self, Mode};
+use shreds::admin::AdminConfig;
+use shreds::app::{App, Args, Command};
+use shreds::arb::{get_mints_of_interest, ArbConfig, FeeSchedule, TradeMode};
+use shreds::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig};
+use shreds::creators::{self, CreatorFilter};
+use shreds::dedup::DedupConfig;
+use shreds::enrich::EnrichConfig;
+use shreds::entry_processor::PumpConfig;
+use shreds::graduates_processor::GraduatesConfig;
+#[cfg(feature = "parquet")]
+use shreds::parquet_processor::ParquetConfig;
+use shreds::queue::QueueSizes;
+use shreds::sandwich::SandwichConfig;
+use shreds::service::{self, Mode, ServiceConfig};
+use shreds::shred_processor::{HangingDumps, SlotRange};
+use shreds::sink::{
+    self, EventLogSink, Sink, SinkKind, StdoutSink, WebhookConfig,
+};
+use shreds::source::{SourceConfig, SourceKind};
+use shreds::watchdog::WatchdogConfig;
+use solana_sdk::native_token::sol_to_lamports;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use log::info;
-use shreds::benchmark::compare_results;
-use shreds::raydium::download_raydium_json;
-use shreds::{benchmark, listener, logger};
+use log::{info, warn};
+use shreds::benchmark::{compare_results, BenchmarkCommitment};
+use shreds::raydium::{download_raydium_json, RaydiumJsonRefresher};
+use shreds::{
+    analyze, benchmark, decode, forward, leaders, listener, logger, recovery,
+    util, validate,
+};
 use tokio::sync::RwLock;
 
 use shreds::constants;
@@ -15,7 +38,14 @@ use shreds::constants;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
 
-    let app = App::parse();
+    let app = App::load()?;
+    let rcvbuf_bytes = app.args.rcvbuf_bytes;
+    let packet_size = app.args.packet_size;
+    let benchmark_commitment = app.args.benchmark_commitment;
+    let slot_range = SlotRange {
+        from: app.args.from_slot,
+        to: app.args.to_slot,
+    };
 
     let log_target = app.args.log_target.unwrap();
     logger::setup(if log_target == "file" {
@@ -26,21 +56,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         panic!("Invalid log target")
     })?;
 
+    #[cfg(feature = "otel")]
+    if let Some(endpoint) = &app.args.otel_endpoint {
+        shreds::otel::init(endpoint)?;
+    }
+
     match app.command {
         Command::Save => {
             let bind = app.args.bind.unwrap();
             info!("Binding to address: {}", bind);
 
             info!("Running in save mode");
-            listener::run_listener_with_save(&bind).await?;
+            listener::run_listener_with_save(
+                &bind,
+                rcvbuf_bytes,
+                packet_size,
+                slot_range,
+            )
+            .await?;
         }
-        Command::Download => {
+        Command::Download { watch_secs } => {
             download_raydium_json(true).await?;
+            if let Some(watch_secs) = watch_secs {
+                watch_raydium_json(Duration::from_secs(watch_secs)).await?;
+            }
         }
         Command::Benchmark => {
-            benchmark_cmd(app.args.bind.unwrap()).await?;
+            util::check_env(
+                &["WS_URL", "RPC_URL", "FUND_KEYPAIR_PATH"],
+                "benchmark",
+            )?;
+            benchmark_cmd(
+                app.args.bind.unwrap(),
+                rcvbuf_bytes,
+                packet_size,
+                benchmark_commitment,
+                app.args.benchmark_win_threshold_ms,
+            )
+            .await?;
         }
         Command::Pubsub => {
+            util::check_env(&["WS_URL"], "pubsub")?;
             let pubsub_sigs = Arc::new(RwLock::new(Vec::new()));
 
             let pubsub_handle = tokio::spawn({
@@ -51,6 +107,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     benchmark::listen_pubsub(
                         vec![constants::RAYDIUM_AMM.to_string()],
                         pubsub_sigs,
+                        benchmark_commitment,
                     )
                     .await
                     .expect("pubsub")
@@ -63,53 +120,461 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             pubsub_handle.await?;
         }
-        Command::ArbMode => {
-            let bind = app.args.bind.unwrap();
-            let post = app.args.post_url.unwrap();
-            health_check(post.clone()).await?;
-            info!("Binding to address: {}, posting to: {}", bind, post);
-            service::run(bind, post, Mode::Arb).await?;
+        Command::ArbMode {
+            paper,
+            live,
+            max_trade_size,
+            min_profit,
+            priority_fee,
+            slippage_bps,
+            lazy_load_pools,
+            rpc_concurrency,
+            default_fee_bps,
+            fee_overrides,
+            pool_snapshot,
+            detect_sandwiches,
+            sandwich_min_sol,
+            sandwich_min_usdc,
+            sandwich_max_distance,
+            ema_half_life_secs,
+            emit_volume,
+            max_consecutive_losses,
+            loss_window_secs,
+            refresh_raydium_json_secs,
+        } => {
+            // the pools are loaded and the fee payer read in any trade mode
+            util::check_env(&["RPC_URL", "FUND_KEYPAIR_PATH"], "arb mode")?;
+            let sink = connect_sink(&app.args, true).await?;
+            let trade_mode = if live {
+                TradeMode::Live
+            } else if paper {
+                TradeMode::Paper
+            } else {
+                TradeMode::ReadOnly
+            };
+            let arb_config = ArbConfig {
+                trade_mode,
+                max_trade_size,
+                min_profit,
+                priority_fee,
+                slippage_bps,
+                lazy_load_pools,
+                rpc_concurrency,
+                fee_schedule: FeeSchedule::load(
+                    default_fee_bps,
+                    fee_overrides.as_deref(),
+                )?,
+                pool_snapshot: pool_snapshot.map(PathBuf::from),
+                sandwich: detect_sandwiches.then_some(SandwichConfig {
+                    min_victim_sol: sandwich_min_sol,
+                    min_victim_usdc: sandwich_min_usdc,
+                    max_distance: sandwich_max_distance,
+                }),
+                ema_half_life: Duration::from_secs(ema_half_life_secs),
+                emit_volume,
+                circuit_breaker: (trade_mode == TradeMode::Live
+                    && max_consecutive_losses > 0)
+                    .then(|| {
+                        Arc::new(CircuitBreaker::new(CircuitBreakerConfig {
+                            max_consecutive_losses,
+                            window: Duration::from_secs(loss_window_secs),
+                        }))
+                    }),
+                raydium_json_refresh: refresh_raydium_json_secs
+                    .map(Duration::from_secs),
+            };
+            service::run(
+                service_config(&app.args).await?,
+                sink,
+                Mode::Arb(arb_config),
+            )
+            .await?;
         }
-        Command::PumpMode => {
-            let bind = app.args.bind.unwrap();
-            let post = app.args.post_url.unwrap();
-            health_check(post.clone()).await?;
-            info!("Binding to address: {}, posting to: {}", bind, post);
-            service::run(bind, post, Mode::Pump).await?;
+        Command::PumpMode {
+            enrich,
+            enrich_timeout_ms,
+            enrich_concurrency,
+            creator_allow,
+            creator_deny,
+            min_dev_buy_sol,
+            mint_cooldown_ms,
+            scan_threads,
+        } => {
+            let sink = connect_sink(&app.args, true).await?;
+            let creator_filter =
+                if creator_allow.is_some() || creator_deny.is_some() {
+                    let filter = Arc::new(CreatorFilter::load(
+                        creator_allow.map(PathBuf::from),
+                        creator_deny.map(PathBuf::from),
+                    )?);
+                    tokio::spawn(creators::reload_on_hangup(filter.clone()));
+                    Some(filter)
+                } else {
+                    None
+                };
+            service::run(
+                service_config(&app.args).await?,
+                sink,
+                Mode::Pump(PumpConfig {
+                    enrich: enrich.then(|| EnrichConfig {
+                        timeout: Duration::from_millis(enrich_timeout_ms),
+                        concurrency: enrich_concurrency,
+                    }),
+                    creator_filter,
+                    min_dev_buy_lamports: sol_to_lamports(min_dev_buy_sol),
+                    mint_cooldown: Duration::from_millis(mint_cooldown_ms),
+                    scan_threads: scan_threads.filter(|&threads| threads > 0),
+                }),
+            )
+            .await?;
         }
-        Command::GraduatesMode => {
-            let bind = app.args.bind.unwrap();
-            let post = app.args.post_url.unwrap();
-            // health_check(post.clone()).await?;
-            info!("Binding to address: {}", bind);
-            service::run(bind, post, Mode::Graduates).await?;
+        Command::GraduatesMode {
+            check_mint_authority,
+            rpc_concurrency,
+        } => {
+            if check_mint_authority {
+                util::check_env(&["RPC_URL"], "--check-mint-authority")?;
+            }
+            let sink = connect_sink(&app.args, false).await?;
+            service::run(
+                service_config(&app.args).await?,
+                sink,
+                Mode::Graduates(GraduatesConfig {
+                    check_mint_authority,
+                    rpc_concurrency,
+                }),
+            )
+            .await?;
+        }
+        #[cfg(feature = "parquet")]
+        Command::ParquetMode {
+            dir,
+            max_rows,
+            max_age_secs,
+        } => {
+            service::run(
+                service_config(&app.args).await?,
+                Arc::new(StdoutSink),
+                Mode::Parquet(ParquetConfig {
+                    dir: PathBuf::from(dir),
+                    max_rows: max_rows.max(1),
+                    // the rotation ticks on it, which cannot be zero
+                    max_age: Duration::from_secs(max_age_secs.max(1)),
+                }),
+            )
+            .await?;
+        }
+        Command::Recover { path, json } => {
+            let path = match path {
+                Some(path) => PathBuf::from(path),
+                None => hanging_dumps(&app.args)
+                    .latest()
+                    .ok_or("No hanging FEC set snapshots to recover")?,
+            };
+            info!("Recovering FEC sets from {}", path.display());
+            let report =
+                recovery::attempt_recovery(recovery::load_fec_sets(&path)?);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                recovery::print_report(&report);
+            }
+        }
+        Command::Analyze { path, json } => {
+            analyze::run(&path, json).await?;
+        }
+        Command::BenchmarkPrefilter { path, json } => {
+            benchmark::run_prefilter(&path, json).await?;
+        }
+        Command::DecodeShred { json } => {
+            decode::run(json)?;
+        }
+        Command::Validate { path, json } => {
+            validate::run(&path, json)?;
+        }
+        Command::Leaders {
+            from,
+            to,
+            cache_dir,
+            json,
+        } => {
+            util::check_env(&["RPC_URL"], "leaders")?;
+            leaders::run(
+                from,
+                to.unwrap_or(from),
+                &PathBuf::from(cache_dir),
+                json,
+            )
+            .await?;
         }
     }
 
+    #[cfg(feature = "otel")]
+    shreds::otel::shutdown();
+
     Ok(())
 }
 
+/// watch_raydium_json keeps downloading raydium.json, logging what changed
+/// in the pools of the mints of interest
+async fn watch_raydium_json(interval: Duration) -> anyhow::Result<()> {
+    let mut refresher =
+        RaydiumJsonRefresher::new(get_mints_of_interest()).await?;
+    loop {
+        tokio::time::sleep(interval).await;
+        match refresher.refresh().await {
+            Ok(diff) => {
+                for (mint, (amm_keys, _)) in &diff.new {
+                    info!("New pool {} of {}", amm_keys.amm_pool, mint);
+                }
+                for (mint, (amm_keys, _)) in &diff.changed {
+                    info!("Changed pool {} of {}", amm_keys.amm_pool, mint);
+                }
+            }
+            Err(e) => warn!("Failed to refresh raydium.json: {}", e),
+        }
+    }
+}
+
+/// connect_sink builds the sink selected with `--sink`, health checking
+/// `post_url` first for webhooks if `check` is set
+pub async fn connect_sink(
+    args: &Args,
+    check: bool,
+) -> Result<Sink, Box<dyn std::error::Error>> {
+    let webhook = webhook_config(args)?;
+    let target = match args.sink {
+        SinkKind::Webhook => {
+            let post = args.post_url.clone().unwrap();
+            if check {
+                health_check(
+                    &webhook.client()?,
+                    post.clone(),
+                    Duration::from_secs(args.health_check_timeout_secs),
+                )
+                .await?;
+            }
+            post
+        }
+        _ => args.sink_target.clone(),
+    };
+    info!("Emitting events to {:?}: {}", args.sink, target);
+    let sink = sink::connect(args.sink, target, &webhook).await?;
+    Ok(match &args.event_log {
+        Some(path) => Arc::new(
+            EventLogSink::new(
+                sink,
+                PathBuf::from(path),
+                args.event_log_max_bytes,
+            )
+            .await?,
+        ),
+        None => sink,
+    })
+}
+
+/// sigs_out is where the service modes write the sigs they detect to, with
+/// the time their shreds came in at; POSTed to `<url>/v2/sig` for an
+/// http(s) url (see WebhookSink), appended as JSON lines to a file
+/// otherwise
+pub async fn sigs_out(
+    args: &Args,
+) -> Result<Option<Sink>, Box<dyn std::error::Error>> {
+    let Some(target) = args.sigs_out.clone() else {
+        return Ok(None);
+    };
+    let kind =
+        if target.starts_with("http://") || target.starts_with("https://") {
+            SinkKind::Webhook
+        } else {
+            SinkKind::File
+        };
+    info!("Writing the sigs to {:?}: {}", kind, target);
+    Ok(Some(
+        sink::connect(kind, target, &webhook_config(args)?).await?,
+    ))
+}
+
+/// service_config is what the service modes share off of the args, the
+/// sigs out sink is connected and the forward targets resolved here
+pub async fn service_config(
+    args: &Args,
+) -> Result<ServiceConfig, Box<dyn std::error::Error>> {
+    Ok(ServiceConfig {
+        source: shred_source(args),
+        ingest_queue_size: args.ingest_queue_size,
+        queue_sizes: queue_sizes(args),
+        slot_range: SlotRange {
+            from: args.from_slot,
+            to: args.to_slot,
+        },
+        shred_version: args.shred_version,
+        max_slot_lag: args.max_slot_lag,
+        sigs_out: sigs_out(args).await?,
+        admin_config: admin_config(args)?,
+        hanging_dumps: hanging_dumps(args),
+        entry_dump_dir: args.dump_entries.clone().map(PathBuf::from),
+        max_entries: args.max_entries,
+        entry_ordering: entry_ordering(args),
+        recovery: !args.no_recovery,
+        drop_repair_shreds: args.drop_repair_shreds,
+        dedup: dedup_config(args),
+        watchdog: watchdog_config(args),
+        forward: forward::resolve_targets(&args.forward)?,
+    })
+}
+
+/// shred_source is where the service modes read the shreds off of
+pub fn shred_source(args: &Args) -> SourceConfig {
+    match args.source {
+        SourceKind::Udp => SourceConfig::Udp {
+            bind_address: args.bind.clone().unwrap(),
+            rcvbuf_bytes: args.rcvbuf_bytes,
+            packet_size: args.packet_size,
+        },
+        SourceKind::Replay => SourceConfig::Replay {
+            path: PathBuf::from(&args.replay_file),
+            interval: Duration::from_micros(args.replay_interval_us),
+        },
+        SourceKind::Shredstream => SourceConfig::Shredstream {
+            url: args.shredstream_url.clone(),
+        },
+    }
+}
+
+/// dedup_config is the signature window of the dedup, None with a window of
+/// zero signatures
+pub fn dedup_config(args: &Args) -> Option<DedupConfig> {
+    (args.dedup_max_signatures > 0).then(|| DedupConfig {
+        max_signatures: args.dedup_max_signatures,
+        max_age: Duration::from_secs(args.dedup_max_age_secs),
+    })
+}
+
+/// queue_sizes are the capacities of the entry and error channels
+pub fn queue_sizes(args: &Args) -> QueueSizes {
+    QueueSizes {
+        entries: args.entry_queue_size,
+        errors: args.error_queue_size,
+    }
+}
+
+/// entry_ordering is the gap timeout of `--ordered-entries`, None without
+pub fn entry_ordering(args: &Args) -> Option<Duration> {
+    args.ordered_entries
+        .then(|| Duration::from_millis(args.ordering_gap_timeout_ms))
+}
+
+/// webhook_config reads the auth and TLS options of the webhook sink, the
+/// secrets fall back to WEBHOOK_TOKEN and WEBHOOK_HMAC_SECRET
+pub fn webhook_config(
+    args: &Args,
+) -> Result<WebhookConfig, Box<dyn std::error::Error>> {
+    let headers = args
+        .webhook_headers
+        .iter()
+        .map(|header| {
+            header
+                .split_once(':')
+                .map(|(name, value)| {
+                    (name.trim().to_string(), value.trim().to_string())
+                })
+                .ok_or_else(|| {
+                    format!("Invalid --webhook-header {:?}, expected `Name: value`", header)
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let from_env = |arg: &Option<String>, var: &str| {
+        arg.clone()
+            .or_else(|| std::env::var(var).ok())
+            .filter(|value| !value.is_empty())
+    };
+    Ok(WebhookConfig {
+        token: from_env(&args.webhook_token, "WEBHOOK_TOKEN"),
+        headers,
+        hmac_secret: from_env(
+            &args.webhook_hmac_secret,
+            "WEBHOOK_HMAC_SECRET",
+        ),
+        ca_cert: args.webhook_ca_cert.clone().map(PathBuf::from),
+        accept_invalid_certs: args.webhook_insecure,
+    })
+}
+
+pub fn hanging_dumps(args: &Args) -> HangingDumps {
+    HangingDumps {
+        dir: PathBuf::from(&args.dump_dir),
+        max_files: args.max_dumps,
+        interval: args.dump_interval_secs.map(Duration::from_secs),
+    }
+}
+
+pub fn watchdog_config(args: &Args) -> Option<WatchdogConfig> {
+    args.watchdog_timeout_secs.map(|secs| WatchdogConfig {
+        timeout: Duration::from_secs(secs),
+        restart: args.watchdog_restart,
+    })
+}
+
+/// admin_config is set if `--admin-bind` is, which requires a token
+pub fn admin_config(
+    args: &Args,
+) -> Result<Option<AdminConfig>, Box<dyn std::error::Error>> {
+    let Some(bind) = args.admin_bind.clone() else {
+        return Ok(None);
+    };
+    let token = args
+        .admin_token
+        .clone()
+        .or_else(|| std::env::var("ADMIN_TOKEN").ok())
+        .filter(|token| !token.is_empty())
+        .ok_or("--admin-bind requires --admin-token or ADMIN_TOKEN")?;
+    Ok(Some(AdminConfig { bind, token }))
+}
+
+/// health_check polls `{post_url}/healthz` until it succeeds, backing off
+/// between the attempts, and fails once `timeout` has elapsed
 pub async fn health_check(
+    client: &reqwest::Client,
     post_url: String,
+    timeout: Duration,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("Running health check on: {}", post_url);
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(post_url + "/healthz")
-        .send()
-        .await
-        .expect("Failed to send request");
-
-    if response.status().is_success() {
-        Ok(())
-    } else {
-        Err("Failed health check".into())
+    let url = post_url + "/healthz";
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut backoff = Duration::from_millis(250);
+    let mut attempt = 1;
+    loop {
+        let failure = match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => format!("responded with {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Err(format!(
+                "Failed health check after {} attempts: {}",
+                attempt, failure
+            )
+            .into());
+        }
+        warn!(
+            "Health check attempt {} failed ({}), retrying in {:?}",
+            attempt, failure, backoff
+        );
+        tokio::time::sleep(backoff.min(deadline - now)).await;
+        backoff = (backoff * 2).min(Duration::from_secs(5));
+        attempt += 1;
     }
 }
 
 pub async fn benchmark_cmd(
     bind_addr: String,
+    rcvbuf_bytes: usize,
+    packet_size: usize,
+    commitment: BenchmarkCommitment,
+    win_threshold_ms: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("Binding to address: {}", bind_addr);
 
@@ -123,6 +588,7 @@ pub async fn benchmark_cmd(
             benchmark::listen_pubsub(
                 vec![constants::RAYDIUM_AMM.to_string()],
                 pubsub_sigs,
+                commitment,
             )
             .await
             .expect("pubsub")
@@ -133,9 +599,13 @@ pub async fn benchmark_cmd(
         async move {
             listener::run_listener_with_algo(
                 &bind_addr,
+                rcvbuf_bytes,
+                packet_size,
+                QueueSizes::default(),
+                SlotRange::default(),
                 Some(shreds_sigs),
-                Mode::Arb,
-                "".to_string(),
+                Mode::Arb(ArbConfig::default()),
+                Arc::new(StdoutSink),
                 true,
             )
             .await
@@ -152,6 +622,7 @@ pub async fn benchmark_cmd(
     compare_results(
         pubsub_sigs.read().await.clone(),
         shreds_sigs.read().await.clone(),
+        win_threshold_ms,
     );
 
     Ok(())
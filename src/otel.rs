@@ -0,0 +1,187 @@
+use std::future::Future;
+
+#[cfg(feature = "otel")]
+use opentelemetry::{
+    global,
+    trace::{FutureExt, Span, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+#[cfg(feature = "otel")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "otel")]
+use std::time::SystemTime;
+
+#[cfg(feature = "otel")]
+const TRACER: &str = "shreds";
+
+// set once the exporter is installed, the receipt times are not even taken
+// before that
+#[cfg(feature = "otel")]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// init installs the OTLP exporter sending the spans to `endpoint`, e.g.
+/// http://localhost:4317, has to be called from within a tokio runtime
+#[cfg(feature = "otel")]
+pub fn init(endpoint: &str) -> anyhow::Result<()> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            opentelemetry_sdk::trace::Config::default().with_resource(
+                opentelemetry_sdk::Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    TRACER,
+                )]),
+            ),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+    global::set_tracer_provider(provider);
+    ENABLED.store(true, Ordering::Relaxed);
+    log::info!("Exporting traces to {}", endpoint);
+    Ok(())
+}
+
+/// shutdown flushes the spans which are still batched
+#[cfg(feature = "otel")]
+pub fn shutdown() {
+    if ENABLED.swap(false, Ordering::Relaxed) {
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// FecSetTrace follows a FEC set from its first shred to the delivery of
+/// the events of its entries: a `fec_set` span with the `collect`,
+/// `deserialize`, `emit` and `deliver` spans under it, exported over OTLP
+/// with the `otel` feature; the `fec_set` span ends once the last clone of
+/// the trace is dropped. Without the feature it is a unit struct and the
+/// tracing compiles away
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Default)]
+pub struct FecSetTrace {
+    received_at: Option<SystemTime>,
+    context: Option<Context>,
+}
+
+#[cfg(not(feature = "otel"))]
+#[derive(Debug, Clone, Default)]
+pub struct FecSetTrace;
+
+/// TraceSpan is a span under the FEC set, ended when dropped
+#[cfg(feature = "otel")]
+pub struct TraceSpan(Option<global::BoxedSpan>);
+
+#[cfg(not(feature = "otel"))]
+pub struct TraceSpan;
+
+#[cfg(feature = "otel")]
+impl Drop for TraceSpan {
+    fn drop(&mut self) {
+        if let Some(span) = self.0.as_mut() {
+            span.end();
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+impl FecSetTrace {
+    /// received notes the receipt of a shred, the first one starts the
+    /// trace
+    pub fn received(&mut self) {
+        if self.received_at.is_none() && ENABLED.load(Ordering::Relaxed) {
+            self.received_at = Some(SystemTime::now());
+        }
+    }
+
+    /// complete starts the `fec_set` span, back from the first shred, once
+    /// the set has enough shreds to be reassembled
+    pub fn complete(&mut self, slot: u64, fec_set_index: u32) {
+        let Some(received_at) = self.received_at else {
+            return;
+        };
+        let tracer = global::tracer(TRACER);
+        let attributes = vec![
+            KeyValue::new("slot", slot as i64),
+            KeyValue::new("fec_set_index", fec_set_index as i64),
+        ];
+        let span = tracer
+            .span_builder("fec_set")
+            .with_start_time(received_at)
+            .with_attributes(attributes.clone())
+            .start(&tracer);
+        let context = Context::current_with_span(span);
+        tracer
+            .span_builder("collect")
+            .with_start_time(received_at)
+            .with_attributes(attributes)
+            .start_with_context(&tracer, &context)
+            .end();
+        self.context = Some(context);
+    }
+
+    /// span starts a span under the `fec_set` one
+    pub fn span(&self, name: &'static str) -> TraceSpan {
+        TraceSpan(self.context.as_ref().map(|context| {
+            let tracer = global::tracer(TRACER);
+            tracer.start_with_context(name, context)
+        }))
+    }
+
+    /// instrument runs `future` within the trace, so that the deliveries
+    /// of the events it emits end up under the `fec_set` span
+    pub fn instrument<F: Future>(
+        &self,
+        future: F,
+    ) -> impl Future<Output = F::Output> {
+        future.with_context(self.context.clone().unwrap_or_default())
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+impl FecSetTrace {
+    #[inline(always)]
+    pub fn received(&mut self) {}
+
+    #[inline(always)]
+    pub fn complete(&mut self, _slot: u64, _fec_set_index: u32) {}
+
+    #[inline(always)]
+    pub fn span(&self, _name: &'static str) -> TraceSpan {
+        TraceSpan
+    }
+
+    #[inline(always)]
+    pub fn instrument<F: Future>(
+        &self,
+        future: F,
+    ) -> impl Future<Output = F::Output> {
+        future
+    }
+}
+
+/// deliver runs the delivery of an event to the sink within a `deliver`
+/// span, under the trace of the FEC set being processed if there is one
+#[cfg(feature = "otel")]
+pub async fn deliver<F: Future>(topic: &str, future: F) -> F::Output {
+    let context = Context::current();
+    if !context.has_active_span() {
+        return future.await;
+    }
+    let tracer = global::tracer(TRACER);
+    let mut span = tracer.start_with_context("deliver", &context);
+    span.set_attribute(KeyValue::new("topic", topic.to_string()));
+    let output = future.await;
+    span.end();
+    output
+}
+
+#[cfg(not(feature = "otel"))]
+#[inline(always)]
+pub async fn deliver<F: Future>(_topic: &str, future: F) -> F::Output {
+    future.await
+}
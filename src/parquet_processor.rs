@@ -0,0 +1,332 @@
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    ArrayRef, BooleanArray, RecordBatch, StringArray, UInt64Array,
+};
+use log::{error, info};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use solana_sdk::clock::Slot;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::VersionedTransaction;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use crate::constants;
+use crate::entry_processor::{EntriesWithMeta, ProcessorMetrics};
+use crate::jupiter::JupiterRoute;
+use crate::pump::PumpIx;
+use crate::raydium::{parse_amm_instruction, ParsedAmmInstruction};
+use crate::raydium_clmm::ClmmSwapIx;
+
+/// Rows per file, a file is written once this many are buffered
+pub const DEFAULT_PARQUET_MAX_ROWS: usize = 100_000;
+/// Longest the rows are buffered for before they are written anyway
+pub const DEFAULT_PARQUET_MAX_AGE_SECS: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct ParquetConfig {
+    pub dir: PathBuf,
+    pub max_rows: usize,
+    pub max_age: Duration,
+}
+
+/// TxRow is a row of the Parquet files, one per transaction; the SOL
+/// amount is as far as the instructions tell, the limits of the pump buys
+/// and sells and the lamports of the system transfers, None if there are
+/// none of those
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxRow {
+    pub signature: String,
+    pub slot: Slot,
+    pub program_ids: Vec<String>,
+    pub is_swap: bool,
+    pub sol_amount: Option<u64>,
+}
+
+/// tx_row decodes the top-level instructions of the tx, None for the txs
+/// without a signature
+pub fn tx_row(tx: &VersionedTransaction, slot: Slot) -> Option<TxRow> {
+    let signature = tx.signatures.first()?.to_string();
+    let account_keys = tx.message.static_account_keys();
+    let mut program_ids = Vec::new();
+    let mut is_swap = false;
+    let mut sol_amount: Option<u64> = None;
+    for ix in tx.message.instructions() {
+        let Some(program_id) = account_keys.get(ix.program_id_index as usize)
+        else {
+            continue;
+        };
+        let program_id_string = program_id.to_string();
+        if !program_ids.contains(&program_id_string) {
+            program_ids.push(program_id_string);
+        }
+
+        let mut add_sol = |lamports: u64| {
+            sol_amount =
+                Some(sol_amount.unwrap_or(0).saturating_add(lamports));
+        };
+        if *program_id == *constants::PUMP_FUN_PROGRAM_PUBKEY {
+            match PumpIx::decode(&ix.data) {
+                Some(PumpIx::Buy(buy)) => {
+                    is_swap = true;
+                    add_sol(buy.max_sol_cost);
+                }
+                Some(PumpIx::Sell(sell)) => {
+                    is_swap = true;
+                    add_sol(sell.min_sol_output);
+                }
+                _ => {}
+            }
+        } else if *program_id == *constants::RAYDIUM_AMM_PUBKEY {
+            is_swap |= matches!(
+                parse_amm_instruction(&ix.data),
+                Ok(ParsedAmmInstruction::SwapBaseIn(_))
+                    | Ok(ParsedAmmInstruction::SwapBaseOut(_))
+            );
+        } else if *program_id == *constants::RAYDIUM_CLMM_PUBKEY {
+            is_swap |= ClmmSwapIx::decode(&ix.data).is_some();
+        } else if *program_id == *constants::JUPITER_V6_PUBKEY {
+            is_swap |= JupiterRoute::decode(&ix.data).is_some();
+        } else if *program_id == system_program::id() {
+            if let Ok(SystemInstruction::Transfer { lamports }) =
+                bincode::deserialize(&ix.data)
+            {
+                add_sol(lamports);
+            }
+        }
+    }
+    Some(TxRow {
+        signature,
+        slot,
+        program_ids,
+        is_swap,
+        sol_amount,
+    })
+}
+
+/// write_rows writes the rows to a new file in `dir`, named after the slot
+/// range of the rows, `txs-<first slot>-<last slot>-<unix ms>.parquet`
+pub fn write_rows(dir: &Path, rows: &[TxRow]) -> anyhow::Result<PathBuf> {
+    let first_slot = rows.iter().map(|row| row.slot).min().unwrap_or(0);
+    let last_slot = rows.iter().map(|row| row.slot).max().unwrap_or(0);
+    let unix_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dir.join(format!(
+        "txs-{}-{}-{}.parquet",
+        first_slot, last_slot, unix_ms
+    ));
+
+    let mut program_ids = ListBuilder::new(StringBuilder::new());
+    for row in rows {
+        for program_id in &row.program_ids {
+            program_ids.values().append_value(program_id);
+        }
+        program_ids.append(true);
+    }
+    let batch = RecordBatch::try_from_iter([
+        (
+            "signature",
+            Arc::new(StringArray::from_iter_values(
+                rows.iter().map(|row| &row.signature),
+            )) as ArrayRef,
+        ),
+        (
+            "slot",
+            Arc::new(UInt64Array::from_iter_values(
+                rows.iter().map(|row| row.slot),
+            )),
+        ),
+        ("program_ids", Arc::new(program_ids.finish())),
+        (
+            "is_swap",
+            Arc::new(BooleanArray::from_iter(
+                rows.iter().map(|row| Some(row.is_swap)),
+            )),
+        ),
+        (
+            "sol_amount",
+            Arc::new(UInt64Array::from_iter(
+                rows.iter().map(|row| row.sol_amount),
+            )),
+        ),
+    ])?;
+
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::File::create(&path)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(file, batch.schema(), Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(path)
+}
+
+/// ParquetProcessor writes a row per transaction of the entries to Parquet
+/// files, for offline analysis; the files are rotated by row count and age
+pub struct ParquetProcessor {
+    entry_rx: mpsc::Receiver<EntriesWithMeta>,
+    error_rx: mpsc::Receiver<String>,
+    config: ParquetConfig,
+    rows: Vec<TxRow>,
+    metrics: Arc<ParquetMetrics>,
+}
+
+/// ParquetMetrics are the counters of the parquet mode
+#[derive(Debug, Default)]
+pub struct ParquetMetrics {
+    rows_written: AtomicU64,
+    files_written: AtomicU64,
+    write_failures: AtomicU64,
+}
+
+impl ProcessorMetrics for ParquetMetrics {
+    fn mode(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "rows_written": self.rows_written.load(Ordering::Relaxed),
+            "files_written": self.files_written.load(Ordering::Relaxed),
+            "write_failures": self.write_failures.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl ParquetProcessor {
+    pub fn new(
+        entry_rx: mpsc::Receiver<EntriesWithMeta>,
+        error_rx: mpsc::Receiver<String>,
+        config: ParquetConfig,
+    ) -> Self {
+        Self {
+            entry_rx,
+            error_rx,
+            config,
+            rows: Vec::new(),
+            metrics: Arc::new(ParquetMetrics::default()),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<ParquetMetrics> {
+        self.metrics.clone()
+    }
+
+    pub async fn receive_entries(&mut self) {
+        let mut rotation = tokio::time::interval(self.config.max_age);
+        // the first tick is right away
+        rotation.tick().await;
+        loop {
+            tokio::select! {
+                Some(entries) = self.entry_rx.recv() => {
+                    self.process_entries(entries).await;
+                }
+                Some(error) = self.error_rx.recv() => {
+                    error!("{}", error);
+                }
+                _ = rotation.tick() => {
+                    self.flush().await;
+                }
+            }
+        }
+    }
+
+    pub async fn process_entries(
+        &mut self,
+        entries_with_meta: EntriesWithMeta,
+    ) {
+        let slot = entries_with_meta.slot;
+        self.rows.extend(
+            entries_with_meta
+                .entries
+                .iter()
+                .flat_map(|entry| entry.transactions.iter())
+                .filter_map(|tx| tx_row(tx, slot)),
+        );
+        if self.rows.len() >= self.config.max_rows {
+            self.flush().await;
+        }
+    }
+
+    /// flush writes the buffered rows to a new file, off of the runtime
+    pub async fn flush(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let dir = self.config.dir.clone();
+        let count = rows.len() as u64;
+        match tokio::task::spawn_blocking(move || write_rows(&dir, &rows))
+            .await
+        {
+            Ok(Ok(path)) => {
+                info!("Wrote {} txs to {}", count, path.display());
+                self.metrics
+                    .rows_written
+                    .fetch_add(count, Ordering::Relaxed);
+                self.metrics.files_written.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(Err(e)) => {
+                error!("Failed to write {} txs to Parquet: {}", count, e);
+                self.metrics.write_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                error!("Parquet writer panicked: {}", e);
+                self.metrics.write_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_transaction;
+
+    #[test]
+    fn writes_a_row_per_transaction() {
+        let tx = VersionedTransaction::from(system_transaction::transfer(
+            &keypair_from_seed(&[5; 32]).unwrap(),
+            &Pubkey::new_unique(),
+            42,
+            Hash::default(),
+        ));
+        let row = tx_row(&tx, 100).unwrap();
+        assert_eq!(
+            row,
+            TxRow {
+                signature: tx.signatures[0].to_string(),
+                slot: 100,
+                program_ids: vec![system_program::id().to_string()],
+                is_swap: false,
+                sol_amount: Some(42),
+            }
+        );
+
+        let dir = std::env::temp_dir()
+            .join(format!("shreds-parquet-{}", std::process::id()));
+        let rows = vec![row.clone(), TxRow { slot: 103, ..row }];
+        let path = write_rows(&dir, &rows).unwrap();
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("txs-100-103-"));
+        let reader =
+            SerializedFileReader::new(std::fs::File::open(&path).unwrap())
+                .unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::arb::LargeSwapEvent;
+use crate::enrich::TokenMetadata;
+use crate::entry_processor::CreatePumpTokenEvent;
+use crate::graduates_processor::GraduateEvent;
+use crate::raydium::QuoteAsset;
+
+/// events are the messages of proto/events.proto, generated by prost
+#[allow(clippy::all)]
+pub mod events {
+    include!(concat!(env!("OUT_DIR"), "/shreds.events.rs"));
+}
+
+impl From<TokenMetadata> for events::TokenMetadata {
+    fn from(metadata: TokenMetadata) -> Self {
+        events::TokenMetadata {
+            image: metadata.image,
+            description: metadata.description,
+            twitter: metadata.twitter,
+            telegram: metadata.telegram,
+            website: metadata.website,
+        }
+    }
+}
+
+impl From<CreatePumpTokenEvent> for events::CreatePumpTokenEvent {
+    fn from(event: CreatePumpTokenEvent) -> Self {
+        events::CreatePumpTokenEvent {
+            sig: event.sig,
+            slot: event.slot,
+            mint: event.mint.to_string(),
+            bounding_curve: event.bounding_curve.to_string(),
+            associated_bounding_curve: event
+                .associated_bounding_curve
+                .to_string(),
+            creator: event.creator.to_string(),
+            name: event.name,
+            symbol: event.symbol,
+            uri: event.uri,
+            dev_bought_amount: event.dev_bought_amount,
+            dev_max_sol_cost: event.dev_max_sol_cost,
+            num_dev_buy_txs: event.num_dev_buy_txs,
+            virtual_sol_reserves: event.virtual_sol_reserves,
+            virtual_token_reserves: event.virtual_token_reserves,
+            price_per_token: event.price_per_token,
+            market_cap_sol: event.market_cap_sol,
+            idempotency_key: event.idempotency_key,
+            metadata: event.metadata.map(Into::into),
+            via_cpi: event.via_cpi,
+        }
+    }
+}
+
+impl From<QuoteAsset> for events::QuoteAsset {
+    fn from(quote: QuoteAsset) -> Self {
+        match quote {
+            QuoteAsset::Wsol => events::QuoteAsset::Wsol,
+            QuoteAsset::Usdc => events::QuoteAsset::Usdc,
+            QuoteAsset::Other => events::QuoteAsset::Other,
+        }
+    }
+}
+
+impl From<LargeSwapEvent> for events::LargeSwapEvent {
+    fn from(event: LargeSwapEvent) -> Self {
+        events::LargeSwapEvent {
+            signature: event.signature,
+            slot: event.slot,
+            timestamp: event.timestamp,
+            swap_direction: event.swap_direction,
+            is_swap_base_in: event.is_swap_base_in,
+            amm_id: event.amm_id,
+            pc_mint: event.pc_mint,
+            amount_specified: event.amount_specified,
+            coin_mint: event.coin_mint,
+            other_amount_threshold: event.other_amount_threshold,
+            quote: events::QuoteAsset::from(event.quote).into(),
+            quote_mint: event.quote_mint,
+            quote_amount: event.quote_amount,
+            initial_price: event.initial_price,
+            new_price: event.new_price,
+        }
+    }
+}
+
+impl From<GraduateEvent> for events::GraduateEvent {
+    fn from(event: GraduateEvent) -> Self {
+        events::GraduateEvent {
+            sig: event.sig,
+            slot: event.slot,
+            mint: event.mint,
+            mint_authority_revoked: event.mint_authority_revoked,
+            idempotency_key: event.idempotency_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn round_trips_the_events() {
+        let mint = Pubkey::new_unique();
+        let event = CreatePumpTokenEvent {
+            sig: "sig".to_string(),
+            slot: 42,
+            mint,
+            name: "gringo".to_string(),
+            price_per_token: Some(0.5),
+            metadata: Some(TokenMetadata {
+                twitter: Some("https://x.com/gringo".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let message = events::CreatePumpTokenEvent::from(event);
+        let decoded = events::CreatePumpTokenEvent::decode(
+            message.encode_to_vec().as_slice(),
+        )
+        .unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.mint, mint.to_string());
+        assert_eq!(decoded.price_per_token, Some(0.5));
+        assert_eq!(decoded.market_cap_sol, None);
+        assert_eq!(
+            decoded.metadata.unwrap().twitter.as_deref(),
+            Some("https://x.com/gringo")
+        );
+
+        let swap = events::LargeSwapEvent::from(LargeSwapEvent {
+            signature: "sig".to_string(),
+            slot: 42,
+            timestamp: 1_700_000_000_000,
+            swap_direction: "Coin2PC".to_string(),
+            is_swap_base_in: true,
+            amm_id: Pubkey::new_unique().to_string(),
+            pc_mint: Pubkey::new_unique().to_string(),
+            amount_specified: 1_000,
+            coin_mint: Pubkey::new_unique().to_string(),
+            other_amount_threshold: 990,
+            quote: QuoteAsset::Usdc,
+            quote_mint: Pubkey::new_unique().to_string(),
+            quote_amount: 2_500.,
+            initial_price: Some(1.),
+            new_price: None,
+        });
+        assert_eq!(swap.quote(), events::QuoteAsset::Usdc);
+
+        let graduate = events::GraduateEvent::from(GraduateEvent {
+            sig: "sig".to_string(),
+            slot: 42,
+            mint: None,
+            mint_authority_revoked: Some(true),
+            idempotency_key: "key".to_string(),
+        });
+        assert_eq!(
+            events::GraduateEvent::decode(
+                graduate.encode_to_vec().as_slice()
+            )
+            .unwrap(),
+            graduate
+        );
+    }
+}
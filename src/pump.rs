@@ -1,8 +1,74 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::constants;
+
+// positions of the accounts within the pump `create` instruction
+const CREATE_IX_MINT_POSITION: usize = 0;
+const CREATE_IX_MINT_AUTHORITY_POSITION: usize = 1;
+const CREATE_IX_BONDING_CURVE_POSITION: usize = 2;
+const CREATE_IX_ASSOCIATED_BONDING_CURVE_POSITION: usize = 3;
+
+// anchor discriminators of the pump instructions, sha256("global:<name>")
+pub const PUMP_CREATE_DISCRIMINATOR: [u8; 8] =
+    [24, 30, 200, 40, 5, 28, 7, 119];
+pub const PUMP_BUY_DISCRIMINATOR: [u8; 8] =
+    [102, 6, 61, 18, 1, 218, 235, 234];
+pub const PUMP_SELL_DISCRIMINATOR: [u8; 8] =
+    [51, 230, 133, 164, 1, 127, 131, 173];
+pub const PUMP_WITHDRAW_DISCRIMINATOR: [u8; 8] =
+    [183, 18, 70, 156, 148, 109, 161, 34];
+pub const PUMP_MIGRATE_DISCRIMINATOR: [u8; 8] =
+    [155, 234, 231, 146, 236, 158, 162, 30];
+
+/// PumpIx is an instruction of the pump program, told apart by its
+/// discriminator; buys and sells have the same layout, so parsing the data
+/// alone would not do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PumpIx {
+    Create(PumpCreateIx),
+    Buy(PumpSwapIx),
+    Sell(PumpSellIx),
+    /// The liquidity of a completed curve taken out to migrate it
+    Withdraw,
+    Migrate,
+}
+
+impl PumpIx {
+    /// decode is None for the other instructions of the program and for
+    /// data which does not fit the layout of its discriminator
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+        match discriminator {
+            PUMP_CREATE_DISCRIMINATOR => {
+                PumpCreateIx::try_from_slice(data).ok().map(PumpIx::Create)
+            }
+            PUMP_BUY_DISCRIMINATOR => {
+                PumpSwapIx::try_from_slice(data).ok().map(PumpIx::Buy)
+            }
+            PUMP_SELL_DISCRIMINATOR => {
+                PumpSellIx::try_from_slice(data).ok().map(PumpIx::Sell)
+            }
+            PUMP_WITHDRAW_DISCRIMINATOR => Some(PumpIx::Withdraw),
+            PUMP_MIGRATE_DISCRIMINATOR => Some(PumpIx::Migrate),
+            _ => None,
+        }
+    }
+}
 
 #[derive(
-    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
 )]
 pub struct PumpCreateIx {
     pub method_id: [u8; 8],
@@ -29,6 +95,8 @@ impl std::fmt::Debug for PumpCreateIx {
     Clone,
     Default,
     Copy,
+    PartialEq,
+    Eq,
 )]
 pub struct PumpSwapIx {
     pub method_id: [u8; 8],
@@ -44,3 +112,430 @@ impl std::fmt::Debug for PumpSwapIx {
             .finish()
     }
 }
+
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    Clone,
+    Default,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+pub struct PumpSellIx {
+    pub method_id: [u8; 8],
+    pub amount: u64,
+    pub min_sol_output: u64,
+}
+
+impl std::fmt::Debug for PumpSellIx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PumpSellIx")
+            .field("amount", &self.amount)
+            .field("min_sol_output", &self.min_sol_output)
+            .finish()
+    }
+}
+
+/// Accounts of a pump token creation, resolved through the account indices
+/// of the `create` instruction itself rather than the tx-wide key layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PumpCreateAccounts {
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub associated_bonding_curve: Pubkey,
+}
+
+/// find_create_accounts looks for an instruction of the pump program which
+/// takes the pump mint authority as its second account (the `create` ix)
+/// and reads the mint and curve accounts off of it; if the mint authority is
+/// present but no instruction matches, the layout is logged as a near-match;
+/// `account_keys` include those loaded from the lookup tables, see
+/// LookupTableCache::account_keys
+pub fn find_create_accounts(
+    tx: &VersionedTransaction,
+    account_keys: &[Pubkey],
+) -> Option<PumpCreateAccounts> {
+    let mint_authority = *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY;
+    if !account_keys.contains(&mint_authority) {
+        return None;
+    }
+    for ix in tx.message.instructions() {
+        if account_keys.get(ix.program_id_index as usize)
+            != Some(&constants::PUMP_FUN_PROGRAM_PUBKEY)
+        {
+            continue;
+        }
+        let key_at = |position| ix_account_key(account_keys, ix, position);
+        if key_at(CREATE_IX_MINT_AUTHORITY_POSITION) != Some(mint_authority) {
+            continue;
+        }
+        if let (Some(mint), Some(bonding_curve), Some(associated)) = (
+            key_at(CREATE_IX_MINT_POSITION),
+            key_at(CREATE_IX_BONDING_CURVE_POSITION),
+            key_at(CREATE_IX_ASSOCIATED_BONDING_CURVE_POSITION),
+        ) {
+            return Some(PumpCreateAccounts {
+                mint,
+                bonding_curve,
+                associated_bonding_curve: associated,
+            });
+        }
+    }
+
+    // the create is made by another program, see find_cpi_create
+    if is_wrapped_create(tx, account_keys) {
+        return None;
+    }
+    warn!(
+        "Near-match pump layout (mint authority present, no create ix): {} keys, {} ixs, sig: {}",
+        account_keys.len(),
+        tx.message.instructions().len(),
+        tx.signatures
+            .first()
+            .map(|sig| sig.to_string())
+            .unwrap_or_default()
+    );
+    None
+}
+
+/// CpiCreate is a pump token creation made by another program (a launchpad
+/// or a bot) through a CPI, the inner instructions are not in the shreds, so
+/// the create instruction is only known if the wrapper passed its data on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpiCreate {
+    pub accounts: PumpCreateAccounts,
+    pub ix: Option<PumpCreateIx>,
+}
+
+/// is_wrapped_create tells if an instruction of another program takes the
+/// pump program as an account, i.e. calls into it
+fn is_wrapped_create(
+    tx: &VersionedTransaction,
+    account_keys: &[Pubkey],
+) -> bool {
+    let Some(pump_index) = account_keys
+        .iter()
+        .position(|key| key == &*constants::PUMP_FUN_PROGRAM_PUBKEY)
+    else {
+        return false;
+    };
+    tx.message.instructions().iter().any(|ix| {
+        ix.program_id_index as usize != pump_index
+            && ix.accounts.contains(&(pump_index as u8))
+    })
+}
+
+/// find_cpi_create looks for the accounts of a `create` among the accounts
+/// of an instruction which calls into the pump program, the wrappers pass
+/// them on in the order of the create, so the mint is right before the mint
+/// authority and the curves follow it; the associated bonding curve has to
+/// match its derivation as nothing else ties the accounts to a create; see
+/// find_create_accounts for `account_keys`
+pub fn find_cpi_create(
+    tx: &VersionedTransaction,
+    account_keys: &[Pubkey],
+) -> Option<CpiCreate> {
+    if !is_wrapped_create(tx, account_keys) {
+        return None;
+    }
+    let mint_authority = *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY;
+    for ix in tx.message.instructions() {
+        if account_keys.get(ix.program_id_index as usize)
+            == Some(&constants::PUMP_FUN_PROGRAM_PUBKEY)
+        {
+            continue;
+        }
+        let mint_authority_positions = ix
+            .accounts
+            .iter()
+            .enumerate()
+            .filter(|(_, &index)| {
+                account_keys.get(index as usize) == Some(&mint_authority)
+            })
+            .map(|(position, _)| position);
+        for position in mint_authority_positions {
+            let Some(mint_position) = position.checked_sub(1) else {
+                continue;
+            };
+            let key_at = |offset| {
+                ix_account_key(account_keys, ix, mint_position + offset)
+            };
+            let (Some(mint), Some(bonding_curve), Some(associated)) = (
+                key_at(CREATE_IX_MINT_POSITION),
+                key_at(CREATE_IX_BONDING_CURVE_POSITION),
+                key_at(CREATE_IX_ASSOCIATED_BONDING_CURVE_POSITION),
+            ) else {
+                continue;
+            };
+            let accounts = PumpCreateAccounts {
+                mint,
+                bonding_curve,
+                associated_bonding_curve: associated,
+            };
+            if accounts.has_valid_associated_bonding_curve() {
+                return Some(CpiCreate {
+                    accounts,
+                    ix: find_embedded_create_ix(&ix.data),
+                });
+            }
+        }
+    }
+    None
+}
+
+/// find_embedded_create_ix looks for the data of a `create` within the data
+/// of the wrapping instruction, which some wrappers pass on as is
+fn find_embedded_create_ix(data: &[u8]) -> Option<PumpCreateIx> {
+    data.windows(PUMP_CREATE_DISCRIMINATOR.len())
+        .enumerate()
+        .filter(|(_, window)| *window == PUMP_CREATE_DISCRIMINATOR)
+        .find_map(|(offset, _)| {
+            // the wrapper's own arguments may follow
+            PumpCreateIx::deserialize(&mut &data[offset..]).ok()
+        })
+}
+
+impl PumpCreateAccounts {
+    /// has_valid_associated_bonding_curve checks that the associated bonding
+    /// curve is the ATA of the bonding curve for the mint, a mismatch means
+    /// the accounts were misread from the instruction
+    pub fn has_valid_associated_bonding_curve(&self) -> bool {
+        derive_associated_token_account(&self.bonding_curve, &self.mint)
+            == self.associated_bonding_curve
+    }
+}
+
+pub fn derive_associated_token_account(
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            owner.as_ref(),
+            constants::TOKEN_PROGRAM_PUBKEY.as_ref(),
+            mint.as_ref(),
+        ],
+        &constants::ASSOCIATED_TOKEN_PROGRAM_PUBKEY,
+    )
+    .0
+}
+
+fn ix_account_key(
+    account_keys: &[Pubkey],
+    ix: &CompiledInstruction,
+    position: usize,
+) -> Option<Pubkey> {
+    ix.accounts
+        .get(position)
+        .and_then(|&index| account_keys.get(index as usize))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn accounts(associated_bonding_curve: &str) -> PumpCreateAccounts {
+        PumpCreateAccounts {
+            mint: Pubkey::from_str(
+                "EbZh3FDVcgnLNbh1ooatcDL1RCRhBgTKirFKNoGPpump",
+            )
+            .unwrap(),
+            bonding_curve: Pubkey::from_str(
+                "FE5FUDW6dX8E2PZ8gk9R44cpurK49j9mVJ6bRUz7ZKvi",
+            )
+            .unwrap(),
+            associated_bonding_curve: Pubkey::from_str(
+                associated_bonding_curve,
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn associated_bonding_curve_matches_derivation() {
+        let valid = accounts("2jGnYmkUTvqeFDRLMWGWo7Tzcs4a8wdoZuor7zx9N7Ed");
+        assert!(valid.has_valid_associated_bonding_curve());
+
+        let invalid =
+            accounts("FE5FUDW6dX8E2PZ8gk9R44cpurK49j9mVJ6bRUz7ZKvi");
+        assert!(!invalid.has_valid_associated_bonding_curve());
+    }
+
+    #[test]
+    fn decodes_instructions_by_discriminator() {
+        let buy =
+            hex::decode("66063d1201daebea301f9fd68d2000008060333c00000000")
+                .unwrap();
+        assert_eq!(
+            PumpIx::decode(&buy),
+            Some(PumpIx::Buy(PumpSwapIx {
+                method_id: PUMP_BUY_DISCRIMINATOR,
+                amount: 35_793_563_230_000,
+                max_sol_cost: 1_010_000_000,
+            }))
+        );
+        // same layout as the buy, only the discriminator tells them apart
+        let sell =
+            hex::decode("33e685a4017f83ad301f9fd68d2000008006d13900000000")
+                .unwrap();
+        assert_eq!(
+            PumpIx::decode(&sell),
+            Some(PumpIx::Sell(PumpSellIx {
+                method_id: PUMP_SELL_DISCRIMINATOR,
+                amount: 35_793_563_230_000,
+                min_sol_output: 970_000_000,
+            }))
+        );
+
+        let create = borsh::to_vec(&PumpCreateIx {
+            method_id: PUMP_CREATE_DISCRIMINATOR,
+            name: "gringo".to_string(),
+            symbol: "GRINGO".to_string(),
+            uri: "https://cf-ipfs.com/ipfs/Qm".to_string(),
+        })
+        .unwrap();
+        assert!(matches!(
+            PumpIx::decode(&create),
+            Some(PumpIx::Create(PumpCreateIx { name, .. })) if name == "gringo"
+        ));
+        assert_eq!(
+            PumpIx::decode(&PUMP_WITHDRAW_DISCRIMINATOR),
+            Some(PumpIx::Withdraw)
+        );
+        assert_eq!(
+            PumpIx::decode(&PUMP_MIGRATE_DISCRIMINATOR),
+            Some(PumpIx::Migrate)
+        );
+
+        // truncated, unknown and too short
+        assert_eq!(PumpIx::decode(&buy[..20]), None);
+        assert_eq!(PumpIx::decode(&[0; 24]), None);
+        assert_eq!(PumpIx::decode(&buy[..4]), None);
+    }
+
+    #[test]
+    fn finds_creates_wrapped_in_a_cpi() {
+        use solana_sdk::hash::Hash;
+        use solana_sdk::message::{Message, MessageHeader, VersionedMessage};
+        use solana_sdk::signature::Signature;
+
+        let accounts =
+            accounts("2jGnYmkUTvqeFDRLMWGWo7Tzcs4a8wdoZuor7zx9N7Ed");
+        let create = PumpCreateIx {
+            method_id: PUMP_CREATE_DISCRIMINATOR,
+            name: "gringo".to_string(),
+            symbol: "GRINGO".to_string(),
+            uri: "https://cf-ipfs.com/ipfs/Qm".to_string(),
+        };
+        // the launchpad's own discriminator, the create and a trailing arg
+        let mut data = vec![7; 8];
+        data.extend(borsh::to_vec(&create).unwrap());
+        data.extend(1_000_000u64.to_le_bytes());
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::Legacy(Message {
+                header: MessageHeader {
+                    num_required_signatures: 2,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 3,
+                },
+                account_keys: vec![
+                    Pubkey::new_unique(),
+                    accounts.mint,
+                    accounts.bonding_curve,
+                    accounts.associated_bonding_curve,
+                    *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY,
+                    *constants::PUMP_FUN_PROGRAM_PUBKEY,
+                    Pubkey::new_unique(),
+                ],
+                recent_blockhash: Hash::default(),
+                instructions: vec![CompiledInstruction::new_from_raw_parts(
+                    6,
+                    data,
+                    vec![0, 5, 1, 4, 2, 3],
+                )],
+            }),
+        };
+
+        let account_keys = tx.message.static_account_keys();
+        assert_eq!(find_create_accounts(&tx, account_keys), None);
+        assert_eq!(
+            find_cpi_create(&tx, account_keys),
+            Some(CpiCreate {
+                accounts,
+                ix: Some(create),
+            })
+        );
+    }
+
+    #[test]
+    fn finds_the_accounts_of_a_create() {
+        use solana_sdk::hash::Hash;
+        use solana_sdk::instruction::{AccountMeta, Instruction};
+        use solana_sdk::message::{Message, VersionedMessage};
+        use solana_sdk::signature::Signature;
+
+        let accounts =
+            accounts("2jGnYmkUTvqeFDRLMWGWo7Tzcs4a8wdoZuor7zx9N7Ed");
+        let user = Pubkey::new_unique();
+        let create = Instruction::new_with_borsh(
+            *constants::PUMP_FUN_PROGRAM_PUBKEY,
+            &PumpCreateIx {
+                method_id: PUMP_CREATE_DISCRIMINATOR,
+                name: "gringo".to_string(),
+                symbol: "GRINGO".to_string(),
+                uri: "https://cf-ipfs.com/ipfs/Qm".to_string(),
+            },
+            // the layout of the pump create, the global, the metadata and
+            // the programs after the curves are not read
+            vec![
+                AccountMeta::new(accounts.mint, true),
+                AccountMeta::new_readonly(
+                    *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY,
+                    false,
+                ),
+                AccountMeta::new(accounts.bonding_curve, false),
+                AccountMeta::new(accounts.associated_bonding_curve, false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new(user, true),
+            ],
+        );
+        // the keys of the message are sorted signers and writable first,
+        // not in the order of the instruction accounts
+        let message = Message::new_with_blockhash(
+            &[create],
+            Some(&user),
+            &Hash::default(),
+        );
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default(); 2],
+            message: VersionedMessage::Legacy(message),
+        };
+        let tx: VersionedTransaction =
+            bincode::deserialize(&bincode::serialize(&tx).unwrap()).unwrap();
+
+        let account_keys = tx.message.static_account_keys();
+        assert_ne!(account_keys[0], accounts.mint);
+        assert_eq!(find_create_accounts(&tx, account_keys), Some(accounts));
+        assert!(accounts.has_valid_associated_bonding_curve());
+
+        // without the mint authority it is not a create
+        let without_authority = account_keys
+            .iter()
+            .map(|&key| {
+                if key == *constants::PUMP_FUN_MINT_AUTHORITY_PUBKEY {
+                    Pubkey::new_unique()
+                } else {
+                    key
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(find_create_accounts(&tx, &without_authority), None);
+    }
+}
@@ -0,0 +1,69 @@
+use tokio::sync::mpsc;
+
+/// Batches of entries queued for the entry processor, past this the
+/// deserialize workers wait for it
+pub const DEFAULT_ENTRY_QUEUE_SIZE: usize = 2000;
+/// Errors queued for the entry processor to log
+pub const DEFAULT_ERROR_QUEUE_SIZE: usize = 2000;
+
+/// QueueSizes are the capacities of the channels between the shred
+/// processor and the entry processor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueSizes {
+    pub entries: usize,
+    pub errors: usize,
+}
+
+impl Default for QueueSizes {
+    fn default() -> Self {
+        QueueSizes {
+            entries: DEFAULT_ENTRY_QUEUE_SIZE,
+            errors: DEFAULT_ERROR_QUEUE_SIZE,
+        }
+    }
+}
+
+/// QueueDepth tells how full a channel is off of a sender of it, a depth
+/// near the capacity means the receiving end is falling behind; the sender
+/// keeps the channel open for as long as the QueueDepth is around
+#[derive(Debug, Clone)]
+pub struct QueueDepth<T> {
+    tx: mpsc::Sender<T>,
+}
+
+impl<T> QueueDepth<T> {
+    pub fn new(tx: &mpsc::Sender<T>) -> Self {
+        QueueDepth { tx: tx.clone() }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "capacity": self.tx.max_capacity(),
+            "depth": self.depth(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tracks_the_depth_of_the_channel() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let queue_depth = QueueDepth::new(&tx);
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(queue_depth.depth(), 3);
+        rx.recv().await.unwrap();
+        assert_eq!(
+            queue_depth.to_json(),
+            serde_json::json!({"capacity": 4, "depth": 2})
+        );
+    }
+}
@@ -6,18 +6,17 @@ use once_cell::sync::Lazy;
 use raydium_amm::math::{CheckedCeilDiv, SwapDirection, U128};
 use raydium_library::amm::{self, openbook, AmmKeys, CalculateResult};
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::clock::Slot;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Keypair;
-use solana_sdk::signer::{EncodableKey, Signer};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
 
 use raydium_amm::instruction::{
     AdminCancelOrdersInstruction, ConfigArgs, DepositInstruction,
@@ -30,32 +29,390 @@ use solana_program::program_error::ProgramError;
 
 use crate::arb::PoolsState;
 use crate::constants;
-use crate::util::env;
+use crate::rpc::RateLimitedRpc;
+use crate::util::{pubkey_to_string, string_to_pubkey};
+use crate::wallet::fund_pubkey;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ParsedAccounts {
     pub amm_id: Pubkey,
     pub pool_coin_vault: Pubkey,
     pub pool_pc_vault: Pubkey,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RaydiumDecimals {
     pub coin_decimals: u8,
     pub pc_decimals: u8,
     pub lp_decimals: u8,
 }
 
-// TODO there might be sol-token or token-sol both versions, gotta be able to handle both
+/// QuoteAsset is what the token of a pool is priced in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteAsset {
+    Wsol,
+    Usdc,
+    Other,
+}
+
+/// Token decimals assumed for new pools until they are loaded, pump.fun
+/// and most fresh mints use 6
+pub const DEFAULT_TOKEN_DECIMALS: u8 = 6;
+
+impl QuoteAsset {
+    pub fn decimals(&self) -> Option<u8> {
+        match self {
+            QuoteAsset::Wsol => Some(9),
+            QuoteAsset::Usdc => Some(6),
+            QuoteAsset::Other => None,
+        }
+    }
+}
+
+/// PoolQuote is the quote side of a pool, for `QuoteAsset::Other` the mint
+/// is the only thing that tells what the amounts are in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolQuote {
+    pub asset: QuoteAsset,
+    pub mint: Pubkey,
+    pub is_coin: bool,
+}
+
+/// detect_quote picks WSOL over USDC as the quote if the pool has both,
+/// pools with neither are taken to be quoted in the pc mint
+pub fn detect_quote(coin_mint: &Pubkey, pc_mint: &Pubkey) -> PoolQuote {
+    for (asset, quote_mint) in [
+        (QuoteAsset::Wsol, *constants::WSOL_PUBKEY),
+        (QuoteAsset::Usdc, *constants::USDC_PUBKEY),
+    ] {
+        if *pc_mint == quote_mint {
+            return PoolQuote {
+                asset,
+                mint: quote_mint,
+                is_coin: false,
+            };
+        }
+        if *coin_mint == quote_mint {
+            return PoolQuote {
+                asset,
+                mint: quote_mint,
+                is_coin: true,
+            };
+        }
+    }
+    PoolQuote {
+        asset: QuoteAsset::Other,
+        mint: *pc_mint,
+        is_coin: false,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RaydiumAmmPool {
     pub token: Pubkey,
     pub amm_keys: AmmKeys,
     pub state: CalculateResult,
     pub decimals: RaydiumDecimals,
+    /// When the state was last updated, by a swap or a load, unix millis
+    pub last_update_ms: Option<i64>,
+    /// Slot of the last swap reduced on the pool
+    pub last_update_slot: Option<Slot>,
+    /// Moving average of the SOL price over the swaps, None until the
+    /// first swap and for the pools not quoted in SOL
+    pub ema_price: Option<Ema>,
+}
+
+/// Half-life of the moving average of the pool prices
+pub const DEFAULT_EMA_HALF_LIFE: Duration = Duration::from_secs(30);
+
+/// Ema is an exponential moving average over irregular observations, an
+/// observation weighs in by how long it has been since the previous one
+/// relative to the half-life, so that a burst of swaps counts for the time
+/// it spans rather than for its number of swaps
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ema {
+    pub value: f64,
+    /// Time of the last observation, unix millis
+    pub updated_ms: i64,
+}
+
+impl Ema {
+    /// new starts off of the first observation rather than from zero
+    pub fn new(value: f64, now_ms: i64) -> Self {
+        Ema {
+            value,
+            updated_ms: now_ms,
+        }
+    }
+
+    /// observe moves the average towards `value`, halfway after a half-life,
+    /// a zero half-life follows the observations
+    pub fn observe(&mut self, value: f64, now_ms: i64, half_life: Duration) {
+        let elapsed_ms = (now_ms - self.updated_ms).max(0) as f64;
+        let half_life_ms = half_life.as_millis() as f64;
+        let weight = if half_life_ms == 0. {
+            1.
+        } else {
+            1. - 0.5f64.powf(elapsed_ms / half_life_ms)
+        };
+        self.value += weight * (value - self.value);
+        self.updated_ms = self.updated_ms.max(now_ms);
+    }
+}
+
+/// RaydiumAmmPoolSnapshot is the serializable form of a RaydiumAmmPool, the
+/// keys, decimals and the last known reserves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaydiumAmmPoolSnapshot {
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub token: Pubkey,
+    pub amm_keys: AmmKeysSnapshot,
+    pub pool_pc_vault_amount: u64,
+    pub pool_coin_vault_amount: u64,
+    pub pool_lp_amount: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+    pub decimals: RaydiumDecimals,
+    #[serde(default)]
+    pub last_update_ms: Option<i64>,
+    #[serde(default)]
+    pub last_update_slot: Option<Slot>,
+    #[serde(default)]
+    pub ema_price: Option<Ema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmmKeysSnapshot {
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_pool: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_coin_mint: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_pc_mint: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_authority: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_target: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_coin_vault: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_pc_vault: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_lp_mint: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub amm_open_order: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub market_program: Pubkey,
+    #[serde(
+        serialize_with = "pubkey_to_string",
+        deserialize_with = "string_to_pubkey"
+    )]
+    pub market: Pubkey,
+    pub nonce: u8,
+}
+
+impl From<&RaydiumAmmPool> for RaydiumAmmPoolSnapshot {
+    fn from(pool: &RaydiumAmmPool) -> Self {
+        let keys = &pool.amm_keys;
+        RaydiumAmmPoolSnapshot {
+            token: pool.token,
+            amm_keys: AmmKeysSnapshot {
+                amm_pool: keys.amm_pool,
+                amm_coin_mint: keys.amm_coin_mint,
+                amm_pc_mint: keys.amm_pc_mint,
+                amm_authority: keys.amm_authority,
+                amm_target: keys.amm_target,
+                amm_coin_vault: keys.amm_coin_vault,
+                amm_pc_vault: keys.amm_pc_vault,
+                amm_lp_mint: keys.amm_lp_mint,
+                amm_open_order: keys.amm_open_order,
+                market_program: keys.market_program,
+                market: keys.market,
+                nonce: keys.nonce,
+            },
+            pool_pc_vault_amount: pool.state.pool_pc_vault_amount,
+            pool_coin_vault_amount: pool.state.pool_coin_vault_amount,
+            pool_lp_amount: pool.state.pool_lp_amount,
+            swap_fee_numerator: pool.state.swap_fee_numerator,
+            swap_fee_denominator: pool.state.swap_fee_denominator,
+            decimals: pool.decimals,
+            last_update_ms: pool.last_update_ms,
+            last_update_slot: pool.last_update_slot,
+            ema_price: pool.ema_price,
+        }
+    }
+}
+
+impl From<RaydiumAmmPoolSnapshot> for RaydiumAmmPool {
+    fn from(snapshot: RaydiumAmmPoolSnapshot) -> Self {
+        let keys = snapshot.amm_keys;
+        RaydiumAmmPool {
+            token: snapshot.token,
+            amm_keys: AmmKeys {
+                amm_pool: keys.amm_pool,
+                amm_coin_mint: keys.amm_coin_mint,
+                amm_pc_mint: keys.amm_pc_mint,
+                amm_authority: keys.amm_authority,
+                amm_target: keys.amm_target,
+                amm_coin_vault: keys.amm_coin_vault,
+                amm_pc_vault: keys.amm_pc_vault,
+                amm_lp_mint: keys.amm_lp_mint,
+                amm_open_order: keys.amm_open_order,
+                market_program: keys.market_program,
+                market: keys.market,
+                nonce: keys.nonce,
+            },
+            state: CalculateResult {
+                pool_pc_vault_amount: snapshot.pool_pc_vault_amount,
+                pool_coin_vault_amount: snapshot.pool_coin_vault_amount,
+                pool_lp_amount: snapshot.pool_lp_amount,
+                swap_fee_numerator: snapshot.swap_fee_numerator,
+                swap_fee_denominator: snapshot.swap_fee_denominator,
+            },
+            decimals: snapshot.decimals,
+            last_update_ms: snapshot.last_update_ms,
+            last_update_slot: snapshot.last_update_slot,
+            ema_price: snapshot.ema_price,
+        }
+    }
+}
+
+impl RaydiumAmmPool {
+    /// touch marks the state as updated now, by a swap in `slot` if any
+    pub fn touch(&mut self, slot: Option<Slot>) {
+        self.last_update_ms = Some(chrono::Utc::now().timestamp_millis());
+        if slot.is_some() {
+            self.last_update_slot = slot;
+        }
+    }
+
+    /// observe_price adds the price to the moving average, which starts
+    /// off of it if it is the first one
+    pub fn observe_price(&mut self, price: f64, half_life: Duration) {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        match self.ema_price.as_mut() {
+            Some(ema) => ema.observe(price, now_ms, half_life),
+            None => self.ema_price = Some(Ema::new(price, now_ms)),
+        }
+    }
+
+    /// age is how long ago the state was last updated, None if never
+    pub fn age(&self) -> Option<Duration> {
+        let last_update_ms = self.last_update_ms?;
+        let elapsed_ms =
+            chrono::Utc::now().timestamp_millis() - last_update_ms;
+        Some(Duration::from_millis(elapsed_ms.max(0) as u64))
+    }
+
+    /// is_stale tells if the state was not updated within `max_age`, the
+    /// reserves of pools nobody trades drift from the chain (e.g. through
+    /// deposits and withdrawals), so their prices cannot be relied on
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age().map_or(true, |age| age > max_age)
+    }
+
+    pub fn quote(&self) -> PoolQuote {
+        detect_quote(&self.amm_keys.amm_coin_mint, &self.amm_keys.amm_pc_mint)
+    }
+
+    /// price_in_sol is the price of the token in SOL, regardless of whether
+    /// WSOL is the coin or the pc side of the pool, None for pools not quoted
+    /// in SOL
+    pub fn price_in_sol(&self) -> Option<f64> {
+        let quote = self.quote();
+        if quote.asset != QuoteAsset::Wsol {
+            return None;
+        }
+        self.price_in_quote(&quote)
+    }
+
+    /// price_in_quote is the price of the token in units of the quote
+    pub fn price_in_quote(&self, quote: &PoolQuote) -> Option<f64> {
+        price_in_quote(
+            self.state.pool_coin_vault_amount,
+            self.state.pool_pc_vault_amount,
+            &self.decimals,
+            quote.is_coin,
+        )
+    }
+
+    pub fn quote_decimals(&self, quote: &PoolQuote) -> u8 {
+        if quote.is_coin {
+            self.decimals.coin_decimals
+        } else {
+            self.decimals.pc_decimals
+        }
+    }
+}
+
+pub fn price_in_sol(
+    coin_vault_amount: u64,
+    pc_vault_amount: u64,
+    decimals: &RaydiumDecimals,
+    sol_is_coin: bool,
+) -> Option<f64> {
+    price_in_quote(coin_vault_amount, pc_vault_amount, decimals, sol_is_coin)
 }
 
+pub fn price_in_quote(
+    coin_vault_amount: u64,
+    pc_vault_amount: u64,
+    decimals: &RaydiumDecimals,
+    quote_is_coin: bool,
+) -> Option<f64> {
+    let coin = coin_vault_amount as f64
+        / 10u64.pow(decimals.coin_decimals as u32) as f64;
+    let pc = pc_vault_amount as f64
+        / 10u64.pow(decimals.pc_decimals as u32) as f64;
+    let (quote, token) = if quote_is_coin {
+        (coin, pc)
+    } else {
+        (pc, coin)
+    };
+    if token == 0. {
+        return None;
+    }
+    Some(quote / token)
+}
+
+/// initialize_raydium_amm_pools loads the pools of the mints of interest,
+/// pools whose market or vaults fail to load are skipped
 pub async fn initialize_raydium_amm_pools(
-    rpc_client: &RpcClient,
+    rpc: &RateLimitedRpc,
     pools_state: &mut PoolsState,
     mints_of_interest: Vec<Pubkey>,
 ) {
@@ -63,37 +420,61 @@ pub async fn initialize_raydium_amm_pools(
     let amm_keys_map =
         parse_raydium_json(RAYDIUM_JSON.clone(), mints_of_interest.clone())
             .expect("parse raydium json");
-    let amm_program =
-        Pubkey::from_str(constants::RAYDIUM_AMM).expect("pubkey");
-    let payer = Keypair::read_from_file(env("FUND_KEYPAIR_PATH"))
-        .expect("Failed to read keypair");
-    let fee_payer = payer.pubkey();
+    let amm_program = *constants::RAYDIUM_AMM_PUBKEY;
+    let fee_payer = fund_pubkey();
 
     // Fetch results
     let futures = mints_of_interest.iter().map(|mint| {
         let amm_keys_map = amm_keys_map.clone();
         async move {
-            let amm_keys_vec = amm_keys_map.get(mint).unwrap(); // bound to exist
             let mut results = Vec::new();
+            let Some(amm_keys_vec) = amm_keys_map.get(mint) else {
+                warn!("No pools for {} in raydium.json", mint);
+                return results;
+            };
             for (amm_keys, decimals) in amm_keys_vec.iter() {
                 info!("Loading AMM keys for pool: {:?}", amm_keys.amm_pool);
-                let market_keys = openbook::get_keys_for_market(
-                    rpc_client,
-                    &amm_keys.market_program,
-                    &amm_keys.market,
-                )
-                .await
-                .expect("get market keys");
-                let state = amm::calculate_pool_vault_amounts(
-                    rpc_client,
-                    &amm_program,
-                    &amm_keys.amm_pool,
-                    amm_keys,
-                    &market_keys,
-                    amm::utils::CalculateMethod::Simulate(fee_payer),
-                )
-                .await
-                .expect("calculate pool vault amounts");
+                let market_keys = match rpc
+                    .call(|client| {
+                        openbook::get_keys_for_market(
+                            client,
+                            &amm_keys.market_program,
+                            &amm_keys.market,
+                        )
+                    })
+                    .await
+                {
+                    Ok(market_keys) => market_keys,
+                    Err(e) => {
+                        warn!(
+                            "Skipping pool {}, failed to get market keys: {}",
+                            amm_keys.amm_pool, e
+                        );
+                        continue;
+                    }
+                };
+                let state = match rpc
+                    .call(|client| {
+                        amm::calculate_pool_vault_amounts(
+                            client,
+                            &amm_program,
+                            &amm_keys.amm_pool,
+                            amm_keys,
+                            &market_keys,
+                            amm::utils::CalculateMethod::Simulate(fee_payer),
+                        )
+                    })
+                    .await
+                {
+                    Ok(state) => state,
+                    Err(e) => {
+                        warn!(
+                            "Skipping pool {}, failed to calculate vault amounts: {}",
+                            amm_keys.amm_pool, e
+                        );
+                        continue;
+                    }
+                };
                 results.push((*mint, *amm_keys, state, *decimals));
             }
             results
@@ -106,35 +487,107 @@ pub async fn initialize_raydium_amm_pools(
     // Update pools_state
     for results in all_results {
         for (mint, amm_keys, state, decimals) in results {
-            pools_state.raydium_pools.insert(
-                amm_keys.amm_pool,
-                Arc::new(RwLock::new(RaydiumAmmPool {
-                    token: mint,
-                    amm_keys,
-                    state,
-                    decimals,
-                })),
-            );
-            pools_state
-                .raydium_pools_by_mint
-                .entry(mint)
-                .or_default()
-                .push(amm_keys.amm_pool);
+            pools_state.insert_raydium_pool(RaydiumAmmPool {
+                token: mint,
+                amm_keys,
+                state,
+                decimals,
+                last_update_ms: Some(chrono::Utc::now().timestamp_millis()),
+                last_update_slot: None,
+                ema_price: None,
+            });
         }
     }
 }
 
+// offset of the decimals in the spl-token mint account layout
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+/// load_raydium_amm_pool loads a pool straight from the chain, for pools
+/// which are not in raydium.json
+pub async fn load_raydium_amm_pool(
+    rpc: &RateLimitedRpc,
+    amm_pool: &Pubkey,
+    fee_payer: Pubkey,
+) -> anyhow::Result<RaydiumAmmPool> {
+    let amm_program = *constants::RAYDIUM_AMM_PUBKEY;
+    let amm_keys = rpc
+        .call(|client| {
+            amm::utils::load_amm_keys(client, &amm_program, amm_pool)
+        })
+        .await?;
+    let market_keys = rpc
+        .call(|client| {
+            openbook::get_keys_for_market(
+                client,
+                &amm_keys.market_program,
+                &amm_keys.market,
+            )
+        })
+        .await?;
+    let state = rpc
+        .call(|client| {
+            amm::calculate_pool_vault_amounts(
+                client,
+                &amm_program,
+                amm_pool,
+                &amm_keys,
+                &market_keys,
+                amm::utils::CalculateMethod::Simulate(fee_payer),
+            )
+        })
+        .await?;
+
+    let mint_keys = [
+        amm_keys.amm_coin_mint,
+        amm_keys.amm_pc_mint,
+        amm_keys.amm_lp_mint,
+    ];
+    let mints = rpc
+        .call(|client| client.get_multiple_accounts(&mint_keys))
+        .await?;
+    let mut decimals = mints.iter().map(|account| {
+        account
+            .as_ref()
+            .and_then(|account| account.data.get(MINT_DECIMALS_OFFSET))
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("invalid mint account"))
+    });
+    let decimals = RaydiumDecimals {
+        coin_decimals: decimals.next().expect("coin mint")?,
+        pc_decimals: decimals.next().expect("pc mint")?,
+        lp_decimals: decimals.next().expect("lp mint")?,
+    };
+
+    let token = if amm_keys.amm_coin_mint == *constants::WSOL_PUBKEY {
+        amm_keys.amm_pc_mint
+    } else {
+        amm_keys.amm_coin_mint
+    };
+    Ok(RaydiumAmmPool {
+        token,
+        amm_keys,
+        state,
+        decimals,
+        last_update_ms: Some(chrono::Utc::now().timestamp_millis()),
+        last_update_slot: None,
+        ema_price: None,
+    })
+}
+
 static RAYDIUM_JSON: Lazy<Arc<Value>> = Lazy::new(|| {
     if !std::path::Path::new("raydium.json").exists() {
         panic!("raydium.json not found, download it first");
     }
-    let json_str = std::fs::read_to_string("raydium.json")
-        .expect("Failed to read raydium.json");
-    let json_value: Value = serde_json::from_str(&json_str)
-        .expect("Failed to parse raydium.json");
-    Arc::new(json_value)
+    read_raydium_json().expect("Failed to read raydium.json")
 });
 
+/// read_raydium_json reads the raydium.json on disk, as it is now
+pub fn read_raydium_json() -> anyhow::Result<Arc<Value>> {
+    let json_str = std::fs::read_to_string("raydium.json")?;
+    Ok(Arc::new(serde_json::from_str(&json_str)?))
+}
+
 pub fn calculate_price(
     state: &CalculateResult,
     decimals: &RaydiumDecimals,
@@ -278,7 +731,116 @@ pub async fn download_raydium_json(
     Ok(())
 }
 
-type Amm = (AmmKeys, RaydiumDecimals);
+pub type Amm = (AmmKeys, RaydiumDecimals);
+
+/// RaydiumJsonDiff is what changed in the pools of the mints of interest
+/// between two versions of raydium.json, with the mint of each pool
+#[derive(Debug, Default)]
+pub struct RaydiumJsonDiff {
+    pub new: Vec<(Pubkey, Amm)>,
+    pub changed: Vec<(Pubkey, Amm)>,
+}
+
+// what tells two entries of a pool apart, the nonce is not read from the
+// json
+fn amm_fields(amm: &Amm) -> ([Pubkey; 11], [u8; 3]) {
+    let (keys, decimals) = amm;
+    (
+        [
+            keys.amm_pool,
+            keys.amm_coin_mint,
+            keys.amm_pc_mint,
+            keys.amm_authority,
+            keys.amm_target,
+            keys.amm_coin_vault,
+            keys.amm_pc_vault,
+            keys.amm_lp_mint,
+            keys.amm_open_order,
+            keys.market_program,
+            keys.market,
+        ],
+        [
+            decimals.coin_decimals,
+            decimals.pc_decimals,
+            decimals.lp_decimals,
+        ],
+    )
+}
+
+/// diff_raydium_pools compares two parses of raydium.json (see
+/// parse_raydium_json) pool by pool, the pools gone from `new` are left
+/// alone
+pub fn diff_raydium_pools(
+    old: &HashMap<Pubkey, Vec<Amm>>,
+    new: &HashMap<Pubkey, Vec<Amm>>,
+) -> RaydiumJsonDiff {
+    let old = old
+        .values()
+        .flatten()
+        .map(|amm| (amm.0.amm_pool, amm_fields(amm)))
+        .collect::<HashMap<_, _>>();
+    let mut diff = RaydiumJsonDiff::default();
+    for (mint, amms) in new {
+        for amm in amms {
+            match old.get(&amm.0.amm_pool) {
+                None => diff.new.push((*mint, *amm)),
+                Some(fields) if *fields != amm_fields(amm) => {
+                    diff.changed.push((*mint, *amm))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    diff
+}
+
+/// RaydiumJsonRefresher re-downloads raydium.json and diffs the pools of
+/// the mints of interest against the version seen before
+pub struct RaydiumJsonRefresher {
+    mints_of_interest: Vec<Pubkey>,
+    known: HashMap<Pubkey, Vec<Amm>>,
+}
+
+impl RaydiumJsonRefresher {
+    /// new starts off of the raydium.json on disk
+    pub async fn new(mints_of_interest: Vec<Pubkey>) -> anyhow::Result<Self> {
+        let known =
+            parse_raydium_json_file(mints_of_interest.clone()).await?;
+        Ok(RaydiumJsonRefresher {
+            mints_of_interest,
+            known,
+        })
+    }
+
+    /// refresh downloads raydium.json again and returns the pools which are
+    /// new or changed since the last version
+    pub async fn refresh(&mut self) -> anyhow::Result<RaydiumJsonDiff> {
+        download_raydium_json(true)
+            .await
+            .map_err(|e| anyhow::anyhow!("download raydium.json: {}", e))?;
+        let pools =
+            parse_raydium_json_file(self.mints_of_interest.clone()).await?;
+        let diff = diff_raydium_pools(&self.known, &pools);
+        self.known = pools;
+        info!(
+            "Refreshed raydium.json: {} new, {} changed pools",
+            diff.new.len(),
+            diff.changed.len()
+        );
+        Ok(diff)
+    }
+}
+
+// the file is large, it is read and parsed off of the runtime
+async fn parse_raydium_json_file(
+    mints_of_interest: Vec<Pubkey>,
+) -> anyhow::Result<HashMap<Pubkey, Vec<Amm>>> {
+    tokio::task::spawn_blocking(move || {
+        parse_raydium_json(read_raydium_json()?, mints_of_interest)
+            .map_err(|e| anyhow::anyhow!("parse raydium.json: {}", e))
+    })
+    .await?
+}
 
 // this takes long, possibly could make it so that it uses a search index it
 // returns all of the
@@ -428,3 +990,150 @@ where
     let ret = unsafe { &*(&data[0] as *const u8 as *const T) };
     Some(ret.clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_in_sol_is_orientation_agnostic() {
+        // 100 SOL against 1,000,000 tokens (6 decimals) either way around
+        let sol_as_pc = price_in_sol(
+            1_000_000_000_000,
+            100_000_000_000,
+            &RaydiumDecimals {
+                coin_decimals: 6,
+                pc_decimals: 9,
+                lp_decimals: 9,
+            },
+            false,
+        );
+        let sol_as_coin = price_in_sol(
+            100_000_000_000,
+            1_000_000_000_000,
+            &RaydiumDecimals {
+                coin_decimals: 9,
+                pc_decimals: 6,
+                lp_decimals: 9,
+            },
+            true,
+        );
+        assert_eq!(sol_as_pc, Some(0.0001));
+        assert_eq!(sol_as_pc, sol_as_coin);
+    }
+
+    #[test]
+    fn detect_usdc_quote() {
+        let token = Pubkey::new_unique();
+        let usdc = *constants::USDC_PUBKEY;
+        let quote = detect_quote(&token, &usdc);
+        assert_eq!(quote.asset, QuoteAsset::Usdc);
+        assert_eq!(quote.mint, usdc);
+        assert!(!quote.is_coin);
+        assert!(detect_quote(&usdc, &token).is_coin);
+
+        // SOL/USDC is a SOL pool priced in USDC
+        let quote = detect_quote(&constants::WSOL_PUBKEY, &usdc);
+        assert_eq!(quote.asset, QuoteAsset::Wsol);
+        assert!(quote.is_coin);
+
+        let other = Pubkey::new_unique();
+        let quote = detect_quote(&token, &other);
+        assert_eq!(quote.asset, QuoteAsset::Other);
+        assert_eq!(quote.mint, other);
+    }
+
+    #[test]
+    fn price_in_usdc() {
+        // 50,000 USDC (6 decimals) against 1,000,000 tokens (6 decimals)
+        let price = price_in_quote(
+            1_000_000_000_000,
+            50_000_000_000,
+            &RaydiumDecimals {
+                coin_decimals: 6,
+                pc_decimals: 6,
+                lp_decimals: 6,
+            },
+            false,
+        );
+        assert_eq!(price, Some(0.05));
+    }
+
+    #[test]
+    fn price_in_sol_without_tokens() {
+        let decimals = RaydiumDecimals {
+            coin_decimals: 6,
+            pc_decimals: 9,
+            lp_decimals: 9,
+        };
+        assert_eq!(price_in_sol(0, 100_000_000_000, &decimals, false), None);
+    }
+
+    #[test]
+    fn ema_converges_to_the_price() {
+        let half_life = Duration::from_secs(10);
+        // starts off of the first observation, not from zero
+        let mut ema = Ema::new(1., 0);
+        assert_eq!(ema.value, 1.);
+
+        // halfway after a half-life
+        ema.observe(2., 10_000, half_life);
+        assert!((ema.value - 1.5).abs() < 1e-12);
+        // a burst at the same time does not move it
+        ema.observe(100., 10_000, half_life);
+        assert!((ema.value - 1.5).abs() < 1e-12);
+
+        // a swap every second at 2 for a minute, 6 half-lives
+        for second in 11..=70 {
+            ema.observe(2., second * 1_000, half_life);
+        }
+        assert!((ema.value - 2.).abs() < 0.5 / 64. + 1e-9);
+        assert!(ema.value < 2.);
+
+        let mut spot = Ema::new(1., 0);
+        spot.observe(3., 1, Duration::ZERO);
+        assert_eq!(spot.value, 3.);
+    }
+
+    fn amm(id: Pubkey, base_vault: Pubkey) -> Amm {
+        let key = || Pubkey::new_unique().to_string();
+        json_to_amm(&serde_json::json!({
+            "id": id.to_string(),
+            "baseMint": key(),
+            "quoteMint": key(),
+            "authority": key(),
+            "targetOrders": key(),
+            "baseVault": base_vault.to_string(),
+            "quoteVault": key(),
+            "lpMint": key(),
+            "openOrders": key(),
+            "marketProgramId": key(),
+            "marketId": key(),
+            "baseDecimals": 6,
+            "quoteDecimals": 9,
+            "lpDecimals": 6,
+        }))
+    }
+
+    #[test]
+    fn diffs_the_pools_of_two_raydium_json_versions() {
+        let mint = Pubkey::new_unique();
+        let (kept, changed, added) = (
+            amm(Pubkey::new_unique(), Pubkey::new_unique()),
+            amm(Pubkey::new_unique(), Pubkey::new_unique()),
+            amm(Pubkey::new_unique(), Pubkey::new_unique()),
+        );
+        let old = HashMap::from([(mint, vec![kept, changed])]);
+        let mut changed_again = changed;
+        changed_again.0.amm_coin_vault = Pubkey::new_unique();
+        let new = HashMap::from([(mint, vec![kept, changed_again, added])]);
+
+        let diff = diff_raydium_pools(&old, &new);
+        assert_eq!(diff.new.len(), 1);
+        assert_eq!(diff.new[0].0, mint);
+        assert_eq!(diff.new[0].1 .0.amm_pool, added.0.amm_pool);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1 .0.amm_pool, changed.0.amm_pool);
+        assert!(diff_raydium_pools(&new, &new).new.is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::Serialize;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use std::time::Instant;
+
+use crate::arb::get_account_key_safely;
+use crate::rpc::RateLimitedRpc;
+use crate::util::pubkey_to_string;
+
+// anchor discriminators of the CLMM swaps, sha256("global:<name>")
+pub const CLMM_SWAP_DISCRIMINATOR: [u8; 8] =
+    [248, 198, 158, 145, 225, 117, 135, 200];
+pub const CLMM_SWAP_V2_DISCRIMINATOR: [u8; 8] =
+    [43, 4, 237, 11, 26, 201, 30, 98];
+
+// positions of the accounts within `swap` and `swap_v2`
+const SWAP_IX_POOL_STATE_POSITION: usize = 2;
+const SWAP_IX_INPUT_VAULT_POSITION: usize = 5;
+const SWAP_IX_OUTPUT_VAULT_POSITION: usize = 6;
+// only `swap_v2` carries the mints
+const SWAP_V2_IX_INPUT_MINT_POSITION: usize = 11;
+const SWAP_V2_IX_OUTPUT_MINT_POSITION: usize = 12;
+
+// offsets of the fields of the PoolState account
+const POOL_STATE_MINT_0_OFFSET: usize = 73;
+const POOL_STATE_MINT_1_OFFSET: usize = 105;
+const POOL_STATE_VAULT_0_OFFSET: usize = 137;
+const POOL_STATE_VAULT_1_OFFSET: usize = 169;
+const POOL_STATE_DECIMALS_0_OFFSET: usize = 233;
+const POOL_STATE_DECIMALS_1_OFFSET: usize = 234;
+const POOL_STATE_LIQUIDITY_OFFSET: usize = 237;
+const POOL_STATE_SQRT_PRICE_OFFSET: usize = 253;
+
+/// Arguments of the CLMM `swap` and `swap_v2`, `amount` is the input if
+/// `is_base_input` and the output otherwise, the price is not let past
+/// `sqrt_price_limit_x64` (0 for no limit)
+#[derive(
+    BorshDeserialize, BorshSerialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+pub struct ClmmSwapIx {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub sqrt_price_limit_x64: u128,
+    pub is_base_input: bool,
+}
+
+impl ClmmSwapIx {
+    /// decode is None for the other instructions of the program
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+        match discriminator {
+            CLMM_SWAP_DISCRIMINATOR | CLMM_SWAP_V2_DISCRIMINATOR => {
+                ClmmSwapIx::try_from_slice(&data[8..]).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// ClmmSwapAccounts are the accounts of a CLMM swap, the mints are only
+/// there for `swap_v2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClmmSwapAccounts {
+    pub pool_id: Pubkey,
+    pub input_vault: Pubkey,
+    pub output_vault: Pubkey,
+    pub mints: Option<(Pubkey, Pubkey)>,
+}
+
+impl ClmmSwapAccounts {
+    pub fn parse(
+        account_keys: &[Pubkey],
+        instruction: &CompiledInstruction,
+    ) -> Option<Self> {
+        let key_at = |position| {
+            get_account_key_safely(account_keys, instruction, position)
+        };
+        let is_v2 = instruction.data.get(..8)
+            == Some(CLMM_SWAP_V2_DISCRIMINATOR.as_slice());
+        Some(ClmmSwapAccounts {
+            pool_id: key_at(SWAP_IX_POOL_STATE_POSITION)?,
+            input_vault: key_at(SWAP_IX_INPUT_VAULT_POSITION)?,
+            output_vault: key_at(SWAP_IX_OUTPUT_VAULT_POSITION)?,
+            mints: if is_v2 {
+                Some((
+                    key_at(SWAP_V2_IX_INPUT_MINT_POSITION)?,
+                    key_at(SWAP_V2_IX_OUTPUT_MINT_POSITION)?,
+                ))
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// Which way a swap moved the price of token 0 in token 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceDirection {
+    /// token 0 in, the price goes down
+    Down,
+    /// token 1 in, the price goes up
+    Up,
+}
+
+/// RaydiumClmmPool is a tracked concentrated liquidity pool; the swaps only
+/// tell the direction the price went, `sqrt_price_x64` and `liquidity` are
+/// as of the last time the account was read
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RaydiumClmmPool {
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub pool_id: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub mint_0: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub mint_1: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub vault_0: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub vault_1: Pubkey,
+    /// None for the pools registered off of a swap
+    pub decimals: Option<(u8, u8)>,
+    /// Q64.64 square root of the price of token 0 in token 1, 0 if unknown
+    pub sqrt_price_x64: u128,
+    pub liquidity: u128,
+    pub last_direction: Option<PriceDirection>,
+    /// Tightest price limit of the last swap, the price is at most this far
+    /// in `last_direction`
+    pub last_sqrt_price_limit_x64: Option<u128>,
+    pub swaps_down: u64,
+    pub swaps_up: u64,
+    /// When the last swap on the pool was seen, the least recent one is
+    /// dropped once too many pools are tracked
+    #[serde(skip)]
+    pub last_swap_at: Option<Instant>,
+}
+
+impl RaydiumClmmPool {
+    /// from_swap registers a pool off of the accounts of a `swap_v2`, the
+    /// mints of a pool are ordered, so the side of each vault is known
+    pub fn from_swap(accounts: &ClmmSwapAccounts) -> Option<Self> {
+        let (input_mint, output_mint) = accounts.mints?;
+        let (mint_0, mint_1, vault_0, vault_1) = if input_mint < output_mint {
+            (
+                input_mint,
+                output_mint,
+                accounts.input_vault,
+                accounts.output_vault,
+            )
+        } else {
+            (
+                output_mint,
+                input_mint,
+                accounts.output_vault,
+                accounts.input_vault,
+            )
+        };
+        Some(RaydiumClmmPool {
+            pool_id: accounts.pool_id,
+            mint_0,
+            mint_1,
+            vault_0,
+            vault_1,
+            ..Default::default()
+        })
+    }
+
+    /// from_account_data reads the pool off of its PoolState account
+    pub fn from_account_data(pool_id: Pubkey, data: &[u8]) -> Option<Self> {
+        let pubkey_at = |offset: usize| {
+            data.get(offset..offset + 32)
+                .and_then(|bytes| Pubkey::try_from(bytes).ok())
+        };
+        let u128_at = |offset: usize| {
+            data.get(offset..offset + 16)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u128::from_le_bytes)
+        };
+        Some(RaydiumClmmPool {
+            pool_id,
+            mint_0: pubkey_at(POOL_STATE_MINT_0_OFFSET)?,
+            mint_1: pubkey_at(POOL_STATE_MINT_1_OFFSET)?,
+            vault_0: pubkey_at(POOL_STATE_VAULT_0_OFFSET)?,
+            vault_1: pubkey_at(POOL_STATE_VAULT_1_OFFSET)?,
+            decimals: Some((
+                *data.get(POOL_STATE_DECIMALS_0_OFFSET)?,
+                *data.get(POOL_STATE_DECIMALS_1_OFFSET)?,
+            )),
+            liquidity: u128_at(POOL_STATE_LIQUIDITY_OFFSET)?,
+            sqrt_price_x64: u128_at(POOL_STATE_SQRT_PRICE_OFFSET)?,
+            ..Default::default()
+        })
+    }
+
+    /// refresh takes the state of the pool off of a freshly read account,
+    /// keeping the swaps recorded on it
+    pub fn refresh(&mut self, loaded: RaydiumClmmPool) {
+        self.mint_0 = loaded.mint_0;
+        self.mint_1 = loaded.mint_1;
+        self.vault_0 = loaded.vault_0;
+        self.vault_1 = loaded.vault_1;
+        self.decimals = loaded.decimals;
+        self.sqrt_price_x64 = loaded.sqrt_price_x64;
+        self.liquidity = loaded.liquidity;
+    }
+
+    /// apply_swap records the direction of the swap, None if its vaults are
+    /// not the ones of the pool
+    pub fn apply_swap(
+        &mut self,
+        accounts: &ClmmSwapAccounts,
+        swap: &ClmmSwapIx,
+    ) -> Option<PriceDirection> {
+        let direction = if accounts.input_vault == self.vault_0
+            && accounts.output_vault == self.vault_1
+        {
+            self.swaps_down += 1;
+            PriceDirection::Down
+        } else if accounts.input_vault == self.vault_1
+            && accounts.output_vault == self.vault_0
+        {
+            self.swaps_up += 1;
+            PriceDirection::Up
+        } else {
+            return None;
+        };
+        self.last_direction = Some(direction);
+        self.last_swap_at = Some(Instant::now());
+        self.last_sqrt_price_limit_x64 = (swap.sqrt_price_limit_x64 != 0)
+            .then_some(swap.sqrt_price_limit_x64);
+        Some(direction)
+    }
+
+    /// price is the price of token 0 in token 1 in UI units, None until the
+    /// account has been read
+    pub fn price(&self) -> Option<f64> {
+        let (decimals_0, decimals_1) = self.decimals?;
+        if self.sqrt_price_x64 == 0 {
+            return None;
+        }
+        let sqrt_price = self.sqrt_price_x64 as f64 / 2f64.powi(64);
+        Some(
+            sqrt_price
+                * sqrt_price
+                * 10f64.powi(decimals_0 as i32 - decimals_1 as i32),
+        )
+    }
+}
+
+/// load_raydium_clmm_pool reads a pool off of its PoolState account
+pub async fn load_raydium_clmm_pool(
+    rpc: &RateLimitedRpc,
+    pool_id: &Pubkey,
+) -> anyhow::Result<RaydiumClmmPool> {
+    let data = rpc.call(|client| client.get_account_data(pool_id)).await?;
+    RaydiumClmmPool::from_account_data(*pool_id, &data)
+        .ok_or_else(|| anyhow::anyhow!("invalid CLMM pool state account"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap_ix(discriminator: [u8; 8], swap: &ClmmSwapIx) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        data.extend(borsh::to_vec(swap).unwrap());
+        data
+    }
+
+    #[test]
+    fn tracks_price_direction() {
+        let swap = ClmmSwapIx {
+            amount: 1_000_000,
+            other_amount_threshold: 0,
+            sqrt_price_limit_x64: 0,
+            is_base_input: true,
+        };
+        assert_eq!(
+            ClmmSwapIx::decode(&swap_ix(CLMM_SWAP_V2_DISCRIMINATOR, &swap)),
+            Some(swap)
+        );
+        assert_eq!(ClmmSwapIx::decode(&swap_ix([0; 8], &swap)), None);
+
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (vault_a, vault_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = ClmmSwapAccounts {
+            pool_id: Pubkey::new_unique(),
+            input_vault: vault_a,
+            output_vault: vault_b,
+            mints: Some((mint_a, mint_b)),
+        };
+        let mut pool = RaydiumClmmPool::from_swap(&accounts).unwrap();
+        assert!(pool.mint_0 < pool.mint_1);
+        let a_is_0 = mint_a < mint_b;
+        let (down, up) = if a_is_0 {
+            (PriceDirection::Down, PriceDirection::Up)
+        } else {
+            (PriceDirection::Up, PriceDirection::Down)
+        };
+        assert_eq!(pool.apply_swap(&accounts, &swap), Some(down));
+        let back = ClmmSwapAccounts {
+            input_vault: vault_b,
+            output_vault: vault_a,
+            mints: None,
+            ..accounts
+        };
+        assert_eq!(pool.apply_swap(&back, &swap), Some(up));
+        assert_eq!((pool.swaps_down, pool.swaps_up), (1, 1));
+        let elsewhere = ClmmSwapAccounts {
+            input_vault: Pubkey::new_unique(),
+            ..accounts
+        };
+        assert_eq!(pool.apply_swap(&elsewhere, &swap), None);
+    }
+
+    #[test]
+    fn reads_pool_state() {
+        let (mint_0, mint_1) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0u8; 1544];
+        data[POOL_STATE_MINT_0_OFFSET..][..32]
+            .copy_from_slice(mint_0.as_ref());
+        data[POOL_STATE_MINT_1_OFFSET..][..32]
+            .copy_from_slice(mint_1.as_ref());
+        data[POOL_STATE_DECIMALS_0_OFFSET] = 9;
+        data[POOL_STATE_DECIMALS_1_OFFSET] = 6;
+        data[POOL_STATE_LIQUIDITY_OFFSET..][..16]
+            .copy_from_slice(&1_000u128.to_le_bytes());
+        // sqrt of 0.15 in Q64.64, e.g. 150 USDC per SOL
+        let sqrt_price_x64 = (0.15f64.sqrt() * 2f64.powi(64)) as u128;
+        data[POOL_STATE_SQRT_PRICE_OFFSET..][..16]
+            .copy_from_slice(&sqrt_price_x64.to_le_bytes());
+
+        let pool =
+            RaydiumClmmPool::from_account_data(Pubkey::new_unique(), &data)
+                .unwrap();
+        assert_eq!(pool.mint_0, mint_0);
+        assert_eq!(pool.mint_1, mint_1);
+        assert_eq!(pool.liquidity, 1_000);
+        assert!((pool.price().unwrap() - 150.).abs() < 1e-6);
+        assert!(RaydiumClmmPool::from_account_data(
+            Pubkey::new_unique(),
+            &data[..200]
+        )
+        .is_none());
+    }
+}
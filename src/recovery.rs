@@ -1,15 +1,356 @@
+use bytes::Bytes;
+use log::info;
+use serde::Serialize;
+use solana_ledger::shred::{ReedSolomonCache, Shred, Shredder};
+use solana_sdk::clock::Slot;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::shred::{deserialize_entries, deshred};
+use crate::shred_processor::FecSet;
+
+/// RecoveryReport is the verdict on each of the dumped FEC sets, in the
+/// order they were given
+#[derive(Debug, Default, Serialize)]
+pub struct RecoveryReport {
+    pub recovered: usize,
+    pub failed: usize,
+    pub sets: Vec<FecSetVerdict>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FecSetVerdict {
+    /// None if none of the shreds of the set could be parsed
+    pub slot: Option<Slot>,
+    pub fec_set_index: Option<u32>,
+    pub data_shreds: usize,
+    pub coding_shreds: usize,
+    pub num_expected_data: Option<u16>,
+    pub recovered_data_shreds: usize,
+    pub entries: usize,
+    pub transactions: usize,
+    /// None if the set was recovered
+    pub failure: Option<RecoveryFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RecoveryFailure {
+    /// Nothing in the set parses as a shred
+    NoShreds,
+    /// Fewer shreds than data shreds in the set, erasure coding can't help
+    NotEnoughShreds {
+        have: usize,
+        need: usize,
+    },
+    /// There are no coding shreds to tell the size of the set and the last
+    /// data shred does not complete it
+    UnknownSetSize,
+    Recovery {
+        error: String,
+    },
+    MissingDataShreds {
+        missing: Vec<u32>,
+    },
+    Deserialize {
+        error: String,
+    },
+}
+
+impl std::fmt::Display for RecoveryFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecoveryFailure::NoShreds => write!(f, "no valid shreds"),
+            RecoveryFailure::NotEnoughShreds { have, need } => {
+                write!(f, "only {} of the {} shreds needed", have, need)
+            }
+            RecoveryFailure::UnknownSetSize => {
+                write!(f, "no coding shreds and the data is incomplete")
+            }
+            RecoveryFailure::Recovery { error } => {
+                write!(f, "recovery failed: {}", error)
+            }
+            RecoveryFailure::MissingDataShreds { missing } => {
+                write!(f, "missing data shreds {:?}", missing)
+            }
+            RecoveryFailure::Deserialize { error } => {
+                write!(f, "deserialize failed: {}", error)
+            }
+        }
+    }
+}
+
+/// load_fec_sets reads a dump written by
+/// ShredProcessor::dump_hanging_fec_sets
+pub fn load_fec_sets(
+    path: &Path,
+) -> Result<Vec<FecSet>, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// attempt_recovery retries the reconstruction of the dumped FEC sets:
+/// parses the stored shreds, recovers the missing data shreds from the
+/// coding shreds, deshreds and deserializes the entries
+pub fn attempt_recovery(fec_sets: Vec<FecSet>) -> RecoveryReport {
+    let mut report = RecoveryReport::default();
+    let reed_solomon_cache = ReedSolomonCache::default();
+    for fec_set in fec_sets {
+        let verdict = recover_fec_set(fec_set, &reed_solomon_cache);
+        match verdict.failure {
+            None => report.recovered += 1,
+            Some(_) => report.failed += 1,
+        }
+        report.sets.push(verdict);
+    }
+    info!(
+        "Recovered {} of {} FEC sets",
+        report.recovered,
+        report.sets.len()
+    );
+    report
+}
+
+fn parse_shreds(raw_shreds: HashMap<u32, Bytes>) -> Vec<Shred> {
+    raw_shreds
+        .into_values()
+        .filter_map(|raw_shred| {
+            Shred::new_from_serialized_shred(Vec::from(raw_shred)).ok()
+        })
+        .collect()
+}
+
+fn recover_fec_set(
+    fec_set: FecSet,
+    reed_solomon_cache: &ReedSolomonCache,
+) -> FecSetVerdict {
+    let num_expected_data = fec_set.num_expected_data;
+    let mut data_shreds = parse_shreds(fec_set.data_shreds);
+    let coding_shreds = parse_shreds(fec_set.coding_shreds);
+    let mut verdict = FecSetVerdict {
+        slot: None,
+        fec_set_index: None,
+        data_shreds: data_shreds.len(),
+        coding_shreds: coding_shreds.len(),
+        num_expected_data,
+        recovered_data_shreds: 0,
+        entries: 0,
+        transactions: 0,
+        failure: None,
+    };
+    let Some(first) = data_shreds.first().or(coding_shreds.first()) else {
+        verdict.failure = Some(RecoveryFailure::NoShreds);
+        return verdict;
+    };
+    let fec_set_index = first.fec_set_index();
+    verdict.slot = Some(first.slot());
+    verdict.fec_set_index = Some(fec_set_index);
+
+    if let Some(expected) = num_expected_data.map(usize::from) {
+        if data_shreds.len() < expected {
+            let have = data_shreds.len() + coding_shreds.len();
+            if have < expected {
+                verdict.failure = Some(RecoveryFailure::NotEnoughShreds {
+                    have,
+                    need: expected,
+                });
+                return verdict;
+            }
+            match Shredder::try_recovery(
+                data_shreds
+                    .iter()
+                    .chain(coding_shreds.iter())
+                    .cloned()
+                    .collect(),
+                reed_solomon_cache,
+            ) {
+                Ok(recovered_shreds) => {
+                    let recovered_shreds = recovered_shreds
+                        .into_iter()
+                        .filter(|shred| shred.is_data())
+                        .collect::<Vec<_>>();
+                    verdict.recovered_data_shreds = recovered_shreds.len();
+                    data_shreds.extend(recovered_shreds);
+                }
+                Err(e) => {
+                    verdict.failure = Some(RecoveryFailure::Recovery {
+                        error: format!("{:?}", e),
+                    });
+                    return verdict;
+                }
+            }
+        }
+    }
+
+    data_shreds.sort_by_key(|shred| shred.index());
+    data_shreds.dedup_by_key(|shred| shred.index());
+    let present = data_shreds
+        .iter()
+        .map(|shred| shred.index())
+        .collect::<BTreeSet<_>>();
+    let end = match num_expected_data {
+        Some(expected) => fec_set_index + expected as u32,
+        None => {
+            // without coding shreds the set ends where the data completes
+            match data_shreds.last() {
+                Some(last) if last.data_complete() || last.last_in_slot() => {
+                    last.index() + 1
+                }
+                _ => {
+                    verdict.failure = Some(RecoveryFailure::UnknownSetSize);
+                    return verdict;
+                }
+            }
+        }
+    };
+    let missing = (fec_set_index..end)
+        .filter(|index| !present.contains(index))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        verdict.failure =
+            Some(RecoveryFailure::MissingDataShreds { missing });
+        return verdict;
+    }
+
+    match deserialize_entries(&deshred(&data_shreds)) {
+        Ok(entries) => {
+            verdict.entries = entries.len();
+            verdict.transactions =
+                entries.iter().map(|entry| entry.transactions.len()).sum();
+        }
+        Err(e) => {
+            verdict.failure = Some(RecoveryFailure::Deserialize {
+                error: e.to_string(),
+            });
+        }
+    }
+    verdict
+}
+
+/// print_report prints a line per FEC set, followed by the totals
+pub fn print_report(report: &RecoveryReport) {
+    for set in report.sets.iter() {
+        let id = match (set.slot, set.fec_set_index) {
+            (Some(slot), Some(fec_set_index)) => {
+                format!("slot {} FEC set {}", slot, fec_set_index)
+            }
+            _ => "unknown FEC set".to_string(),
+        };
+        let verdict = match &set.failure {
+            None => format!(
+                "recovered {} entries ({} txs), {} data shreds recovered",
+                set.entries, set.transactions, set.recovered_data_shreds
+            ),
+            Some(failure) => format!("failed: {}", failure),
+        };
+        println!(
+            "{} (data {}, coding {}, expected data {:?}): {}",
+            id,
+            set.data_shreds,
+            set.coding_shreds,
+            set.num_expected_data,
+            verdict
+        );
+    }
+    println!(
+        "recovered {} of {} FEC sets, {} failed",
+        report.recovered,
+        report.sets.len(),
+        report.failed
+    );
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::shred_processor::FecSet;
+    use super::*;
+    use crate::shred::shred_entries;
+    use crate::shred_processor::HangingDumps;
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_transaction;
+
     #[test]
     fn recovery_works() {
-        let contents = std::fs::read_to_string("hanging_fec_sets.json")
-            .expect("Failed to read hanging_fec_sets.json");
-        let fec_sets: Vec<FecSet> = serde_json::from_str(&contents).unwrap();
+        // HANGING_FEC_SETS picks the snapshot, the latest one otherwise
+        let path = std::env::var("HANGING_FEC_SETS")
+            .map(std::path::PathBuf::from)
+            .ok()
+            .or_else(|| HangingDumps::default().latest())
+            .unwrap_or_else(|| "hanging_fec_sets.json".into());
+        let fec_sets = load_fec_sets(&path)
+            .unwrap_or_else(|_| panic!("Failed to read {}", path.display()));
         println!("FecSets hanging: {}", fec_sets.len());
 
-        fec_sets.iter().for_each(|fec_set| {
-            println!("FecSet: {:?}", fec_set);
-        });
+        print_report(&attempt_recovery(fec_sets));
+    }
+
+    /// fec_set shreds 20 transfers into a single FEC set, keeping the data
+    /// shreds for which `keep_data` holds
+    fn fec_set(keep_data: impl Fn(u32) -> bool, keep_coding: bool) -> FecSet {
+        let keypair = keypair_from_seed(&[1; 32]).unwrap();
+        let entries = (0..20)
+            .map(|i| {
+                let tx = system_transaction::transfer(
+                    &keypair,
+                    &Pubkey::new_unique(),
+                    i + 1,
+                    Hash::default(),
+                );
+                Entry::new(&Hash::default(), 1, vec![tx])
+            })
+            .collect::<Vec<_>>();
+        let (data_shreds, coding_shreds) =
+            shred_entries(10, &entries, true, 0, 0, true);
+        let num_expected_data = data_shreds.len() as u16;
+        FecSet {
+            data_shreds: data_shreds
+                .into_iter()
+                .filter(|shred| keep_data(shred.index()))
+                .map(|shred| {
+                    (shred.index(), Bytes::from(shred.into_payload()))
+                })
+                .collect(),
+            coding_shreds: coding_shreds
+                .into_iter()
+                .filter(|_| keep_coding)
+                .map(|shred| {
+                    (shred.index(), Bytes::from(shred.into_payload()))
+                })
+                .collect(),
+            num_expected_data: keep_coding.then_some(num_expected_data),
+            num_expected_coding: None,
+            is_last_in_slot: true,
+            processed: false,
+            trace: Default::default(),
+            first_shred_at: None,
+        }
+    }
+
+    #[test]
+    fn recovers_missing_data_shreds() {
+        let report =
+            attempt_recovery(vec![fec_set(|index| index != 1, true)]);
+        assert_eq!(report.recovered, 1);
+        let verdict = &report.sets[0];
+        assert_eq!(verdict.failure, None);
+        assert_eq!(verdict.slot, Some(10));
+        assert_eq!(verdict.recovered_data_shreds, 1);
+        assert_eq!(verdict.transactions, 20);
+    }
+
+    #[test]
+    fn reports_why_recovery_failed() {
+        let report = attempt_recovery(vec![
+            fec_set(|index| index != 1, false),
+            fec_set(|_| false, false),
+        ]);
+        assert_eq!(report.failed, 2);
+        assert_eq!(
+            report.sets[0].failure,
+            Some(RecoveryFailure::MissingDataShreds { missing: vec![1] })
+        );
+        assert_eq!(report.sets[1].failure, Some(RecoveryFailure::NoShreds));
     }
 }
@@ -0,0 +1,185 @@
+use solana_sdk::clock::Slot;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::entry_processor::EntriesWithMeta;
+
+/// Longest the FEC sets after a gap are held back for it, about a slot
+pub const DEFAULT_GAP_TIMEOUT_MS: u64 = 400;
+/// How often the held back FEC sets are checked against the gap timeout
+pub const REORDER_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+/// Slots kept track of, the oldest past this are flushed and forgotten
+const MAX_TRACKED_SLOTS: usize = 64;
+
+/// ReorderBuffer holds back the entries of the FEC sets which complete out
+/// of order, so that the entries of a slot go out in the order of the FEC
+/// set indices; a set is let through once the sets before it are, or once
+/// it waited `gap_timeout` for them
+#[derive(Debug)]
+pub struct ReorderBuffer {
+    gap_timeout: Duration,
+    slots: BTreeMap<Slot, SlotBuffer>,
+}
+
+#[derive(Debug, Default)]
+struct SlotBuffer {
+    // the fec_set_index of a set is the index of its first data shred, so
+    // the set after it starts past its data shreds
+    next_fec_set_index: u32,
+    pending: BTreeMap<u32, PendingFecSet>,
+}
+
+#[derive(Debug)]
+struct PendingFecSet {
+    next_fec_set_index: u32,
+    // None for the sets which failed to deserialize, they only move the
+    // cursor
+    entries: Option<EntriesWithMeta>,
+    received_at: Instant,
+}
+
+impl SlotBuffer {
+    fn take_ready(&mut self) -> Vec<EntriesWithMeta> {
+        let mut ready = Vec::new();
+        while let Some(set) = self.pending.remove(&self.next_fec_set_index) {
+            self.next_fec_set_index = set.next_fec_set_index;
+            ready.extend(set.entries);
+        }
+        ready
+    }
+
+    /// take_all gives up on the gaps, letting every pending set through
+    fn take_all(&mut self) -> Vec<EntriesWithMeta> {
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(last) = pending.values().last() {
+            self.next_fec_set_index = last.next_fec_set_index;
+        }
+        pending
+            .into_values()
+            .filter_map(|set| set.entries)
+            .collect()
+    }
+}
+
+impl ReorderBuffer {
+    pub fn new(gap_timeout: Duration) -> Self {
+        ReorderBuffer {
+            gap_timeout,
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// push adds the FEC set of `num_data_shreds` data shreds and returns
+    /// the entries which are now in order
+    pub fn push(
+        &mut self,
+        slot: Slot,
+        fec_set_index: u32,
+        num_data_shreds: u32,
+        entries: Option<EntriesWithMeta>,
+        now: Instant,
+    ) -> Vec<EntriesWithMeta> {
+        let buffer = self.slots.entry(slot).or_default();
+        // late past a gap that was given up on, there is nothing to wait for
+        if fec_set_index < buffer.next_fec_set_index {
+            return entries.into_iter().collect();
+        }
+        buffer.pending.insert(
+            fec_set_index,
+            PendingFecSet {
+                next_fec_set_index: fec_set_index + num_data_shreds,
+                entries,
+                received_at: now,
+            },
+        );
+        let mut ready = buffer.take_ready();
+        while self.slots.len() > MAX_TRACKED_SLOTS {
+            if let Some((_, mut oldest)) = self.slots.pop_first() {
+                ready.extend(oldest.take_all());
+            }
+        }
+        ready
+    }
+
+    /// flush_expired lets through the sets of the slots with a set that
+    /// waited past the gap timeout
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<EntriesWithMeta> {
+        let gap_timeout = self.gap_timeout;
+        self.slots
+            .values_mut()
+            .filter(|buffer| {
+                buffer.pending.values().any(|set| {
+                    now.duration_since(set.received_at) >= gap_timeout
+                })
+            })
+            .flat_map(|buffer| buffer.take_all())
+            .collect()
+    }
+
+    /// flush_all lets every pending set through, for the shutdown
+    pub fn flush_all(&mut self) -> Vec<EntriesWithMeta> {
+        self.slots
+            .values_mut()
+            .flat_map(|buffer| buffer.take_all())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+
+    fn entries(slot: Slot, num_hashes: u64) -> Option<EntriesWithMeta> {
+        Some(EntriesWithMeta {
+            entries: vec![Entry::new(&Hash::default(), num_hashes, vec![])],
+            slot,
+            trace: Default::default(),
+            received_at: None,
+        })
+    }
+
+    fn num_hashes(ready: &[EntriesWithMeta]) -> Vec<u64> {
+        ready
+            .iter()
+            .map(|entries| entries.entries[0].num_hashes)
+            .collect()
+    }
+
+    #[test]
+    fn emits_fec_sets_in_order() {
+        let start = Instant::now();
+        let mut buffer = ReorderBuffer::new(Duration::from_millis(400));
+
+        assert!(buffer.push(1, 64, 32, entries(1, 3), start).is_empty());
+        assert!(buffer.push(1, 32, 32, entries(1, 2), start).is_empty());
+        let ready = buffer.push(1, 0, 32, entries(1, 1), start);
+        assert_eq!(num_hashes(&ready), vec![1, 2, 3]);
+
+        // a failed set moves the cursor along without entries
+        assert!(buffer.push(1, 128, 32, entries(1, 5), start).is_empty());
+        let ready = buffer.push(1, 96, 32, None, start);
+        assert_eq!(num_hashes(&ready), vec![5]);
+    }
+
+    #[test]
+    fn flushes_gaps_after_the_timeout() {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(400);
+        let mut buffer = ReorderBuffer::new(timeout);
+
+        assert!(buffer.push(1, 64, 32, entries(1, 3), start).is_empty());
+        assert!(buffer.push(1, 32, 32, entries(1, 2), start).is_empty());
+        assert!(buffer.flush_expired(start).is_empty());
+        let ready = buffer.flush_expired(start + timeout);
+        assert_eq!(num_hashes(&ready), vec![2, 3]);
+
+        // the set of the gap goes out as soon as it shows up, the ones past
+        // the flushed ones are in order again
+        let late = buffer.push(1, 0, 32, entries(1, 1), start + timeout);
+        assert_eq!(num_hashes(&late), vec![1]);
+        let ready = buffer.push(1, 96, 32, entries(1, 4), start + timeout);
+        assert_eq!(num_hashes(&ready), vec![4]);
+    }
+}
@@ -0,0 +1,142 @@
+use log::warn;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::future::Future;
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, Duration};
+
+use crate::util::require_env;
+
+/// Requests in flight at once, low enough for the public endpoints
+pub const DEFAULT_RPC_CONCURRENCY: usize = 8;
+/// Retries of a rate limited request before giving up on it
+pub const RPC_MAX_RETRIES: u32 = 5;
+/// Backoff after the first rate limited response, doubled on each retry
+pub const RPC_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// RateLimitedRpc bounds the RPC requests in flight and retries the ones
+/// that got rate limited (429), it is shared by everything loading pools
+pub struct RateLimitedRpc {
+    client: RpcClient,
+    permits: Semaphore,
+    retry_backoff: Duration,
+}
+
+impl std::fmt::Debug for RateLimitedRpc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimitedRpc")
+            .field("url", &self.client.url())
+            .field("available_permits", &self.permits.available_permits())
+            .finish()
+    }
+}
+
+impl RateLimitedRpc {
+    pub fn new(url: String, max_concurrency: usize) -> Self {
+        RateLimitedRpc {
+            client: RpcClient::new(url),
+            permits: Semaphore::new(max_concurrency.max(1)),
+            retry_backoff: RPC_RETRY_BACKOFF,
+        }
+    }
+
+    pub fn with_retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// from_env connects to RPC_URL
+    pub fn from_env(max_concurrency: usize) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            require_env("RPC_URL", "loading the pools")?,
+            max_concurrency,
+        ))
+    }
+
+    /// client is the underlying client, requests made on it directly are
+    /// not limited
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    /// call runs the request once a permit is free, retrying it with
+    /// backoff for as long as it is rate limited (up to RPC_MAX_RETRIES),
+    /// any other error is returned right away
+    pub async fn call<'a, T, E, F, Fut>(&'a self, request: F) -> Result<T, E>
+    where
+        F: Fn(&'a RpcClient) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        let mut backoff = self.retry_backoff;
+        let mut retries = 0;
+        loop {
+            let result = {
+                let _permit =
+                    self.permits.acquire().await.expect("semaphore closed");
+                request(&self.client).await
+            };
+            match result {
+                Err(e)
+                    if retries < RPC_MAX_RETRIES && is_rate_limited(&e) =>
+                {
+                    retries += 1;
+                    warn!(
+                        "Rate limited, retry {}/{} in {:?}",
+                        retries, RPC_MAX_RETRIES, backoff
+                    );
+                    sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// is_rate_limited tells a 429 from the other errors, the clients only
+/// carry the status in the message
+pub fn is_rate_limited(error: &impl std::fmt::Display) -> bool {
+    let message = error.to_string();
+    message.contains("429") || message.contains("Too Many Requests")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let rpc = RateLimitedRpc::new("http://localhost:8899".into(), 2)
+            .with_retry_backoff(Duration::from_millis(1));
+        let attempts = AtomicUsize::new(0);
+        let result = rpc
+            .call(|_| async {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 | 1 => Err(
+                        "HTTP status client error (429 Too Many Requests)",
+                    ),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(42));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+        // other errors are not retried
+        attempts.store(0, Ordering::Relaxed);
+        let result: Result<(), _> = rpc
+            .call(|_| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err("account not found")
+            })
+            .await;
+        assert_eq!(result, Err("account not found"));
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+        // gives up eventually
+        let result: Result<(), _> = rpc.call(|_| async { Err("429") }).await;
+        assert!(result.is_err());
+        assert_eq!(rpc.permits.available_permits(), 2);
+    }
+}
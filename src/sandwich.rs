@@ -0,0 +1,287 @@
+use serde::Serialize;
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::raydium::QuoteAsset;
+use crate::sink::{idempotency_key, SANDWICH_TOPIC};
+use crate::util::pubkey_to_string;
+
+/// Slots are analysed once a slot this far ahead shows up, so that the FEC
+/// sets completing late still make it in
+pub const DETECTION_LAG_SLOTS: u64 = 2;
+
+/// SandwichConfig is what counts as a sandwich: the victim swap has to be
+/// at least `min_victim_sol` (or `min_victim_usdc` on USDC pools), and the
+/// front-run and back-run at most `max_distance` swaps apart on the pool
+#[derive(Debug, Clone)]
+pub struct SandwichConfig {
+    pub min_victim_sol: f64,
+    pub min_victim_usdc: f64,
+    pub max_distance: usize,
+}
+
+impl Default for SandwichConfig {
+    fn default() -> Self {
+        SandwichConfig {
+            min_victim_sol: 1.,
+            min_victim_usdc: 200.,
+            max_distance: 4,
+        }
+    }
+}
+
+impl SandwichConfig {
+    fn is_victim_size(&self, quote: QuoteAsset, quote_amount: f64) -> bool {
+        match quote {
+            QuoteAsset::Wsol => quote_amount >= self.min_victim_sol,
+            QuoteAsset::Usdc => quote_amount >= self.min_victim_usdc,
+            QuoteAsset::Other => false,
+        }
+    }
+}
+
+/// SwapRecord is a Raydium AMM swap on a tracked pool, in the order it was
+/// reconstructed in
+#[derive(Debug, Clone)]
+pub struct SwapRecord {
+    pub signature: String,
+    /// Fee payer of the tx
+    pub signer: Pubkey,
+    pub amm_id: Pubkey,
+    pub mint: Pubkey,
+    /// Whether the token was bought with the quote, rather than sold
+    pub is_buy: bool,
+    pub quote: QuoteAsset,
+    /// In UI units of the quote
+    pub quote_amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SandwichDetectedEvent {
+    pub slot: Slot,
+    /// When the sandwich was detected, unix millis
+    pub timestamp: i64,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub amm_id: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub mint: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub attacker: Pubkey,
+    #[serde(serialize_with = "pubkey_to_string")]
+    pub victim: Pubkey,
+    pub front_run_signature: String,
+    pub victim_signature: String,
+    pub back_run_signature: String,
+    /// Whether the victim (and so the front-run) bought the token
+    pub victim_is_buy: bool,
+    pub quote: QuoteAsset,
+    pub front_run_quote_amount: f64,
+    pub victim_quote_amount: f64,
+    pub back_run_quote_amount: f64,
+    pub idempotency_key: String,
+}
+
+/// SandwichDetector buffers the swaps per slot and analyses a slot once it
+/// is DETECTION_LAG_SLOTS behind the newest one seen
+#[derive(Debug, Default)]
+pub struct SandwichDetector {
+    config: SandwichConfig,
+    swaps_by_slot: BTreeMap<Slot, Vec<SwapRecord>>,
+}
+
+impl SandwichDetector {
+    pub fn new(config: SandwichConfig) -> Self {
+        SandwichDetector {
+            config,
+            swaps_by_slot: BTreeMap::new(),
+        }
+    }
+
+    /// record adds the swaps of a batch of entries of `slot` and returns the
+    /// sandwiches of the slots which are now old enough to be analysed
+    pub fn record(
+        &mut self,
+        slot: Slot,
+        swaps: Vec<SwapRecord>,
+    ) -> Vec<SandwichDetectedEvent> {
+        if !swaps.is_empty() {
+            self.swaps_by_slot.entry(slot).or_default().extend(swaps);
+        }
+        let newest = match self.swaps_by_slot.last_key_value() {
+            Some((newest, _)) => (*newest).max(slot),
+            None => return Vec::new(),
+        };
+        let pending = self
+            .swaps_by_slot
+            .split_off(&newest.saturating_sub(DETECTION_LAG_SLOTS - 1));
+        let ready = std::mem::replace(&mut self.swaps_by_slot, pending);
+        ready
+            .into_iter()
+            .flat_map(|(slot, swaps)| {
+                detect_sandwiches(slot, &swaps, &self.config)
+            })
+            .collect()
+    }
+}
+
+/// detect_sandwiches looks for a swap by one signer right before a large
+/// swap of someone else in the same direction on the same pool, and a swap
+/// back by that signer right after it
+pub fn detect_sandwiches(
+    slot: Slot,
+    swaps: &[SwapRecord],
+    config: &SandwichConfig,
+) -> Vec<SandwichDetectedEvent> {
+    let mut swaps_by_pool = HashMap::<Pubkey, Vec<&SwapRecord>>::new();
+    for swap in swaps {
+        swaps_by_pool.entry(swap.amm_id).or_default().push(swap);
+    }
+
+    let mut sandwiches = Vec::new();
+    for pool_swaps in swaps_by_pool.values() {
+        for (victim_idx, victim) in pool_swaps.iter().enumerate() {
+            if !config.is_victim_size(victim.quote, victim.quote_amount) {
+                continue;
+            }
+            let from = victim_idx
+                .saturating_sub(config.max_distance.saturating_sub(1));
+            let mut sandwich = None;
+            for front_idx in (from..victim_idx).rev() {
+                let front = pool_swaps[front_idx];
+                if front.signer == victim.signer
+                    || front.is_buy != victim.is_buy
+                {
+                    continue;
+                }
+                let to = (front_idx + config.max_distance + 1)
+                    .min(pool_swaps.len());
+                let back =
+                    pool_swaps[victim_idx + 1..to].iter().find(|back| {
+                        back.signer == front.signer
+                            && back.is_buy != front.is_buy
+                    });
+                if let Some(back) = back {
+                    sandwich = Some((front, *back));
+                    break;
+                }
+            }
+            let Some((front, back)) = sandwich else {
+                continue;
+            };
+            sandwiches.push(SandwichDetectedEvent {
+                slot,
+                timestamp: chrono::Utc::now().timestamp_millis(),
+                amm_id: victim.amm_id,
+                mint: victim.mint,
+                attacker: front.signer,
+                victim: victim.signer,
+                front_run_signature: front.signature.clone(),
+                victim_signature: victim.signature.clone(),
+                back_run_signature: back.signature.clone(),
+                victim_is_buy: victim.is_buy,
+                quote: victim.quote,
+                front_run_quote_amount: front.quote_amount,
+                victim_quote_amount: victim.quote_amount,
+                back_run_quote_amount: back.quote_amount,
+                idempotency_key: idempotency_key(
+                    SANDWICH_TOPIC,
+                    &[&victim.signature],
+                ),
+            });
+        }
+    }
+    sandwiches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(
+        signature: &str,
+        signer: &Pubkey,
+        amm_id: &Pubkey,
+        is_buy: bool,
+        quote_amount: f64,
+    ) -> SwapRecord {
+        SwapRecord {
+            signature: signature.to_string(),
+            signer: *signer,
+            amm_id: *amm_id,
+            mint: Pubkey::default(),
+            is_buy,
+            quote: QuoteAsset::Wsol,
+            quote_amount,
+        }
+    }
+
+    #[test]
+    fn detects_sandwiches() {
+        let (attacker, victim, other) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (pool, other_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = SandwichConfig::default();
+
+        let swaps = vec![
+            swap("front", &attacker, &pool, true, 5.),
+            // swaps on other pools do not count towards the distance
+            swap("elsewhere", &other, &other_pool, true, 50.),
+            swap("victim", &victim, &pool, true, 10.),
+            swap("bystander", &other, &pool, false, 0.1),
+            swap("back", &attacker, &pool, false, 5.),
+        ];
+        let sandwiches = detect_sandwiches(1, &swaps, &config);
+        assert_eq!(sandwiches.len(), 1);
+        let sandwich = &sandwiches[0];
+        assert_eq!(sandwich.attacker, attacker);
+        assert_eq!(sandwich.victim, victim);
+        assert_eq!(sandwich.front_run_signature, "front");
+        assert_eq!(sandwich.victim_signature, "victim");
+        assert_eq!(sandwich.back_run_signature, "back");
+        assert_eq!(sandwich.idempotency_key, "sandwich:victim");
+
+        // a victim below the threshold
+        let mut small = swaps.clone();
+        small[2].quote_amount = 0.5;
+        assert!(detect_sandwiches(1, &small, &config).is_empty());
+        // the back-run too far from the front-run
+        let tight = SandwichConfig {
+            max_distance: 2,
+            ..config.clone()
+        };
+        assert!(detect_sandwiches(1, &swaps, &tight).is_empty());
+        // the attacker buying back rather than selling
+        let mut same_way = swaps.clone();
+        same_way[4].is_buy = true;
+        assert!(detect_sandwiches(1, &same_way, &config).is_empty());
+    }
+
+    #[test]
+    fn analyses_slots_once_behind() {
+        let (attacker, victim) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = Pubkey::new_unique();
+        let mut detector = SandwichDetector::new(SandwichConfig::default());
+
+        assert!(detector
+            .record(
+                10,
+                vec![
+                    swap("front", &attacker, &pool, true, 5.),
+                    swap("victim", &victim, &pool, true, 10.),
+                ],
+            )
+            .is_empty());
+        assert!(detector
+            .record(10, vec![swap("back", &attacker, &pool, false, 5.)])
+            .is_empty());
+        assert!(detector.record(11, Vec::new()).is_empty());
+        let sandwiches = detector.record(12, Vec::new());
+        assert_eq!(sandwiches.len(), 1);
+        assert_eq!(sandwiches[0].slot, 10);
+        assert!(detector.swaps_by_slot.is_empty());
+    }
+}
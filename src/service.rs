@@ -1,135 +1,480 @@
-use crate::arb::PoolsState;
-use crate::entry_processor::ArbEntryProcessor;
-use crate::entry_processor::PumpEntryProcessor;
-use crate::graduates_processor::GraduatesProcessor;
-use crate::listener::PACKET_SIZE;
-use crate::shred_processor::ShredProcessor;
-use log::{error, info};
-use reqwest::Url;
-use std::sync::Arc;
-use tokio::net::UdpSocket;
+use crate::admin::{self, AdminConfig, Intake};
+use crate::alt::LookupTableCache;
+use crate::arb::{
+    run_pool_loader, run_pool_resync, run_pool_snapshots,
+    run_raydium_json_refresh, ArbConfig, PoolsState,
+};
+use crate::dedup::{self, DedupConfig, DedupMetrics};
+use crate::entry_processor::{
+    ArbEntryProcessor, ProcessorMetrics, SigWithMeta,
+};
+use crate::entry_processor::{PumpConfig, PumpEntryProcessor};
+use crate::forward::Forwarder;
+use crate::graduates_processor::{GraduatesConfig, GraduatesProcessor};
+use crate::ingest::{self, IngestTx};
+#[cfg(feature = "parquet")]
+use crate::parquet_processor::{ParquetConfig, ParquetProcessor};
+use crate::queue::{QueueDepth, QueueSizes};
+use crate::rpc::RateLimitedRpc;
+use crate::shred_processor::{HangingDumps, ShredProcessor, SlotRange};
+use crate::sink::{self, Sink, SIG_TOPIC};
+use crate::source::{self, ShredSource, SourceConfig};
+use crate::udp::UdpMetrics;
+use crate::watchdog::{self, WatchdogConfig};
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 
+/// How many sigs can wait on `--sigs-out`, past that they are dropped
+/// rather than held up on a slow sink
+pub const SIGS_OUT_QUEUE_SIZE: usize = 2000;
+
+/// DetectedSig is a sig as written to `--sigs-out`
+#[derive(Debug, Serialize)]
+struct DetectedSig {
+    /// When the shreds it came in were received, unix millis
+    timestamp: i64,
+    sig: String,
+}
+
 pub enum Mode {
-    Arb,
-    Pump,
-    Graduates,
+    Arb(ArbConfig),
+    Pump(PumpConfig),
+    Graduates(GraduatesConfig),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetConfig),
+}
+
+/// ServiceConfig is what the service modes run with apart from the sink
+/// and the mode itself, built off of the args once
+pub struct ServiceConfig {
+    pub source: SourceConfig,
+    pub ingest_queue_size: usize,
+    pub queue_sizes: QueueSizes,
+    pub slot_range: SlotRange,
+    pub shred_version: Option<u16>,
+    pub max_slot_lag: Option<u64>,
+    // the sigs are written to it on top of the sink, with their timestamp
+    pub sigs_out: Option<Sink>,
+    pub admin_config: Option<AdminConfig>,
+    pub hanging_dumps: HangingDumps,
+    pub entry_dump_dir: Option<PathBuf>,
+    pub max_entries: u64,
+    pub entry_ordering: Option<Duration>,
+    pub recovery: bool,
+    pub drop_repair_shreds: bool,
+    pub dedup: Option<DedupConfig>,
+    pub watchdog: Option<WatchdogConfig>,
+    // the raw packets are sent on to these as they come in
+    pub forward: Vec<SocketAddr>,
 }
 
 pub async fn run(
-    bind_address: String,
-    post_url: String,
+    config: ServiceConfig,
+    sink: Sink,
     mode: Mode,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    Url::parse(&post_url)?;
-
-    info!(
-        "Starting listener on {}, sending to {}",
-        bind_address, post_url
-    );
-
-    let socket = Arc::new(
-        UdpSocket::bind(bind_address)
-            .await
-            .expect("Couldn't bind to address"),
-    );
-    let (entry_tx, entry_rx) = mpsc::channel(2000);
-    let (error_tx, error_rx) = mpsc::channel(2000);
+    let ServiceConfig {
+        source,
+        ingest_queue_size,
+        queue_sizes,
+        slot_range,
+        shred_version,
+        max_slot_lag,
+        sigs_out,
+        admin_config,
+        hanging_dumps,
+        entry_dump_dir,
+        max_entries,
+        entry_ordering,
+        recovery,
+        drop_repair_shreds,
+        dedup,
+        watchdog,
+        forward,
+    } = config;
+    let (entry_tx, entry_rx) = mpsc::channel(queue_sizes.entries);
+    let (error_tx, error_rx) = mpsc::channel(queue_sizes.errors);
+    let entry_queue = QueueDepth::new(&entry_tx);
+    let error_queue = QueueDepth::new(&error_tx);
     let (sig_tx, mut sig_rx) = mpsc::channel(2000);
 
-    let shred_processor =
-        Arc::new(RwLock::new(ShredProcessor::new(entry_tx, error_tx)));
+    // with the dedup the shred processor sends the entries to it, and it
+    // sends them on to the entry processor
+    let (shred_entry_tx, dedup_stage) = match dedup {
+        Some(config) => {
+            info!(
+                "Deduplicating txs, up to {} signatures for {:?}",
+                config.max_signatures, config.max_age
+            );
+            let (dedup_tx, dedup_rx) = mpsc::channel(queue_sizes.entries);
+            let metrics = Arc::new(DedupMetrics::default());
+            let handle = tokio::spawn(dedup::run(
+                dedup_rx,
+                entry_tx,
+                config,
+                metrics.clone(),
+            ));
+            (dedup_tx, Some((handle, metrics)))
+        }
+        None => (entry_tx, None),
+    };
+    let (dedup_handle, dedup_metrics) = dedup_stage.unzip();
+
+    let dump_interval = hanging_dumps.interval;
+    let mut shred_processor = ShredProcessor::new(shred_entry_tx, error_tx)
+        .with_slot_range(slot_range)
+        .with_shred_version(shred_version)
+        .with_max_slot_lag(max_slot_lag)
+        .with_hanging_dumps(hanging_dumps)
+        .with_max_entries(max_entries)
+        .with_entry_ordering(entry_ordering)
+        .with_recovery(recovery)
+        .with_drop_repair_shreds(drop_repair_shreds);
+    if let Some(dir) = entry_dump_dir {
+        std::fs::create_dir_all(&dir)?;
+        info!("Dumping entries to {}", dir.display());
+        shred_processor = shred_processor.with_entry_dump(dir);
+    }
+    let udp_metrics = shred_processor.udp_metrics();
+    let open_sources = source::open(source, udp_metrics.clone())?;
+    let shred_processor = Arc::new(RwLock::new(shred_processor));
+
+    let forwarder = match forward.is_empty() {
+        true => None,
+        false => Some(Arc::new(Forwarder::spawn(forward).await?)),
+    };
 
-    // metrics loop
-    info!("Starting metrics loop");
     let shred_processor_clone = shred_processor.clone();
-    let metrics_handle = tokio::spawn(async move {
-        loop {
-            sleep(Duration::from_secs(6)).await;
-            {
-                let metrics = shred_processor_clone.read().await.metrics();
-                info!("metrics: {}", metrics);
-                drop(metrics);
+    let forward_metrics = forwarder.as_ref().map(|f| f.metrics());
+    let (ingest_tx, ingest_rx) = ingest::channel(ingest_queue_size);
+    let ingest_metrics = ingest_rx.metrics();
+
+    // snapshots of the hanging FEC sets over time (fishing for testing data)
+    let dumps_handle = dump_interval.map(|interval| {
+        info!("Dumping hanging FEC sets every {:?}", interval);
+        tokio::spawn({
+            let shred_processor = shred_processor.clone();
+            async move {
+                loop {
+                    sleep(interval).await;
+                    if let Err(e) = shred_processor
+                        .read()
+                        .await
+                        .snapshot_hanging_fec_sets()
+                    {
+                        error!("Failed to dump hanging FEC sets: {}", e);
+                    }
+                }
             }
-        }
+        })
     });
 
+    let intake = Arc::new(Intake::default());
+    let circuit_breaker = match &mode {
+        Mode::Arb(arb_config) => arb_config.circuit_breaker.clone(),
+        _ => None,
+    };
     info!("Starting sigs rx");
+    // the sink is written to off of the sigs loop, which the processors
+    // would otherwise wait on
+    let sigs_out_tx = sigs_out.map(|sigs_out| {
+        let (sigs_out_tx, mut sigs_out_rx) =
+            mpsc::channel::<DetectedSig>(SIGS_OUT_QUEUE_SIZE);
+        tokio::spawn(async move {
+            while let Some(sig) = sigs_out_rx.recv().await {
+                sink::emit(sigs_out.as_ref(), SIG_TOPIC, &sig).await;
+            }
+        });
+        sigs_out_tx
+    });
     let sigs_handle = tokio::spawn(async move {
-        while let Some(sig) = sig_rx.recv().await {
-            let timestamp = chrono::Utc::now().timestamp_millis();
+        while let Some(SigWithMeta { sig, received_at }) = sig_rx.recv().await
+        {
+            // back from now to when the shreds came in
+            let timestamp = chrono::Utc::now().timestamp_millis()
+                - received_at.map_or(0, |at| at.elapsed().as_millis() as i64);
             log::debug!("shreds: {} {}", timestamp, sig);
+            if let Some(sigs_out_tx) = &sigs_out_tx {
+                if let Err(e) =
+                    sigs_out_tx.try_send(DetectedSig { timestamp, sig })
+                {
+                    warn!("Dropping a sig for --sigs-out: {}", e);
+                }
+            }
         }
     });
 
     info!("Starting shred processor");
-    let mut buf = [0u8; PACKET_SIZE]; // max shred size
-    let shred_processor = shred_processor.clone();
-    let shred_processor_handle = tokio::spawn(async move {
-        loop {
-            match socket.recv_from(&mut buf).await {
-                Ok((received, _)) => {
-                    let packet = Vec::from(&buf[..received]);
-                    shred_processor
-                        .write()
-                        .await
-                        .collect(Arc::new(packet))
-                        .await;
-                }
-                Err(e) => {
-                    error!("Error receiving packet: {:?}", e);
-                }
-            }
+    let ingest_handle = tokio::spawn(ingest_rx.run(shred_processor));
+    let spawn_shred_processor = {
+        let udp_metrics = udp_metrics.clone();
+        let intake = intake.clone();
+        // None once the finite sources ended, there is nothing to restart
+        move || {
+            let sources = open_sources();
+            (!sources.is_empty()).then(|| {
+                spawn_receiver(
+                    sources,
+                    ingest_tx.clone(),
+                    intake.clone(),
+                    udp_metrics.clone(),
+                    forwarder.clone(),
+                )
+            })
         }
+    };
+    let shred_processor_handle = Arc::new(Mutex::new(
+        spawn_shred_processor().ok_or("No shred sources to read")?,
+    ));
+
+    let watchdog_handle = watchdog.map(|config| {
+        info!("Starting watchdog");
+        tokio::spawn(watchdog::run(
+            config,
+            udp_metrics,
+            intake.clone(),
+            shred_processor_handle.clone(),
+            spawn_shred_processor,
+        ))
     });
 
     info!("Starting entry processor");
-    let entry_processor_handle = match mode {
-        Mode::Arb => tokio::spawn(async move {
+    let (entry_processor_handle, processor_metrics) = match mode {
+        Mode::Arb(arb_config) => {
+            let rpc = RateLimitedRpc::from_env(arb_config.rpc_concurrency)?;
             info!("Arb mode");
-            let pools_state = Arc::new(RwLock::new(PoolsState::default()));
-            pools_state.write().await.initialize().await;
+            let pools_state = Arc::new(RwLock::new(PoolsState {
+                fee_schedule: arb_config.fee_schedule.clone(),
+                ema_half_life: arb_config.ema_half_life,
+                rpc: Some(Arc::new(rpc)),
+                ..Default::default()
+            }));
             let mut entry_processor = ArbEntryProcessor::new(
                 entry_rx,
                 error_rx,
                 pools_state.clone(),
                 sig_tx,
+                arb_config.clone(),
+                sink,
             );
-            entry_processor.receive_entries().await;
-        }),
-        Mode::Pump => {
+            let metrics: Arc<dyn ProcessorMetrics> =
+                entry_processor.metrics();
+            let handle = tokio::spawn(async move {
+                if let Some(path) = arb_config.pool_snapshot.clone() {
+                    if pools_state.write().await.initialize_warm(&path).await
+                    {
+                        tokio::spawn(run_pool_resync(pools_state.clone()));
+                    }
+                    tokio::spawn(run_pool_snapshots(
+                        pools_state.clone(),
+                        path,
+                    ));
+                } else {
+                    pools_state.write().await.initialize().await;
+                }
+                if arb_config.lazy_load_pools {
+                    tokio::spawn(run_pool_loader(pools_state.clone()));
+                }
+                if let Some(interval) = arb_config.raydium_json_refresh {
+                    tokio::spawn(run_raydium_json_refresh(
+                        pools_state.clone(),
+                        interval,
+                    ));
+                }
+                entry_processor.receive_entries().await;
+            });
+            (handle, metrics)
+        }
+        Mode::Pump(pump_config) => {
             info!("Pump mode");
-            tokio::spawn(async move {
-                let mut entry_processor = PumpEntryProcessor::new(
-                    entry_rx, error_rx, sig_tx, post_url,
-                );
+            let mut entry_processor = PumpEntryProcessor::new(
+                entry_rx,
+                error_rx,
+                sig_tx,
+                sink,
+                pump_config,
+            )
+            .with_lookup_tables(Arc::new(LookupTableCache::from_env()));
+            let metrics: Arc<dyn ProcessorMetrics> =
+                entry_processor.metrics();
+            let handle = tokio::spawn(async move {
                 entry_processor.receive_entries().await;
-            })
+            });
+            (handle, metrics)
         }
-        Mode::Graduates => {
+        Mode::Graduates(graduates_config) => {
             info!("Graduates mode");
-            tokio::spawn(async move {
-                let mut entry_processor =
-                    GraduatesProcessor::new(entry_rx, error_rx, sig_tx);
+            let mut entry_processor =
+                GraduatesProcessor::new(entry_rx, error_rx, sig_tx, sink)
+                    .with_lookup_tables(Arc::new(
+                        LookupTableCache::from_env(),
+                    ));
+            if graduates_config.check_mint_authority {
+                info!("Checking the mint authority of the graduates");
+                entry_processor = entry_processor.with_rpc(Arc::new(
+                    RateLimitedRpc::from_env(
+                        graduates_config.rpc_concurrency,
+                    )?,
+                ));
+            }
+            let metrics: Arc<dyn ProcessorMetrics> =
+                entry_processor.metrics();
+            let handle = tokio::spawn(async move {
                 entry_processor.receive_entries().await;
-            })
+            });
+            (handle, metrics)
+        }
+        #[cfg(feature = "parquet")]
+        Mode::Parquet(parquet_config) => {
+            info!(
+                "Parquet mode, writing to {}",
+                parquet_config.dir.display()
+            );
+            let mut entry_processor =
+                ParquetProcessor::new(entry_rx, error_rx, parquet_config);
+            let metrics: Arc<dyn ProcessorMetrics> =
+                entry_processor.metrics();
+            let handle = tokio::spawn(async move {
+                entry_processor.receive_entries().await;
+            });
+            (handle, metrics)
         }
     };
 
+    let admin_handle = admin_config.map(|admin_config| {
+        info!("Starting admin API");
+        tokio::spawn({
+            let shred_processor = shred_processor_clone.clone();
+            let processor_metrics = processor_metrics.clone();
+            let intake = intake.clone();
+            async move {
+                if let Err(e) = admin::run(
+                    admin_config,
+                    shred_processor,
+                    processor_metrics,
+                    intake,
+                    circuit_breaker,
+                )
+                .await
+                {
+                    error!("Admin API failed: {:?}", e);
+                }
+            }
+        })
+    });
+
+    // metrics loop, the counters of the entry processor go under the mode
+    info!("Starting metrics loop");
+    let metrics_handle = tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(6)).await;
+            {
+                let metrics = shred_processor_clone.read().await.metrics();
+                info!("metrics: {}", metrics);
+                drop(metrics);
+            }
+            info!("ingest metrics: {}", ingest_metrics.to_json());
+            info!(
+                "queue metrics: {}",
+                serde_json::json!({
+                    "entries": entry_queue.to_json(),
+                    "errors": error_queue.to_json(),
+                })
+            );
+            if let Some(dedup_metrics) = &dedup_metrics {
+                info!("dedup metrics: {}", dedup_metrics.to_json());
+            }
+            if let Some(forward_metrics) = &forward_metrics {
+                info!("forward metrics: {}", forward_metrics.to_json());
+            }
+            info!(
+                "entry processor metrics: {}",
+                serde_json::json!({
+                    "mode": processor_metrics.mode(),
+                    "metrics": processor_metrics.to_json(),
+                })
+            );
+        }
+    });
+
     tokio::signal::ctrl_c().await?;
 
     info!("Shutting down");
 
-    for handle in [
-        metrics_handle,
-        sigs_handle,
-        shred_processor_handle,
-        entry_processor_handle,
-    ] {
+    // the watchdog goes first so that it does not respawn the receiver
+    for handle in watchdog_handle
+        .into_iter()
+        .chain([
+            metrics_handle,
+            sigs_handle,
+            entry_processor_handle,
+            ingest_handle,
+        ])
+        .chain(dedup_handle)
+        .chain(dumps_handle)
+        .chain(admin_handle)
+    {
         handle.abort();
     }
+    shred_processor_handle
+        .lock()
+        .expect("receiver lock")
+        .abort();
 
     Ok(())
 }
+
+/// spawn_receiver starts the receive loops, one per source, which queue the
+/// packets off of the sources for the shred processor (see
+/// ingest::IngestRx::run), never waiting on the processor lock, and stamp
+/// each one in `udp_metrics`; with a `forwarder` every packet is also queued
+/// to the downstream listeners first, regardless of the intake being paused
+fn spawn_receiver(
+    sources: Vec<Box<dyn ShredSource>>,
+    ingest: IngestTx,
+    intake: Arc<Intake>,
+    udp_metrics: Arc<UdpMetrics>,
+    forwarder: Option<Arc<Forwarder>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // aborting the task stops all of the loops
+        join_all(sources.into_iter().map(|source| {
+            receive(
+                source,
+                ingest.clone(),
+                intake.clone(),
+                udp_metrics.clone(),
+                forwarder.clone(),
+            )
+        }))
+        .await;
+    })
+}
+
+async fn receive(
+    mut source: Box<dyn ShredSource>,
+    ingest: IngestTx,
+    intake: Arc<Intake>,
+    udp_metrics: Arc<UdpMetrics>,
+    forwarder: Option<Arc<Forwarder>>,
+) {
+    while let Some(packet) = source.next_packet().await {
+        udp_metrics.record_packet();
+        if let Some(forwarder) = &forwarder {
+            forwarder.forward(packet.clone());
+        }
+        if intake.is_paused() {
+            continue;
+        }
+        ingest.push(packet);
+    }
+    info!("Source exhausted");
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use bincode::Options;
 use solana_ledger::shred::{ReedSolomonCache, ShredFlags, Shredder};
 use std::collections::{HashMap, HashSet};
 
@@ -8,12 +9,42 @@ use solana_entry::entry::Entry;
 use solana_ledger::shred::{Error, Shred};
 use solana_sdk::signature::SIGNATURE_BYTES;
 
-pub fn get_shred_variant(shred: &[u8]) -> Result<ShredVariant, Error> {
+/// ShredError is why a shred or a set of them could not be read, the
+/// variants tell the failures worth retrying from the ones to drop
+#[derive(Debug, thiserror::Error)]
+pub enum ShredError {
+    #[error("Shred too short: {0} bytes")]
+    TooShort(usize),
+    #[error("Invalid shred variant: {0:#04x}")]
+    InvalidVariant(u8),
+    #[error("Not a coding shred")]
+    NotCoding,
+    #[error("No data shreds")]
+    NoDataShreds,
+    #[error("No coding shreds")]
+    NoCodingShreds,
+    #[error("Too many missing data shreds: {missing:?}")]
+    TooManyMissing { missing: Vec<u32> },
+    #[error("Recovery failed: {0}")]
+    RecoveryFailed(Error),
+    #[error("Shreds aligned: {aligned} complete: {complete}")]
+    Misaligned { aligned: bool, complete: bool },
+    #[error("Failed to deserialize entry {index}: {reason}")]
+    DeserializeFailed { index: u64, reason: String },
+}
+
+pub fn get_shred_variant(shred: &[u8]) -> Result<ShredVariant, ShredError> {
     let Some(&shred_variant) = shred.get(OFFSET_OF_SHRED_VARIANT) else {
-        return Err(Error::InvalidPayloadSize(shred.len()));
+        return Err(ShredError::TooShort(shred.len()));
     };
     ShredVariant::try_from(shred_variant)
-        .map_err(|_| Error::InvalidShredVariant)
+        .map_err(|_| ShredError::InvalidVariant(shred_variant))
+}
+
+/// classify_shred reads the variant of a raw packet without deserializing
+/// the whole shred, None if it is too short or has an unknown variant
+pub fn classify_shred(raw_shred: &[u8]) -> Option<ShredVariant> {
+    get_shred_variant(raw_shred).ok()
 }
 
 pub fn is_shred_data(raw_shred: &[u8]) -> bool {
@@ -23,21 +54,37 @@ pub fn is_shred_data(raw_shred: &[u8]) -> bool {
     )
 }
 
-pub fn get_fec_set_index(
-    raw_shred: &[u8],
-) -> Result<u32, Box<dyn std::error::Error>> {
-    Ok(u32::from_le_bytes(raw_shred[0x4f..0x4f + 4].try_into()?))
+/// read_u32 reads a little endian u32 at `offset`, erroring out rather than
+/// panicking on packets too short to hold it
+fn read_u32(raw_shred: &[u8], offset: usize) -> Result<u32, ShredError> {
+    match raw_shred.get(offset..offset + 4) {
+        Some(&[a, b, c, d]) => Ok(u32::from_le_bytes([a, b, c, d])),
+        _ => Err(ShredError::TooShort(raw_shred.len())),
+    }
+}
+
+pub fn get_fec_set_index(raw_shred: &[u8]) -> Result<u32, ShredError> {
+    read_u32(raw_shred, 0x4f)
+}
+
+/// get_shred_version is the version of the cluster the shred was made for,
+/// it sits between the index and the fec set index
+pub fn get_shred_version(raw_shred: &[u8]) -> Result<u16, ShredError> {
+    match raw_shred.get(0x4d..0x4f) {
+        Some(&[lo, hi]) => Ok(u16::from_le_bytes([lo, hi])),
+        _ => Err(ShredError::TooShort(raw_shred.len())),
+    }
 }
 
 pub fn get_last_in_slot(raw_shred: &[u8]) -> bool {
-    if is_shred_data(raw_shred) {
-        let flags = raw_shred[0x55];
-        ShredFlags::from_bits(flags)
-            .map(|f| f.contains(ShredFlags::LAST_SHRED_IN_SLOT))
-            .unwrap_or(false)
-    } else {
-        false
+    if !is_shred_data(raw_shred) {
+        return false;
     }
+    raw_shred
+        .get(0x55)
+        .and_then(|flags| ShredFlags::from_bits(*flags))
+        .map(|f| f.contains(ShredFlags::LAST_SHRED_IN_SLOT))
+        .unwrap_or(false)
 }
 
 pub struct CodingShredHeader {
@@ -46,21 +93,26 @@ pub struct CodingShredHeader {
     pub position: u16,
 }
 
+/// get_coding_shred_header parses the coding header, which sits right after
+/// the common header in both the legacy and the merkle coding shreds
 pub fn get_coding_shred_header(
     raw_shred: &[u8],
-) -> Result<CodingShredHeader, Box<dyn std::error::Error>> {
-    if is_shred_data(raw_shred) {
-        return Err("Not a coding shred".into());
+) -> Result<CodingShredHeader, ShredError> {
+    match get_shred_variant(raw_shred)? {
+        ShredVariant::LegacyCode | ShredVariant::MerkleCode { .. } => {}
+        _ => return Err(ShredError::NotCoding),
+    }
+    if raw_shred.len() < 0x59 {
+        return Err(ShredError::TooShort(raw_shred.len()));
     }
+    let read_u16 = |offset: usize| {
+        u16::from_le_bytes([raw_shred[offset], raw_shred[offset + 1]])
+    };
 
     Ok(CodingShredHeader {
-        num_data_shreds: u16::from_le_bytes(
-            raw_shred[0x53..0x55].try_into()?,
-        ),
-        num_coding_shreds: u16::from_le_bytes(
-            raw_shred[0x55..0x57].try_into()?,
-        ),
-        position: u16::from_le_bytes(raw_shred[0x57..0x59].try_into()?),
+        num_data_shreds: read_u16(0x53),
+        num_coding_shreds: read_u16(0x55),
+        position: read_u16(0x57),
     })
 }
 
@@ -97,68 +149,235 @@ pub fn deserialize_shred(data: Vec<u8>) -> Result<Shred, Error> {
     Shred::new_from_serialized_shred(data)
 }
 
-pub fn deserialize_entries(
+/// parse_untrusted runs the parsers of the raw packets off the wire over
+/// `packet`, shared by the fuzz targets and the tests, none of them may
+/// panic whatever the bytes
+#[cfg(any(test, feature = "testing"))]
+pub fn parse_untrusted(packet: &[u8]) {
+    let _ = classify_packet(packet);
+    let _ = get_shred_variant(packet);
+    let _ = get_shred_index(packet);
+    let _ = get_fec_set_index(packet);
+    let _ = get_shred_version(packet);
+    let _ = get_coding_shred_header(packet);
+    let _ = get_shred_is_last(packet);
+    let _ = get_last_in_slot(packet);
+    let _ = get_shred_data_flags(packet);
+    if let Ok(shred) = deserialize_shred(packet.to_vec()) {
+        let _ = shred_data(&shred);
+    }
+}
+
+/// load_fixture reads the checked in legacy shreds of fixtures/shreds.json,
+/// written by fixtures/make_shreds.py apart from the shredder, with data
+/// shred 1 left out so that the first FEC set is recovered
+#[cfg(test)]
+pub(crate) fn load_fixture() -> Vec<Vec<u8>> {
+    let data = std::fs::read_to_string("fixtures/shreds.json")
+        .expect("Failed to read shreds fixture");
+    serde_json::from_str(&data).expect("Failed to parse shreds fixture")
+}
+
+/// shred_entries shreds the entries into `slot` (a child of the slot before
+/// it) the way a leader would, always signed by the same keypair, for the
+/// tests
+#[cfg(test)]
+pub(crate) fn shred_entries(
+    slot: solana_sdk::clock::Slot,
+    entries: &[Entry],
+    is_last_in_slot: bool,
+    next_shred_index: u32,
+    next_code_index: u32,
+    merkle_variant: bool,
+) -> (Vec<Shred>, Vec<Shred>) {
+    Shredder::new(slot, slot - 1, 0, 0)
+        .unwrap()
+        .entries_to_shreds(
+            &solana_sdk::signer::keypair::keypair_from_seed(&[42; 32])
+                .unwrap(),
+            entries,
+            is_last_in_slot,
+            next_shred_index,
+            next_code_index,
+            merkle_variant,
+            &ReedSolomonCache::default(),
+            &mut solana_ledger::shred::ProcessShredsStats::default(),
+        )
+}
+
+/// Entry counts past this are taken for a corrupt length prefix
+pub const DEFAULT_MAX_ENTRIES: u64 = 10_000;
+
+pub fn deserialize_entries(payload: &[u8]) -> Result<Vec<Entry>, ShredError> {
+    deserialize_entries_capped(payload, DEFAULT_MAX_ENTRIES)
+}
+
+/// deserialize_entries_capped reads at most `max_entries` entries, a count
+/// prefix past the cap does not discard the payload, the entries are read
+/// up to the cap or the first one that fails to deserialize; it fails only
+/// if not a single entry could be read
+pub fn deserialize_entries_capped(
     payload: &[u8],
-) -> Result<Vec<Entry>, Box<dyn std::error::Error + Send + Sync>> {
+    max_entries: u64,
+) -> Result<Vec<Entry>, ShredError> {
     if payload.len() < 8 {
-        error!("Payload too short: {} bytes", payload.len());
-        return Ok(Vec::new());
+        return Err(ShredError::TooShort(payload.len()));
     }
 
     let entry_count = u64::from_le_bytes(
         payload[0..8].try_into().expect("entry count parse"),
     );
-    if entry_count > 10_000 {
-        return Err(format!("entry count: {}", entry_count).into());
+    let capped = entry_count > max_entries;
+    if capped {
+        warn!(
+            "Entry count {} over the cap of {}, reading up to the cap",
+            entry_count, max_entries
+        );
     }
     trace!("Entry count prefix: {}", entry_count);
-    trace!("First 16 bytes of payload: {:?}", &payload[..16]);
+    trace!("First 16 bytes of payload: {:?}", payload.get(..16));
 
     // SUPER CRUCIAL
     // you cannot just Ok(bincode::deserialize(&payload[8..])?)
     // since the entries are not serialized as a vec, just separate entries
     // each next to the other, took me too long to figure this out :P
+    //
+    // the limit keeps a bogus length prefix from allocating more than the
+    // payload could possibly hold, past a bad entry the cursor is lost
     let mut entries = Vec::new();
     let mut cursor = std::io::Cursor::new(&payload[8..]);
-    for i in 0..entry_count {
-        match bincode::deserialize_from::<_, Entry>(&mut cursor) {
+    for i in 0..entry_count.min(max_entries) {
+        match bincode::options()
+            .with_limit(payload.len() as u64)
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .deserialize_from::<_, Entry>(&mut cursor)
+        {
             Ok(entry) => {
                 entries.push(entry);
             }
+            Err(e) if entries.is_empty() => {
+                return Err(ShredError::DeserializeFailed {
+                    index: i,
+                    reason: e.to_string(),
+                });
+            }
             Err(e) => {
                 error!(
                     "Failed to deserialize entry {}/{}: {}",
                     i, entry_count, e
                 );
+                break;
             }
         }
     }
+    if capped {
+        warn!(
+            "Read {} of the {} entries claimed",
+            entries.len(),
+            entry_count
+        );
+    }
 
     Ok(entries)
 }
 
+/// drop_unsigned_transactions removes the txs without a single signature,
+/// which only come out of corrupt payloads and have no id to go by;
+/// returns how many were dropped
+pub fn drop_unsigned_transactions(entries: &mut [Entry]) -> usize {
+    let mut dropped = 0;
+    for entry in entries.iter_mut() {
+        let before = entry.transactions.len();
+        entry.transactions.retain(|tx| !tx.signatures.is_empty());
+        dropped += before - entry.transactions.len();
+    }
+    dropped
+}
+
 const OFFSET_OF_SHRED_VARIANT: usize = SIGNATURE_BYTES;
+const SIZE_OF_DATA_SHRED_HEADERS: usize = 0x58;
+/// Sizes of the frames which arrive on the shred port next to the shreds
+/// in the captures, too short to be shreds (a shred is at least its
+/// headers), they carry no shred data and are not produced by the
+/// validators; most likely keepalives of the relay in front of the port
+pub const NON_SHRED_PACKET_SIZES: [usize; 3] = [21, 28, 29];
+
+/// PacketKind is what a packet received on the shred port is, told apart
+/// by its size alone, the shreds are only parsed past this
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    /// Long enough for the headers of a shred
+    Shred,
+    /// One of the NON_SHRED_PACKET_SIZES
+    NonShred,
+    /// Any other packet shorter than the headers of a shred
+    Truncated,
+}
+
+/// classify_packet is the one place the packets are told apart by size
+pub fn classify_packet(packet: &[u8]) -> PacketKind {
+    if NON_SHRED_PACKET_SIZES.contains(&packet.len()) {
+        PacketKind::NonShred
+    } else if packet.len() < SIZE_OF_DATA_SHRED_HEADERS {
+        PacketKind::Truncated
+    } else {
+        PacketKind::Shred
+    }
+}
 
-pub fn shred_data(shred: &Shred) -> Result<&[u8], Error> {
-    let variant =
-        ShredVariant::try_from(shred.payload()[OFFSET_OF_SHRED_VARIANT])?;
-    let (data_start, size) = match variant {
-        ShredVariant::MerkleData { .. } => {
-            let size = u16::from_le_bytes([
-                shred.payload()[0x56],
-                shred.payload()[0x57],
-            ]) as usize;
-            (0x58usize, size.saturating_sub(0x58))
+/// Size of the nonce a repair response carries past the shred, the turbine
+/// packets are the shred alone
+pub const SIZE_OF_REPAIR_NONCE: usize = 4;
+const SIZE_OF_LEGACY_PAYLOAD: usize = 1228;
+const SIZE_OF_MERKLE_DATA_PAYLOAD: usize = 1203;
+const SIZE_OF_MERKLE_CODE_PAYLOAD: usize = 1228;
+
+/// shred_payload_size is the size of the payload of a shred of the variant,
+/// the shreds are never sent shorter or longer than that
+pub fn shred_payload_size(variant: ShredVariant) -> usize {
+    match variant {
+        ShredVariant::LegacyCode | ShredVariant::LegacyData => {
+            SIZE_OF_LEGACY_PAYLOAD
+        }
+        ShredVariant::MerkleCode { .. } => SIZE_OF_MERKLE_CODE_PAYLOAD,
+        ShredVariant::MerkleData { .. } => SIZE_OF_MERKLE_DATA_PAYLOAD,
+    }
+}
+
+/// repair_shred_size is the size of the shred of a repair response, the
+/// packet less its nonce trailer, None for a turbine shred (or anything
+/// not sized like either)
+pub fn repair_shred_size(packet: &[u8]) -> Option<usize> {
+    let size = shred_payload_size(get_shred_variant(packet).ok()?);
+    (packet.len() == size + SIZE_OF_REPAIR_NONCE).then_some(size)
+}
+
+/// shred_data is the data of a data shred, the size in the header includes
+/// the headers for both the legacy and the merkle variants
+pub fn shred_data(shred: &Shred) -> Result<&[u8], ShredError> {
+    let payload = shred.payload();
+    let (data_start, size) = match get_shred_variant(payload)? {
+        ShredVariant::MerkleData { .. } | ShredVariant::LegacyData => {
+            let Some(&[lo, hi]) = payload.get(0x56..0x58) else {
+                return Err(ShredError::TooShort(payload.len()));
+            };
+            let size = u16::from_le_bytes([lo, hi]) as usize;
+            (
+                SIZE_OF_DATA_SHRED_HEADERS,
+                size.saturating_sub(SIZE_OF_DATA_SHRED_HEADERS),
+            )
         }
-        ShredVariant::LegacyData => {
-            (0x56, shred.payload().len().saturating_sub(0x56))
+        _ => {
+            return Err(ShredError::InvalidVariant(
+                payload[OFFSET_OF_SHRED_VARIANT],
+            ))
         }
-        _ => return Err(Error::InvalidShredVariant),
     };
 
     let data_end = data_start.saturating_add(size);
     if data_end > shred.payload().len() {
-        return Err(Error::InvalidPayloadSize(shred.payload().len()));
+        return Err(ShredError::TooShort(shred.payload().len()));
     }
     Ok(&shred.payload()[data_start..data_end])
 }
@@ -166,10 +385,7 @@ pub fn shred_data(shred: &Shred) -> Result<&[u8], Error> {
 pub fn load_shreds(raw_shreds: Vec<Vec<u8>>) -> HashMap<u64, Vec<Shred>> {
     let mut shreds_by_slot: HashMap<u64, Vec<Shred>> = HashMap::new();
     for raw_shred in raw_shreds {
-        if raw_shred.len() == 29
-            || raw_shred.len() == 28
-            || raw_shred.len() == 21
-        {
+        if classify_packet(&raw_shred) != PacketKind::Shred {
             continue;
         }
         let shred =
@@ -207,33 +423,54 @@ pub fn debug_shred_sizes(raw_shreds: Vec<Vec<u8>>) {
 }
 
 pub fn deshred(data_shreds: &[Shred]) -> Vec<u8> {
-    data_shreds
+    let mut deshredded_data = Vec::new();
+    deshred_into(data_shreds, &mut deshredded_data);
+    deshredded_data
+}
+
+/// deshred_into appends the data of the shreds to `buf`, reserving the
+/// total size upfront so that the buffer is grown at most once
+pub fn deshred_into(data_shreds: &[Shred], buf: &mut Vec<u8>) {
+    // the data is located once, for both the size and the copy
+    let datas = data_shreds
         .iter()
-        .flat_map(|shred| {
-            shred_data(shred)
-                .map(|data| data.to_vec())
-                .unwrap_or_default()
-        })
-        .collect()
+        .filter_map(|shred| shred_data(shred).ok())
+        .collect::<Vec<_>>();
+    buf.reserve(datas.iter().map(|data| data.len()).sum());
+    for data in datas {
+        buf.extend_from_slice(data);
+    }
+}
+
+/// is_contiguous_and_complete tells if the data shreds, sorted by index,
+/// have no gaps and end an entry batch (or the slot), so that they can be
+/// deshredded as they are
+pub fn is_contiguous_and_complete(data_shreds: &[Shred]) -> bool {
+    let Some(first) = data_shreds.first() else {
+        return false;
+    };
+    let aligned = data_shreds
+        .iter()
+        .zip(first.index()..)
+        .all(|(s, i)| s.index() == i);
+    let data_complete = data_shreds
+        .last()
+        .is_some_and(|shred| shred.data_complete() || shred.last_in_slot());
+    aligned && data_complete
 }
 
 pub fn validate_and_try_repair(
     data_shreds: &[Shred],
     code_shreds: &[Shred],
-) -> Result<Vec<Shred>, Box<dyn std::error::Error>> {
-    let index = data_shreds.first().expect("first shred").index();
-    let aligned =
-        data_shreds.iter().zip(index..).all(|(s, i)| s.index() == i);
-    let data_complete = {
-        let shred = data_shreds.last().expect("last shred");
-        shred.data_complete() || shred.last_in_slot()
+    reed_solomon_cache: &ReedSolomonCache,
+) -> Result<Vec<Shred>, ShredError> {
+    let Some(first) = data_shreds.first() else {
+        return Err(ShredError::NoDataShreds);
     };
-    if !aligned || !data_complete {
-        if data_shreds.is_empty() {
-            return Err("No data shreds".into());
-        }
+    let index = first.index();
+    if !is_contiguous_and_complete(data_shreds) {
         if code_shreds.is_empty() {
-            return Err("No code shreds".into());
+            return Err(ShredError::NoCodingShreds);
         }
         // find the missing indices
         let mut missing_indices = Vec::new();
@@ -253,11 +490,9 @@ pub fn validate_and_try_repair(
                 );
             }
             false => {
-                return Err(format!(
-                    "Too many missing indices: {:?}",
-                    missing_indices
-                )
-                .into());
+                return Err(ShredError::TooManyMissing {
+                    missing: missing_indices,
+                });
             }
         }
         info!("code shreds len: {}", code_shreds.len());
@@ -268,16 +503,14 @@ pub fn validate_and_try_repair(
             .chain(code_shreds.iter())
             .cloned()
             .collect::<Vec<_>>();
-        let data_shreds = match Shredder::try_recovery(
-            all_shreds,
-            &ReedSolomonCache::default(),
-        ) {
-            Ok(data_shreds) => data_shreds,
-            Err(e) => {
-                error!("Failed to repair shreds: {}", e);
-                return Err(e.into());
-            }
-        };
+        let data_shreds =
+            match Shredder::try_recovery(all_shreds, reed_solomon_cache) {
+                Ok(data_shreds) => data_shreds,
+                Err(e) => {
+                    error!("Failed to repair shreds: {}", e);
+                    return Err(ShredError::RecoveryFailed(e));
+                }
+            };
         let aligned =
             data_shreds.iter().zip(index..).all(|(s, i)| s.index() == i);
         let data_complete = {
@@ -285,30 +518,25 @@ pub fn validate_and_try_repair(
             shred.data_complete() || shred.last_in_slot()
         };
         if !aligned || !data_complete {
-            return Err(format!(
-                "Shreds aligned: {} complete: {}, repair no workerino",
-                aligned, data_complete
-            )
-            .into());
+            return Err(ShredError::Misaligned {
+                aligned,
+                complete: data_complete,
+            });
         }
     }
 
     Ok(data_shreds.to_vec())
 }
 
-pub fn get_shred_index(
-    raw_shred: &[u8],
-) -> Result<u32, Box<dyn std::error::Error>> {
-    Ok(u32::from_le_bytes(raw_shred[0x49..0x49 + 4].try_into()?))
+pub fn get_shred_index(raw_shred: &[u8]) -> Result<u32, ShredError> {
+    read_u32(raw_shred, 0x49)
 }
 
 /// get_shred_is_last works for data shreds only
-pub fn get_shred_is_last(
-    raw_shred: &[u8],
-) -> Result<bool, Box<dyn std::error::Error>> {
+pub fn get_shred_is_last(raw_shred: &[u8]) -> Result<bool, ShredError> {
     match raw_shred.get(0x55) {
         Some(flags) => {
-            let flags = ShredFlags::from_bits(*flags).expect("parse flags");
+            let flags = ShredFlags::from_bits_truncate(*flags);
             if flags.contains(ShredFlags::DATA_COMPLETE_SHRED)
                 || flags.contains(ShredFlags::LAST_SHRED_IN_SLOT)
             {
@@ -317,12 +545,12 @@ pub fn get_shred_is_last(
                 Ok(false)
             }
         }
-        None => Err("Error getting flags".into()),
+        None => Err(ShredError::TooShort(raw_shred.len())),
     }
 }
 
 pub fn get_shred_data_flags(raw_shred: &[u8]) -> (bool, bool, u8) {
-    let flags = raw_shred[0x55];
+    let flags = raw_shred.get(0x55).copied().unwrap_or_default();
     // Extract block_complete (bit 7)
     let block_complete = (flags & 0b1000_0000) != 0;
 
@@ -379,21 +607,357 @@ mod tests {
 
     use super::*;
     use log::debug;
+    use proptest::prelude::*;
+    use solana_ledger::shred::{ProcessShredsStats, ShredType};
+    use solana_sdk::clock::Slot;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_transaction;
+
+    /// arb_entries are entries of transfers signed by keypairs from random
+    /// seeds, some of them empty (ticks)
+    fn arb_entries() -> impl Strategy<Value = Vec<Entry>> {
+        prop::collection::vec(
+            prop::collection::vec((any::<[u8; 32]>(), any::<u64>()), 0..8),
+            1..16,
+        )
+        .prop_map(|entries| {
+            let mut hash = Hash::default();
+            entries
+                .into_iter()
+                .map(|transfers| {
+                    let transactions = transfers
+                        .into_iter()
+                        .map(|(seed, lamports)| {
+                            system_transaction::transfer(
+                                &keypair_from_seed(&seed).unwrap(),
+                                &Pubkey::new_from_array([7; 32]),
+                                lamports,
+                                Hash::default(),
+                            )
+                        })
+                        .collect();
+                    let entry = Entry::new(&hash, 1, transactions);
+                    hash = entry.hash;
+                    entry
+                })
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn untrusted_bytes_do_not_panic(
+            packet in prop::collection::vec(any::<u8>(), 0..1300),
+        ) {
+            parse_untrusted(&packet);
+            let _ = deserialize_entries(&packet);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn deshred_leaves_out_the_padding(
+            entries in arb_entries(),
+            merkle_variant in any::<bool>(),
+        ) {
+            let (data_shreds, _coding_shreds) =
+                shred_entries(1000, &entries, true, 0, 0, merkle_variant);
+            // the last shred is padded past the end of the entries, its
+            // data ends where the entries do
+            prop_assert_eq!(
+                deshred(&data_shreds),
+                bincode::serialize(&entries).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn deshred_has_no_trailing_zeros() {
+        let shred = Shred::new_from_data(
+            1000,
+            0,
+            1,
+            &[1, 2, 3],
+            ShredFlags::DATA_COMPLETE_SHRED,
+            0,
+            0,
+            0,
+        );
+        // zero padded to the full shred size
+        assert_eq!(shred.payload().last(), Some(&0));
+        assert_eq!(deshred(&[shred]), vec![1, 2, 3]);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn raw_parsing_matches_solana_ledger(
+            entries in arb_entries(),
+            slot in 1..u32::MAX as Slot,
+            parent_offset in 1..u16::MAX as Slot,
+            next_shred_index in 0..10_000u32,
+            next_code_index in 0..10_000u32,
+            is_last_in_slot in any::<bool>(),
+            merkle_variant in any::<bool>(),
+        ) {
+            let shredder = Shredder::new(
+                slot,
+                slot.saturating_sub(parent_offset),
+                0,
+                0,
+            )
+            .unwrap();
+            let (data_shreds, coding_shreds) = shredder.entries_to_shreds(
+                &keypair_from_seed(&[42; 32]).unwrap(),
+                &entries,
+                is_last_in_slot,
+                next_shred_index,
+                next_code_index,
+                merkle_variant,
+                &ReedSolomonCache::default(),
+                &mut ProcessShredsStats::default(),
+            );
+
+            for shred in data_shreds.iter().chain(coding_shreds.iter()) {
+                let payload = shred.payload();
+                prop_assert_eq!(
+                    get_shred_index(payload).unwrap(),
+                    shred.index()
+                );
+                prop_assert_eq!(
+                    get_fec_set_index(payload).unwrap(),
+                    shred.fec_set_index()
+                );
+                let variant = get_shred_variant(payload).unwrap();
+                prop_assert_eq!(ShredType::from(variant), shred.shred_type());
+                prop_assert_eq!(
+                    matches!(
+                        variant,
+                        ShredVariant::MerkleData { .. }
+                            | ShredVariant::MerkleCode { .. }
+                    ),
+                    merkle_variant
+                );
+                prop_assert_eq!(is_shred_data(payload), shred.is_data());
+            }
+
+            for shred in data_shreds.iter() {
+                let payload = shred.payload();
+                prop_assert_eq!(
+                    get_shred_is_last(payload).unwrap(),
+                    shred.data_complete() || shred.last_in_slot()
+                );
+                prop_assert_eq!(
+                    get_last_in_slot(payload),
+                    shred.last_in_slot()
+                );
+            }
+
+            // deshredding through shred_data has to give back exactly the
+            // serialized entries
+            prop_assert_eq!(
+                deshred(&data_shreds),
+                Shredder::deshred(&data_shreds).unwrap()
+            );
+            prop_assert_eq!(
+                deserialize_entries(&deshred(&data_shreds)).unwrap(),
+                entries
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_garbage_entries() {
+        // one entry claiming u64::MAX transactions
+        let mut payload = 1u64.to_le_bytes().to_vec();
+        payload.extend_from_slice(&1u64.to_le_bytes()); // num_hashes
+        payload.extend_from_slice(&[0; 32]); // hash
+        payload.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            deserialize_entries(&payload),
+            Err(ShredError::DeserializeFailed { index: 0, .. })
+        ));
+
+        for len in 0..8 {
+            assert!(matches!(
+                deserialize_entries(&payload[..len]),
+                Err(ShredError::TooShort(_))
+            ));
+        }
+        for len in 8..payload.len() {
+            assert!(deserialize_entries(&payload[..len]).is_err());
+        }
+        // no entries claimed is not a failure
+        assert!(deserialize_entries(&0u64.to_le_bytes()).unwrap().is_empty());
+        assert!(get_shred_data_flags(&[]) == (false, false, 0));
+    }
+
+    #[test]
+    fn deserialize_entries_past_the_cap() {
+        let entries = (0..3)
+            .map(|_| Entry::new(&Hash::default(), 1, vec![]))
+            .collect::<Vec<_>>();
+        // a corrupt count prefix followed by valid entries
+        let mut payload = u64::MAX.to_le_bytes().to_vec();
+        for entry in entries.iter() {
+            payload.extend(bincode::serialize(entry).unwrap());
+        }
+        assert_eq!(deserialize_entries(&payload).unwrap(), entries);
+        assert_eq!(
+            deserialize_entries_capped(&payload, 2).unwrap(),
+            entries[..2]
+        );
+    }
+
+    #[test]
+    fn classify_variant_bytes() {
+        let mut raw_shred = vec![0u8; 0x58];
+        for (variant, is_merkle, shred_type) in [
+            (0xa5, false, ShredType::Data),
+            (0x5a, false, ShredType::Code),
+            (0x86, true, ShredType::Data),
+            (0x96, true, ShredType::Data),
+            (0x46, true, ShredType::Code),
+            (0x66, true, ShredType::Code),
+        ] {
+            raw_shred[OFFSET_OF_SHRED_VARIANT] = variant;
+            let shred_variant = classify_shred(&raw_shred).unwrap();
+            assert_eq!(shred_variant.is_merkle(), is_merkle);
+            assert_eq!(shred_variant.is_legacy(), !is_merkle);
+            assert_eq!(ShredType::from(shred_variant), shred_type);
+        }
+        raw_shred[OFFSET_OF_SHRED_VARIANT] = 0x00;
+        assert_eq!(classify_shred(&raw_shred), None);
+        assert_eq!(
+            classify_shred(&raw_shred[..OFFSET_OF_SHRED_VARIANT]),
+            None
+        );
+    }
+
+    #[test]
+    fn classify_packets() {
+        let raw_shreds = load_fixture();
+
+        let mut buckets = HashMap::new();
+        for raw_shred in raw_shreds {
+            let shred_variant = classify_shred(&raw_shred);
+            if let (Some(shred_variant), Ok(shred)) =
+                (shred_variant, deserialize_shred(raw_shred))
+            {
+                assert_eq!(
+                    ShredType::from(shred_variant),
+                    shred.shred_type()
+                );
+            }
+            *buckets
+                .entry(shred_variant.map(|variant| {
+                    (ShredType::from(variant), variant.is_merkle())
+                }))
+                .or_insert(0) += 1;
+        }
+        info!("shred variants (type, merkle): {:?}", buckets);
+        assert!(buckets.keys().any(|bucket| bucket.is_some()));
+    }
+
+    #[test]
+    fn tells_the_repair_responses_apart() {
+        let entries = vec![Entry::new(&Hash::default(), 1, vec![])];
+        for merkle_variant in [false, true] {
+            let (data_shreds, coding_shreds) =
+                shred_entries(1000, &entries, true, 0, 0, merkle_variant);
+            for shred in data_shreds.iter().chain(&coding_shreds) {
+                let mut packet = shred.payload().clone();
+                assert_eq!(repair_shred_size(&packet), None);
+                packet.extend_from_slice(&42u32.to_le_bytes());
+                assert_eq!(
+                    repair_shred_size(&packet),
+                    Some(shred.payload().len())
+                );
+            }
+        }
+    }
+
+    fn assert_coding_headers(merkle_variant: bool) {
+        let entries = (0..64u8)
+            .map(|i| {
+                Entry::new(
+                    &Hash::default(),
+                    1,
+                    vec![system_transaction::transfer(
+                        &keypair_from_seed(&[i; 32]).unwrap(),
+                        &Pubkey::new_from_array([7; 32]),
+                        1,
+                        Hash::default(),
+                    )],
+                )
+            })
+            .collect::<Vec<_>>();
+        let (data_shreds, coding_shreds) =
+            shred_entries(1000, &entries, true, 0, 0, merkle_variant);
+        assert!(!coding_shreds.is_empty());
+
+        for shred in coding_shreds.iter() {
+            let payload = shred.payload();
+            let variant = get_shred_variant(payload).unwrap();
+            assert_eq!(
+                matches!(variant, ShredVariant::MerkleCode { .. }),
+                merkle_variant
+            );
+            assert_eq!(
+                matches!(variant, ShredVariant::LegacyCode),
+                !merkle_variant
+            );
+            let header = get_coding_shred_header(payload).unwrap();
+            let fec_set_index = shred.fec_set_index();
+            let num_data = data_shreds
+                .iter()
+                .filter(|s| s.fec_set_index() == fec_set_index)
+                .count();
+            let coding_positions = coding_shreds
+                .iter()
+                .filter(|s| s.fec_set_index() == fec_set_index)
+                .map(|s| {
+                    get_coding_shred_header(s.payload()).unwrap().position
+                })
+                .collect::<HashSet<_>>();
+            assert_eq!(header.num_data_shreds as usize, num_data);
+            assert_eq!(
+                header.num_coding_shreds as usize,
+                coding_positions.len()
+            );
+            assert!(header.position < header.num_coding_shreds);
+        }
+        for shred in data_shreds.iter() {
+            assert!(get_coding_shred_header(shred.payload()).is_err());
+        }
+    }
+
+    #[test]
+    fn legacy_coding_shred_header() {
+        assert_coding_headers(false);
+    }
+
+    #[test]
+    fn merkle_coding_shred_header() {
+        assert_coding_headers(true);
+    }
 
     #[test]
     fn deserialize_shreds() {
         crate::logger::setup(crate::logger::Target::Stdout).expect("logger");
 
-        let data = std::fs::read_to_string("packets.json")
-            .expect("Failed to read packets.json");
-        let raw_shreds: Vec<Vec<u8>> =
-            serde_json::from_str(&data).expect("Failed to parse JSON");
+        let raw_shreds = load_fixture();
 
         // debugging, useful
         {
             let shreds = raw_shreds
                 .iter()
-                .filter(|shred| shred.len() > 29)
+                .filter(|shred| classify_packet(shred) == PacketKind::Shred)
                 .map(|shred| deserialize_shred(shred.clone()).expect("shred"))
                 .collect::<Vec<_>>();
             let mut shreds_by_slot = HashMap::new();
@@ -425,6 +989,7 @@ mod tests {
         }
 
         // Process shreds for each slot
+        let reed_solomon_cache = ReedSolomonCache::default();
         for (slot, slot_shreds) in shreds_by_slot {
             let (data_shreds, code_shreds) = preprocess_shreds(slot_shreds);
             info!(
@@ -433,14 +998,17 @@ mod tests {
                 data_shreds.len(),
                 code_shreds.len()
             );
-            let data_shreds =
-                match validate_and_try_repair(&data_shreds, &code_shreds) {
-                    Ok(data_shreds) => data_shreds,
-                    Err(e) => {
-                        error!("Failed to validate and repair shreds: {}", e);
-                        continue;
-                    }
-                };
+            let data_shreds = match validate_and_try_repair(
+                &data_shreds,
+                &code_shreds,
+                &reed_solomon_cache,
+            ) {
+                Ok(data_shreds) => data_shreds,
+                Err(e) => {
+                    error!("Failed to validate and repair shreds: {}", e);
+                    continue;
+                }
+            };
 
             assert!(!data_shreds.is_empty());
 
@@ -1,22 +1,46 @@
+use bytes::Bytes;
+use futures_util::StreamExt;
 use log::{error, info, warn};
 use serde_json::json;
-use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+use solana_entry::entry::Entry;
 use solana_ledger::shred::{
     layout, ReedSolomonCache, Shred, ShredId, Shredder,
 };
 use solana_sdk::clock::Slot;
 
 use crate::entry_processor::EntriesWithMeta;
+use crate::otel::FecSetTrace;
+use crate::reorder::{ReorderBuffer, REORDER_FLUSH_INTERVAL};
 use crate::shred::{
-    deserialize_entries, deshred, get_coding_shred_header, get_fec_set_index,
-    get_last_in_slot, get_shred_index, is_shred_data, CodingShredHeader,
+    classify_packet, deserialize_entries_capped, deshred,
+    drop_unsigned_transactions, get_coding_shred_header, get_fec_set_index,
+    get_last_in_slot, get_shred_index, get_shred_is_last, get_shred_version,
+    is_contiguous_and_complete, is_shred_data, repair_shred_size,
+    CodingShredHeader, PacketKind, ShredError, DEFAULT_MAX_ENTRIES,
 };
+use crate::udp::UdpMetrics;
 use serde::{Deserialize, Serialize};
 
 pub const MAX_SHREDS_PER_SLOT: usize = 32_768 / 2;
+/// Number of FEC sets deshredded and deserialized at once
+pub const DESERIALIZE_WORKERS: usize = 8;
+pub const DESERIALIZE_QUEUE_SIZE: usize = 1000;
+pub const HANGING_FEC_SETS_PREFIX: &str = "hanging_fec_sets";
+/// How long a set which would need recovering waits for its missing data
+/// shreds without recovery, before its slot ends, see with_recovery
+pub const UNRECOVERED_FEC_SET_TIMEOUT: Duration = Duration::from_millis(400);
+/// Slots tracked at most, past it the oldest ones are evicted along with
+/// their sets and shred ids, so that the slots which never complete do not
+/// pile up without a max slot lag (about three minutes of slots)
+pub const MAX_TRACKED_SLOTS: usize = 512;
 
 pub struct FecSetSuccess {
     pub slot: Slot,
@@ -25,12 +49,22 @@ pub struct FecSetSuccess {
 
 #[derive(Serialize, Deserialize)]
 pub struct FecSet {
-    pub data_shreds: HashMap<u32, Arc<Vec<u8>>>,
-    pub coding_shreds: HashMap<u32, Arc<Vec<u8>>>,
+    pub data_shreds: HashMap<u32, Bytes>,
+    pub coding_shreds: HashMap<u32, Bytes>,
     pub num_expected_data: Option<u16>,
     pub num_expected_coding: Option<u16>,
     pub is_last_in_slot: bool,
     pub processed: bool,
+    /// Set once the workers decoded the set, its late shreds are dropped
+    /// from then on; a set they failed on is handed back instead
+    #[serde(skip)]
+    pub retired: bool,
+    #[serde(skip)]
+    pub trace: FecSetTrace,
+    /// When the first shred of the set came in, the reconstruction latency
+    /// is measured from it
+    #[serde(skip)]
+    pub first_shred_at: Option<Instant>,
 }
 
 impl std::fmt::Debug for FecSet {
@@ -42,76 +76,689 @@ impl std::fmt::Debug for FecSet {
             .field("num_expected_coding", &self.num_expected_coding)
             .field("is_last_in_slot", &self.is_last_in_slot)
             .field("processed", &self.processed)
+            .field("retired", &self.retired)
             .finish()
     }
 }
 
+/// SlotRange bounds the slots that get collected, both ends are inclusive
+/// and optional
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotRange {
+    pub from: Option<Slot>,
+    pub to: Option<Slot>,
+}
+
+impl SlotRange {
+    pub fn contains(&self, slot: Slot) -> bool {
+        self.from.map_or(true, |from| slot >= from)
+            && self.to.map_or(true, |to| slot <= to)
+    }
+}
+
+/// HangingDumps is where the snapshots of the hanging FEC sets are written,
+/// as `hanging_fec_sets-<unix ms>.json`, only the latest `max_files` are
+/// kept; with an `interval` a snapshot is taken periodically, otherwise
+/// only on demand
+#[derive(Debug, Clone)]
+pub struct HangingDumps {
+    pub dir: PathBuf,
+    pub max_files: usize,
+    pub interval: Option<Duration>,
+}
+
+impl Default for HangingDumps {
+    fn default() -> Self {
+        HangingDumps {
+            dir: PathBuf::from("."),
+            max_files: 10,
+            interval: None,
+        }
+    }
+}
+
+impl HangingDumps {
+    fn next_path(&self) -> PathBuf {
+        self.dir.join(format!(
+            "{}-{}.json",
+            HANGING_FEC_SETS_PREFIX,
+            chrono::Utc::now().timestamp_millis()
+        ))
+    }
+
+    /// list returns the snapshots in `dir`, oldest first
+    pub fn list(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut paths = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name().and_then(|name| name.to_str()).is_some_and(
+                    |name| {
+                        name.starts_with(&format!(
+                            "{}-",
+                            HANGING_FEC_SETS_PREFIX
+                        )) && name.ends_with(".json")
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        // the timestamps have the same number of digits, so this is also
+        // the chronological order
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// latest is the most recent snapshot in `dir`, if any
+    pub fn latest(&self) -> Option<PathBuf> {
+        self.list().ok()?.pop()
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        let paths = self.list()?;
+        let excess = paths.len().saturating_sub(self.max_files);
+        for path in paths.into_iter().take(excess) {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shreds of a complete FEC set, ready to be reassembled and deshredded
+#[derive(Debug)]
+struct DeserializeJob {
+    slot: Slot,
+    fec_set_index: u32,
+    num_expected_data: Option<u16>,
+    data_shreds: HashMap<u32, Bytes>,
+    coding_shreds: HashMap<u32, Bytes>,
+    entry_dump_dir: Option<Arc<PathBuf>>,
+    max_entries: u64,
+    entry_ordering: Option<Duration>,
+    trace: FecSetTrace,
+    first_shred_at: Option<Instant>,
+    // queued by the retry pass over the slot once it ended
+    late: bool,
+}
+
+/// What became of a queued FEC set, sent back to the processor
+enum FecSetOutcome {
+    Decoded {
+        slot: Slot,
+        fec_set_index: u32,
+    },
+    /// the set could not be recovered or decoded off of the shreds it had,
+    /// they are handed back so that it is queued again once more come in
+    Failed {
+        slot: Slot,
+        fec_set_index: u32,
+        shreds: RawShreds,
+    },
+}
+
+/// The payloads of a FEC set by index, as the FecSet holds them
+#[derive(Default)]
+struct RawShreds {
+    data_shreds: HashMap<u32, Bytes>,
+    coding_shreds: HashMap<u32, Bytes>,
+}
+
+/// What the workers made of a DeserializeJob
+struct DeserializeResult {
+    slot: Slot,
+    fec_set_index: u32,
+    data_shreds_count: usize,
+    // the data shreds the set has, whether they were all there or not
+    num_data_shreds: u32,
+    entry_ordering: Option<Duration>,
+    entries: Result<Vec<Entry>, ShredError>,
+    trace: FecSetTrace,
+    first_shred_at: Option<Instant>,
+    late: bool,
+}
+
+/// Counters updated by the deserialize workers
+#[derive(Debug, Default)]
+struct DeserializeMetrics {
+    total_processed_data: AtomicU64,
+    fec_set_success: AtomicU64,
+    fec_set_failure: AtomicU64,
+    // the fec_set_failure by class, truncated payloads and payloads not a
+    // single entry could be read out of
+    fec_set_too_short: AtomicU64,
+    fec_set_undecodable: AtomicU64,
+    // complete sets which decoded to zero entries, counted apart from the
+    // successes, a rising count points at the reconstruction
+    fec_set_empty: AtomicU64,
+    unsigned_txs: AtomicU64,
+    total_entries: AtomicU64,
+    // the shreds of complete sets which did not parse
+    malformed_shreds: AtomicU64,
+    // sets deshredded straight off of their data shreds
+    fec_set_fast_path: AtomicU64,
+    // the sets queued by the retry pass over their slot once it ended,
+    // which decoded
+    late_recovery_success: AtomicU64,
+    phases: PhaseTimings,
+    // from the first shred of a set in to its entries out
+    reconstruction_latency: LatencyWindow,
+}
+
+/// PhaseTiming accumulates how long a phase of the FEC set processing took
+#[derive(Debug, Default)]
+struct PhaseTiming {
+    count: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl PhaseTiming {
+    fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// time runs `f` and records how long it took
+    fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(start.elapsed());
+        result
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.total_us.store(0, Ordering::Relaxed);
+        self.max_us.store(0, Ordering::Relaxed);
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let count = self.count.load(Ordering::Relaxed);
+        let total_us = self.total_us.load(Ordering::Relaxed);
+        json!({
+            "count": count,
+            "avg_ms": (count > 0)
+                .then(|| total_us as f64 / count as f64 / 1_000.),
+            "max_ms": self.max_us.load(Ordering::Relaxed) as f64 / 1_000.,
+        })
+    }
+}
+
+/// Latency samples the percentiles are computed over, the oldest are
+/// dropped first
+const LATENCY_WINDOW_SIZE: usize = 10_000;
+
+/// LatencyWindow keeps the latest samples of a latency, for its
+/// distribution rather than just the average
+#[derive(Debug, Default)]
+struct LatencyWindow {
+    samples_ms: Mutex<VecDeque<f64>>,
+}
+
+impl LatencyWindow {
+    fn record(&self, elapsed: Duration) {
+        let mut samples_ms = self.samples_ms.lock().unwrap();
+        if samples_ms.len() == LATENCY_WINDOW_SIZE {
+            samples_ms.pop_front();
+        }
+        samples_ms.push_back(elapsed.as_micros() as f64 / 1_000.);
+    }
+
+    fn reset(&self) {
+        self.samples_ms.lock().unwrap().clear();
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut sorted =
+            Vec::from_iter(self.samples_ms.lock().unwrap().iter().copied());
+        sorted.sort_by(f64::total_cmp);
+        // nearest rank, null with no samples
+        let percentile = |p: f64| {
+            let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+            sorted.get(rank.saturating_sub(1)).copied()
+        };
+        json!({
+            "count": sorted.len(),
+            "p50": percentile(50.),
+            "p90": percentile(90.),
+            "p99": percentile(99.),
+            "max": sorted.last(),
+        })
+    }
+}
+
+/// PhaseTimings are the timings of each phase a FEC set goes through, to
+/// attribute the latency under load
+#[derive(Debug, Default)]
+struct PhaseTimings {
+    // parsing the data shreds of the set
+    reassembly: PhaseTiming,
+    // only the sets missing data shreds go through it
+    recovery: PhaseTiming,
+    deshred: PhaseTiming,
+    deserialize: PhaseTiming,
+}
+
+impl PhaseTimings {
+    fn reset(&self) {
+        self.reassembly.reset();
+        self.recovery.reset();
+        self.deshred.reset();
+        self.deserialize.reset();
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "reassembly": self.reassembly.to_json(),
+            "recovery": self.recovery.to_json(),
+            "deshred": self.deshred.to_json(),
+            "deserialize": self.deserialize.to_json(),
+        })
+    }
+}
+
+impl DeserializeMetrics {
+    fn reset(&self) {
+        self.total_processed_data.store(0, Ordering::Relaxed);
+        self.fec_set_success.store(0, Ordering::Relaxed);
+        self.fec_set_failure.store(0, Ordering::Relaxed);
+        self.fec_set_too_short.store(0, Ordering::Relaxed);
+        self.fec_set_undecodable.store(0, Ordering::Relaxed);
+        self.fec_set_empty.store(0, Ordering::Relaxed);
+        self.unsigned_txs.store(0, Ordering::Relaxed);
+        self.total_entries.store(0, Ordering::Relaxed);
+        self.malformed_shreds.store(0, Ordering::Relaxed);
+        self.fec_set_fast_path.store(0, Ordering::Relaxed);
+        self.late_recovery_success.store(0, Ordering::Relaxed);
+        self.phases.reset();
+        self.reconstruction_latency.reset();
+    }
+}
+
+/// Totals the rates are computed off of
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct RateTotals {
+    shreds: u128,
+    entries: u64,
+    fec_sets: u64,
+}
+
+/// Per second rates over the window since the previous metrics
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rates {
+    window_secs: f64,
+    shreds_per_sec: f64,
+    entries_per_sec: f64,
+    fec_sets_per_sec: f64,
+}
+
+/// RateWindow keeps the totals as of the previous metrics, the rates are
+/// the deltas over the time since
+#[derive(Debug, Default)]
+struct RateWindow {
+    last: Option<(Instant, RateTotals)>,
+}
+
+impl RateWindow {
+    /// update is None on the first call (and the first after a clear), as
+    /// there is no window yet
+    fn update(&mut self, now: Instant, totals: RateTotals) -> Option<Rates> {
+        let (since, previous) = self.last.replace((now, totals))?;
+        let window_secs = now.duration_since(since).as_secs_f64();
+        if window_secs <= 0. {
+            return None;
+        }
+        let per_sec = |delta: u128| delta as f64 / window_secs;
+        Some(Rates {
+            window_secs,
+            shreds_per_sec: per_sec(
+                totals.shreds.saturating_sub(previous.shreds),
+            ),
+            entries_per_sec: per_sec(
+                totals.entries.saturating_sub(previous.entries) as u128,
+            ),
+            fec_sets_per_sec: per_sec(
+                totals.fec_sets.saturating_sub(previous.fec_sets) as u128,
+            ),
+        })
+    }
+
+    fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
 #[derive(Debug)]
 pub struct ShredProcessor {
     fec_sets: HashMap<(Slot, u32), FecSet>, // (slot, fec_set_index) -> FecSet
     uniqueness: HashSet<ShredId>,
     _handles: Vec<tokio::task::JoinHandle<()>>,
-    entry_tx: mpsc::Sender<EntriesWithMeta>,
+    deserialize_tx: mpsc::Sender<DeserializeJob>,
+    outcome_rx: mpsc::UnboundedReceiver<FecSetOutcome>,
     _error_tx: mpsc::Sender<String>,
+    deserialize_metrics: Arc<DeserializeMetrics>,
+    udp_metrics: Arc<UdpMetrics>,
     total_collected_data: u128,
-    total_processed_data: u128,
     total_collected_coding: u128,
-    fec_set_success: u128,
-    fec_set_failure: u128,
+    malformed_shreds: u64,
+    // frames which are not shreds, see shred::NON_SHRED_PACKET_SIZES
+    non_shred_packets: u64,
+    // shreds with the id of a stored one but different bytes
+    equivocation_detected: u64,
+    slot_range: SlotRange,
+    // shreds of any other version are dropped, all are kept if None
+    shred_version: Option<u16>,
+    foreign_shreds: u64,
+    // shreds this many slots behind the highest ended slot are dropped,
+    // along with the sets and the ids of the slots which fall behind
+    max_slot_lag: Option<u64>,
+    // only raised by the slots whose last shred came in, a stray shred off
+    // in the future would have every slot taken for lagging otherwise
+    highest_slot: Slot,
+    lagging_shreds: u64,
+    hanging_dumps: HangingDumps,
+    entry_dump_dir: Option<Arc<PathBuf>>,
+    max_entries: u64,
+    // the entries of a slot are held back until the FEC sets before them
+    // are out, up to the gap timeout, if set
+    entry_ordering: Option<Duration>,
+    // without recovery only the sets with all of their data shreds go out,
+    // the ones which would need recovering are dropped once their slot
+    // ends or they time out, whichever comes first
+    recovery: bool,
+    unrecovered_fec_sets: u64,
+    // the slots whose last shred came in
+    ended_slots: HashSet<Slot>,
+    // the FEC set indices of the tracked slots, oldest slot first, so that
+    // the sets of a slot are gone over without scanning all of them
+    fec_set_indices: BTreeMap<Slot, BTreeSet<u32>>,
+    // the sets which would need recovering are given up on this often,
+    // without recovery
+    last_timeout_check_at: Instant,
+    // sets handed back by the workers, which failed to decode them
+    failed_fec_sets: u64,
+    // the repair responses, told apart by their nonce trailer, which is
+    // stripped off unless they are dropped altogether
+    repair_shreds: u64,
+    drop_repair_shreds: bool,
+    // the metrics are read through &self, by the metrics loop and the admin
+    rate_window: Mutex<RateWindow>,
 }
 
 impl ShredProcessor {
+    /// new has to be called from within a tokio runtime, it spawns the
+    /// deserialize workers which send the entries to `entry_tx`
     pub fn new(
         entry_tx: mpsc::Sender<EntriesWithMeta>,
         error_tx: mpsc::Sender<String>,
     ) -> Self {
+        let (deserialize_tx, deserialize_rx) =
+            mpsc::channel(DESERIALIZE_QUEUE_SIZE);
+        let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
+        let deserialize_metrics = Arc::new(DeserializeMetrics::default());
+        // the encoders are built per erasure config, the FEC sets of a
+        // leader mostly share one, so the cache is kept across all of them
+        let reed_solomon_cache = Arc::new(ReedSolomonCache::default());
+        tokio::spawn(run_deserialize_workers(
+            deserialize_rx,
+            entry_tx,
+            outcome_tx,
+            deserialize_metrics.clone(),
+            reed_solomon_cache,
+        ));
         ShredProcessor {
             fec_sets: HashMap::new(),
             uniqueness: HashSet::new(),
             _handles: Vec::new(),
-            entry_tx,
+            deserialize_tx,
+            outcome_rx,
             _error_tx: error_tx,
+            deserialize_metrics,
+            udp_metrics: Arc::new(UdpMetrics::default()),
             total_collected_data: 0,
-            total_processed_data: 0,
             total_collected_coding: 0,
-            fec_set_success: 0,
-            fec_set_failure: 0,
+            malformed_shreds: 0,
+            non_shred_packets: 0,
+            equivocation_detected: 0,
+            slot_range: SlotRange::default(),
+            shred_version: None,
+            foreign_shreds: 0,
+            max_slot_lag: None,
+            highest_slot: 0,
+            lagging_shreds: 0,
+            hanging_dumps: HangingDumps::default(),
+            entry_dump_dir: None,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            entry_ordering: None,
+            recovery: true,
+            unrecovered_fec_sets: 0,
+            ended_slots: HashSet::new(),
+            fec_set_indices: BTreeMap::new(),
+            last_timeout_check_at: Instant::now(),
+            failed_fec_sets: 0,
+            repair_shreds: 0,
+            drop_repair_shreds: false,
+            rate_window: Mutex::new(RateWindow::default()),
         }
     }
 
-    fn dump_hanging_fec_sets(&self) {
+    /// with_slot_range makes the processor ignore shreds outside of the
+    /// range, before they are deduplicated or stored
+    pub fn with_slot_range(mut self, slot_range: SlotRange) -> Self {
+        self.slot_range = slot_range;
+        self
+    }
+
+    /// with_shred_version makes the processor drop the shreds of other
+    /// clusters, `None` keeps all of them
+    pub fn with_shred_version(mut self, shred_version: Option<u16>) -> Self {
+        self.shred_version = shred_version;
+        self
+    }
+
+    /// with_max_slot_lag makes the processor drop the shreds of slots more
+    /// than `max_slot_lag` behind the highest slot ended, so that the late
+    /// shreds of old slots are not recovered once the intake falls behind
+    pub fn with_max_slot_lag(mut self, max_slot_lag: Option<u64>) -> Self {
+        self.max_slot_lag = max_slot_lag;
+        self
+    }
+
+    pub fn with_hanging_dumps(mut self, hanging_dumps: HangingDumps) -> Self {
+        self.hanging_dumps = hanging_dumps;
+        self
+    }
+
+    /// with_entry_dump makes the deserialize workers write out every FEC
+    /// set they complete, see dump_entries, `dir` has to exist
+    pub fn with_entry_dump(mut self, dir: PathBuf) -> Self {
+        self.entry_dump_dir = Some(Arc::new(dir));
+        self
+    }
+
+    /// with_max_entries caps the entries read out of a FEC set, see
+    /// deserialize_entries_capped
+    pub fn with_max_entries(mut self, max_entries: u64) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// with_entry_ordering sends the entries of each slot on in the order
+    /// of the FEC sets, holding a set back for up to `gap_timeout` for the
+    /// ones before it
+    pub fn with_entry_ordering(
+        mut self,
+        gap_timeout: Option<Duration>,
+    ) -> Self {
+        self.entry_ordering = gap_timeout;
+        self
+    }
+
+    /// with_recovery turns off the Reed-Solomon recovery if false, trading
+    /// the sets with missing data shreds for latency and CPU
+    pub fn with_recovery(mut self, recovery: bool) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// with_drop_repair_shreds drops the repair responses rather than
+    /// collecting them less their nonce, for the turbine shreds alone
+    pub fn with_drop_repair_shreds(mut self, drop: bool) -> Self {
+        self.drop_repair_shreds = drop;
+        self
+    }
+
+    pub fn hanging_dumps(&self) -> &HangingDumps {
+        &self.hanging_dumps
+    }
+
+    /// snapshot_hanging_fec_sets dumps the incomplete FEC sets to a new
+    /// timestamped file and rotates out the oldest snapshots, returns the
+    /// path and how many sets were written
+    pub fn snapshot_hanging_fec_sets(
+        &self,
+    ) -> std::io::Result<(PathBuf, usize)> {
+        let path = self.hanging_dumps.next_path();
+        let count = self.dump_hanging_fec_sets(&path)?;
+        self.hanging_dumps.rotate()?;
+        Ok((path, count))
+    }
+
+    /// dump_hanging_fec_sets writes the incomplete FEC sets to `path` and
+    /// returns how many there were
+    pub fn dump_hanging_fec_sets(
+        &self,
+        path: &Path,
+    ) -> std::io::Result<usize> {
         let start = std::time::Instant::now();
-        let file = std::fs::File::create("hanging_fec_sets.json").unwrap();
+        let file = std::fs::File::create(path)?;
         let incomplete = self
             .fec_sets
             .values()
-            .filter(|set| !Self::is_fec_set_complete(set))
+            .filter(|set| !set.processed && !Self::is_fec_set_complete(set))
             .collect::<Vec<_>>();
-        let serialized = serde_json::to_string_pretty(&incomplete).unwrap();
+        let serialized = serde_json::to_string_pretty(&incomplete)?;
         std::io::Write::write_all(
             &mut std::io::BufWriter::new(file),
             serialized.as_bytes(),
-        )
-        .unwrap();
+        )?;
         info!(
-            "Dumped {} incomplete FEC sets in {}ms",
+            "Dumped {} incomplete FEC sets to {} in {}ms",
             incomplete.len(),
+            path.display(),
             start.elapsed().as_millis()
         );
+        Ok(incomplete.len())
+    }
+
+    /// reset_metrics zeroes the counters, the kernel drops are kept as they
+    /// are read from the kernel
+    pub fn reset_metrics(&mut self) {
+        self.total_collected_data = 0;
+        self.total_collected_coding = 0;
+        self.malformed_shreds = 0;
+        self.non_shred_packets = 0;
+        self.equivocation_detected = 0;
+        self.foreign_shreds = 0;
+        self.lagging_shreds = 0;
+        self.unrecovered_fec_sets = 0;
+        self.failed_fec_sets = 0;
+        self.repair_shreds = 0;
+        self.deserialize_metrics.reset();
+        self.udp_metrics.reset();
+        // the next window would start off of the totals before the reset
+        self.rate_window.lock().expect("rate window").clear();
+    }
+
+    /// udp_metrics are shared with the receive loop, so that errors can be
+    /// counted without taking the processor lock
+    pub fn udp_metrics(&self) -> Arc<UdpMetrics> {
+        self.udp_metrics.clone()
     }
 
     pub fn metrics(&self) -> String {
         let incomplete_count = self
             .fec_sets
             .values()
-            .filter(|set| !Self::is_fec_set_complete(set))
+            .filter(|set| !set.processed && !Self::is_fec_set_complete(set))
             .count();
+        let rates = self.rate_window.lock().expect("rate window").update(
+            Instant::now(),
+            RateTotals {
+                shreds: self.total_collected_data
+                    + self.total_collected_coding,
+                entries: self
+                    .deserialize_metrics
+                    .total_entries
+                    .load(Ordering::Relaxed),
+                fec_sets: self
+                    .deserialize_metrics
+                    .fec_set_success
+                    .load(Ordering::Relaxed),
+            },
+        );
         let metrics = json!({
             "total_collected_data": self.total_collected_data,
             "total_collected_coding": self.total_collected_coding,
-            "total_processed_data": self.total_processed_data,
-            "fec_set_success_count": self.fec_set_success,
-            "fec_set_failure_count": self.fec_set_failure,
+            "total_processed_data": self.deserialize_metrics
+                .total_processed_data
+                .load(Ordering::Relaxed),
+            "fec_set_success_count": self.deserialize_metrics
+                .fec_set_success
+                .load(Ordering::Relaxed),
+            "fec_set_failure_count": self.deserialize_metrics
+                .fec_set_failure
+                .load(Ordering::Relaxed),
+            "fec_set_too_short": self.deserialize_metrics
+                .fec_set_too_short
+                .load(Ordering::Relaxed),
+            "fec_set_undecodable": self.deserialize_metrics
+                .fec_set_undecodable
+                .load(Ordering::Relaxed),
+            "fec_set_empty": self.deserialize_metrics
+                .fec_set_empty
+                .load(Ordering::Relaxed),
+            "unsigned_txs": self.deserialize_metrics
+                .unsigned_txs
+                .load(Ordering::Relaxed),
+            "total_entries": self.deserialize_metrics
+                .total_entries
+                .load(Ordering::Relaxed),
+            // null until there is a previous metrics to diff against
+            "window_secs": rates.map(|rates| rates.window_secs),
+            "shreds_per_sec": rates.map(|rates| rates.shreds_per_sec),
+            "entries_per_sec": rates.map(|rates| rates.entries_per_sec),
+            "fec_sets_per_sec": rates.map(|rates| rates.fec_sets_per_sec),
+            "malformed_shreds": self.malformed_shreds
+                + self
+                    .deserialize_metrics
+                    .malformed_shreds
+                    .load(Ordering::Relaxed),
+            "non_shred_packets": self.non_shred_packets,
+            "repair_shreds": self.repair_shreds,
+            "equivocation_detected": self.equivocation_detected,
+            "fec_set_fast_path": self.deserialize_metrics
+                .fec_set_fast_path
+                .load(Ordering::Relaxed),
+            "foreign_shreds": self.foreign_shreds,
+            "lagging_shreds": self.lagging_shreds,
+            "unrecovered_fec_sets": self.unrecovered_fec_sets,
+            "failed_fec_sets": self.failed_fec_sets,
+            "late_recovery_success": self.deserialize_metrics
+                .late_recovery_success
+                .load(Ordering::Relaxed),
+            "highest_slot": self.highest_slot,
+            "udp_recv_errors": self.udp_metrics.recv_errors(),
+            "udp_kernel_drops": self.udp_metrics.kernel_drops(),
+            "udp_full_datagrams": self.udp_metrics.full_datagrams(),
+            "seconds_since_last_packet": self
+                .udp_metrics
+                .seconds_since_last_packet(),
+            "phase_timings": self.deserialize_metrics.phases.to_json(),
+            "reconstruction_latency_ms": self
+                .deserialize_metrics
+                .reconstruction_latency
+                .to_json(),
             "fec_sets_remaining": self.fec_sets.len(),
             "fec_sets_summary": {
                 "total_count": self.fec_sets.len(),
@@ -119,35 +766,52 @@ impl ShredProcessor {
             }
         });
 
-        // dump only once (fishing for more testing data)
-        if incomplete_count > 1000
-            && !std::path::Path::new("hanging_fec_sets.json").exists()
-        {
-            self.dump_hanging_fec_sets();
-        }
-
         serde_json::to_string_pretty(&metrics)
             .unwrap_or_else(|_| "Error serializing metrics".to_string())
     }
 
-    pub async fn insert(&mut self, slot: Slot, raw_shred: Arc<Vec<u8>>) {
+    pub async fn insert(&mut self, slot: Slot, raw_shred: Bytes) {
         let is_data = is_shred_data(&raw_shred);
-        let index = get_shred_index(&raw_shred).expect("get index");
-        let fec_set_index =
-            get_fec_set_index(&raw_shred).expect("get fec set index");
+        let (index, fec_set_index) = match (
+            get_shred_index(&raw_shred),
+            get_fec_set_index(&raw_shred),
+        ) {
+            (Ok(index), Ok(fec_set_index)) => (index, fec_set_index),
+            _ => {
+                self.malformed_shreds += 1;
+                return;
+            }
+        };
+        if self.is_stored(slot, fec_set_index, index, is_data, &raw_shred) {
+            return;
+        }
+        let is_new_fec_set =
+            !self.fec_sets.contains_key(&(slot, fec_set_index));
+        if is_new_fec_set && !self.track_fec_set(slot, fec_set_index) {
+            return;
+        }
 
         let fec_set = self
             .fec_sets
             .entry((slot, fec_set_index))
             .or_insert_with(|| FecSet {
                 processed: false,
+                retired: false,
                 data_shreds: HashMap::new(),
                 coding_shreds: HashMap::new(),
                 num_expected_data: None,
                 num_expected_coding: None,
                 is_last_in_slot: false,
+                trace: FecSetTrace::default(),
+                first_shred_at: Some(Instant::now()),
             });
 
+        // late shreds of a set that has already been decoded
+        if fec_set.retired {
+            return;
+        }
+        fec_set.trace.received();
+
         if is_data {
             self.total_collected_data += 1;
             fec_set.data_shreds.insert(index, raw_shred.clone());
@@ -171,132 +835,873 @@ impl ShredProcessor {
             }
         }
 
-        if Self::is_fec_set_complete(fec_set) {
-            self.process_fec_set(slot, fec_set_index).await;
+        let is_last_in_slot = is_data && get_last_in_slot(&raw_shred);
+        if is_last_in_slot {
+            self.end_slot(slot);
+        }
+        self.process_if_complete(slot, fec_set_index).await;
+        // the sets of an ended slot are only known complete off of the
+        // sets around them, they are gone over again as the slot ends, a
+        // set of it starts (which tells where the one before ends) or a set
+        // of it is still left pending
+        let is_pending = self
+            .fec_sets
+            .get(&(slot, fec_set_index))
+            .is_some_and(|fec_set| !fec_set.processed);
+        if self.ended_slots.contains(&slot)
+            && (is_last_in_slot || is_new_fec_set || is_pending)
+        {
+            self.retry_incomplete_fec_sets(slot).await;
         }
     }
 
-    fn is_fec_set_complete(fec_set: &FecSet) -> bool {
+    /// track_fec_set indexes a new set under its slot, evicting the oldest
+    /// slots past MAX_TRACKED_SLOTS; false if the slot of the set is the
+    /// one evicted, as older than all of the tracked ones
+    fn track_fec_set(&mut self, slot: Slot, fec_set_index: u32) -> bool {
+        self.fec_set_indices
+            .entry(slot)
+            .or_default()
+            .insert(fec_set_index);
+        let num_evicted =
+            self.fec_set_indices.len().saturating_sub(MAX_TRACKED_SLOTS);
+        if num_evicted > 0 {
+            if let Some(&oldest_kept) =
+                self.fec_set_indices.keys().nth(num_evicted)
+            {
+                self.evict_slots_before(oldest_kept);
+            }
+        }
+        self.fec_set_indices.contains_key(&slot)
+    }
+
+    /// evict_slots_before drops the sets, the shred ids and the ends of the
+    /// slots before `slot`
+    fn evict_slots_before(&mut self, slot: Slot) {
+        self.fec_sets
+            .retain(|(fec_set_slot, _), _| *fec_set_slot >= slot);
+        self.uniqueness.retain(|shred_id| shred_id.slot() >= slot);
+        self.ended_slots.retain(|ended_slot| *ended_slot >= slot);
+        self.fec_set_indices = self.fec_set_indices.split_off(&slot);
+    }
+
+    /// give_up_timed_out_fec_sets gives up, without recovery, the sets with
+    /// enough shreds to be recovered which have been missing data shreds
+    /// for UNRECOVERED_FEC_SET_TIMEOUT, whether or not more shreds of them
+    /// come in; checked once per timeout rather than on every shred
+    fn give_up_timed_out_fec_sets(&mut self) {
+        if self.recovery
+            || self.last_timeout_check_at.elapsed()
+                < UNRECOVERED_FEC_SET_TIMEOUT
+        {
+            return;
+        }
+        self.last_timeout_check_at = Instant::now();
+        for fec_set in self.fec_sets.values_mut() {
+            if !fec_set.processed
+                && Self::is_fec_set_complete(fec_set)
+                && fec_set.first_shred_at.is_some_and(|at| {
+                    at.elapsed() >= UNRECOVERED_FEC_SET_TIMEOUT
+                })
+            {
+                Self::give_up_fec_set(fec_set);
+                self.unrecovered_fec_sets += 1;
+            }
+        }
+    }
+
+    /// process_if_complete queues the set once it has enough shreds, any
+    /// num_data of them with recovery and all of its data shreds without;
+    /// the shreds of a set with the workers are only kept in case it is
+    /// handed back
+    async fn process_if_complete(&mut self, slot: Slot, fec_set_index: u32) {
+        let Some(fec_set) = self.fec_sets.get_mut(&(slot, fec_set_index))
+        else {
+            return;
+        };
+        if fec_set.processed {
+            return;
+        }
+        if !self.recovery {
+            if Self::is_data_complete(fec_set) {
+                self.process_fec_set(slot, fec_set_index, false).await;
+            } else if Self::is_fec_set_complete(fec_set)
+                && (self.ended_slots.contains(&slot)
+                    || fec_set.first_shred_at.is_some_and(|at| {
+                        at.elapsed() >= UNRECOVERED_FEC_SET_TIMEOUT
+                    }))
+            {
+                // the data shreds of the sets interleave, the missing ones
+                // are only taken as lost once they had the time to come in
+                Self::give_up_fec_set(fec_set);
+                self.unrecovered_fec_sets += 1;
+            }
+        } else if Self::is_fec_set_complete(fec_set) {
+            self.process_fec_set(slot, fec_set_index, false).await;
+        }
+    }
+
+    /// give_up_fec_set drops the shreds of a set which would need
+    /// recovering, with recovery off, along with any that come in later
+    fn give_up_fec_set(fec_set: &mut FecSet) {
+        fec_set.processed = true;
+        fec_set.retired = true;
+        fec_set.data_shreds.clear();
+        fec_set.coding_shreds.clear();
+    }
+
+    /// apply_fec_set_outcomes takes in what the workers made of the queued
+    /// sets: a decoded set is retired, a failed one is armed again with its
+    /// shreds and those which came in since it was queued
+    async fn apply_fec_set_outcomes(&mut self) {
+        while let Ok(outcome) = self.outcome_rx.try_recv() {
+            match outcome {
+                FecSetOutcome::Decoded {
+                    slot,
+                    fec_set_index,
+                } => {
+                    if let Some(fec_set) =
+                        self.fec_sets.get_mut(&(slot, fec_set_index))
+                    {
+                        fec_set.retired = true;
+                        fec_set.data_shreds.clear();
+                        fec_set.coding_shreds.clear();
+                    }
+                }
+                FecSetOutcome::Failed {
+                    slot,
+                    fec_set_index,
+                    shreds,
+                } => {
+                    let Some(fec_set) =
+                        self.fec_sets.get_mut(&(slot, fec_set_index))
+                    else {
+                        continue;
+                    };
+                    self.failed_fec_sets += 1;
+                    fec_set.processed = false;
+                    // without new shreds it would only fail the same way
+                    let has_new_shreds = !fec_set.data_shreds.is_empty()
+                        || !fec_set.coding_shreds.is_empty();
+                    for (index, raw_shred) in shreds.data_shreds {
+                        fec_set.data_shreds.entry(index).or_insert(raw_shred);
+                    }
+                    for (index, raw_shred) in shreds.coding_shreds {
+                        fec_set
+                            .coding_shreds
+                            .entry(index)
+                            .or_insert(raw_shred);
+                    }
+                    if has_new_shreds {
+                        self.process_if_complete(slot, fec_set_index).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// retry_incomplete_fec_sets goes over the sets of the ended slot still
+    /// left, see insert; a set with all of its data shreds, but without a
+    /// coding shred or a batch end to tell how many there are, is only
+    /// known to be complete off of where the set after it starts; without
+    /// recovery, the sets still missing data shreds are given up
+    async fn retry_incomplete_fec_sets(&mut self, slot: Slot) {
+        let Some(fec_set_indices) = self.fec_set_indices.get(&slot) else {
+            return;
+        };
+        let fec_set_indices =
+            fec_set_indices.iter().copied().collect::<Vec<_>>();
+        for (i, &fec_set_index) in fec_set_indices.iter().enumerate() {
+            let Some(fec_set) = self.fec_sets.get_mut(&(slot, fec_set_index))
+            else {
+                continue;
+            };
+            if fec_set.processed {
+                continue;
+            }
+            if fec_set.num_expected_data.is_none() {
+                fec_set.num_expected_data =
+                    fec_set_indices.get(i + 1).and_then(|&next| {
+                        Self::num_data_before(fec_set, fec_set_index, next)
+                    });
+            }
+            if Self::is_data_complete(fec_set)
+                || (self.recovery && Self::is_fec_set_complete(fec_set))
+            {
+                self.process_fec_set(slot, fec_set_index, true).await;
+            } else if !self.recovery && Self::is_fec_set_complete(fec_set) {
+                Self::give_up_fec_set(fec_set);
+                self.unrecovered_fec_sets += 1;
+            }
+        }
+    }
+
+    /// num_data_before is the number of data shreds of the set given the
+    /// index of the set after it, if they are all there
+    fn num_data_before(
+        fec_set: &FecSet,
+        fec_set_index: u32,
+        next_fec_set_index: u32,
+    ) -> Option<u16> {
+        let num_data =
+            u16::try_from(next_fec_set_index.checked_sub(fec_set_index)?)
+                .ok()?;
+        (fec_set_index..next_fec_set_index)
+            .all(|index| fec_set.data_shreds.contains_key(&index))
+            .then_some(num_data)
+    }
+
+    /// is_stored tells if a payload is already stored for the index of the
+    /// shred, the first one seen is kept; a different payload for the same
+    /// index means the leader equivocated and is counted as such
+    fn is_stored(
+        &mut self,
+        slot: Slot,
+        fec_set_index: u32,
+        index: u32,
+        is_data: bool,
+        raw_shred: &Bytes,
+    ) -> bool {
+        let Some(fec_set) = self.fec_sets.get(&(slot, fec_set_index)) else {
+            return false;
+        };
+        let shreds = if is_data {
+            &fec_set.data_shreds
+        } else {
+            &fec_set.coding_shreds
+        };
+        let Some(stored) = shreds.get(&index) else {
+            return false;
+        };
+        if stored != raw_shred {
+            self.equivocation_detected += 1;
+            warn!(
+                "Conflicting payloads for slot {} {} shred {}, keeping the first",
+                slot,
+                if is_data { "data" } else { "coding" },
+                index
+            );
+        }
+        true
+    }
+
+    /// is_fec_set_complete tells if the set has enough shreds to be
+    /// deshredded, recovering the missing data shreds if needed; without a
+    /// coding shred it is complete once all of its data shreds are there,
+    /// see num_data_through_last
+    pub fn is_fec_set_complete(fec_set: &FecSet) -> bool {
         if let (Some(expected_data), Some(expected_coding)) =
             (fec_set.num_expected_data, fec_set.num_expected_coding)
         {
             let total_shreds =
                 fec_set.data_shreds.len() + fec_set.coding_shreds.len();
-            let total_expected =
-                expected_data as usize + expected_coding as usize;
 
+            // any num_data of the shreds are enough to recover the rest
             fec_set.data_shreds.len() == expected_data as usize
-                || total_shreds >= total_expected
+                || (expected_coding > 0
+                    && total_shreds >= expected_data as usize)
         } else {
-            false
+            Self::num_data_through_last(fec_set).is_some()
+        }
+    }
+
+    /// is_data_complete tells if all of the data shreds of the set are
+    /// there, no recovery needed
+    fn is_data_complete(fec_set: &FecSet) -> bool {
+        match fec_set.num_expected_data {
+            Some(expected_data) => {
+                fec_set.data_shreds.len() == expected_data as usize
+            }
+            None => Self::num_data_through_last(fec_set).is_some(),
         }
     }
 
-    async fn process_fec_set(&mut self, slot: Slot, fec_set_index: u32) {
+    /// num_data_through_last is the number of data shreds of the set as
+    /// told by the data shreds themselves, if they are contiguous from the
+    /// start of the set through one that ends a batch (DATA_COMPLETE_SHRED
+    /// or LAST_SHRED_IN_SLOT), as the sets do not span batches
+    fn num_data_through_last(fec_set: &FecSet) -> Option<u16> {
+        let raw_shred = fec_set.data_shreds.values().next()?;
+        let fec_set_index = get_fec_set_index(raw_shred).ok()?;
+        // inclusive, an open range overflows on a set at u32::MAX
+        (fec_set_index..=u32::MAX)
+            .map_while(|index| fec_set.data_shreds.get(&index))
+            .position(|raw_shred| {
+                matches!(get_shred_is_last(raw_shred), Ok(true))
+            })
+            .map(|position| position as u16 + 1)
+    }
+
+    /// process_fec_set queues the set with the deserialize workers, `late`
+    /// if it is only found complete by the retry pass over its slot
+    async fn process_fec_set(
+        &mut self,
+        slot: Slot,
+        fec_set_index: u32,
+        late: bool,
+    ) {
+        // the shreds are taken out of the set and reassembled by the
+        // workers, off of the processor lock, the set itself stays around
+        // marked as processed so that it is not picked up twice, until the
+        // workers tell if it decoded
         let fec_set = match self.fec_sets.get_mut(&(slot, fec_set_index)) {
-            Some(set) => set,
-            None => return,
+            Some(set) if !set.processed => set,
+            _ => return,
         };
+        fec_set.processed = true;
+        fec_set.trace.complete(slot, fec_set_index);
+        let num_expected_data = fec_set
+            .num_expected_data
+            .or_else(|| Self::num_data_through_last(fec_set));
+        let data_shreds = std::mem::take(&mut fec_set.data_shreds);
+        let mut coding_shreds = std::mem::take(&mut fec_set.coding_shreds);
+        if !self.recovery {
+            // only ever used to recover from
+            coding_shreds.clear();
+        }
+        let trace = std::mem::take(&mut fec_set.trace);
+        let first_shred_at = fec_set.first_shred_at;
 
-        if fec_set.processed {
+        if let Err(e) = self
+            .deserialize_tx
+            .send(DeserializeJob {
+                slot,
+                fec_set_index,
+                num_expected_data,
+                data_shreds,
+                coding_shreds,
+                entry_dump_dir: self.entry_dump_dir.clone(),
+                max_entries: self.max_entries,
+                entry_ordering: self.entry_ordering,
+                trace,
+                first_shred_at,
+                late,
+            })
+            .await
+        {
+            error!(
+                "Failed to queue slot {} FEC set {}, dropping {} data shreds",
+                slot,
+                fec_set_index,
+                e.0.data_shreds.len()
+            );
+        }
+    }
+
+    /// collect is kept for callers holding Arc<Vec<u8>> packets, the bytes
+    /// are only copied if the Arc is shared
+    pub async fn collect(&mut self, raw_shred: Arc<Vec<u8>>) {
+        self.collect_bytes(Bytes::from(into_payload(raw_shred)))
+            .await;
+    }
+
+    /// is_lagging tells if the slot is past the max lag
+    fn is_lagging(&self, slot: Slot) -> bool {
+        self.max_slot_lag.is_some_and(|max_slot_lag| {
+            slot.saturating_add(max_slot_lag) < self.highest_slot
+        })
+    }
+
+    /// end_slot marks the slot as ended once its last shred is in, raising
+    /// the highest slot and evicting the slots left past the max lag
+    fn end_slot(&mut self, slot: Slot) {
+        self.ended_slots.insert(slot);
+        if slot <= self.highest_slot {
             return;
         }
+        self.highest_slot = slot;
+        if let Some(max_slot_lag) = self.max_slot_lag {
+            self.evict_slots_before(slot.saturating_sub(max_slot_lag));
+        }
+    }
 
-        let expected_data_shreds =
-            fec_set.num_expected_data.unwrap_or(1) as usize;
-        let mut data_shreds: Vec<Shred> = fec_set
-            .data_shreds
-            .values()
+    pub async fn collect_bytes(&mut self, mut raw_shred: Bytes) {
+        self.apply_fec_set_outcomes().await;
+        self.give_up_timed_out_fec_sets();
+        match classify_packet(&raw_shred) {
+            PacketKind::Shred => {}
+            PacketKind::NonShred => {
+                self.non_shred_packets += 1;
+                return;
+            }
+            PacketKind::Truncated => {
+                self.malformed_shreds += 1;
+                return;
+            }
+        }
+        if let Some(size) = repair_shred_size(&raw_shred) {
+            self.repair_shreds += 1;
+            if self.drop_repair_shreds {
+                return;
+            }
+            // left on, the nonce makes the payload differ from the same
+            // shred off of turbine (taken for an equivocation) and throws
+            // off the parsing of the shred
+            raw_shred.truncate(size);
+        }
+        if let Some(shred_version) = self.shred_version {
+            if get_shred_version(&raw_shred).ok() != Some(shred_version) {
+                self.foreign_shreds += 1;
+                return;
+            }
+        }
+        match layout::get_shred_id(&raw_shred) {
+            Some(shred_id) => {
+                if !self.slot_range.contains(shred_id.slot()) {
+                    return;
+                }
+                if self.is_lagging(shred_id.slot()) {
+                    self.lagging_shreds += 1;
+                    return;
+                }
+                if !self.uniqueness.insert(shred_id) {
+                    // the payloads of processed sets are gone, only the
+                    // duplicates of pending shreds can be told apart
+                    if let (Ok(index), Ok(fec_set_index)) = (
+                        get_shred_index(&raw_shred),
+                        get_fec_set_index(&raw_shred),
+                    ) {
+                        self.is_stored(
+                            shred_id.slot(),
+                            fec_set_index,
+                            index,
+                            is_shred_data(&raw_shred),
+                            &raw_shred,
+                        );
+                    }
+                    return;
+                }
+                self.insert(shred_id.slot(), raw_shred).await;
+            }
+            None => {
+                self.malformed_shreds += 1;
+                error!("Error getting shred id");
+            }
+        }
+    }
+}
+
+/// reconstruct_entries replays a capture through a ShredProcessor and returns
+/// the entries of every FEC set it managed to deshred, in the order they came
+/// out of the deserialize workers
+pub async fn reconstruct_entries(
+    raw_shreds: Vec<Vec<u8>>,
+) -> Vec<EntriesWithMeta> {
+    let (entry_tx, mut entry_rx) = mpsc::channel(DESERIALIZE_QUEUE_SIZE);
+    let (error_tx, _error_rx) = mpsc::channel(DESERIALIZE_QUEUE_SIZE);
+    let collector = tokio::spawn(async move {
+        let mut entries = Vec::new();
+        while let Some(entries_with_meta) = entry_rx.recv().await {
+            entries.push(entries_with_meta);
+        }
+        entries
+    });
+
+    let mut processor = ShredProcessor::new(entry_tx, error_tx);
+    for raw_shred in raw_shreds {
+        processor.collect_bytes(Bytes::from(raw_shred)).await;
+    }
+    // closes the deserialize queue, the workers drop `entry_tx` once drained
+    drop(processor);
+
+    collector.await.unwrap_or_default()
+}
+
+/// into_payload takes the shred bytes out of the Arc, only copying them if
+/// the Arc is still shared
+fn into_payload(raw_shred: Arc<Vec<u8>>) -> Vec<u8> {
+    Arc::try_unwrap(raw_shred).unwrap_or_else(|raw_shred| raw_shred.to_vec())
+}
+
+/// dump_entries appends the deshredded payload of the FEC set to
+/// `slot-<n>.bin` and, if it deserialized, a line with its entries to
+/// `slot-<n>.json`; the sets are appended in the order they complete, so
+/// every line carries the FEC set index and where its payload sits in the
+/// .bin file
+fn dump_entries(
+    dir: &Path,
+    slot: Slot,
+    fec_set_index: u32,
+    payload: &[u8],
+    entries: Option<&[Entry]>,
+) -> std::io::Result<()> {
+    // the offset has to be read and written to without another worker
+    // appending in between
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let append = |path: PathBuf| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+    };
+    let mut bin = append(dir.join(format!("slot-{}.bin", slot)))?;
+    let offset = bin.metadata()?.len();
+    bin.write_all(payload)?;
+    if let Some(entries) = entries {
+        let mut line = serde_json::to_vec(&json!({
+            "fec_set_index": fec_set_index,
+            "offset": offset,
+            "len": payload.len(),
+            "entries": entries,
+        }))?;
+        line.push(b'\n');
+        append(dir.join(format!("slot-{}.json", slot)))?.write_all(&line)?;
+    }
+    Ok(())
+}
+
+/// into_raw_shreds turns the parsed shreds back into the payloads by
+/// index, as the FecSet holds them
+fn into_raw_shreds(shreds: Vec<Shred>) -> HashMap<u32, Bytes> {
+    shreds
+        .into_iter()
+        .map(|shred| (shred.index(), Bytes::from(shred.into_payload())))
+        .collect()
+}
+
+/// reassemble parses the shreds of the FEC set, recovering the missing
+/// data shreds off of the coding ones if needed, and returns the data
+/// shreds sorted by index; the shreds are handed back if the set is short
+/// of data shreds and can not be recovered
+fn reassemble(
+    slot: Slot,
+    fec_set_index: u32,
+    num_expected_data: Option<u16>,
+    data_shreds: HashMap<u32, Bytes>,
+    coding_shreds: HashMap<u32, Bytes>,
+    reed_solomon_cache: &ReedSolomonCache,
+    metrics: &DeserializeMetrics,
+) -> Result<Vec<Shred>, RawShreds> {
+    let expected_data_shreds = num_expected_data.unwrap_or(1) as usize;
+    let raw_data_shreds = data_shreds.len();
+    let phases = &metrics.phases;
+    let mut data_shreds: Vec<Shred> = phases.reassembly.time(|| {
+        data_shreds
+            .into_values()
             .filter_map(|raw_shred| {
-                Shred::new_from_serialized_shred(raw_shred.to_vec()).ok()
+                Shred::new_from_serialized_shred(Vec::from(raw_shred)).ok()
             })
-            .collect();
+            .collect()
+    });
+    metrics.malformed_shreds.fetch_add(
+        (raw_data_shreds - data_shreds.len()) as u64,
+        Ordering::Relaxed,
+    );
+    data_shreds.sort_unstable_by_key(|shred| shred.index());
 
-        if data_shreds.len() < expected_data_shreds {
-            let coding_shreds: Vec<Shred> = fec_set
-                .coding_shreds
-                .values()
-                .filter_map(|raw_shred| {
-                    Shred::new_from_serialized_shred(raw_shred.to_vec()).ok()
-                })
-                .collect();
+    // the common case, all of the data is there and ends a batch, so
+    // the coding shreds are not even parsed
+    let data_complete = data_shreds.len() == expected_data_shreds
+        && data_shreds.first().map(|shred| shred.index())
+            == Some(fec_set_index)
+        && is_contiguous_and_complete(&data_shreds);
+    if data_complete {
+        metrics.fec_set_fast_path.fetch_add(1, Ordering::Relaxed);
+    } else if data_shreds.len() < expected_data_shreds {
+        let raw_coding_shreds = coding_shreds.len();
+        let coding_shreds: Vec<Shred> = coding_shreds
+            .into_values()
+            .filter_map(|raw_shred| {
+                Shred::new_from_serialized_shred(Vec::from(raw_shred)).ok()
+            })
+            .collect();
+        metrics.malformed_shreds.fetch_add(
+            (raw_coding_shreds - coding_shreds.len()) as u64,
+            Ordering::Relaxed,
+        );
 
-            info!("Attempting to recover missing data shreds for slot {} FEC set {}", slot, fec_set_index);
-            match Shredder::try_recovery(
+        info!("Attempting to recover missing data shreds for slot {} FEC set {}", slot, fec_set_index);
+        match phases.recovery.time(|| {
+            Shredder::try_recovery(
                 data_shreds
                     .iter()
                     .chain(coding_shreds.iter())
                     .cloned()
                     .collect(),
-                &ReedSolomonCache::default(),
-            ) {
-                Ok(recovered_shreds) => {
-                    info!(
-                        "Recovered {} data shreds for slot {} FEC set {}",
-                        recovered_shreds.len(),
-                        slot,
-                        fec_set_index
-                    );
-                    data_shreds.extend(
-                        recovered_shreds.into_iter().filter(|s| s.is_data()),
-                    );
-                }
-                Err(e) => {
-                    warn!("Failed to recover data shreds for slot {} FEC set {}: {:?}", 
-                    slot, fec_set_index, e);
-                }
+                reed_solomon_cache,
+            )
+        }) {
+            Ok(recovered_shreds) => {
+                info!(
+                    "Recovered {} data shreds for slot {} FEC set {}",
+                    recovered_shreds.len(),
+                    slot,
+                    fec_set_index
+                );
+                data_shreds.extend(
+                    recovered_shreds.into_iter().filter(|s| s.is_data()),
+                );
+                data_shreds.sort_unstable_by_key(|shred| shred.index());
+            }
+            Err(e) => {
+                warn!("Failed to recover data shreds for slot {} FEC set {}: {:?}", 
+                slot, fec_set_index, e);
             }
         }
-
-        if data_shreds.is_empty() {
-            error!(
-                "No valid data shreds found for slot {} FEC set {}",
-                slot, fec_set_index
-            );
-            return;
+        // deshredded as it is the set would only fail to decode
+        if data_shreds.len() < expected_data_shreds {
+            return Err(RawShreds {
+                data_shreds: into_raw_shreds(data_shreds),
+                coding_shreds: into_raw_shreds(coding_shreds),
+            });
         }
+    }
 
-        data_shreds.sort_by_key(|shred| shred.index());
-        let deshredded_data = deshred(&data_shreds);
+    Ok(data_shreds)
+}
 
-        match deserialize_entries(&deshredded_data) {
-            Ok(entries) => {
-                self.fec_set_success += 1;
-                self.total_processed_data += data_shreds.len() as u128;
-                fec_set.processed = true;
-                self.fec_sets.remove(&(slot, fec_set_index));
-                if let Err(e) = self
-                    .entry_tx
-                    .send(EntriesWithMeta { entries, slot })
-                    .await
+/// run_deserialize_workers deshreds and deserializes the queued FEC sets on
+/// the blocking pool, up to DESERIALIZE_WORKERS at a time; the entries are
+/// sent on in the order the FEC sets were queued in
+async fn run_deserialize_workers(
+    deserialize_rx: mpsc::Receiver<DeserializeJob>,
+    entry_tx: mpsc::Sender<EntriesWithMeta>,
+    outcome_tx: mpsc::UnboundedSender<FecSetOutcome>,
+    metrics: Arc<DeserializeMetrics>,
+    reed_solomon_cache: Arc<ReedSolomonCache>,
+) {
+    let jobs = futures_util::stream::unfold(
+        deserialize_rx,
+        |mut deserialize_rx| async move {
+            deserialize_rx.recv().await.map(|job| (job, deserialize_rx))
+        },
+    );
+    let mut results = jobs
+        .map(|job| {
+            let metrics = metrics.clone();
+            let reed_solomon_cache = reed_solomon_cache.clone();
+            let outcome_tx = outcome_tx.clone();
+            tokio::task::spawn_blocking(move || {
+                let DeserializeJob {
+                    slot,
+                    fec_set_index,
+                    num_expected_data,
+                    data_shreds,
+                    coding_shreds,
+                    entry_dump_dir,
+                    max_entries,
+                    entry_ordering,
+                    trace,
+                    first_shred_at,
+                    late,
+                } = job;
+                let _span = trace.span("deserialize");
+                let reassembled = reassemble(
+                    slot,
+                    fec_set_index,
+                    num_expected_data,
+                    data_shreds,
+                    coding_shreds,
+                    &reed_solomon_cache,
+                    &metrics,
+                );
+                let data_shreds = match reassembled {
+                    Ok(data_shreds) if !data_shreds.is_empty() => data_shreds,
+                    reassembled => {
+                        if reassembled.is_ok() {
+                            error!(
+                                "No valid data shreds found for slot {} FEC set {}",
+                                slot, fec_set_index
+                            );
+                        }
+                        // the send fails once the processor is dropped
+                        let _ = outcome_tx.send(FecSetOutcome::Failed {
+                            slot,
+                            fec_set_index,
+                            shreds: reassembled.err().unwrap_or_default(),
+                        });
+                        return None;
+                    }
+                };
+                let deshredded_data =
+                    metrics.phases.deshred.time(|| deshred(&data_shreds));
+                let entries = metrics.phases.deserialize.time(|| {
+                    deserialize_entries_capped(&deshredded_data, max_entries)
+                });
+                if let Some(dir) = entry_dump_dir {
+                    if let Err(e) = dump_entries(
+                        &dir,
+                        slot,
+                        fec_set_index,
+                        &deshredded_data,
+                        entries.as_deref().ok(),
+                    ) {
+                        error!(
+                            "Failed to dump slot {} FEC set {}: {}",
+                            slot, fec_set_index, e
+                        );
+                    }
+                }
+                let data_shreds_count = data_shreds.len();
+                // the recovery can fail, the index of the next set is past
+                // what the set should have had
+                let num_data_shreds = num_expected_data
+                    .map(u32::from)
+                    .unwrap_or(data_shreds_count as u32);
+                // a set short of data shreds may still decode once the
+                // rest of them come in
+                let outcome = if entries.is_err()
+                    && (data_shreds_count as u32) < num_data_shreds
                 {
-                    error!(
-                        "Failed to send entries for slot {} FEC set {}: {:?}",
-                        slot, fec_set_index, e
+                    FecSetOutcome::Failed {
+                        slot,
+                        fec_set_index,
+                        shreds: RawShreds {
+                            data_shreds: into_raw_shreds(data_shreds),
+                            ..Default::default()
+                        },
+                    }
+                } else {
+                    FecSetOutcome::Decoded {
+                        slot,
+                        fec_set_index,
+                    }
+                };
+                let _ = outcome_tx.send(outcome);
+                Some(DeserializeResult {
+                    slot,
+                    fec_set_index,
+                    data_shreds_count,
+                    num_data_shreds,
+                    entry_ordering,
+                    entries,
+                    trace,
+                    first_shred_at,
+                    late,
+                })
+            })
+        })
+        .buffered(DESERIALIZE_WORKERS);
+
+    let mut reorder: Option<ReorderBuffer> = None;
+    let mut flush_interval = tokio::time::interval(REORDER_FLUSH_INTERVAL);
+
+    loop {
+        let result = tokio::select! {
+            result = results.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = flush_interval.tick(), if reorder.is_some() => {
+                let expired = reorder
+                    .as_mut()
+                    .map(|reorder| reorder.flush_expired(Instant::now()))
+                    .unwrap_or_default();
+                send_entries(&entry_tx, expired, &metrics).await;
+                continue;
+            }
+        };
+        let DeserializeResult {
+            slot,
+            fec_set_index,
+            data_shreds_count,
+            num_data_shreds,
+            entry_ordering,
+            entries,
+            trace,
+            first_shred_at,
+            late,
+        } = match result {
+            Ok(Some(result)) => result,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Deserialize worker failed: {:?}", e);
+                continue;
+            }
+        };
+        if let (Some(gap_timeout), None) = (entry_ordering, &reorder) {
+            reorder = Some(ReorderBuffer::new(gap_timeout));
+        }
+        let entries = match entries {
+            Ok(entries) if entries.is_empty() => {
+                metrics.fec_set_empty.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "No entries in slot {} FEC set {}",
+                    slot, fec_set_index
+                );
+                None
+            }
+            Ok(mut entries) => {
+                metrics.fec_set_success.fetch_add(1, Ordering::Relaxed);
+                if late {
+                    metrics
+                        .late_recovery_success
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                let unsigned = drop_unsigned_transactions(&mut entries);
+                if unsigned > 0 {
+                    metrics
+                        .unsigned_txs
+                        .fetch_add(unsigned as u64, Ordering::Relaxed);
+                    warn!(
+                        "Dropped {} unsigned txs in slot {} FEC set {}",
+                        unsigned, slot, fec_set_index
                     );
                 }
+                metrics
+                    .total_processed_data
+                    .fetch_add(data_shreds_count as u64, Ordering::Relaxed);
+                metrics
+                    .total_entries
+                    .fetch_add(entries.len() as u64, Ordering::Relaxed);
+                Some(EntriesWithMeta {
+                    entries,
+                    slot,
+                    trace,
+                    received_at: first_shred_at,
+                })
             }
             Err(e) => {
-                self.fec_set_failure += 1;
-                error!("Failed to deserialize entries for slot {} FEC set {}: {:?}", 
-                slot, fec_set_index, e);
+                metrics.fec_set_failure.fetch_add(1, Ordering::Relaxed);
+                match e {
+                    ShredError::TooShort(_) => &metrics.fec_set_too_short,
+                    _ => &metrics.fec_set_undecodable,
+                }
+                .fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "Failed to deserialize entries for slot {} FEC set {}: {:?}",
+                    slot, fec_set_index, e
+                );
+                None
             }
-        }
+        };
+        let ready = match reorder.as_mut() {
+            Some(reorder) => reorder.push(
+                slot,
+                fec_set_index,
+                num_data_shreds,
+                entries,
+                Instant::now(),
+            ),
+            None => entries.into_iter().collect(),
+        };
+        send_entries(&entry_tx, ready, &metrics).await;
     }
+    if let Some(mut reorder) = reorder {
+        send_entries(&entry_tx, reorder.flush_all(), &metrics).await;
+    }
+}
 
-    pub async fn collect(&mut self, raw_shred: Arc<Vec<u8>>) {
-        if raw_shred.len() < 0x58 {
-            return;
-        }
-        match layout::get_shred_id(&raw_shred) {
-            Some(shred_id) => {
-                if !self.uniqueness.insert(shred_id) {
-                    return;
+async fn send_entries(
+    entry_tx: &mpsc::Sender<EntriesWithMeta>,
+    ready: Vec<EntriesWithMeta>,
+    metrics: &DeserializeMetrics,
+) {
+    for entries_with_meta in ready {
+        let slot = entries_with_meta.slot;
+        let received_at = entries_with_meta.received_at;
+        let _span = entries_with_meta.trace.span("emit");
+        match entry_tx.send(entries_with_meta).await {
+            Ok(()) => {
+                if let Some(received_at) = received_at {
+                    metrics
+                        .reconstruction_latency
+                        .record(received_at.elapsed());
                 }
-                self.insert(shred_id.slot(), raw_shred.clone()).await;
             }
-            None => {
-                error!("Error getting shred id");
+            Err(e) => {
+                error!("Failed to send entries for slot {}: {:?}", slot, e);
             }
         }
     }
@@ -305,33 +1710,883 @@ impl ShredProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::arb::PoolsState;
-    use crate::entry_processor::ArbEntryProcessor;
+    use crate::arb::{ArbConfig, PoolsState};
+    use crate::entry_processor::{ArbEntryProcessor, SigWithMeta};
     use crate::pump::PumpCreateIx;
+    use crate::shred::{load_fixture, shred_entries, NON_SHRED_PACKET_SIZES};
+    use crate::sink::StdoutSink;
     use borsh::BorshDeserialize;
     use log::info;
+    use solana_entry::entry::Entry;
+    use solana_ledger::shred::ShredFlags;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::keypair_from_seed;
+    use solana_sdk::system_transaction;
     use tokio::sync::RwLock;
 
+    const FIXTURE_SIGNATURES: &str = "fixtures/signatures.txt";
+    const FIXTURE_SLOT: Slot = 1000;
+
+    /// fixture_batches are the entry batches the fixture is shredded from:
+    /// 200 transfers, the i-th one of `i + 1` lamports from the keypair
+    /// seeded with `[i; 32]`, each batch small enough for a single FEC set
+    fn fixture_batches() -> Vec<Vec<Entry>> {
+        let to = Pubkey::new_from_array([7; 32]);
+        let mut hash = Hash::default();
+        let mut batches = Vec::new();
+        for batch in 0..4u8 {
+            let mut entries = Vec::new();
+            for entry in 0..10u8 {
+                let transactions = (0..5u8)
+                    .map(|tx| {
+                        let i = batch * 50 + entry * 5 + tx;
+                        let from = keypair_from_seed(&[i; 32]).unwrap();
+                        system_transaction::transfer(
+                            &from,
+                            &to,
+                            i as u64 + 1,
+                            Hash::default(),
+                        )
+                    })
+                    .collect();
+                let entry = Entry::new(&hash, 1, transactions);
+                hash = entry.hash;
+                entries.push(entry);
+            }
+            batches.push(entries);
+        }
+        batches
+    }
+
+    /// capture_fixture shreds the fixture batches into merkle shreds, with
+    /// data shred 1 left out so that the first FEC set has to be recovered
+    fn capture_fixture() -> Vec<Vec<u8>> {
+        let batches = fixture_batches();
+        let (mut next_shred_index, mut next_code_index) = (0, 0);
+        let mut raw_shreds = Vec::new();
+        for (i, entries) in batches.iter().enumerate() {
+            let (data_shreds, coding_shreds) = shred_entries(
+                FIXTURE_SLOT,
+                entries,
+                i == batches.len() - 1,
+                next_shred_index,
+                next_code_index,
+                true,
+            );
+            next_shred_index = data_shreds.last().unwrap().index() + 1;
+            next_code_index = coding_shreds.last().unwrap().index() + 1;
+            raw_shreds.extend(
+                data_shreds
+                    .into_iter()
+                    .filter(|shred| shred.index() != 1)
+                    .chain(coding_shreds)
+                    .map(|shred| shred.into_payload()),
+            );
+        }
+        raw_shreds
+    }
+
+    fn fixture_needs_recovery(raw_shreds: &[Vec<u8>]) -> bool {
+        let mut data_counts = HashMap::<u32, u16>::new();
+        let mut expected_counts = HashMap::new();
+        for raw_shred in raw_shreds {
+            let fec_set_index = get_fec_set_index(raw_shred).unwrap();
+            if is_shred_data(raw_shred) {
+                *data_counts.entry(fec_set_index).or_default() += 1;
+            } else if let Ok(header) = get_coding_shred_header(raw_shred) {
+                expected_counts.insert(fec_set_index, header.num_data_shreds);
+            }
+        }
+        expected_counts.iter().any(|(fec_set_index, expected)| {
+            data_counts.get(fec_set_index).copied().unwrap_or(0) < *expected
+        })
+    }
+
+    #[test]
+    fn dump_entries_appends_per_slot() {
+        let dir = std::env::temp_dir()
+            .join(format!("shreds-entries-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entries = fixture_batches().swap_remove(0);
+        dump_entries(&dir, 7, 0, &[1, 2, 3], Some(&entries)).unwrap();
+        dump_entries(&dir, 7, 32, &[4, 5], None).unwrap();
+        dump_entries(&dir, 7, 64, &[6], Some(&[])).unwrap();
+
+        let bin = std::fs::read(dir.join("slot-7.bin")).unwrap();
+        let json = std::fs::read_to_string(dir.join("slot-7.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bin, vec![1, 2, 3, 4, 5, 6]);
+        let lines = json
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(lines[1]["fec_set_index"], 64);
+        assert_eq!(lines[1]["offset"], 5);
+        assert_eq!(lines[1]["len"], 1);
+    }
+
+    #[tokio::test]
+    async fn hanging_fec_set_snapshots_rotate() {
+        let dir = std::env::temp_dir()
+            .join(format!("shreds-dumps-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx)
+            .with_hanging_dumps(HangingDumps {
+                dir: dir.clone(),
+                max_files: 2,
+                interval: None,
+            });
+        // a data shred alone leaves its set hanging
+        let raw_shred = capture_fixture().swap_remove(0);
+        processor.collect_bytes(Bytes::from(raw_shred)).await;
+
+        let mut paths = Vec::new();
+        for _ in 0..3 {
+            let (path, count) =
+                processor.snapshot_hanging_fec_sets().unwrap();
+            assert_eq!(count, 1);
+            paths.push(path);
+            // the snapshots are named by the millisecond
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let kept = processor.hanging_dumps().list().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(kept, paths[1..]);
+    }
+
+    #[tokio::test]
+    async fn malformed_shreds_are_dropped() {
+        let raw_shred = capture_fixture().swap_remove(0);
+        let mut garbage = raw_shred.clone();
+        garbage[0x40] = 0x00; // unknown variant
+
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        for packet in [
+            vec![],
+            vec![0xff; 10],
+            raw_shred[..0x57].to_vec(),
+            vec![0x00; 0x58],
+            garbage,
+        ] {
+            processor.collect_bytes(Bytes::from(packet)).await;
+        }
+        assert_eq!(processor.malformed_shreds, 5);
+
+        for size in NON_SHRED_PACKET_SIZES {
+            processor.collect_bytes(Bytes::from(vec![0xff; size])).await;
+        }
+        assert_eq!(processor.non_shred_packets, 3);
+        assert_eq!(processor.malformed_shreds, 5);
+
+        // truncated past the header it is still collected, the payload is
+        // only rejected once the FEC set is deserialized
+        processor
+            .insert(FIXTURE_SLOT, Bytes::from(raw_shred[..0x4c].to_vec()))
+            .await;
+        assert_eq!(processor.malformed_shreds, 6);
+        processor
+            .collect_bytes(Bytes::from(raw_shred[..0x60].to_vec()))
+            .await;
+        assert_eq!(processor.malformed_shreds, 6);
+    }
+
+    #[tokio::test]
+    async fn slot_range_filters_before_insert() {
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx)
+            .with_slot_range(SlotRange {
+                from: Some(FIXTURE_SLOT + 1),
+                to: None,
+            });
+        for raw_shred in capture_fixture() {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        assert!(processor.fec_sets.is_empty());
+        assert!(processor.uniqueness.is_empty());
+
+        let range = SlotRange {
+            from: Some(10),
+            to: Some(20),
+        };
+        assert!(!range.contains(9));
+        assert!(range.contains(10));
+        assert!(range.contains(20));
+        assert!(!range.contains(21));
+        assert!(SlotRange::default().contains(0));
+    }
+
+    #[tokio::test]
+    async fn shred_version_filters_foreign_shreds() {
+        let raw_shreds = capture_fixture();
+        let version = get_shred_version(&raw_shreds[0]).unwrap();
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx)
+            .with_shred_version(Some(version.wrapping_add(1)));
+        for raw_shred in raw_shreds.iter().cloned() {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        assert!(processor.uniqueness.is_empty());
+        assert_eq!(processor.foreign_shreds, raw_shreds.len() as u64);
+
+        processor.shred_version = Some(version);
+        processor
+            .collect_bytes(Bytes::from(raw_shreds[0].clone()))
+            .await;
+        assert_eq!(processor.uniqueness.len(), 1);
+        assert_eq!(processor.foreign_shreds, raw_shreds.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn max_slot_lag_drops_old_slots() {
+        let entries = fixture_batches().swap_remove(0);
+        let data_shreds_of = |slot: Slot, is_last_in_slot: bool| {
+            let (data_shreds, _) =
+                shred_entries(slot, &entries, is_last_in_slot, 0, 0, true);
+            data_shreds
+                .into_iter()
+                .map(|shred| Bytes::from(shred.into_payload()))
+                .collect::<Vec<_>>()
+        };
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx)
+            .with_max_slot_lag(Some(10));
+
+        // an incomplete set, left pending
+        let raw_shreds = capture_fixture();
+        for raw_shred in raw_shreds.iter().take(3).cloned() {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        assert_eq!(processor.uniqueness.len(), 3);
+        // a stray shred far ahead does not raise the highest slot
+        let stray = data_shreds_of(FIXTURE_SLOT + 1000, false);
+        processor.collect_bytes(stray[0].clone()).await;
+        assert_eq!(processor.highest_slot, 0);
+        assert!(!processor.is_lagging(FIXTURE_SLOT));
+
+        // the end of a later slot evicts the slots past the lag
+        let ended = data_shreds_of(FIXTURE_SLOT + 20, true);
+        processor.collect_bytes(ended.last().unwrap().clone()).await;
+        assert_eq!(processor.highest_slot, FIXTURE_SLOT + 20);
+        assert!(processor.is_lagging(FIXTURE_SLOT));
+        assert!(!processor.is_lagging(FIXTURE_SLOT + 10));
+        assert!(processor
+            .fec_sets
+            .keys()
+            .all(|(slot, _)| *slot != FIXTURE_SLOT));
+        assert!(processor
+            .uniqueness
+            .iter()
+            .all(|shred_id| shred_id.slot() != FIXTURE_SLOT));
+
+        for raw_shred in raw_shreds.iter().cloned() {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        assert_eq!(processor.lagging_shreds, raw_shreds.len() as u64);
+
+        // without a max lag every slot is kept, however old
+        processor.max_slot_lag = None;
+        assert!(!processor.is_lagging(0));
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_slots_past_the_max_tracked() {
+        let entries = fixture_batches().swap_remove(0);
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        let first_slot = FIXTURE_SLOT;
+        let last_slot = first_slot + MAX_TRACKED_SLOTS as Slot;
+        for slot in first_slot..=last_slot {
+            // the first data shred alone leaves the set pending, the slot
+            // ends with a shred of its own
+            let (data_shreds, _) =
+                shred_entries(slot, &entries, true, 0, 0, true);
+            for shred in [data_shreds.first(), data_shreds.last()] {
+                let raw_shred = shred.unwrap().payload().clone();
+                processor.collect_bytes(Bytes::from(raw_shred)).await;
+            }
+        }
+
+        assert_eq!(processor.fec_set_indices.len(), MAX_TRACKED_SLOTS);
+        assert!(!processor.fec_set_indices.contains_key(&first_slot));
+        assert!(processor
+            .fec_sets
+            .keys()
+            .all(|(slot, _)| *slot > first_slot));
+        assert!(processor
+            .uniqueness
+            .iter()
+            .all(|shred_id| shred_id.slot() > first_slot));
+        assert!(!processor.ended_slots.contains(&first_slot));
+        assert!(processor.ended_slots.contains(&last_slot));
+    }
+
+    #[tokio::test]
+    async fn equivocating_shreds_keep_the_first_payload() {
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        // a data shred alone leaves its set pending
+        let raw_shred = Bytes::from(capture_fixture().swap_remove(0));
+        let index = get_shred_index(&raw_shred).unwrap();
+        let mut conflicting = raw_shred.to_vec();
+        *conflicting.last_mut().unwrap() ^= 0xff;
+        let conflicting = Bytes::from(conflicting);
+
+        processor.collect_bytes(raw_shred.clone()).await;
+        processor.collect_bytes(raw_shred.clone()).await;
+        assert_eq!(processor.equivocation_detected, 0);
+        processor.collect_bytes(conflicting.clone()).await;
+        assert_eq!(processor.equivocation_detected, 1);
+        processor.insert(FIXTURE_SLOT, conflicting).await;
+        assert_eq!(processor.equivocation_detected, 2);
+
+        let fec_set = &processor.fec_sets[&(FIXTURE_SLOT, 0)];
+        assert_eq!(fec_set.data_shreds[&index], raw_shred);
+        assert_eq!(processor.total_collected_data, 1);
+    }
+
+    #[tokio::test]
+    async fn unsigned_transactions_are_dropped() {
+        let mut entries = fixture_batches().swap_remove(0);
+        entries[0].transactions[0].signatures.clear();
+        let (data_shreds, coding_shreds) =
+            shred_entries(FIXTURE_SLOT, &entries, true, 0, 0, true);
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        for shred in data_shreds.into_iter().chain(coding_shreds) {
+            processor
+                .collect_bytes(Bytes::from(shred.into_payload()))
+                .await;
+        }
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let transactions = entries_with_meta
+            .entries
+            .iter()
+            .flat_map(|entry| entry.transactions.iter())
+            .collect::<Vec<_>>();
+        assert_eq!(transactions.len(), 49);
+        assert!(transactions.iter().all(|tx| !tx.signatures.is_empty()));
+        assert_eq!(
+            processor
+                .deserialize_metrics
+                .unsigned_txs
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_fec_sets_are_not_successes() {
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        // complete sets of a single data shred, the first with a zero entry
+        // count, the second shorter than the count prefix
+        for (fec_set_index, data) in
+            [(0, &0u64.to_le_bytes()[..]), (1, &[0u8; 4][..])]
+        {
+            let shred = Shred::new_from_data(
+                FIXTURE_SLOT,
+                fec_set_index,
+                1,
+                data,
+                ShredFlags::DATA_COMPLETE_SHRED,
+                0,
+                0,
+                fec_set_index,
+            );
+            processor
+                .collect_bytes(Bytes::from(shred.into_payload()))
+                .await;
+        }
+        let metrics = &processor.deserialize_metrics;
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while metrics.fec_set_empty.load(Ordering::Relaxed)
+                + metrics.fec_set_failure.load(Ordering::Relaxed)
+                < 2
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(10))
+                    .await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(metrics.fec_set_success.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.fec_set_empty.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.fec_set_too_short.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn complete_data_skips_recovery() {
+        let entries = fixture_batches().swap_remove(0);
+        let (data_shreds, coding_shreds) =
+            shred_entries(FIXTURE_SLOT, &entries, true, 0, 0, true);
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        // the data shreds out of order, the set is only known to be
+        // complete once they are all in, through the last one
+        for shred in data_shreds.into_iter().rev().chain(coding_shreds) {
+            processor
+                .collect_bytes(Bytes::from(shred.into_payload()))
+                .await;
+        }
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(entries_with_meta.entries, entries);
+        assert!(entries_with_meta.received_at.is_some());
+        let metrics = &processor.deserialize_metrics;
+        assert_eq!(metrics.fec_set_fast_path.load(Ordering::Relaxed), 1);
+        let phases = &metrics.phases;
+        assert_eq!(phases.recovery.count.load(Ordering::Relaxed), 0);
+        assert_eq!(phases.reassembly.count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn hands_back_the_sets_it_fails_to_recover() {
+        let (data_shreds, mut coding_shreds): (Vec<_>, Vec<_>) =
+            load_fixture()
+                .into_iter()
+                .filter(|raw_shred| {
+                    get_fec_set_index(raw_shred).ok() == Some(0)
+                })
+                .partition(|raw_shred| is_shred_data(raw_shred));
+        // a position past the coding shreds, it is not parsed and the set
+        // can not be recovered off of it
+        let mut bogus_shred = coding_shreds.remove(0);
+        bogus_shred[0x57..0x59].copy_from_slice(&u16::MAX.to_le_bytes());
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        for raw_shred in data_shreds.into_iter().chain([bogus_shred]) {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while processor.failed_fec_sets == 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(10))
+                    .await;
+                processor.apply_fec_set_outcomes().await;
+            }
+        })
+        .await
+        .unwrap();
+        let fec_set = &processor.fec_sets[&(FIXTURE_SLOT, 0)];
+        assert!(!fec_set.processed && !fec_set.retired);
+
+        // a late coding shred, the set is queued again and recovered
+        processor
+            .collect_bytes(Bytes::from(coding_shreds.swap_remove(0)))
+            .await;
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(entries_with_meta.entries, fixture_batches()[0]);
+    }
+
+    #[tokio::test]
+    async fn retries_the_incomplete_sets_once_the_slot_ended() {
+        let raw_shreds = load_fixture();
+        let mut fec_set_indices = raw_shreds
+            .iter()
+            .map(|raw_shred| get_fec_set_index(raw_shred).unwrap())
+            .collect::<Vec<_>>();
+        fec_set_indices.sort_unstable();
+        fec_set_indices.dedup();
+        assert_eq!(fec_set_indices.len(), 4);
+        let data_shreds_of = |fec_set_index: u32| {
+            let mut data_shreds = raw_shreds
+                .iter()
+                .filter(|raw_shred| {
+                    is_shred_data(raw_shred)
+                        && get_fec_set_index(raw_shred).unwrap()
+                            == fec_set_index
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            data_shreds
+                .sort_by_key(|raw_shred| get_shred_index(raw_shred).unwrap());
+            data_shreds
+        };
+        // no coding shreds nor batch ends, the sets before the last have
+        // nothing to tell how many data shreds they have
+        let mut middle_sets = [
+            data_shreds_of(fec_set_indices[1]),
+            data_shreds_of(fec_set_indices[2]),
+        ];
+        for raw_shred in middle_sets.iter_mut().flatten() {
+            raw_shred[0x55] &= !ShredFlags::DATA_COMPLETE_SHRED.bits();
+        }
+        let [first_set, mut second_set] = middle_sets;
+        let late_shred = second_set.pop().unwrap();
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        for raw_shred in first_set
+            .into_iter()
+            .chain(second_set)
+            .chain(data_shreds_of(fec_set_indices[3]))
+        {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        // the second set is only complete once its last shred is in, after
+        // the end of the slot
+        processor.collect_bytes(Bytes::from(late_shred)).await;
+
+        let batches = fixture_batches();
+        let mut decoded = Vec::new();
+        while decoded.len() < 3 {
+            let entries_with_meta = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                entry_rx.recv(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            decoded.push(entries_with_meta.entries);
+        }
+        assert!(batches[1..].iter().all(|batch| decoded.contains(batch)));
+        let metrics = &processor.deserialize_metrics;
+        assert_eq!(metrics.late_recovery_success.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn strips_the_nonce_of_the_repair_responses() {
+        let entries = fixture_batches().swap_remove(0);
+        let (data_shreds, _) =
+            shred_entries(FIXTURE_SLOT, &entries, true, 0, 0, true);
+        let num_data_shreds = data_shreds.len();
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        // the first shred off of turbine too, the same shred either way
+        let turbine = Bytes::from(data_shreds[0].payload().clone());
+        processor.collect_bytes(turbine).await;
+        for shred in data_shreds {
+            let mut packet = shred.into_payload();
+            packet.extend_from_slice(&7u32.to_le_bytes());
+            processor.collect_bytes(Bytes::from(packet)).await;
+        }
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(entries_with_meta.entries, entries);
+        assert_eq!(processor.repair_shreds, num_data_shreds as u64);
+        assert_eq!(processor.equivocation_detected, 0);
+    }
+
+    #[test]
+    fn latency_window_percentiles() {
+        let window = LatencyWindow::default();
+        assert_eq!(window.to_json()["p50"], serde_json::Value::Null);
+        for ms in 1..=100 {
+            window.record(Duration::from_millis(ms));
+        }
+        let json = window.to_json();
+        assert_eq!(json["count"], 100);
+        assert_eq!(json["p50"], 50.);
+        assert_eq!(json["p90"], 90.);
+        assert_eq!(json["p99"], 99.);
+        assert_eq!(json["max"], 100.);
+
+        // only the latest samples are kept
+        for _ in 0..LATENCY_WINDOW_SIZE {
+            window.record(Duration::from_millis(1));
+        }
+        assert_eq!(window.to_json()["count"], LATENCY_WINDOW_SIZE);
+        assert_eq!(window.to_json()["max"], 1.);
+    }
+
     #[tokio::test]
+    async fn no_recovery_drops_the_sets_missing_data() {
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor =
+            ShredProcessor::new(entry_tx, error_tx).with_recovery(false);
+        let mut lossy_sets = HashSet::new();
+        for raw_shred in capture_fixture() {
+            let fec_set_index = get_fec_set_index(&raw_shred).unwrap();
+            // the first data shred of every set but the first one is lost
+            if fec_set_index != 0
+                && is_shred_data(&raw_shred)
+                && get_shred_index(&raw_shred).unwrap() == fec_set_index
+            {
+                lossy_sets.insert(fec_set_index);
+                continue;
+            }
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+        assert!(!lossy_sets.is_empty());
+        assert_eq!(processor.unrecovered_fec_sets, lossy_sets.len() as u64);
+        assert!(lossy_sets.iter().all(|fec_set_index| {
+            let fec_set =
+                &processor.fec_sets[&(FIXTURE_SLOT, *fec_set_index)];
+            fec_set.processed && fec_set.coding_shreds.is_empty()
+        }));
+
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(!entries_with_meta.entries.is_empty());
+        let phases = &processor.deserialize_metrics.phases;
+        assert_eq!(phases.recovery.count.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn no_recovery_waits_for_the_data_shreds_after_the_coding() {
+        let entries = fixture_batches().swap_remove(0);
+        let (mut data_shreds, coding_shreds) =
+            shred_entries(FIXTURE_SLOT, &entries, false, 0, 0, true);
+        assert!(data_shreds.len() > 2);
+        let last_data_shreds = data_shreds.split_off(data_shreds.len() - 2);
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor =
+            ShredProcessor::new(entry_tx, error_tx).with_recovery(false);
+        // enough shreds to recover the set come in before its last data
+        // shreds do
+        for shred in data_shreds
+            .into_iter()
+            .chain(coding_shreds)
+            .chain(last_data_shreds)
+        {
+            processor
+                .collect_bytes(Bytes::from(shred.into_payload()))
+                .await;
+        }
+        assert_eq!(processor.unrecovered_fec_sets, 0);
+
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(entries_with_meta.entries, entries);
+        let phases = &processor.deserialize_metrics.phases;
+        assert_eq!(phases.recovery.count.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn shared_reed_solomon_cache_recovers_the_same_shreds() {
+        // data shreds, coding shreds and the expected data count per set
+        let mut fec_sets = HashMap::<
+            u32,
+            (HashMap<u32, Bytes>, HashMap<u32, Bytes>, Option<u16>),
+        >::new();
+        for raw_shred in capture_fixture() {
+            let fec_set_index = get_fec_set_index(&raw_shred).unwrap();
+            let (data_shreds, coding_shreds, num_expected_data) =
+                fec_sets.entry(fec_set_index).or_default();
+            let index = get_shred_index(&raw_shred).unwrap();
+            if is_shred_data(&raw_shred) {
+                // the first data shred of every set is lost too
+                if index != fec_set_index {
+                    data_shreds.insert(index, Bytes::from(raw_shred));
+                }
+            } else {
+                *num_expected_data = get_coding_shred_header(&raw_shred)
+                    .ok()
+                    .map(|header| header.num_data_shreds);
+                coding_shreds.insert(index, Bytes::from(raw_shred));
+            }
+        }
+        assert!(fec_sets.len() > 1);
+
+        let metrics = DeserializeMetrics::default();
+        let reed_solomon_cache = ReedSolomonCache::default();
+        for (
+            fec_set_index,
+            (data_shreds, coding_shreds, num_expected_data),
+        ) in fec_sets
+        {
+            let shared = reassemble(
+                FIXTURE_SLOT,
+                fec_set_index,
+                num_expected_data,
+                data_shreds.clone(),
+                coding_shreds.clone(),
+                &reed_solomon_cache,
+                &metrics,
+            );
+            let per_call = reassemble(
+                FIXTURE_SLOT,
+                fec_set_index,
+                num_expected_data,
+                data_shreds,
+                coding_shreds,
+                &ReedSolomonCache::default(),
+                &metrics,
+            );
+            assert_eq!(shared.len(), num_expected_data.unwrap() as usize);
+            assert_eq!(shared, per_call);
+        }
+    }
+
+    #[tokio::test]
+    async fn data_shred_at_the_last_index_does_not_overflow() {
+        // found by fuzz/fuzz_targets/collect.rs
+        let mut raw_shred = capture_fixture()
+            .into_iter()
+            .find(|raw_shred| is_shred_data(raw_shred))
+            .unwrap();
+        raw_shred[0x49..0x4d].copy_from_slice(&u32::MAX.to_le_bytes());
+        raw_shred[0x4f..0x53].copy_from_slice(&u32::MAX.to_le_bytes());
+        raw_shred[0x55] |= ShredFlags::DATA_COMPLETE_SHRED.bits();
+
+        let (entry_tx, _entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        processor.collect_bytes(Bytes::from(raw_shred)).await;
+        assert!(processor.fec_sets[&(FIXTURE_SLOT, u32::MAX)].processed);
+    }
+
+    #[tokio::test]
+    async fn data_shreds_through_the_last_complete_the_set() {
+        let entries = fixture_batches().swap_remove(0);
+        let (data_shreds, _coding_shreds) =
+            shred_entries(FIXTURE_SLOT, &entries, true, 0, 0, true);
+        assert!(data_shreds.len() > 1);
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        // no coding shred, the last data shred ends the set
+        let mut data_shreds = data_shreds.into_iter().rev();
+        let last = data_shreds.next().unwrap();
+        processor
+            .collect_bytes(Bytes::from(last.into_payload()))
+            .await;
+        assert!(!ShredProcessor::is_fec_set_complete(
+            &processor.fec_sets[&(FIXTURE_SLOT, 0)]
+        ));
+        for shred in data_shreds {
+            processor
+                .collect_bytes(Bytes::from(shred.into_payload()))
+                .await;
+        }
+        let entries_with_meta = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            entry_rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(entries_with_meta.entries, entries);
+        assert!(processor.fec_sets[&(FIXTURE_SLOT, 0)].processed);
+        assert_eq!(
+            processor
+                .deserialize_metrics
+                .fec_set_fast_path
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn fixture_matches_golden_signatures() {
+        let raw_shreds = load_fixture();
+        assert!(fixture_needs_recovery(&raw_shreds));
+
+        let mut expected = std::fs::read_to_string(FIXTURE_SIGNATURES)
+            .expect("Failed to read signatures fixture")
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let (entry_tx, mut entry_rx) = mpsc::channel(2000);
+        let (error_tx, _error_rx) = mpsc::channel(2000);
+        let mut processor = ShredProcessor::new(entry_tx, error_tx);
+        for raw_shred in raw_shreds {
+            processor.collect_bytes(Bytes::from(raw_shred)).await;
+        }
+
+        let mut signatures = Vec::new();
+        while signatures.len() < expected.len() {
+            let Ok(Some(entries_with_meta)) = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                entry_rx.recv(),
+            )
+            .await
+            else {
+                break;
+            };
+            assert_eq!(entries_with_meta.slot, FIXTURE_SLOT);
+            signatures.extend(
+                entries_with_meta
+                    .entries
+                    .iter()
+                    .flat_map(|entry| entry.transactions.iter())
+                    .filter_map(|tx| tx.signatures.first())
+                    .map(|sig| sig.to_string()),
+            );
+        }
+
+        signatures.sort();
+        expected.sort();
+        assert_eq!(signatures, expected);
+    }
+
+    #[tokio::test]
+    #[ignore = "loads the Raydium pools over RPC_URL"]
     async fn processor_works() {
         dotenv::dotenv().ok();
         // env_logger::Builder::default()
         //     .filter_level(log::LevelFilter::Info)
         //     .init();
 
-        let data = std::fs::read_to_string("packets.json")
-            .expect("Failed to read packets.json");
-        let raw_shreds: Vec<Vec<u8>> =
-            serde_json::from_str(&data).expect("Failed to parse JSON");
+        let raw_shreds = load_fixture();
 
         let (entry_tx, entry_rx) = mpsc::channel(2000);
         let (error_tx, error_rx) = mpsc::channel(2000);
-        let (sig_tx, mut sig_rx) = mpsc::channel(2000);
+        let (sig_tx, mut sig_rx) = mpsc::channel::<SigWithMeta>(2000);
 
         tokio::spawn(async move {
             while let Some(sig) = sig_rx.recv().await {
                 let timestamp = chrono::Utc::now().timestamp_millis();
-                log::debug!("shreds: {} {}", timestamp, sig);
+                log::debug!("shreds: {} {}", timestamp, sig.sig);
             }
         });
 
@@ -348,6 +2603,8 @@ mod tests {
                 error_rx,
                 pools_state.clone(),
                 sig_tx,
+                ArbConfig::default(),
+                Arc::new(StdoutSink),
             );
             entry_processor.receive_entries().await;
         });
@@ -376,4 +2633,56 @@ mod tests {
         let create = PumpCreateIx::try_from_slice(&bytes).unwrap();
         println!("{:?}", create);
     }
+
+    #[test]
+    fn rates_are_deltas_over_the_window() {
+        let mut window = RateWindow::default();
+        let start = Instant::now();
+        let totals = RateTotals {
+            shreds: 1000,
+            entries: 50,
+            fec_sets: 10,
+        };
+        assert_eq!(window.update(start, totals), None);
+
+        let later = start + Duration::from_secs(2);
+        let rates = window
+            .update(
+                later,
+                RateTotals {
+                    shreds: 3000,
+                    entries: 150,
+                    fec_sets: 30,
+                },
+            )
+            .unwrap();
+        assert_eq!(rates.window_secs, 2.);
+        assert_eq!(rates.shreds_per_sec, 1000.);
+        assert_eq!(rates.entries_per_sec, 50.);
+        assert_eq!(rates.fec_sets_per_sec, 10.);
+
+        // counters reset under the window do not go negative
+        let rates = window
+            .update(later + Duration::from_secs(1), RateTotals::default())
+            .unwrap();
+        assert_eq!(rates.shreds_per_sec, 0.);
+        window.clear();
+        assert_eq!(window.update(later, totals), None);
+    }
+
+    #[test]
+    fn phase_timings_average_and_max() {
+        let timing = PhaseTiming::default();
+        assert_eq!(timing.to_json()["avg_ms"], serde_json::Value::Null);
+        timing.record(Duration::from_millis(1));
+        timing.record(Duration::from_millis(3));
+        assert_eq!(timing.time(|| 7), 7);
+        let stats = timing.to_json();
+        assert_eq!(stats["count"], 3);
+        assert_eq!(stats["max_ms"], 3.);
+        assert!(stats["avg_ms"].as_f64().unwrap() >= 4. / 3.);
+
+        timing.reset();
+        assert_eq!(timing.to_json()["count"], 0);
+    }
 }
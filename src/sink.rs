@@ -0,0 +1,625 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use log::{debug, error, info};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+pub const PUMP_BUY_TOPIC: &str = "pump-buy";
+pub const ARB_TRADE_TOPIC: &str = "arb-trade";
+pub const GRADUATE_TOPIC: &str = "graduate";
+pub const NEW_RAYDIUM_POOL_TOPIC: &str = "new-raydium-pool";
+pub const SANDWICH_TOPIC: &str = "sandwich";
+pub const CIRCUIT_BREAKER_TOPIC: &str = "circuit-breaker";
+pub const SLOT_VOLUME_TOPIC: &str = "slot-volume";
+pub const SIG_TOPIC: &str = "sig";
+
+/// EventSink is where the processors deliver the events they detect, every
+/// event goes out under a topic (`pump-buy`, `arb-trade`, `graduate`,
+/// `new-raydium-pool`, `sandwich`, `circuit-breaker`, `slot-volume`, and
+/// `sig` for `--sigs-out`)
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()>;
+}
+
+pub type Sink = Arc<dyn EventSink>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkKind {
+    Stdout,
+    File,
+    Webhook,
+    #[cfg(feature = "kafka")]
+    Kafka,
+    #[cfg(feature = "nats")]
+    Nats,
+}
+
+/// emit serializes the event and sends it to the sink, failures are logged
+/// rather than returned so that delivery never stops the processing
+pub async fn emit<T: Serialize>(
+    sink: &dyn EventSink,
+    topic: &str,
+    event: &T,
+) {
+    let value = match serde_json::to_value(event) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("Failed to serialize {} event: {:?}", topic, e);
+            return;
+        }
+    };
+    if let Err(e) =
+        crate::otel::deliver(topic, sink.emit(topic, &value)).await
+    {
+        error!("Failed to emit {} event: {:?}", topic, e);
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    topic: &'a str,
+    event: &'a serde_json::Value,
+}
+
+/// StdoutSink prints the events as JSON lines
+pub struct StdoutSink;
+
+#[async_trait]
+impl EventSink for StdoutSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string(&Line { topic, event })?);
+        Ok(())
+    }
+}
+
+/// VecSink keeps the events in memory, for asserting on what the
+/// processors emit without a receiver
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+pub struct VecSink {
+    events: std::sync::Mutex<Vec<(String, serde_json::Value)>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl VecSink {
+    /// events are the topics and events emitted so far, in order
+    pub fn events(&self) -> Vec<(String, serde_json::Value)> {
+        self.events.lock().expect("events").clone()
+    }
+
+    /// events_of are the events of the topic, deserialized
+    pub fn events_of<T: serde::de::DeserializeOwned>(
+        &self,
+        topic: &str,
+    ) -> Vec<T> {
+        self.events()
+            .into_iter()
+            .filter(|(event_topic, _)| event_topic == topic)
+            .map(|(_, event)| serde_json::from_value(event).expect("event"))
+            .collect()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+#[async_trait]
+impl EventSink for VecSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.events
+            .lock()
+            .expect("events")
+            .push((topic.to_string(), event.clone()));
+        Ok(())
+    }
+}
+
+/// FileSink appends the events as JSON lines to a file
+pub struct FileSink {
+    file: Mutex<tokio::fs::File>,
+}
+
+impl FileSink {
+    pub async fn new(path: PathBuf) -> anyhow::Result<Self> {
+        info!("Appending events to: {}", path.display());
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(FileSink {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for FileSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(&Line { topic, event })?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// The event log is rotated once it grows past this, in bytes
+pub const DEFAULT_EVENT_LOG_MAX_BYTES: u64 = 256 * 1024 * 1024;
+/// Rotated event logs kept next to the live one, as `<path>.1` (the newest)
+/// to `<path>.10`
+pub const EVENT_LOG_MAX_FILES: usize = 10;
+pub const EVENT_LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    /// When the event was emitted, unix millis
+    timestamp: i64,
+    topic: &'a str,
+    event: &'a serde_json::Value,
+}
+
+/// EventLog is the append-only JSON lines file behind EventLogSink, the
+/// writes are buffered and flushed every EVENT_LOG_FLUSH_INTERVAL
+struct EventLog {
+    path: PathBuf,
+    max_bytes: u64,
+    writer: BufWriter<tokio::fs::File>,
+    written: u64,
+}
+
+/// rotated_path is the path of the `n`-th rotated log
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+impl EventLog {
+    async fn open(path: PathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let written = file.metadata().await?.len();
+        Ok(EventLog {
+            path,
+            max_bytes,
+            writer: BufWriter::new(file),
+            written,
+        })
+    }
+
+    async fn append(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        if self.written > 0
+            && self.written + line.len() as u64 > self.max_bytes
+        {
+            self.rotate().await?;
+        }
+        self.writer.write_all(line).await?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// rotate moves the live log to `<path>.1`, shifting the older ones up
+    /// and dropping the one past EVENT_LOG_MAX_FILES
+    async fn rotate(&mut self) -> anyhow::Result<()> {
+        self.writer.flush().await?;
+        for n in (1..EVENT_LOG_MAX_FILES).rev() {
+            match tokio::fs::rename(
+                rotated_path(&self.path, n),
+                rotated_path(&self.path, n + 1),
+            )
+            .await
+            {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e.into())
+                }
+                _ => {}
+            }
+        }
+        tokio::fs::rename(&self.path, rotated_path(&self.path, 1)).await?;
+        *self = EventLog::open(self.path.clone(), self.max_bytes).await?;
+        info!("Rotated the event log {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// EventLogSink appends every event to an event log before handing it to
+/// the sink it wraps, so that what was detected can be audited and replayed
+/// whatever the sink did with it
+pub struct EventLogSink {
+    inner: Sink,
+    log: Arc<Mutex<EventLog>>,
+}
+
+impl EventLogSink {
+    /// new has to be called from within a tokio runtime, it spawns the
+    /// periodic flush which stops once the sink is dropped
+    pub async fn new(
+        inner: Sink,
+        path: PathBuf,
+        max_bytes: u64,
+    ) -> anyhow::Result<Self> {
+        info!("Logging events to: {}", path.display());
+        let log =
+            Arc::new(Mutex::new(EventLog::open(path, max_bytes).await?));
+        tokio::spawn(flush_periodically(Arc::downgrade(&log)));
+        Ok(EventLogSink { inner, log })
+    }
+
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.log.lock().await.writer.flush().await?;
+        Ok(())
+    }
+}
+
+async fn flush_periodically(log: Weak<Mutex<EventLog>>) {
+    let mut interval = tokio::time::interval(EVENT_LOG_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(log) = log.upgrade() else {
+            return;
+        };
+        if let Err(e) = log.lock().await.writer.flush().await {
+            error!("Failed to flush the event log: {:?}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for EventLogSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(&LogLine {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            topic,
+            event,
+        })?;
+        line.push(b'\n');
+        // a failing log should not hold back the delivery
+        if let Err(e) = self.log.lock().await.append(&line).await {
+            error!("Failed to log {} event: {:?}", topic, e);
+        }
+        self.inner.emit(topic, event).await
+    }
+}
+
+/// Header carrying the `idempotency_key` of the event
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// idempotency_key is the topic followed by what identifies the event (the
+/// signature of its tx, and the pools for trades), so the same event gets
+/// the same key when emitted twice, be it off of duplicate shreds or after
+/// a restart, and receivers can dedupe on it
+pub fn idempotency_key(topic: &str, parts: &[&str]) -> String {
+    std::iter::once(topic)
+        .chain(parts.iter().copied())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Header carrying the HMAC-SHA256 of the body, as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+/// WebhookConfig is how the receiver tells our events from anyone else's,
+/// `token` goes out as `Authorization: Bearer <token>` next to the extra
+/// `headers`, and with `hmac_secret` every body is signed; `ca_cert` is a
+/// PEM root to trust on top of the system ones
+#[derive(Debug, Clone, Default)]
+pub struct WebhookConfig {
+    pub token: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub hmac_secret: Option<String>,
+    pub ca_cert: Option<PathBuf>,
+    pub accept_invalid_certs: bool,
+}
+
+impl WebhookConfig {
+    /// client is built once with the headers and TLS options, it is used
+    /// for the health check as well as for the events
+    pub fn client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        if let Some(token) = &self.token {
+            let mut value =
+                HeaderValue::from_str(&format!("Bearer {}", token))?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        if let Some(path) = &self.ca_cert {
+            let pem = std::fs::read(path)?;
+            builder = builder
+                .add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// sign_body is the hex HMAC-SHA256 of the body under the secret
+pub fn sign_body(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac takes keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// WebhookSink posts each event to `{post_url}/v2/{topic}`
+pub struct WebhookSink {
+    post_url: String,
+    client: reqwest::Client,
+    hmac_secret: Option<String>,
+}
+
+impl WebhookSink {
+    pub fn new(
+        post_url: String,
+        config: &WebhookConfig,
+    ) -> anyhow::Result<Self> {
+        Url::parse(&post_url)?;
+        Ok(WebhookSink {
+            post_url,
+            client: config.client()?,
+            hmac_secret: config.hmac_secret.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/v2/{}", self.post_url, topic);
+        // signed as sent, the receiver verifies the raw body
+        let body = serde_json::to_vec(event)?;
+        let mut request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(key) =
+            event.get("idempotency_key").and_then(|key| key.as_str())
+        {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        if let Some(secret) = &self.hmac_secret {
+            request = request.header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", sign_body(secret, &body)),
+            );
+        }
+        let resp = request.body(body).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("{} responded with {}", url, resp.status());
+        }
+        debug!("Webhook sent: {}", url);
+        Ok(())
+    }
+}
+
+/// KafkaSink produces the events onto a Kafka topic of the same name
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    producer: rdkafka::producer::FutureProducer,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    pub fn new(brokers: &str) -> anyhow::Result<Self> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+        Ok(KafkaSink { producer })
+    }
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventSink for KafkaSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(event)?;
+        self.producer
+            .send(
+                rdkafka::producer::FutureRecord::<(), _>::to(topic)
+                    .payload(&payload),
+                std::time::Duration::from_secs(0),
+            )
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
+
+/// NatsSink publishes the events on the subject of the same name
+#[cfg(feature = "nats")]
+pub struct NatsSink {
+    client: async_nats::Client,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    pub async fn new(url: &str) -> anyhow::Result<Self> {
+        Ok(NatsSink {
+            client: async_nats::connect(url).await?,
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventSink for NatsSink {
+    async fn emit(
+        &self,
+        topic: &str,
+        event: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.client
+            .publish(topic.to_string(), serde_json::to_vec(event)?.into())
+            .await?;
+        Ok(())
+    }
+}
+
+/// connect builds the sink of the given kind, `target` is the post url for
+/// webhooks, the path for files and the broker/server url for queues
+pub async fn connect(
+    kind: SinkKind,
+    target: String,
+    webhook: &WebhookConfig,
+) -> anyhow::Result<Sink> {
+    Ok(match kind {
+        SinkKind::Stdout => Arc::new(StdoutSink),
+        SinkKind::File => {
+            Arc::new(FileSink::new(PathBuf::from(target)).await?)
+        }
+        SinkKind::Webhook => Arc::new(WebhookSink::new(target, webhook)?),
+        #[cfg(feature = "kafka")]
+        SinkKind::Kafka => Arc::new(KafkaSink::new(&target)?),
+        #[cfg(feature = "nats")]
+        SinkKind::Nats => Arc::new(NatsSink::new(&target).await?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_sink_appends_json_lines() {
+        let path = std::env::temp_dir()
+            .join(format!("shreds-sink-{}.jsonl", std::process::id()));
+        let sink = FileSink::new(path.clone()).await.unwrap();
+        emit(&sink, PUMP_BUY_TOPIC, &serde_json::json!({"sig": "a"})).await;
+        emit(&sink, ARB_TRADE_TOPIC, &serde_json::json!({"sig": "b"})).await;
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"topic":"pump-buy","event":{"sig":"a"}}"#,
+                r#"{"topic":"arb-trade","event":{"sig":"b"}}"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_log_rotates() {
+        let dir = std::env::temp_dir()
+            .join(format!("shreds-event-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        let sink = EventLogSink::new(Arc::new(StdoutSink), path.clone(), 64)
+            .await
+            .unwrap();
+        for sig in ["a", "b", "c"] {
+            emit(&sink, PUMP_BUY_TOPIC, &serde_json::json!({"sig": sig}))
+                .await;
+        }
+        sink.flush().await.unwrap();
+
+        let read_lines = |path: PathBuf| {
+            std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    serde_json::from_str::<serde_json::Value>(line).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        // every line is past the max, so each ends up in a file of its own
+        let live = read_lines(path.clone());
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0]["topic"], "pump-buy");
+        assert_eq!(live[0]["event"]["sig"], "c");
+        assert!(live[0]["timestamp"].as_i64().unwrap() > 0);
+        assert_eq!(
+            read_lines(rotated_path(&path, 1))[0]["event"]["sig"],
+            "b"
+        );
+        assert_eq!(
+            read_lines(rotated_path(&path, 2))[0]["event"]["sig"],
+            "a"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        assert_eq!(
+            idempotency_key(ARB_TRADE_TOPIC, &["sig", "buy", "sell"]),
+            "arb-trade:sig:buy:sell"
+        );
+        assert_eq!(idempotency_key(GRADUATE_TOPIC, &["sig"]), "graduate:sig");
+    }
+
+    #[test]
+    fn test_sign_body() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_body("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_webhook_client_rejects_bad_headers() {
+        let config = WebhookConfig {
+            token: Some("secret".to_string()),
+            headers: vec![("x-source".to_string(), "shreds".to_string())],
+            ..Default::default()
+        };
+        assert!(config.client().is_ok());
+        let config = WebhookConfig {
+            headers: vec![("bad header".to_string(), "x".to_string())],
+            ..Default::default()
+        };
+        assert!(config.client().is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use clap::ValueEnum;
+use log::{error, info};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+use crate::udp::{self, UdpMetrics};
+
+/// ShredSource is what the service reads the shreds off of, a packet at a
+/// time; None once the source is exhausted, the receive loop of the source
+/// stops then
+#[async_trait]
+pub trait ShredSource: Send {
+    async fn next_packet(&mut self) -> Option<Bytes>;
+}
+
+/// OpenSources opens a fresh set of sources, each read by a receive loop of
+/// its own; it is called again whenever the watchdog restarts the receive
+/// task, so that a source reconnects rather than reads a dead connection,
+/// the finite sources which were read to the end are not opened again
+pub type OpenSources =
+    Arc<dyn Fn() -> Vec<Box<dyn ShredSource>> + Send + Sync>;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// the shreds sent to the bind addresses, by the validator, a
+    /// shredstream proxy or a `--forward` of another instance
+    #[default]
+    Udp,
+    /// a capture of `save`, to test against the same shreds over and over
+    Replay,
+    /// the shreds of a jito shredstream proxy over gRPC, needs the
+    /// `shredstream` feature
+    Shredstream,
+}
+
+#[derive(Debug, Clone)]
+pub enum SourceConfig {
+    Udp {
+        bind_address: String,
+        rcvbuf_bytes: usize,
+        packet_size: usize,
+    },
+    Replay {
+        path: PathBuf,
+        interval: Duration,
+    },
+    Shredstream {
+        url: String,
+    },
+}
+
+/// open binds the sockets or loads the capture once, the sources opened
+/// off of it later share them
+pub fn open(
+    config: SourceConfig,
+    udp_metrics: Arc<UdpMetrics>,
+) -> anyhow::Result<OpenSources> {
+    Ok(match config {
+        SourceConfig::Udp {
+            bind_address,
+            rcvbuf_bytes,
+            packet_size,
+        } => {
+            info!("Starting listener on {}", bind_address);
+            let sockets = udp::bind_all(&bind_address, rcvbuf_bytes)?
+                .into_iter()
+                .map(Arc::new)
+                .collect::<Vec<_>>();
+            for socket in sockets.iter() {
+                udp_metrics.add_local_port(socket.local_addr()?.port());
+            }
+            Arc::new(move || {
+                sockets
+                    .iter()
+                    .map(|socket| {
+                        Box::new(UdpSource::new(
+                            socket.clone(),
+                            packet_size,
+                            udp_metrics.clone(),
+                        )) as Box<dyn ShredSource>
+                    })
+                    .collect()
+            })
+        }
+        SourceConfig::Replay { path, interval } => {
+            let packets = load_capture(&path)?;
+            info!(
+                "Replaying {} packets from {}",
+                packets.len(),
+                path.display()
+            );
+            // set once the capture was played to the end, a restart of the
+            // receive task does not play it again
+            let exhausted = Arc::new(AtomicBool::new(false));
+            Arc::new(move || {
+                if exhausted.load(Ordering::Relaxed) {
+                    info!("Replay of {} already ended", path.display());
+                    return Vec::new();
+                }
+                vec![Box::new(
+                    ReplaySource::new(packets.clone(), interval)
+                        .with_exhausted(exhausted.clone()),
+                ) as Box<dyn ShredSource>]
+            })
+        }
+        #[cfg(feature = "shredstream")]
+        SourceConfig::Shredstream { url } => {
+            info!("Starting shredstream source on {}", url);
+            Arc::new(move || {
+                vec![Box::new(ShredstreamSource::new(url.clone()))
+                    as Box<dyn ShredSource>]
+            })
+        }
+        #[cfg(not(feature = "shredstream"))]
+        SourceConfig::Shredstream { .. } => anyhow::bail!(
+            "the shredstream source needs the `shredstream` feature"
+        ),
+    })
+}
+
+/// UdpSource reads the datagrams of a socket, up to `packet_size` bytes of
+/// them, the receive errors are logged and counted in `udp_metrics` and
+/// never end the source
+pub struct UdpSource {
+    socket: Arc<UdpSocket>,
+    buf: Vec<u8>,
+    packet_size: usize,
+    udp_metrics: Arc<UdpMetrics>,
+}
+
+impl UdpSource {
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        packet_size: usize,
+        udp_metrics: Arc<UdpMetrics>,
+    ) -> Self {
+        UdpSource {
+            socket,
+            buf: udp::receive_buffer(packet_size),
+            packet_size,
+            udp_metrics,
+        }
+    }
+}
+
+#[async_trait]
+impl ShredSource for UdpSource {
+    async fn next_packet(&mut self) -> Option<Bytes> {
+        loop {
+            match self.socket.recv_from(&mut self.buf).await {
+                Ok((received, _)) => {
+                    let received = self
+                        .udp_metrics
+                        .check_truncation(received, self.packet_size);
+                    return Some(Bytes::copy_from_slice(
+                        &self.buf[..received],
+                    ));
+                }
+                Err(e) => {
+                    self.udp_metrics.record_recv_error();
+                    error!("Error receiving packet: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// load_capture reads a capture of `save` (a packets.json-style array of
+/// packets)
+pub fn load_capture(path: &Path) -> anyhow::Result<Vec<Bytes>> {
+    let data = std::fs::read_to_string(path)?;
+    let packets: Vec<Vec<u8>> = serde_json::from_str(&data)?;
+    Ok(packets.into_iter().map(Bytes::from).collect())
+}
+
+/// ReplaySource plays the packets back in order, `interval` apart (as fast
+/// as they are read with a zero interval), and ends with the last one
+pub struct ReplaySource {
+    packets: std::vec::IntoIter<Bytes>,
+    interval: Duration,
+    // set once the last packet was played
+    exhausted: Option<Arc<AtomicBool>>,
+}
+
+impl ReplaySource {
+    pub fn new(packets: Vec<Bytes>, interval: Duration) -> Self {
+        ReplaySource {
+            packets: packets.into_iter(),
+            interval,
+            exhausted: None,
+        }
+    }
+
+    /// with_exhausted sets `exhausted` once the replay ended
+    pub fn with_exhausted(mut self, exhausted: Arc<AtomicBool>) -> Self {
+        self.exhausted = Some(exhausted);
+        self
+    }
+}
+
+#[async_trait]
+impl ShredSource for ReplaySource {
+    async fn next_packet(&mut self) -> Option<Bytes> {
+        let Some(packet) = self.packets.next() else {
+            if let Some(exhausted) = &self.exhausted {
+                exhausted.store(true, Ordering::Relaxed);
+            }
+            return None;
+        };
+        if !self.interval.is_zero() {
+            tokio::time::sleep(self.interval).await;
+        }
+        Some(packet)
+    }
+}
+
+/// ShredstreamSource is the stub of the gRPC shredstream source, it is not
+/// implemented yet and ends right away, without reading from `url`
+#[cfg(feature = "shredstream")]
+pub struct ShredstreamSource {
+    url: String,
+}
+
+#[cfg(feature = "shredstream")]
+impl ShredstreamSource {
+    pub fn new(url: String) -> Self {
+        ShredstreamSource { url }
+    }
+}
+
+#[cfg(feature = "shredstream")]
+#[async_trait]
+impl ShredSource for ShredstreamSource {
+    async fn next_packet(&mut self) -> Option<Bytes> {
+        error!("The shredstream source ({}) is not implemented", self.url);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_the_capture_then_ends() {
+        let path = std::env::temp_dir()
+            .join(format!("shreds-replay-{}.json", std::process::id()));
+        std::fs::write(&path, "[[1,2,3],[4],[]]").unwrap();
+        let open_sources = open(
+            SourceConfig::Replay {
+                path: path.clone(),
+                interval: Duration::ZERO,
+            },
+            Arc::new(UdpMetrics::default()),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // an opening before the replay ended starts over from the first
+        // packet
+        let mut sources = open_sources();
+        assert_eq!(open_sources().len(), 1);
+        assert_eq!(sources.len(), 1);
+        let source = &mut sources[0];
+        let mut packets = Vec::new();
+        while let Some(packet) = source.next_packet().await {
+            packets.push(packet);
+        }
+        assert_eq!(
+            packets,
+            vec![
+                Bytes::from_static(&[1, 2, 3]),
+                Bytes::from_static(&[4]),
+                Bytes::new(),
+            ]
+        );
+        // not played again once it ended
+        assert!(open_sources().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_the_datagrams_of_the_socket() {
+        let udp_metrics = Arc::new(UdpMetrics::default());
+        let socket = Arc::new(udp::bind("127.0.0.1:0", 64 * 1024).unwrap());
+        let mut source =
+            UdpSource::new(socket.clone(), 4, udp_metrics.clone());
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        sender.send_to(&[1, 2], addr).await.unwrap();
+        sender.send_to(&[1, 2, 3, 4], addr).await.unwrap();
+        sender.send_to(&[1, 2, 3, 4, 5, 6], addr).await.unwrap();
+
+        assert_eq!(
+            source.next_packet().await,
+            Some(Bytes::from_static(&[1, 2]))
+        );
+        // exactly the packet size, not truncated
+        assert_eq!(
+            source.next_packet().await,
+            Some(Bytes::from_static(&[1, 2, 3, 4]))
+        );
+        assert_eq!(udp_metrics.full_datagrams(), 0);
+        // cut off at the packet size
+        assert_eq!(
+            source.next_packet().await,
+            Some(Bytes::from_static(&[1, 2, 3, 4]))
+        );
+        assert_eq!(udp_metrics.full_datagrams(), 1);
+    }
+}
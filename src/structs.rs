@@ -28,6 +28,19 @@ pub enum ShredVariant {
     }, // 0b10??_????
 }
 
+impl ShredVariant {
+    pub fn is_merkle(&self) -> bool {
+        matches!(
+            self,
+            ShredVariant::MerkleCode { .. } | ShredVariant::MerkleData { .. }
+        )
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, ShredVariant::LegacyCode | ShredVariant::LegacyData)
+    }
+}
+
 impl From<ShredVariant> for ShredType {
     #[inline]
     fn from(shred_variant: ShredVariant) -> Self {
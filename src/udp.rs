@@ -0,0 +1,245 @@
+use log::{info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+/// Matches the `net.core.rmem_max` jito recommends for shredstream
+pub const DEFAULT_RCVBUF_BYTES: usize = 128 * 1024 * 1024;
+
+/// bind binds the UDP socket with a `rcvbuf_bytes` receive buffer, so that
+/// bursts of shreds are not dropped before they are read; the kernel caps
+/// the buffer at `net.core.rmem_max`, in which case a warning is logged
+pub fn bind(addr: &str, rcvbuf_bytes: usize) -> std::io::Result<UdpSocket> {
+    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid bind address: {}", addr),
+        )
+    })?;
+    let socket = Socket::new(
+        Domain::for_address(addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    // so that the IPv4 and IPv6 wildcards can be bound on the same port
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_recv_buffer_size(rcvbuf_bytes)?;
+    let granted = socket.recv_buffer_size()?;
+    // linux reports double the size it was set to, for bookkeeping overhead
+    let usable = if cfg!(target_os = "linux") {
+        granted / 2
+    } else {
+        granted
+    };
+    if usable < rcvbuf_bytes {
+        warn!(
+            "Receive buffer clamped to {} bytes (requested {}), raise net.core.rmem_max",
+            usable, rcvbuf_bytes
+        );
+    } else {
+        info!("Receive buffer: {} bytes", granted);
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// receive_buffer is the buffer the datagrams of up to `packet_size` bytes
+/// are read into, a byte larger to catch the longer ones
+pub fn receive_buffer(packet_size: usize) -> Vec<u8> {
+    vec![0u8; packet_size + 1]
+}
+
+/// bind_all binds a socket for each of the comma separated addresses, IPv4
+/// and IPv6 alike, failing on the first address that cannot be bound
+pub fn bind_all(
+    addrs: &str,
+    rcvbuf_bytes: usize,
+) -> std::io::Result<Vec<UdpSocket>> {
+    addrs
+        .split(',')
+        .map(str::trim)
+        .map(|addr| {
+            bind(addr, rcvbuf_bytes).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Failed to bind {}: {}", addr, e),
+                )
+            })
+        })
+        .collect()
+}
+
+/// UdpMetrics counts the packets that never make it to the shred processor,
+/// the receive errors are counted by the receive loops and the kernel drops
+/// are read from the socket stats of the bound port
+#[derive(Debug, Default)]
+pub struct UdpMetrics {
+    recv_errors: AtomicU64,
+    // of the bound sockets, the kernel drops are read off of these
+    local_ports: Mutex<Vec<u16>>,
+    // unix millis, 0 until the first packet
+    last_packet_at: AtomicU64,
+    full_datagrams: AtomicU64,
+}
+
+impl UdpMetrics {
+    pub fn record_packet(&self) {
+        self.last_packet_at.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    /// seconds_since_last_packet is None until a packet is received
+    pub fn seconds_since_last_packet(&self) -> Option<u64> {
+        match self.last_packet_at.load(Ordering::Relaxed) {
+            0 => None,
+            at => Some(unix_millis().saturating_sub(at) / 1000),
+        }
+    }
+
+    /// check_truncation counts the datagrams longer than `packet_size`, read
+    /// into a buffer a byte larger (see receive_buffer) so that a packet of
+    /// exactly `packet_size` is told apart from a truncated one, and returns
+    /// how much of the datagram to keep
+    pub fn check_truncation(
+        &self,
+        received: usize,
+        packet_size: usize,
+    ) -> usize {
+        if received <= packet_size {
+            return received;
+        }
+        let count = self.full_datagrams.fetch_add(1, Ordering::Relaxed) + 1;
+        if count == 1 || count % 10_000 == 0 {
+            warn!(
+                "Datagram longer than the {} byte packet size, truncated ({} so far), raise --packet-size",
+                packet_size, count
+            );
+        }
+        packet_size
+    }
+
+    pub fn full_datagrams(&self) -> u64 {
+        self.full_datagrams.load(Ordering::Relaxed)
+    }
+
+    pub fn record_recv_error(&self) {
+        self.recv_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_local_port(&self, port: u16) {
+        let mut local_ports = self.local_ports.lock().expect("local ports");
+        if !local_ports.contains(&port) {
+            local_ports.push(port);
+        }
+    }
+
+    pub fn reset(&self) {
+        self.recv_errors.store(0, Ordering::Relaxed);
+    }
+
+    pub fn recv_errors(&self) -> u64 {
+        self.recv_errors.load(Ordering::Relaxed)
+    }
+
+    /// kernel_drops is best-effort, None off Linux or before a port is set
+    pub fn kernel_drops(&self) -> Option<u64> {
+        self.local_ports
+            .lock()
+            .expect("local ports")
+            .iter()
+            .filter_map(|&port| read_kernel_drops(port))
+            .reduce(|a, b| a + b)
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// read_kernel_drops sums the `drops` column of /proc/net/udp{,6} for the
+/// sockets bound to `port`, that is the packets the kernel discarded because
+/// the receive buffer was full
+#[cfg(target_os = "linux")]
+pub fn read_kernel_drops(port: u16) -> Option<u64> {
+    let mut found = false;
+    let mut drops = 0;
+    for path in ["/proc/net/udp", "/proc/net/udp6"] {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        if let Some(socket_drops) = parse_kernel_drops(&contents, port) {
+            found = true;
+            drops += socket_drops;
+        }
+    }
+    found.then_some(drops)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_kernel_drops(_port: u16) -> Option<u64> {
+    None
+}
+
+fn parse_kernel_drops(contents: &str, port: u16) -> Option<u64> {
+    let port = format!("{:04X}", port);
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+            if local_port != port {
+                return None;
+            }
+            fields.get(12)?.parse::<u64>().ok()
+        })
+        .reduce(|a, b| a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binds_every_address_of_the_list() {
+        let sockets =
+            bind_all("127.0.0.1:0, 127.0.0.2:0", 64 * 1024).unwrap();
+        assert_eq!(sockets.len(), 2);
+
+        let err = bind_all("127.0.0.1:0,not-an-address", 64 * 1024)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not-an-address"), "{}", err);
+    }
+
+    #[test]
+    fn parse_proc_net_udp() {
+        let contents = "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  123: 00000000:1F41 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 40123 2 0000000000000000 17
+  124: 0100007F:0035 00000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 40124 2 0000000000000000 0
+";
+        assert_eq!(parse_kernel_drops(contents, 8001), Some(17));
+        assert_eq!(parse_kernel_drops(contents, 53), Some(0));
+        assert_eq!(parse_kernel_drops(contents, 8002), None);
+    }
+
+    #[test]
+    fn counts_datagrams_filling_the_buffer() {
+        let metrics = UdpMetrics::default();
+        assert_eq!(metrics.check_truncation(1000, 1232), 1000);
+        // a repair response, a full shred and its nonce
+        assert_eq!(metrics.check_truncation(1232, 1232), 1232);
+        assert_eq!(metrics.full_datagrams(), 0);
+        assert_eq!(metrics.check_truncation(1233, 1232), 1232);
+        metrics.check_truncation(1233, 1232);
+        assert_eq!(metrics.full_datagrams(), 2);
+    }
+}
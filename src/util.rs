@@ -1,12 +1,40 @@
+use anyhow::anyhow;
 use serde::Deserialize;
 use solana_sdk::pubkey::Pubkey;
 use std::str::FromStr;
 
-pub fn env(key: &str) -> String {
-    std::env::var(key).unwrap_or_else(|_| {
-        panic!("{} env var not set", key);
+/// env is the value of the env var, None if it is unset or empty
+pub fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// require_env is the value of the env var, the error names the var and
+/// what needs it
+pub fn require_env(key: &str, needed_by: &str) -> anyhow::Result<String> {
+    env(key).ok_or_else(|| {
+        anyhow!("{} env var not set, {} needs it", key, needed_by)
     })
 }
+
+/// check_env fails unless all of `keys` are set, naming every missing one,
+/// so that a mode fails at startup rather than once it first needs a var
+pub fn check_env(keys: &[&str], mode: &str) -> anyhow::Result<()> {
+    let missing = keys
+        .iter()
+        .filter(|key| env(key).is_none())
+        .copied()
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} env var{} not set, {} needs {}",
+        missing.join(", "),
+        if missing.len() > 1 { "s" } else { "" },
+        mode,
+        if missing.len() > 1 { "them" } else { "it" }
+    ))
+}
 /// Helper function for pubkey serialize
 pub fn pubkey_to_string<S>(
     pubkey: &Pubkey,
@@ -26,3 +54,36 @@ where
     let s = String::deserialize(deserializer)?;
     Pubkey::from_str(&s).map_err(serde::de::Error::custom)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_env_names_the_missing_vars() {
+        std::env::set_var("SHREDS_TEST_SET_VAR", "x");
+        std::env::set_var("SHREDS_TEST_EMPTY_VAR", "");
+        assert!(check_env(&["SHREDS_TEST_SET_VAR"], "test mode").is_ok());
+
+        let err = check_env(
+            &[
+                "SHREDS_TEST_SET_VAR",
+                "SHREDS_TEST_EMPTY_VAR",
+                "SHREDS_TEST_UNSET_VAR",
+            ],
+            "test mode",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SHREDS_TEST_EMPTY_VAR, SHREDS_TEST_UNSET_VAR env vars not set, \
+             test mode needs them"
+        );
+        assert_eq!(
+            require_env("SHREDS_TEST_UNSET_VAR", "test mode")
+                .unwrap_err()
+                .to_string(),
+            "SHREDS_TEST_UNSET_VAR env var not set, test mode needs it"
+        );
+    }
+}
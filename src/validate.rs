@@ -0,0 +1,184 @@
+use bytes::Bytes;
+use serde::Serialize;
+use solana_ledger::shred::Shred;
+use std::collections::HashMap;
+
+use crate::otel::FecSetTrace;
+use crate::shred::{
+    classify_packet, get_coding_shred_header, load_shreds, preprocess_shreds,
+    CodingShredHeader, PacketKind,
+};
+use crate::shred_processor::{FecSet, ShredProcessor};
+
+/// CaptureStats is the shape of a capture, enough to tell if it is usable
+/// as a fixture without replaying it
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CaptureStats {
+    pub packets: usize,
+    /// Packets too short to be a shred (heartbeats and the like)
+    pub runts: usize,
+    /// Packets long enough which still do not parse as a shred
+    pub invalid: usize,
+    pub data_shreds: usize,
+    pub coding_shreds: usize,
+    pub slots: usize,
+    pub fec_sets: usize,
+    pub complete_fec_sets: usize,
+    pub hanging_fec_sets: usize,
+}
+
+/// run loads the capture (a packets.json-style array of packets) and prints
+/// its stats as a table, or as JSON with `json`
+pub fn run(path: &str, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string(path)?;
+    let raw_shreds: Vec<Vec<u8>> = serde_json::from_str(&data)?;
+    let stats = validate_capture(raw_shreds);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print_stats(&stats);
+    }
+    Ok(())
+}
+
+fn empty_fec_set() -> FecSet {
+    FecSet {
+        data_shreds: HashMap::new(),
+        coding_shreds: HashMap::new(),
+        num_expected_data: None,
+        num_expected_coding: None,
+        is_last_in_slot: false,
+        processed: false,
+        trace: FecSetTrace::default(),
+        first_shred_at: None,
+    }
+}
+
+fn is_runt(raw_shred: &[u8]) -> bool {
+    classify_packet(raw_shred) != PacketKind::Shred
+}
+
+/// validate_capture counts the packets by kind and groups the shreds into
+/// FEC sets, which are complete by the same rule the processor goes by
+pub fn validate_capture(raw_shreds: Vec<Vec<u8>>) -> CaptureStats {
+    let mut stats = CaptureStats {
+        packets: raw_shreds.len(),
+        ..Default::default()
+    };
+    let mut valid = Vec::new();
+    for raw_shred in raw_shreds {
+        if is_runt(&raw_shred) {
+            stats.runts += 1;
+        } else if Shred::new_from_serialized_shred(raw_shred.clone()).is_ok()
+        {
+            valid.push(raw_shred);
+        } else {
+            stats.invalid += 1;
+        }
+    }
+
+    let shreds_by_slot = load_shreds(valid);
+    stats.slots = shreds_by_slot.len();
+    for (_, shreds) in shreds_by_slot {
+        let (data_shreds, coding_shreds) = preprocess_shreds(shreds);
+        stats.data_shreds += data_shreds.len();
+        stats.coding_shreds += coding_shreds.len();
+
+        let mut fec_sets = HashMap::<u32, FecSet>::new();
+        for shred in data_shreds {
+            let set = fec_sets
+                .entry(shred.fec_set_index())
+                .or_insert_with(empty_fec_set);
+            set.is_last_in_slot |= shred.last_in_slot();
+            set.data_shreds
+                .insert(shred.index(), Bytes::from(shred.payload().clone()));
+        }
+        for shred in coding_shreds {
+            let set = fec_sets
+                .entry(shred.fec_set_index())
+                .or_insert_with(empty_fec_set);
+            if let Ok(CodingShredHeader {
+                num_data_shreds,
+                num_coding_shreds,
+                ..
+            }) = get_coding_shred_header(shred.payload())
+            {
+                set.num_expected_data = Some(num_data_shreds);
+                set.num_expected_coding = Some(num_coding_shreds);
+            }
+            set.coding_shreds
+                .insert(shred.index(), Bytes::from(shred.payload().clone()));
+        }
+
+        stats.fec_sets += fec_sets.len();
+        for set in fec_sets.values() {
+            if ShredProcessor::is_fec_set_complete(set) {
+                stats.complete_fec_sets += 1;
+            } else {
+                stats.hanging_fec_sets += 1;
+            }
+        }
+    }
+    stats
+}
+
+pub fn print_stats(stats: &CaptureStats) {
+    for (name, value) in [
+        ("packets", stats.packets),
+        ("runts", stats.runts),
+        ("invalid", stats.invalid),
+        ("data shreds", stats.data_shreds),
+        ("coding shreds", stats.coding_shreds),
+        ("slots", stats.slots),
+        ("FEC sets", stats.fec_sets),
+        ("complete FEC sets", stats.complete_fec_sets),
+        ("hanging FEC sets", stats.hanging_fec_sets),
+    ] {
+        println!("{:<20} {}", name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shred::shred_entries;
+    use solana_entry::entry::Entry;
+    use solana_sdk::hash::Hash;
+
+    fn shreds(slot: u64) -> (Vec<Shred>, Vec<Shred>) {
+        let entries = vec![Entry::new(&Hash::default(), 1, vec![])];
+        shred_entries(slot, &entries, true, 0, 0, true)
+    }
+
+    #[test]
+    fn validates_capture() {
+        let mut raw_shreds = Vec::new();
+        // a whole FEC set
+        let (data_shreds, coding_shreds) = shreds(42);
+        let (num_data, num_coding) = (data_shreds.len(), coding_shreds.len());
+        for shred in data_shreds.iter().chain(coding_shreds.iter()) {
+            raw_shreds.push(shred.payload().clone());
+        }
+        // a FEC set with a single data shred and nothing to recover it by
+        let (data_shreds, _) = shreds(43);
+        raw_shreds.push(data_shreds[0].payload().clone());
+        raw_shreds.push(vec![0; 29]);
+        raw_shreds.push(vec![0xff; 0x58]);
+
+        let stats = validate_capture(raw_shreds);
+        assert_eq!(
+            stats,
+            CaptureStats {
+                packets: num_data + num_coding + 3,
+                runts: 1,
+                invalid: 1,
+                data_shreds: num_data + 1,
+                coding_shreds: num_coding,
+                slots: 2,
+                fec_sets: 2,
+                complete_fec_sets: 1,
+                hanging_fec_sets: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use serde::Serialize;
+use solana_sdk::clock::Slot;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::raydium::QuoteAsset;
+use crate::sandwich::{SwapRecord, DETECTION_LAG_SLOTS};
+use crate::sink::{idempotency_key, SLOT_VOLUME_TOPIC};
+
+/// PoolVolume is what was swapped on a pool within a slot, `volume` is in
+/// UI units of the quote of the pool, so in SOL for the SOL pools
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolVolume {
+    pub quote: QuoteAsset,
+    pub volume: f64,
+    pub num_swaps: u64,
+}
+
+/// SlotVolumeEvent is the volume of every pool swapped on in a slot, keyed
+/// by the amm id
+#[derive(Debug, Clone, Serialize)]
+pub struct SlotVolumeEvent {
+    pub slot: Slot,
+    /// When the slot was flushed, unix millis
+    pub timestamp: i64,
+    /// Volume of the SOL pools, in SOL
+    pub total_sol: f64,
+    pub pools: BTreeMap<String, PoolVolume>,
+    pub idempotency_key: String,
+}
+
+/// VolumeAggregator sums the swaps up per pool and slot, a slot is flushed
+/// once it is DETECTION_LAG_SLOTS behind the newest one seen, the same as
+/// for the sandwiches, so that the FEC sets completing late still count;
+/// the swaps of a slot which is already behind the flushed ones are dropped
+/// (and counted) rather than reported again under the same idempotency key
+#[derive(Debug, Default)]
+pub struct VolumeAggregator {
+    volume_by_slot: BTreeMap<Slot, HashMap<String, PoolVolume>>,
+    // the slots before this one were flushed
+    flushed_before: Slot,
+    late_swaps: u64,
+}
+
+impl VolumeAggregator {
+    /// record adds the swaps of a batch of entries of `slot` and returns the
+    /// volume of the slots which are now complete
+    pub fn record(
+        &mut self,
+        slot: Slot,
+        swaps: &[SwapRecord],
+    ) -> Vec<SlotVolumeEvent> {
+        if slot < self.flushed_before {
+            self.late_swaps += swaps.len() as u64;
+        } else if !swaps.is_empty() {
+            let pools = self.volume_by_slot.entry(slot).or_default();
+            for swap in swaps {
+                let pool = pools.entry(swap.amm_id.to_string()).or_insert(
+                    PoolVolume {
+                        quote: swap.quote,
+                        volume: 0.,
+                        num_swaps: 0,
+                    },
+                );
+                pool.volume += swap.quote_amount;
+                pool.num_swaps += 1;
+            }
+        }
+        let newest = match self.volume_by_slot.last_key_value() {
+            Some((newest, _)) => (*newest).max(slot),
+            None => return Vec::new(),
+        };
+        let flushed_before = newest.saturating_sub(DETECTION_LAG_SLOTS - 1);
+        self.flushed_before = self.flushed_before.max(flushed_before);
+        let pending = self.volume_by_slot.split_off(&flushed_before);
+        std::mem::replace(&mut self.volume_by_slot, pending)
+            .into_iter()
+            .map(|(slot, pools)| slot_volume_event(slot, pools))
+            .collect()
+    }
+
+    /// late_swaps is the number of swaps dropped for coming in after their
+    /// slot was flushed
+    pub fn late_swaps(&self) -> u64 {
+        self.late_swaps
+    }
+}
+
+fn slot_volume_event(
+    slot: Slot,
+    pools: HashMap<String, PoolVolume>,
+) -> SlotVolumeEvent {
+    let total_sol = pools
+        .values()
+        .filter(|pool| pool.quote == QuoteAsset::Wsol)
+        .map(|pool| pool.volume)
+        .sum();
+    SlotVolumeEvent {
+        slot,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        total_sol,
+        pools: pools.into_iter().collect(),
+        idempotency_key: idempotency_key(
+            SLOT_VOLUME_TOPIC,
+            &[&slot.to_string()],
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn swap(
+        amm_id: Pubkey,
+        quote: QuoteAsset,
+        quote_amount: f64,
+    ) -> SwapRecord {
+        SwapRecord {
+            signature: String::new(),
+            signer: Pubkey::new_unique(),
+            amm_id,
+            mint: Pubkey::new_unique(),
+            is_buy: true,
+            quote,
+            quote_amount,
+        }
+    }
+
+    #[test]
+    fn sums_the_volume_per_pool_once_the_slot_is_complete() {
+        let (sol_pool, usdc_pool) =
+            (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut aggregator = VolumeAggregator::default();
+
+        assert!(aggregator
+            .record(
+                10,
+                &[
+                    swap(sol_pool, QuoteAsset::Wsol, 1.5),
+                    swap(usdc_pool, QuoteAsset::Usdc, 200.),
+                ],
+            )
+            .is_empty());
+        // a late FEC set of the same slot
+        assert!(aggregator
+            .record(10, &[swap(sol_pool, QuoteAsset::Wsol, 0.5)])
+            .is_empty());
+        assert!(aggregator.record(11, &[]).is_empty());
+
+        let events = aggregator.record(12, &[]);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.slot, 10);
+        assert_eq!(event.total_sol, 2.);
+        assert_eq!(
+            event.pools[&sol_pool.to_string()],
+            PoolVolume {
+                quote: QuoteAsset::Wsol,
+                volume: 2.,
+                num_swaps: 2,
+            }
+        );
+        assert_eq!(event.pools[&usdc_pool.to_string()].volume, 200.);
+        // the slots without swaps are not reported
+        assert!(aggregator.record(20, &[]).is_empty());
+    }
+
+    #[test]
+    fn drops_the_swaps_of_the_flushed_slots() {
+        let pool = Pubkey::new_unique();
+        let mut aggregator = VolumeAggregator::default();
+
+        aggregator.record(10, &[swap(pool, QuoteAsset::Wsol, 1.)]);
+        assert_eq!(aggregator.record(12, &[]).len(), 1);
+        // slot 10 was reported already, not again with the same key
+        assert!(aggregator
+            .record(10, &[swap(pool, QuoteAsset::Wsol, 1.)])
+            .is_empty());
+        assert!(aggregator.record(14, &[]).is_empty());
+        assert_eq!(aggregator.late_swaps(), 1);
+    }
+}
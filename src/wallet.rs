@@ -0,0 +1,146 @@
+use log::info;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::{EncodableKey, Signer};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::util::require_env;
+
+/// KeypairPool is the set of funded wallets trades are signed with, handed
+/// out round robin so that consecutive trades go out of different wallets
+#[derive(Debug)]
+pub struct KeypairPool {
+    keypairs: Vec<Arc<Keypair>>,
+    next: AtomicUsize,
+}
+
+impl KeypairPool {
+    pub fn new(keypairs: Vec<Keypair>) -> anyhow::Result<Self> {
+        if keypairs.is_empty() {
+            anyhow::bail!("No keypairs in the pool");
+        }
+        Ok(KeypairPool {
+            keypairs: keypairs.into_iter().map(Arc::new).collect(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// load reads every `.json` keypair of `source` if it is a directory,
+    /// otherwise it is taken for a comma-separated list of keypair paths
+    pub fn load(source: &str) -> anyhow::Result<Self> {
+        let paths = if Path::new(source).is_dir() {
+            let mut paths = std::fs::read_dir(source)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.retain(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            });
+            paths.sort();
+            paths
+        } else {
+            source
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        };
+        let keypairs = paths
+            .iter()
+            .map(|path| {
+                Keypair::read_from_file(path).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to read keypair {}: {}",
+                        path.display(),
+                        e
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let pool = Self::new(keypairs)?;
+        info!("Loaded {} wallets: {:?}", pool.len(), pool.pubkeys());
+        Ok(pool)
+    }
+
+    /// from_env loads FUND_KEYPAIR_PATH, a keypair, a directory of them or
+    /// a comma-separated list
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::load(&require_env("FUND_KEYPAIR_PATH", "signing")?)
+    }
+
+    /// next is the wallet to sign the next trade with
+    pub fn next(&self) -> Arc<Keypair> {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        self.keypairs[next % self.keypairs.len()].clone()
+    }
+
+    /// primary is the first wallet, for the reads which need a payer but
+    /// never land on-chain
+    pub fn primary(&self) -> &Keypair {
+        &self.keypairs[0]
+    }
+
+    pub fn pubkeys(&self) -> Vec<Pubkey> {
+        self.keypairs
+            .iter()
+            .map(|keypair| keypair.pubkey())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keypairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keypairs.is_empty()
+    }
+}
+
+/// fund_pubkey is the primary wallet of FUND_KEYPAIR_PATH
+pub fn fund_pubkey() -> Pubkey {
+    KeypairPool::from_env()
+        .expect("Failed to read keypair")
+        .primary()
+        .pubkey()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_and_rotates_wallets() {
+        let dir = std::env::temp_dir()
+            .join(format!("shreds-wallets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let keypairs = (0..3).map(|_| Keypair::new()).collect::<Vec<_>>();
+        let mut paths = Vec::new();
+        for (i, keypair) in keypairs.iter().enumerate() {
+            let path = dir.join(format!("wallet-{}.json", i));
+            keypair.write_to_file(&path).unwrap();
+            paths.push(path.display().to_string());
+        }
+        std::fs::write(dir.join("README.md"), "not a keypair").unwrap();
+        let pubkeys = keypairs.iter().map(|k| k.pubkey()).collect::<Vec<_>>();
+
+        let pool = KeypairPool::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!(pool.pubkeys(), pubkeys);
+        let rotation =
+            (0..4).map(|_| pool.next().pubkey()).collect::<Vec<_>>();
+        assert_eq!(
+            rotation,
+            vec![pubkeys[0], pubkeys[1], pubkeys[2], pubkeys[0]]
+        );
+
+        let pool = KeypairPool::load(&paths[1..].join(", ")).unwrap();
+        assert_eq!(pool.pubkeys(), pubkeys[1..]);
+        assert_eq!(pool.primary().pubkey(), pubkeys[1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(KeypairPool::load("").is_err());
+        assert!(KeypairPool::new(Vec::new()).is_err());
+    }
+}
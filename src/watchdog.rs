@@ -0,0 +1,104 @@
+use log::{error, info, warn};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+use crate::admin::Intake;
+use crate::udp::UdpMetrics;
+
+/// WatchdogConfig is the silence tolerated while the service is live, with
+/// `restart` the receive task is respawned on top of being reported
+#[derive(Debug, Clone, Copy)]
+pub struct WatchdogConfig {
+    pub timeout: Duration,
+    pub restart: bool,
+}
+
+/// is_stalled tells if nothing was received for longer than `timeout`,
+/// counting from the last packet or, if more recent (or no packet yet),
+/// from when the receive task was (re)started
+pub fn is_stalled(
+    seconds_since_last_packet: Option<u64>,
+    since_start: Duration,
+    timeout: Duration,
+) -> bool {
+    let silence = match seconds_since_last_packet {
+        Some(secs) => Duration::from_secs(secs).min(since_start),
+        None => since_start,
+    };
+    silence > timeout
+}
+
+/// run checks on the receive task every half the timeout and reports when
+/// it died or went silent, the silence is not checked while the intake is
+/// paused; `spawn_receiver` starts a replacement, which is stored in
+/// `receiver` so that it can be aborted on shutdown, or None when there are
+/// no sources left to read (the finite ones ended), which stops the watchdog
+pub async fn run(
+    config: WatchdogConfig,
+    udp_metrics: Arc<UdpMetrics>,
+    intake: Arc<Intake>,
+    receiver: Arc<Mutex<JoinHandle<()>>>,
+    spawn_receiver: impl Fn() -> Option<JoinHandle<()>>,
+) {
+    info!(
+        "Watchdog timeout: {:?}, restart: {}",
+        config.timeout, config.restart
+    );
+    let mut started_at = Instant::now();
+    loop {
+        sleep((config.timeout / 2).max(Duration::from_secs(1))).await;
+        let since_last_packet = udp_metrics.seconds_since_last_packet();
+        let finished = receiver.lock().expect("receiver lock").is_finished();
+        if finished {
+            error!("Receive task is not running");
+        } else if intake.is_paused() {
+            continue;
+        } else if is_stalled(
+            since_last_packet,
+            started_at.elapsed(),
+            config.timeout,
+        ) {
+            error!(
+                "No packets received for over {:?} (last one {}s ago)",
+                config.timeout,
+                since_last_packet
+                    .map(|secs| secs.to_string())
+                    .unwrap_or_else(|| "never".to_string())
+            );
+        } else {
+            continue;
+        }
+        if config.restart {
+            warn!("Restarting the receive task");
+            let mut receiver = receiver.lock().expect("receiver lock");
+            receiver.abort();
+            match spawn_receiver() {
+                Some(respawned) => *receiver = respawned,
+                None => {
+                    info!("No sources left to read, stopping the watchdog");
+                    return;
+                }
+            }
+            started_at = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalls_after_timeout_of_silence() {
+        let timeout = Duration::from_secs(30);
+        // no packets yet, counts from the start
+        assert!(!is_stalled(None, Duration::from_secs(10), timeout));
+        assert!(is_stalled(None, Duration::from_secs(31), timeout));
+        assert!(!is_stalled(Some(5), Duration::from_secs(600), timeout));
+        assert!(is_stalled(Some(60), Duration::from_secs(600), timeout));
+        // just restarted, given the full timeout again
+        assert!(!is_stalled(Some(60), Duration::from_secs(1), timeout));
+    }
+}